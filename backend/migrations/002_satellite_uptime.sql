@@ -0,0 +1,10 @@
+-- One row per period a satellite was online; offline_at is NULL while it still is
+CREATE TABLE satellite_uptime (
+    id TEXT PRIMARY KEY,
+    satellite_id TEXT NOT NULL REFERENCES satellites(id) ON DELETE CASCADE,
+    online_at DATETIME NOT NULL,
+    offline_at DATETIME,
+    offline_reason TEXT
+);
+
+CREATE INDEX idx_satellite_uptime_satellite ON satellite_uptime(satellite_id, online_at);
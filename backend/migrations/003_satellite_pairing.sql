@@ -0,0 +1,15 @@
+-- Hash of the secret issued to a satellite when it is paired
+ALTER TABLE satellites ADD COLUMN secret_hash TEXT;
+
+-- Satellites that announced themselves and are waiting for, or got, an admin decision
+CREATE TABLE pairing_requests (
+    id TEXT PRIMARY KEY,
+    mac_address TEXT UNIQUE NOT NULL,
+    name TEXT,
+    ip_address TEXT,
+    state TEXT NOT NULL DEFAULT 'pending',
+    satellite_id TEXT REFERENCES satellites(id) ON DELETE CASCADE,
+    requested_at DATETIME NOT NULL,
+    decided_at DATETIME,
+    decided_by TEXT
+);
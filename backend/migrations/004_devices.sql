@@ -0,0 +1,21 @@
+CREATE TABLE rooms (
+    id TEXT PRIMARY KEY,
+    name TEXT UNIQUE NOT NULL COLLATE NOCASE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Smart home devices controlled over MQTT
+CREATE TABLE devices (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    room_id TEXT REFERENCES rooms(id) ON DELETE SET NULL,
+    device_type TEXT NOT NULL DEFAULT 'light',
+    capabilities TEXT NOT NULL, -- JSON array, e.g. ["on_off", "brightness", "color"]
+    command_topic TEXT NOT NULL,
+    state_topic TEXT,
+    state TEXT, -- JSON, last state reported by the device
+    state_updated_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_devices_room ON devices(room_id);
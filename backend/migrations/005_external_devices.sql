@@ -0,0 +1,26 @@
+-- Devices can now be controlled through a home automation backend instead of
+-- MQTT, so command_topic becomes optional. SQLite can't relax NOT NULL in
+-- place, hence the rebuild.
+CREATE TABLE devices_new (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    room_id TEXT REFERENCES rooms(id) ON DELETE SET NULL,
+    device_type TEXT NOT NULL DEFAULT 'light',
+    capabilities TEXT NOT NULL, -- JSON array, e.g. ["on_off", "brightness", "color"]
+    backend TEXT NOT NULL DEFAULT 'mqtt', -- 'mqtt' or the home automation backend that owns it
+    external_id TEXT, -- id of the device in that backend, e.g. an OpenHAB item name
+    command_topic TEXT,
+    state_topic TEXT,
+    state TEXT, -- JSON, last state reported by the device
+    state_updated_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO devices_new (id, name, room_id, device_type, capabilities, command_topic, state_topic, state, state_updated_at, created_at)
+SELECT id, name, room_id, device_type, capabilities, command_topic, state_topic, state, state_updated_at, created_at FROM devices;
+
+DROP TABLE devices;
+ALTER TABLE devices_new RENAME TO devices;
+
+CREATE INDEX idx_devices_room ON devices(room_id);
+CREATE UNIQUE INDEX idx_devices_external ON devices(backend, external_id);
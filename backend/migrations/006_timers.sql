@@ -0,0 +1,15 @@
+-- Timers and alarms, kept here so they survive a restart
+CREATE TABLE timers (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('timer', 'alarm')),
+    label TEXT,
+    user_id TEXT REFERENCES users(id) ON DELETE CASCADE,
+    satellite_id TEXT REFERENCES satellites(id) ON DELETE SET NULL,
+    duration_secs INTEGER, -- what a timer was set for; NULL for alarms
+    fires_at DATETIME NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'fired', 'cancelled', 'missed')),
+    fired_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_timers_due ON timers(status, fires_at);
@@ -0,0 +1,16 @@
+-- Reminders belong to a user and are delivered wherever they last talked to Barnaby
+CREATE TABLE reminders (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    satellite_id TEXT REFERENCES satellites(id) ON DELETE SET NULL, -- where it was set
+    text TEXT NOT NULL,
+    remind_at DATETIME NOT NULL,
+    recurrence TEXT CHECK (recurrence IN ('daily', 'weekly', 'weekdays')),
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'cancelled')),
+    delivered_at DATETIME, -- most recent delivery, recurring reminders stay pending
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_reminders_due ON reminders(status, remind_at);
+CREATE INDEX idx_reminders_user ON reminders(user_id);
@@ -0,0 +1,26 @@
+-- Shopping and to-do lists. Lists without an owner belong to the household
+CREATE TABLE lists (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    user_id TEXT REFERENCES users(id) ON DELETE CASCADE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_lists_name ON lists(COALESCE(user_id, ''), name);
+
+CREATE TABLE list_items (
+    id TEXT PRIMARY KEY,
+    list_id TEXT NOT NULL REFERENCES lists(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    quantity REAL, -- NULL when none was given, e.g. just "milk"
+    unit TEXT, -- "litre", "bottle", ...
+    checked BOOLEAN NOT NULL DEFAULT FALSE,
+    added_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_list_items_list ON list_items(list_id);
+
+INSERT INTO lists (id, name) VALUES ('list-shopping', 'shopping'), ('list-to-do', 'to-do');
@@ -0,0 +1,18 @@
+-- Corrections to what Barnaby understood, reviewed by an admin before they
+-- become training data
+CREATE TABLE intent_feedback (
+    id TEXT PRIMARY KEY,
+    command_id TEXT REFERENCES command_history(id) ON DELETE SET NULL,
+    user_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+    text TEXT NOT NULL,
+    predicted_intent TEXT,
+    correct_intent TEXT NOT NULL,
+    confidence REAL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted', 'rejected')),
+    reviewed_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at DATETIME,
+    exported_at DATETIME, -- last written to the training data, accepted entries only
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_intent_feedback_status ON intent_feedback(status, created_at);
@@ -1,13 +1,93 @@
 pub mod routes;
+pub mod websocket;
 
 use axum::Router;
 use crate::AppState;
 
-pub fn create_routes() -> Router<AppState> {
+pub fn create_routes(state: AppState) -> Router<AppState> {
     Router::new()
-        .nest("/api/auth", routes::auth::create_routes())
-        .nest("/api/users", routes::users::create_routes())
-        .nest("/api/audio", routes::audio::create_routes())
-        .nest("/api/commands", routes::commands::create_routes())
-        .nest("/api/feedback", routes::feedback::create_routes())
-}
\ No newline at end of file
+        .nest("/api/auth", routes::auth::create_routes(state.clone()))
+        .nest("/api/users", routes::users::create_routes(state.clone()))
+        .nest("/api/audio", routes::audio::create_routes(state.clone()))
+        .nest("/api/commands", routes::commands::create_routes(state.clone()))
+        .nest("/api/feedback", routes::feedback::create_routes(state.clone()))
+        .nest("/api/satellites", routes::satellites::create_routes(state.clone()))
+        .nest("/api/rooms", routes::rooms::create_routes(state.clone()))
+        .nest("/api/devices", routes::devices::create_routes(state.clone()))
+        .nest("/api/home-automation", routes::home_automation::create_routes(state.clone()))
+        .nest("/api/timers", routes::timers::create_routes(state.clone()))
+        .nest("/api/reminders", routes::reminders::create_routes(state.clone()))
+        .nest("/api/lists", routes::lists::create_routes(state.clone()))
+        .nest("/api/location", routes::location::create_routes(state.clone()))
+        .nest("/api/nlu", routes::nlu::create_routes(state))
+        .merge(websocket::create_routes())
+}
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use super::create_routes;
+    use crate::test_support;
+
+    /// The status of a request through all the API routes, as a user with
+    /// `role` when one is given.
+    async fn status(method: Method, uri: &str, role: Option<&str>) -> StatusCode {
+        let state = test_support::state().await;
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(role) = role {
+            let user_id = test_support::user(&state, "someone", role).await;
+            let token = test_support::token(&state, &user_id, role);
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request
+            .header("content-type", "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let app = create_routes(state.clone()).with_state(state);
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn user_management_needs_a_token() {
+        assert_eq!(status(Method::GET, "/api/users", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Method::POST, "/api/users", None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn user_management_needs_an_admin() {
+        assert_eq!(status(Method::GET, "/api/users", Some("user")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(Method::POST, "/api/users", Some("user")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(Method::GET, "/api/users", Some("admin")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn a_bad_token_is_rejected() {
+        let state = test_support::state().await;
+        let request = Request::get("/api/users")
+            .header(AUTHORIZATION, "Bearer not-a-token")
+            .body(Body::empty())
+            .unwrap();
+        let app = create_routes(state.clone()).with_state(state);
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn commands_audio_and_feedback_need_a_token() {
+        let requests = [
+            (Method::GET, "/api/commands/history"),
+            (Method::POST, "/api/commands/process"),
+            (Method::POST, "/api/audio/transcribe"),
+            (Method::POST, "/api/audio/synthesize"),
+            (Method::GET, "/api/audio/voices"),
+            (Method::POST, "/api/feedback/intent"),
+            (Method::GET, "/api/feedback"),
+        ];
+        for (method, uri) in requests {
+            assert_eq!(status(method, uri, None).await, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+    }
+}
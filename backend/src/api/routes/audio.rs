@@ -1,14 +1,20 @@
 use axum::{
     extract::State,
     http::StatusCode,
+    middleware,
     response::Json,
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tracing::warn;
 
-use crate::AppState;
+use crate::{
+    audio::{self, stt::TranscriptionSegment, Transcription},
+    auth::middleware::auth_middleware,
+    AppState,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct TranscribeRequest {
@@ -19,6 +25,7 @@ pub struct TranscribeRequest {
 pub struct TranscribeResponse {
     pub text: String,
     pub confidence: f32,
+    pub segments: Vec<TranscriptionSegment>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,33 +37,98 @@ pub struct SynthesizeRequest {
 #[derive(Debug, Serialize)]
 pub struct SynthesizeResponse {
     pub audio_data: String, // Base64 encoded audio
+    pub voice: String,
 }
 
-pub fn create_routes() -> Router<AppState> {
+pub fn create_routes(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/transcribe", post(transcribe))
         .route("/synthesize", post(synthesize))
+        .route("/voices", get(list_voices))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
 }
 
 pub async fn transcribe(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Json(payload): Json<TranscribeRequest>,
 ) -> Result<Json<TranscribeResponse>, StatusCode> {
-    // TODO: Implement actual STT processing
-    // For now, return a placeholder response
+    let transcription = transcribe_base64(&state, &payload.audio_data).await?;
+
     Ok(Json(TranscribeResponse {
-        text: "Hello, this is a placeholder transcription".to_string(),
-        confidence: 0.95,
+        text: transcription.text,
+        confidence: transcription.confidence,
+        segments: transcription.segments,
     }))
 }
 
+/// Decodes a base64 WAV/PCM payload and runs it through the configured STT engine.
+pub async fn transcribe_base64(state: &AppState, audio_data: &str) -> Result<Transcription, StatusCode> {
+    let stt = state.stt.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let buffer = audio::decode_base64_audio(audio_data, state.config.audio.sample_rate)
+        .map_err(|e| {
+            warn!("Could not decode audio: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    stt.transcribe(&buffer).await.map_err(|e| {
+        warn!("Transcription with {} failed: {}", stt.name(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
 pub async fn synthesize(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Json(payload): Json<SynthesizeRequest>,
 ) -> Result<Json<SynthesizeResponse>, StatusCode> {
-    // TODO: Implement actual TTS processing
-    // For now, return a placeholder response
-    Ok(Json(SynthesizeResponse {
-        audio_data: "placeholder_audio_data".to_string(),
-    }))
-}
\ No newline at end of file
+    let tts = state.tts.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let voice = match payload.voice {
+        Some(voice) => {
+            let voices = tts.voices().await.map_err(|e| {
+                warn!("Could not list {} voices: {}", tts.name(), e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            if !voices.iter().any(|v| v.id == voice) {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            voice
+        }
+        None => state.config.audio.tts_voice.clone(),
+    };
+
+    let audio_data = synthesize_base64(&state, &payload.text, &voice).await?;
+
+    Ok(Json(SynthesizeResponse { audio_data, voice }))
+}
+
+pub async fn list_voices(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let tts = state.tts.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let voices = tts.voices().await.map_err(|e| {
+        warn!("Could not list {} voices: {}", tts.name(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({
+        "engine": tts.name(),
+        "default": state.config.audio.tts_voice,
+        "voices": voices
+    })))
+}
+
+/// Runs `text` through the configured TTS engine and returns base64 WAV.
+pub async fn synthesize_base64(state: &AppState, text: &str, voice: &str) -> Result<String, StatusCode> {
+    let tts = state.tts.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    if text.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let wav = tts.synthesize(text, voice).await.map_err(|e| {
+        warn!("Synthesis with {} failed: {}", tts.name(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(audio::encode_base64(&wav))
+}
@@ -1,9 +1,10 @@
 use axum::{
     extract::State,
     http::StatusCode,
+    middleware,
     response::Json,
-    routing::post,
-    Router,
+    routing::{get, post},
+    Extension, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -11,8 +12,13 @@ use sqlx::Row;
 use uuid::Uuid;
 
 use crate::{
-    auth::jwt::{generate_token, hash_password, verify_password},
+    auth::{
+        jwt::{generate_token, hash_password, verify_password, Claims},
+        middleware::auth_middleware,
+        sessions,
+    },
     database::models::{CreateUser, UserRole},
+    events::Event,
     AppState,
 };
 
@@ -25,6 +31,7 @@ pub struct LoginRequest {
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
     pub token: String,
+    pub refresh_token: String,
     pub user: UserInfo,
 }
 
@@ -36,10 +43,42 @@ pub struct UserInfo {
     pub role: String,
 }
 
-pub fn create_routes() -> Router<AppState> {
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    let authenticated = Router::new()
+        .route("/logout", post(logout))
+        .route("/me", get(me))
+        .route("/password", post(change_password))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware));
+
     Router::new()
         .route("/login", post(login))
         .route("/register", post(register))
+        .route("/refresh", post(refresh))
+        .merge(authenticated)
 }
 
 pub async fn login(
@@ -77,8 +116,17 @@ pub async fn login(
     )
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let refresh_token = sessions::create_session(
+        &state.db,
+        &user_id,
+        state.config.auth.refresh_token_expiration,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(Json(LoginResponse {
         token,
+        refresh_token,
         user: UserInfo {
             id: user_id,
             username,
@@ -95,8 +143,12 @@ pub async fn register(
     let user_id = Uuid::new_v4().to_string();
     let password_hash = hash_password(&payload.password)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let role = payload.role.unwrap_or(UserRole::User).to_string();
+
+    // Self-registration always creates a regular user; admins are made via /api/users
+    if payload.role.as_ref().is_some_and(|role| *role != UserRole::User) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let role = UserRole::User.to_string();
 
     sqlx::query(
         "INSERT INTO users (id, username, email, password_hash, role) VALUES (?, ?, ?, ?, ?)",
@@ -110,8 +162,282 @@ pub async fn register(
     .await
     .map_err(|_| StatusCode::CONFLICT)?;
 
+    state.events.publish(Event::UserChanged {
+        user_id: user_id.clone(),
+        action: "created".to_string(),
+    });
+
     Ok(Json(json!({
         "message": "User created successfully",
         "user_id": user_id
     })))
-}
\ No newline at end of file
+}
+
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Json<RefreshResponse>, StatusCode> {
+    let (user_id, refresh_token) = sessions::rotate_session(
+        &state.db,
+        &payload.refresh_token,
+        state.config.auth.refresh_token_expiration,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Re-read the user so role changes take effect on the next access token
+    let user = sqlx::query("SELECT username, role FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let username: String = user.get("username");
+    let role: String = user.get("role");
+
+    let token = generate_token(
+        &user_id,
+        &username,
+        &role,
+        &state.config.auth.jwt_secret,
+        state.config.auth.jwt_expiration,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RefreshResponse {
+        token,
+        refresh_token,
+    }))
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<LogoutRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let revoked = if payload.all {
+        sessions::revoke_user_sessions(&state.db, &claims.sub)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    } else {
+        let refresh_token = payload.refresh_token.ok_or(StatusCode::BAD_REQUEST)?;
+        sessions::revoke_session(&state.db, &claims.sub, &refresh_token)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? as u64
+    };
+
+    Ok(Json(json!({
+        "message": "Logged out",
+        "sessions_revoked": revoked
+    })))
+}
+
+pub async fn me(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<UserInfo>, StatusCode> {
+    let user = sqlx::query("SELECT id, username, email, role FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(UserInfo {
+        id: user.get("id"),
+        username: user.get("username"),
+        email: user.get("email"),
+        role: user.get("role"),
+    }))
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let password_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !verify_password(&payload.current_password, &password_hash)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let new_hash = hash_password(&payload.new_password)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    sqlx::query("UPDATE users SET password_hash = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(&new_hash)
+        .bind(&claims.sub)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Every existing session has to log in again with the new password
+    let revoked = sessions::revoke_user_sessions(&state.db, &claims.sub)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "message": "Password changed",
+        "sessions_revoked": revoked
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::{auth::sessions, test_support, AppState};
+
+    const PASSWORD: &str = "correct horse";
+
+    /// A user who can log in with `PASSWORD`. The hash is cheap to check,
+    /// so logging in repeatedly stays quick.
+    async fn account(state: &AppState, username: &str) -> String {
+        let id = test_support::user(state, username, "user").await;
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(bcrypt::hash(PASSWORD, 4).unwrap())
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        id
+    }
+
+    /// Logs in and returns the access and refresh tokens.
+    async fn login(state: &AppState, username: &str) -> (String, String) {
+        let (status, body) = test_support::call(
+            state,
+            Method::POST,
+            "/api/auth/login",
+            None,
+            json!({"username": username, "password": PASSWORD}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        (body["token"].as_str().unwrap().to_string(), body["refresh_token"].as_str().unwrap().to_string())
+    }
+
+    async fn refresh(state: &AppState, refresh_token: &str) -> (StatusCode, Value) {
+        test_support::call(
+            state,
+            Method::POST,
+            "/api/auth/refresh",
+            None,
+            json!({"refresh_token": refresh_token}),
+        )
+        .await
+    }
+
+    async fn logout(state: &AppState, token: &str, body: Value) -> (StatusCode, Value) {
+        test_support::call(state, Method::POST, "/api/auth/logout", Some(token), body).await
+    }
+
+    #[tokio::test]
+    async fn a_refresh_token_can_be_used_once() {
+        let state = test_support::state().await;
+        account(&state, "sam").await;
+        let (_, refresh_token) = login(&state, "sam").await;
+
+        let (status, body) = refresh(&state, &refresh_token).await;
+        assert_eq!(status, StatusCode::OK);
+        let rotated = body["refresh_token"].as_str().unwrap();
+        assert_ne!(rotated, refresh_token);
+
+        assert_eq!(refresh(&state, &refresh_token).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, rotated).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn an_expired_refresh_token_is_rejected() {
+        let state = test_support::state().await;
+        let user_id = account(&state, "sam").await;
+        let expired = sessions::create_session(&state.db, &user_id, 0).await.unwrap();
+
+        assert_eq!(refresh(&state, &expired).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, "never-issued").await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn logout_revokes_only_its_own_session() {
+        let state = test_support::state().await;
+        account(&state, "sam").await;
+        account(&state, "ops").await;
+        let (token, phone) = login(&state, "sam").await;
+        let (_, laptop) = login(&state, "sam").await;
+        let (_, other_user) = login(&state, "ops").await;
+
+        // Someone else's refresh token isn't theirs to revoke
+        let (status, body) = logout(&state, &token, json!({"refresh_token": other_user})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["sessions_revoked"], 0);
+
+        let (_, body) = logout(&state, &token, json!({"refresh_token": phone})).await;
+        assert_eq!(body["sessions_revoked"], 1);
+        assert_eq!(refresh(&state, &phone).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, &laptop).await.0, StatusCode::OK);
+        assert_eq!(refresh(&state, &other_user).await.0, StatusCode::OK);
+
+        assert_eq!(logout(&state, &token, json!({})).await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn logging_out_everywhere_revokes_every_session() {
+        let state = test_support::state().await;
+        account(&state, "sam").await;
+        account(&state, "ops").await;
+        let (token, phone) = login(&state, "sam").await;
+        let (_, laptop) = login(&state, "sam").await;
+        let (_, other_user) = login(&state, "ops").await;
+
+        let (status, body) = logout(&state, &token, json!({"all": true})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["sessions_revoked"], 2);
+        assert_eq!(refresh(&state, &phone).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, &laptop).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, &other_user).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn changing_the_password_revokes_existing_sessions() {
+        let state = test_support::state().await;
+        account(&state, "sam").await;
+        let (token, phone) = login(&state, "sam").await;
+        let (_, laptop) = login(&state, "sam").await;
+
+        let change = |current: &str| json!({"current_password": current, "new_password": "battery staple"});
+        let (status, _) =
+            test_support::call(&state, Method::POST, "/api/auth/password", Some(&token), change("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = refresh(&state, &laptop).await;
+        assert_eq!(status, StatusCode::OK, "a failed change leaves sessions alone");
+        let laptop = body["refresh_token"].as_str().unwrap().to_string();
+
+        let (status, body) =
+            test_support::call(&state, Method::POST, "/api/auth/password", Some(&token), change(PASSWORD)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["sessions_revoked"], 2);
+        assert_eq!(refresh(&state, &phone).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&state, &laptop).await.0, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = test_support::call(
+            &state,
+            Method::POST,
+            "/api/auth/login",
+            None,
+            json!({"username": "sam", "password": PASSWORD}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
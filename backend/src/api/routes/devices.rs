@@ -0,0 +1,257 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::types::Json as SqlJson;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::rooms::find_room;
+use crate::{
+    auth::middleware::{admin_middleware, auth_middleware},
+    database::models::Device,
+    devices::{self, Capability, DeviceError, StateChange, DEVICE_COLUMNS},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceQuery {
+    pub room_id: Option<String>,
+}
+
+/// Topics default to `barnaby/devices/{id}/set` and `.../state`. An empty
+/// `state_topic` means the device doesn't report its state.
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceRequest {
+    pub name: String,
+    pub room_id: Option<String>,
+    pub device_type: Option<String>,
+    pub capabilities: Vec<String>,
+    pub command_topic: Option<String>,
+    pub state_topic: Option<String>,
+}
+
+/// Fields left out are unchanged; an empty `room_id` or `state_topic` clears it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceRequest {
+    pub name: Option<String>,
+    pub room_id: Option<String>,
+    pub device_type: Option<String>,
+    pub capabilities: Option<Vec<String>>,
+    pub command_topic: Option<String>,
+    pub state_topic: Option<String>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    // Any signed-in user may look at and control devices, only admins may
+    // add, change or remove them
+    let admin_only = middleware::from_fn(admin_middleware);
+
+    Router::new()
+        .route(
+            "/",
+            get(list_devices).merge(post(create_device).route_layer(admin_only.clone())),
+        )
+        .route(
+            "/:id",
+            get(get_device).merge(
+                put(update_device)
+                    .merge(delete(delete_device))
+                    .route_layer(admin_only),
+            ),
+        )
+        .route("/:id/state", post(set_device_state))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+pub async fn list_devices(
+    State(state): State<AppState>,
+    Query(query): Query<DeviceQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let devices = sqlx::query_as::<_, Device>(&format!(
+        "SELECT {} FROM devices WHERE ? IS NULL OR room_id = ? ORDER BY name",
+        DEVICE_COLUMNS
+    ))
+    .bind(&query.room_id)
+    .bind(&query.room_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "devices": devices
+    })))
+}
+
+pub async fn get_device(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> Result<Json<Device>, StatusCode> {
+    devices::find_device(&state, &device_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn create_device(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateDeviceRequest>,
+) -> Result<Json<Device>, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let capabilities = parse_capabilities(&payload.capabilities)?;
+    if let Some(room_id) = &payload.room_id {
+        find_room(&state, room_id).await?.ok_or(StatusCode::BAD_REQUEST)?;
+    }
+
+    let device_id = Uuid::new_v4().to_string();
+    let command_topic = non_empty(payload.command_topic.as_deref())
+        .map(str::to_string)
+        .unwrap_or_else(|| devices::default_command_topic(&device_id));
+    let state_topic = match payload.state_topic.as_deref() {
+        Some(topic) => non_empty(Some(topic)).map(str::to_string),
+        None => Some(devices::default_state_topic(&device_id)),
+    };
+
+    let device = sqlx::query_as::<_, Device>(&format!(
+        "INSERT INTO devices (id, name, room_id, device_type, capabilities, command_topic, state_topic) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+        DEVICE_COLUMNS
+    ))
+    .bind(&device_id)
+    .bind(name)
+    .bind(&payload.room_id)
+    .bind(payload.device_type.as_deref().unwrap_or("light"))
+    .bind(SqlJson(&capabilities))
+    .bind(&command_topic)
+    .bind(&state_topic)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    devices::sync_subscriptions(&state).await;
+    Ok(Json(device))
+}
+
+pub async fn update_device(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    Json(payload): Json<UpdateDeviceRequest>,
+) -> Result<Json<Device>, StatusCode> {
+    if payload.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let capabilities = match &payload.capabilities {
+        Some(capabilities) => Some(SqlJson(parse_capabilities(capabilities)?)),
+        None => None,
+    };
+    if let Some(room_id) = non_empty(payload.room_id.as_deref()) {
+        find_room(&state, room_id).await?.ok_or(StatusCode::BAD_REQUEST)?;
+    }
+
+    let device = sqlx::query_as::<_, Device>(&format!(
+        "UPDATE devices SET
+             name = COALESCE(?, name),
+             room_id = CASE WHEN ? IS NULL THEN room_id ELSE NULLIF(?, '') END,
+             device_type = COALESCE(?, device_type),
+             capabilities = COALESCE(?, capabilities),
+             command_topic = COALESCE(NULLIF(?, ''), command_topic),
+             state_topic = CASE WHEN ? IS NULL THEN state_topic ELSE NULLIF(?, '') END
+         WHERE id = ? RETURNING {}",
+        DEVICE_COLUMNS
+    ))
+    .bind(payload.name.as_deref().map(str::trim))
+    .bind(&payload.room_id)
+    .bind(&payload.room_id)
+    .bind(&payload.device_type)
+    .bind(capabilities)
+    .bind(&payload.command_topic)
+    .bind(&payload.state_topic)
+    .bind(&payload.state_topic)
+    .bind(&device_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let device = device.ok_or(StatusCode::NOT_FOUND)?;
+    devices::sync_subscriptions(&state).await;
+    Ok(Json(device))
+}
+
+pub async fn delete_device(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM devices WHERE id = ?")
+        .bind(&device_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    devices::sync_subscriptions(&state).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends a state change to the device. The stored state is updated once the
+/// device or its home automation backend reports back.
+pub async fn set_device_state(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    Json(change): Json<StateChange>,
+) -> Result<(StatusCode, Json<Value>), StatusCode> {
+    let device = devices::find_device(&state, &device_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    devices::set_state(&state, &device, &change)
+        .await
+        .map_err(|e| device_error_status(&device_id, e))?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "device_id": device_id,
+            "command": change.to_payload()
+        })),
+    ))
+}
+
+fn parse_capabilities(capabilities: &[String]) -> Result<Vec<String>, StatusCode> {
+    let mut parsed = Vec::new();
+    for capability in capabilities {
+        let capability: Capability = capability.parse().map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+        if !parsed.contains(&capability.as_str().to_string()) {
+            parsed.push(capability.as_str().to_string());
+        }
+    }
+    Ok(parsed)
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn device_error_status(device_id: &str, error: DeviceError) -> StatusCode {
+    match error {
+        DeviceError::Unsupported(_) | DeviceError::InvalidValue(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        DeviceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        DeviceError::Command(e) => {
+            warn!("Failed to send command to device {}: {}", device_id, e);
+            StatusCode::BAD_GATEWAY
+        }
+        DeviceError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -1,51 +1,252 @@
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
     http::StatusCode,
+    middleware,
     response::Json,
-    routing::post,
-    Router,
+    routing::{get, post},
+    Extension, Router,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use std::fs::OpenOptions;
-use std::io::Write;
+use tracing::{info, warn};
+use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    auth::{
+        jwt::Claims,
+        middleware::{admin_middleware, auth_middleware},
+    },
+    database::models::{CommandHistory, IntentFeedback},
+    feedback::{self, FEEDBACK_COLUMNS},
+    AppState,
+};
 
+/// With a `command_id`, the text, predicted intent and confidence are taken
+/// from the command as it was logged.
 #[derive(Debug, Deserialize)]
 pub struct FeedbackRequest {
-    pub text: String,
-    pub predicted_intent: String,
+    pub command_id: Option<String>,
+    pub text: Option<String>,
+    pub predicted_intent: Option<String>,
     pub correct_intent: String,
-    pub confidence: f64,
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackQuery {
+    pub status: Option<String>,
 }
 
-pub fn create_routes() -> Router<AppState> {
+/// Accepting may correct the intent the user suggested.
+#[derive(Debug, Deserialize)]
+pub struct AcceptFeedbackRequest {
+    pub correct_intent: Option<String>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    // Anyone signed in may report a misunderstanding, only admins review
+    let admin_only = middleware::from_fn(admin_middleware);
+
     Router::new()
         .route("/intent", post(submit_intent_feedback))
+        .route("/", get(list_feedback).route_layer(admin_only.clone()))
+        .route("/:id/accept", post(accept_feedback).route_layer(admin_only.clone()))
+        .route("/:id/reject", post(reject_feedback).route_layer(admin_only.clone()))
+        .route("/export", post(export_feedback).route_layer(admin_only.clone()))
+        .route("/retrain", get(get_training_status).post(retrain).route_layer(admin_only))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
 }
 
 pub async fn submit_intent_feedback(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Json(payload): Json<FeedbackRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    // Log feedback for manual review and future training
-    let feedback_entry = format!(
-        "- intent: {}\n  examples: |\n    - {}\n",
-        payload.correct_intent, payload.text
+) -> Result<(StatusCode, Json<IntentFeedback>), StatusCode> {
+    let correct_intent = payload.correct_intent.trim();
+    if !feedback::is_known_intent(&state, correct_intent) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let command = match &payload.command_id {
+        Some(command_id) => {
+            let command = sqlx::query_as::<_, CommandHistory>("SELECT * FROM command_history WHERE id = ?")
+                .bind(command_id)
+                .fetch_optional(&state.db)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            // Someone else's command looks the same as one that doesn't exist
+            if claims.role != "admin" && command.user_id.as_deref() != Some(claims.sub.as_str()) {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            Some(command)
+        }
+        None => None,
+    };
+
+    let text = match &command {
+        Some(command) => command.command_text.clone(),
+        None => payload.text.as_deref().map(str::trim).unwrap_or_default().to_string(),
+    };
+    if text.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let predicted_intent = command
+        .as_ref()
+        .and_then(|command| command.intent.clone())
+        .or(payload.predicted_intent);
+    let confidence = command
+        .as_ref()
+        .and_then(|command| command.confidence.map(f64::from))
+        .or(payload.confidence);
+
+    let feedback = sqlx::query_as::<_, IntentFeedback>(&format!(
+        "INSERT INTO intent_feedback (id, command_id, user_id, text, predicted_intent, correct_intent, confidence)
+         VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+        FEEDBACK_COLUMNS
+    ))
+    .bind(Uuid::new_v4().to_string())
+    .bind(command.as_ref().map(|command| &command.id))
+    .bind(&claims.sub)
+    .bind(&text)
+    .bind(&predicted_intent)
+    .bind(correct_intent)
+    .bind(confidence)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(
+        "Feedback on '{}': {:?} should be {}",
+        feedback.text, feedback.predicted_intent, feedback.correct_intent
     );
-    
-    // Append to a feedback file
-    if let Ok(mut file) = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("../nlu/rasa/data/feedback.yml")
-    {
-        let _ = writeln!(file, "{}", feedback_entry);
+    Ok((StatusCode::CREATED, Json(feedback)))
+}
+
+/// Feedback waiting for review by default, newest first.
+pub async fn list_feedback(
+    State(state): State<AppState>,
+    Query(query): Query<FeedbackQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let status = query.status.as_deref().unwrap_or("pending");
+    if !["pending", "accepted", "rejected", "all"].contains(&status) {
+        return Err(StatusCode::BAD_REQUEST);
     }
-    
+
+    let feedback = sqlx::query_as::<_, IntentFeedback>(&format!(
+        "SELECT {} FROM intent_feedback WHERE ? = 'all' OR status = ? ORDER BY created_at DESC",
+        FEEDBACK_COLUMNS
+    ))
+    .bind(status)
+    .bind(status)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(Json(json!({
-        "status": "feedback_recorded",
-        "message": "Thank you for the feedback. This will help improve the system."
+        "feedback": feedback
     })))
-}
\ No newline at end of file
+}
+
+/// Marks feedback as good training data. It is used once exported.
+pub async fn accept_feedback(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(feedback_id): Path<String>,
+    payload: Option<Json<AcceptFeedbackRequest>>,
+) -> Result<Json<IntentFeedback>, StatusCode> {
+    let correct_intent = payload
+        .and_then(|Json(payload)| payload.correct_intent)
+        .map(|intent| intent.trim().to_string());
+    if correct_intent
+        .as_deref()
+        .is_some_and(|intent| !feedback::is_known_intent(&state, intent))
+    {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    review(&state, &claims, &feedback_id, "accepted", correct_intent.as_deref()).await
+}
+
+pub async fn reject_feedback(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(feedback_id): Path<String>,
+) -> Result<Json<IntentFeedback>, StatusCode> {
+    review(&state, &claims, &feedback_id, "rejected", None).await
+}
+
+/// Reviews can be changed, e.g. to reject feedback accepted by mistake. It
+/// leaves the training data at the next export.
+async fn review(
+    state: &AppState,
+    claims: &Claims,
+    feedback_id: &str,
+    status: &str,
+    correct_intent: Option<&str>,
+) -> Result<Json<IntentFeedback>, StatusCode> {
+    let feedback = sqlx::query_as::<_, IntentFeedback>(&format!(
+        "UPDATE intent_feedback SET
+             status = ?,
+             correct_intent = COALESCE(?, correct_intent),
+             reviewed_by = ?,
+             reviewed_at = CURRENT_TIMESTAMP
+         WHERE id = ? RETURNING {}",
+        FEEDBACK_COLUMNS
+    ))
+    .bind(status)
+    .bind(correct_intent)
+    .bind(&claims.sub)
+    .bind(feedback_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!("Feedback {} {} by {}", feedback.id, status, claims.sub);
+    Ok(Json(feedback))
+}
+
+/// Writes accepted feedback to the training data. `RustNlu` picks it up
+/// straight away, Rasa once retrained.
+pub async fn export_feedback(State(state): State<AppState>) -> Result<Json<feedback::ExportReport>, StatusCode> {
+    feedback::export(&state).await.map(Json).map_err(|e| {
+        warn!("Feedback export failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Exports feedback and retrains Rasa in the background. Training takes
+/// minutes; its progress is at GET.
+pub async fn retrain(State(state): State<AppState>) -> Result<(StatusCode, Json<Value>), StatusCode> {
+    let mut rasa = state.rasa.clone().try_lock_owned().map_err(|_| StatusCode::CONFLICT)?;
+    let report = feedback::export(&state).await.map_err(|e| {
+        warn!("Feedback export failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tokio::spawn(async move {
+        let _ = rasa.retrain().await;
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "status": "training",
+            "examples": report.examples
+        })),
+    ))
+}
+
+pub async fn get_training_status(State(state): State<AppState>) -> Json<Value> {
+    match state.rasa.try_lock() {
+        Ok(rasa) => Json(json!({
+            "training": false,
+            "last_trained_at": rasa.last_trained_at,
+            "last_error": rasa.last_error
+        })),
+        Err(_) => Json(json!({
+            "training": true
+        })),
+    }
+}
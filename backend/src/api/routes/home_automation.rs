@@ -0,0 +1,54 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{
+    auth::middleware::{admin_middleware, auth_middleware},
+    home_automation::{self, SyncReport},
+    AppState,
+};
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_status))
+        .route("/sync", post(sync_now))
+        .route_layer(middleware::from_fn(admin_middleware))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+pub async fn get_status(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let counts: Vec<(String, i64)> =
+        sqlx::query_as("SELECT backend, COUNT(*) FROM devices GROUP BY backend ORDER BY backend")
+            .fetch_all(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let config = &state.config.home_automation;
+    Ok(Json(json!({
+        "enabled": state.home_automation.is_some(),
+        "backend": config.backend,
+        "url": config.url(),
+        "sync_interval_secs": config.sync_interval_secs,
+        "devices": counts.into_iter().collect::<std::collections::BTreeMap<_, _>>()
+    })))
+}
+
+/// Imports devices from the backend without waiting for the next scheduled
+/// sync.
+pub async fn sync_now(State(state): State<AppState>) -> Result<Json<SyncReport>, StatusCode> {
+    if state.home_automation.is_none() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    home_automation::sync(&state).await.map(Json).map_err(|e| {
+        warn!("Home automation sync failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })
+}
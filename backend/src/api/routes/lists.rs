@@ -0,0 +1,293 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{get, post, put},
+    Extension, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    auth::{jwt::Claims, middleware::auth_middleware},
+    database::models::{List, ListItem},
+    lists::{self, normalize_list_name, ParsedItem, ITEM_COLUMNS, LIST_COLUMNS},
+    AppState,
+};
+
+/// Lists are shared by the household unless `shared` is false.
+#[derive(Debug, Deserialize)]
+pub struct CreateListRequest {
+    pub name: String,
+    pub shared: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateListRequest {
+    pub name: String,
+}
+
+/// Without a quantity or unit, `name` is parsed like a spoken item, so
+/// "2 litres of milk" works too.
+#[derive(Debug, Deserialize)]
+pub struct AddItemRequest {
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+}
+
+/// Fields left out are unchanged; an empty `unit` clears it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateItemRequest {
+    pub name: Option<String>,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub checked: Option<bool>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_lists).post(create_list))
+        .route("/:id", get(get_list).put(update_list).delete(delete_list))
+        .route("/:id/items", post(add_item).delete(clear_checked_items))
+        .route("/:id/items/:item_id", put(update_item).delete(delete_item))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// Household lists and the user's own. Admins see everyone's.
+pub async fn list_lists(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>, StatusCode> {
+    let lists = sqlx::query_as::<_, List>(&format!(
+        "SELECT {} FROM lists WHERE ? = 'admin' OR user_id IS NULL OR user_id = ? ORDER BY user_id IS NOT NULL, name",
+        LIST_COLUMNS
+    ))
+    .bind(&claims.role)
+    .bind(&claims.sub)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "lists": lists
+    })))
+}
+
+pub async fn create_list(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<CreateListRequest>,
+) -> Result<Json<List>, StatusCode> {
+    if normalize_list_name(&payload.name).is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let owner = (!payload.shared.unwrap_or(true)).then_some(claims.sub.as_str());
+
+    // The name is taken, for the household or by the user
+    let list = lists::create_list(&state, &payload.name, owner)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(list))
+}
+
+/// The list with its items, ticked-off ones last.
+pub async fn get_list(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(list_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+    let items = lists::items(&state, &list.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "list": list,
+        "items": items
+    })))
+}
+
+pub async fn update_list(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(list_id): Path<String>,
+    Json(payload): Json<UpdateListRequest>,
+) -> Result<Json<List>, StatusCode> {
+    find_accessible_list(&state, &claims, &list_id).await?;
+    let name = normalize_list_name(&payload.name);
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let list = sqlx::query_as::<_, List>(&format!(
+        "UPDATE lists SET name = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? RETURNING {}",
+        LIST_COLUMNS
+    ))
+    .bind(&name)
+    .bind(&list_id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::CONFLICT)?;
+
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(list))
+}
+
+pub async fn delete_list(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(list_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+
+    sqlx::query("DELETE FROM lists WHERE id = ?")
+        .bind(&list_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Adds an item, merging it with one already on the list.
+pub async fn add_item(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(list_id): Path<String>,
+    Json(payload): Json<AddItemRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+
+    let parsed = if payload.quantity.is_none() && payload.unit.is_none() {
+        ParsedItem::parse(&payload.name)
+    } else {
+        let name = payload.name.trim();
+        (!name.is_empty()).then(|| ParsedItem {
+            name: name.to_string(),
+            quantity: payload.quantity,
+            unit: payload.unit.as_deref().map(str::trim).filter(|unit| !unit.is_empty()).map(str::to_string),
+        })
+    };
+    let parsed = parsed.ok_or(StatusCode::BAD_REQUEST)?;
+    if parsed.quantity.is_some_and(|quantity| quantity <= 0.0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let added = lists::add_item(&state, &list, &parsed, Some(&claims.sub))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "item": added.item,
+        "merged": added.merged
+    })))
+}
+
+/// Edits an item. Ticking it off is setting `checked`.
+pub async fn update_item(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path((list_id, item_id)): Path<(String, String)>,
+    Json(payload): Json<UpdateItemRequest>,
+) -> Result<Json<ListItem>, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+    if payload.name.as_deref().is_some_and(|name| name.trim().is_empty())
+        || payload.quantity.is_some_and(|quantity| quantity <= 0.0)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let item = sqlx::query_as::<_, ListItem>(&format!(
+        "UPDATE list_items SET
+             name = COALESCE(?, name),
+             quantity = COALESCE(?, quantity),
+             unit = CASE WHEN ? IS NULL THEN unit ELSE NULLIF(?, '') END,
+             checked = COALESCE(?, checked),
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND list_id = ? RETURNING {}",
+        ITEM_COLUMNS
+    ))
+    .bind(payload.name.as_deref().map(str::trim))
+    .bind(payload.quantity)
+    .bind(&payload.unit)
+    .bind(payload.unit.as_deref().map(str::trim))
+    .bind(payload.checked)
+    .bind(&item_id)
+    .bind(&list_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(item))
+}
+
+pub async fn delete_item(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path((list_id, item_id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+
+    let result = sqlx::query("DELETE FROM list_items WHERE id = ? AND list_id = ?")
+        .bind(&item_id)
+        .bind(&list_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes the items that have been ticked off.
+pub async fn clear_checked_items(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(list_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let list = find_accessible_list(&state, &claims, &list_id).await?;
+
+    let result = sqlx::query("DELETE FROM list_items WHERE list_id = ? AND checked")
+        .bind(&list_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    lists::touch(&state, &list)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(json!({
+        "removed": result.rows_affected()
+    })))
+}
+
+/// Someone else's personal list looks the same as one that doesn't exist.
+async fn find_accessible_list(state: &AppState, claims: &Claims, list_id: &str) -> Result<List, StatusCode> {
+    let list = lists::find_list(state, list_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !lists::can_access(&list, claims) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(list)
+}
@@ -0,0 +1,72 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{get, put},
+    Router,
+};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{
+    auth::middleware::{admin_middleware, auth_middleware},
+    location::{self, Location},
+    AppState,
+};
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    // Any signed-in user may see where home is, only admins may move it
+    Router::new()
+        .route(
+            "/",
+            get(get_location).merge(
+                put(update_location)
+                    .delete(delete_location)
+                    .route_layer(middleware::from_fn(admin_middleware)),
+            ),
+        )
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// The home location and where it comes from, `null` when none is set.
+pub async fn get_location(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let home = location::home(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "location": home.as_ref().map(|(location, _)| location),
+        "source": home.map(|(_, source)| source.as_str()),
+        "ip_lookup": state.config.location.ip_lookup
+    })))
+}
+
+/// Saves the home location over the one in the settings.
+pub async fn update_location(
+    State(state): State<AppState>,
+    Json(payload): Json<Location>,
+) -> Result<Json<Location>, StatusCode> {
+    if let Err(e) = payload.validate() {
+        warn!("Rejected home location: {}", e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let location = Location {
+        name: payload.name.map(|name| name.trim().to_string()),
+        ..payload
+    };
+
+    location::save_home(&state, Some(&location))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    info!("Home location set to {:?}", location);
+    Ok(Json(location))
+}
+
+/// Forgets the saved home location, so the one in the settings applies.
+pub async fn delete_location(State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    location::save_home(&state, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -2,4 +2,13 @@ pub mod auth;
 pub mod users;
 pub mod audio;
 pub mod commands;
-pub mod feedback;
\ No newline at end of file
+pub mod feedback;
+pub mod satellites;
+pub mod rooms;
+pub mod devices;
+pub mod home_automation;
+pub mod timers;
+pub mod reminders;
+pub mod lists;
+pub mod location;
+pub mod nlu;
\ No newline at end of file
@@ -0,0 +1,171 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{
+    auth::middleware::{admin_middleware, auth_middleware},
+    nlu::patterns::{self, IntentSummary},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePatternsRequest {
+    pub patterns: Vec<String>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/patterns", get(list_patterns))
+        .route(
+            "/patterns/:intent",
+            get(get_patterns).put(update_patterns).delete(delete_patterns),
+        )
+        .route_layer(middleware::from_fn(admin_middleware))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// Every intent RustNlu knows, with its patterns and training examples.
+pub async fn list_patterns(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let intents = list_intents(&state)?;
+    Ok(Json(json!({
+        "intents": intents
+    })))
+}
+
+pub async fn get_patterns(
+    State(state): State<AppState>,
+    Path(intent): Path<String>,
+) -> Result<Json<IntentSummary>, StatusCode> {
+    list_intents(&state)?
+        .into_iter()
+        .find(|summary| summary.intent == intent)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Replaces the intent's patterns, or adds a new intent. RustNlu uses them
+/// straight away.
+pub async fn update_patterns(
+    State(state): State<AppState>,
+    Path(intent): Path<String>,
+    Json(payload): Json<UpdatePatternsRequest>,
+) -> Result<Json<IntentSummary>, StatusCode> {
+    let valid_name = !intent.is_empty()
+        && intent
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid_name {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    if let Err(e) = patterns::validate(&payload.patterns) {
+        warn!("Rejected patterns for '{}': {}", intent, e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    patterns::set_patterns(&state.config.nlu, &intent, payload.patterns)
+        .and_then(|_| patterns::reload(&state))
+        .map_err(|e| {
+            warn!("Failed to update patterns for '{}': {:#}", intent, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    get_patterns(State(state), Path(intent)).await
+}
+
+/// Removes the intent's patterns. Its training examples stay.
+pub async fn delete_patterns(
+    State(state): State<AppState>,
+    Path(intent): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let removed = patterns::remove_patterns(&state.config.nlu, &intent)
+        .and_then(|removed| patterns::reload(&state).map(|_| removed))
+        .map_err(|e| {
+            warn!("Failed to remove patterns for '{}': {:#}", intent, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+fn list_intents(state: &AppState) -> Result<Vec<IntentSummary>, StatusCode> {
+    patterns::list_intents(&state.config.nlu).map_err(|e| {
+        warn!("Failed to read NLU patterns: {:#}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::*;
+    use crate::{config::Settings, test_support};
+
+    #[tokio::test]
+    async fn patterns_are_edited_by_admins_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Settings::new().unwrap();
+        config.nlu.patterns_dir = dir.path().join("patterns").to_str().unwrap().to_string();
+        config.nlu.rasa_dir = dir.path().join("rasa").to_str().unwrap().to_string();
+        let state = test_support::state_with(config).await;
+
+        let user_id = test_support::user(&state, "sam", "user").await;
+        let user = test_support::token(&state, &user_id, "user");
+        let put = json!({"patterns": ["^hello"]});
+        for (method, uri) in [
+            (Method::GET, "/api/nlu/patterns"),
+            (Method::GET, "/api/nlu/patterns/say_hello"),
+            (Method::PUT, "/api/nlu/patterns/say_hello"),
+            (Method::DELETE, "/api/nlu/patterns/say_hello"),
+        ] {
+            let (status, _) = test_support::call(&state, method.clone(), uri, Some(&user), put.clone()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            let (status, _) = test_support::call(&state, method.clone(), uri, None, put.clone()).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+        }
+        assert!(!dir.path().join("patterns").exists());
+
+        let admin_id = test_support::user(&state, "ops", "admin").await;
+        let admin = test_support::token(&state, &admin_id, "admin");
+        let uri = "/api/nlu/patterns/say_hello";
+
+        let (status, body) = test_support::call(&state, Method::PUT, uri, Some(&admin), put.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"intent": "say_hello", "file": "custom.yml", "patterns": ["^hello"], "examples": []}));
+        assert_eq!(state.rust_nlu.read().unwrap().parse("hello there").0.name, "say_hello");
+
+        let (status, body) =
+            test_support::call(&state, Method::GET, "/api/nlu/patterns", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["intents"].as_array().unwrap().len(), 1);
+
+        for (uri, body) in [
+            ("/api/nlu/patterns/Say-Hello", put.clone()),
+            (uri, json!({"patterns": ["(hello"]})),
+            (uri, json!({"patterns": []})),
+        ] {
+            let (status, _) = test_support::call(&state, Method::PUT, uri, Some(&admin), body.clone()).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{} {}", uri, body);
+        }
+
+        let (status, _) = test_support::call(&state, Method::DELETE, uri, Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(state.rust_nlu.read().unwrap().parse("hello there").0.name, "unknown");
+        for method in [Method::GET, Method::DELETE] {
+            let (status, _) = test_support::call(&state, method.clone(), uri, Some(&admin), Value::Null).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", method);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{get, put},
+    Extension, Router,
+};
+use chrono::{DateTime, Local, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    auth::{jwt::Claims, middleware::auth_middleware},
+    database::models::Reminder,
+    reminders::{self, Recurrence, REMINDER_COLUMNS},
+    AppState,
+};
+
+/// Fields left out are unchanged; an empty `recurrence` makes it a one-off.
+#[derive(Debug, Deserialize)]
+pub struct UpdateReminderRequest {
+    pub text: Option<String>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub recurrence: Option<String>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_reminders))
+        .route("/:id", put(update_reminder).delete(delete_reminder))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// Reminders still to go off. Users see their own, admins see everyone's.
+pub async fn list_reminders(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>, StatusCode> {
+    let reminders = sqlx::query_as::<_, Reminder>(&format!(
+        "SELECT {} FROM reminders
+         WHERE status = 'pending' AND (? = 'admin' OR user_id = ?)
+         ORDER BY remind_at",
+        REMINDER_COLUMNS
+    ))
+    .bind(&claims.role)
+    .bind(&claims.sub)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "reminders": reminders
+    })))
+}
+
+pub async fn update_reminder(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reminder_id): Path<String>,
+    Json(payload): Json<UpdateReminderRequest>,
+) -> Result<Json<Reminder>, StatusCode> {
+    let reminder = find_own_reminder(&state, &claims, &reminder_id).await?;
+    if reminder.status != "pending" {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    if payload.text.as_deref().is_some_and(|text| text.trim().is_empty()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let recurrence = match payload.recurrence.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(recurrence) => Some(recurrence.parse::<Recurrence>().map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => reminder.recurrence.as_deref().and_then(|r| r.parse().ok()),
+    };
+    let mut remind_at = payload.remind_at.unwrap_or(reminder.remind_at);
+    if payload.remind_at.is_some() && remind_at <= Utc::now() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(recurrence) = recurrence {
+        remind_at = recurrence.first(remind_at.with_timezone(&Local)).with_timezone(&Utc);
+    }
+
+    let reminder = sqlx::query_as::<_, Reminder>(&format!(
+        "UPDATE reminders SET
+             text = COALESCE(?, text),
+             remind_at = ?,
+             recurrence = ?,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND status = 'pending' RETURNING {}",
+        REMINDER_COLUMNS
+    ))
+    .bind(payload.text.as_deref().map(str::trim))
+    .bind(remind_at)
+    .bind(recurrence.map(|recurrence| recurrence.as_str()))
+    .bind(&reminder_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Went off while we were looking at it
+    reminder.map(Json).ok_or(StatusCode::CONFLICT)
+}
+
+pub async fn delete_reminder(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reminder_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    find_own_reminder(&state, &claims, &reminder_id).await?;
+
+    let cancelled = reminders::cancel(&state, &reminder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !cancelled {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Someone else's reminder looks the same as one that doesn't exist, except
+/// to admins.
+async fn find_own_reminder(state: &AppState, claims: &Claims, reminder_id: &str) -> Result<Reminder, StatusCode> {
+    let reminder = reminders::find_reminder(state, reminder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if claims.role != "admin" && reminder.user_id != claims.sub {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(reminder)
+}
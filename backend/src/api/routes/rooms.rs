@@ -0,0 +1,130 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    auth::middleware::{admin_middleware, auth_middleware},
+    database::models::Room,
+    devices, AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct RoomRequest {
+    pub name: String,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    // Any signed-in user may look at rooms, only admins may change them
+    let admin_only = middleware::from_fn(admin_middleware);
+
+    Router::new()
+        .route(
+            "/",
+            get(list_rooms).merge(post(create_room).route_layer(admin_only.clone())),
+        )
+        .route(
+            "/:id",
+            get(get_room).merge(
+                put(update_room)
+                    .merge(delete(delete_room))
+                    .route_layer(admin_only),
+            ),
+        )
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+pub async fn list_rooms(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let rooms = devices::list_rooms(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "rooms": rooms
+    })))
+}
+
+pub async fn get_room(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let room = find_room(&state, &room_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let devices = devices::devices_in_room(&state, &room_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "room": room,
+        "devices": devices
+    })))
+}
+
+pub async fn create_room(
+    State(state): State<AppState>,
+    Json(payload): Json<RoomRequest>,
+) -> Result<Json<Room>, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    sqlx::query_as::<_, Room>("INSERT INTO rooms (id, name) VALUES (?, ?) RETURNING id, name, created_at")
+        .bind(Uuid::new_v4().to_string())
+        .bind(name)
+        .fetch_one(&state.db)
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::CONFLICT)
+}
+
+pub async fn update_room(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+    Json(payload): Json<RoomRequest>,
+) -> Result<Json<Room>, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let room = sqlx::query_as::<_, Room>("UPDATE rooms SET name = ? WHERE id = ? RETURNING id, name, created_at")
+        .bind(name)
+        .bind(&room_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    room.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Devices in the room are kept and become unassigned.
+pub async fn delete_room(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn find_room(state: &AppState, room_id: &str) -> Result<Option<Room>, StatusCode> {
+    sqlx::query_as::<_, Room>("SELECT id, name, created_at FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
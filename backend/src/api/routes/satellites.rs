@@ -0,0 +1,521 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension,
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{
+    auth::{
+        jwt::Claims,
+        middleware::{admin_middleware, auth_middleware},
+    },
+    database::models::{PairingRequest, Satellite, SatelliteUptime},
+    events::Event,
+    location::Location,
+    mqtt::pairing::{self, PairingError, PairingState},
+    AppState,
+};
+
+const SATELLITE_COLUMNS: &str =
+    "id, name, mac_address, ip_address, status, last_seen, config, created_at";
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSatelliteRequest {
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub config: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PairingQuery {
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApprovePairingRequest {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UptimeQuery {
+    pub hours: Option<i64>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    // Any signed-in user may look at satellites, only admins may change them
+    let admin_only = middleware::from_fn(admin_middleware);
+
+    Router::new()
+        .route("/", get(list_satellites))
+        .route("/pairing", get(list_pairing_requests).route_layer(admin_only.clone()))
+        .route(
+            "/pairing/:id",
+            delete(delete_pairing_request).route_layer(admin_only.clone()),
+        )
+        .route(
+            "/pairing/:id/approve",
+            post(approve_pairing_request).route_layer(admin_only.clone()),
+        )
+        .route(
+            "/pairing/:id/reject",
+            post(reject_pairing_request).route_layer(admin_only.clone()),
+        )
+        .route(
+            "/:id",
+            get(get_satellite).merge(
+                put(update_satellite)
+                    .merge(delete(delete_satellite))
+                    .route_layer(admin_only.clone()),
+            ),
+        )
+        .route("/:id/uptime", get(get_satellite_uptime))
+        .route("/:id/restart", post(restart_satellite).route_layer(admin_only))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+pub async fn list_satellites(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let satellites = sqlx::query_as::<_, Satellite>(&format!(
+        "SELECT {} FROM satellites ORDER BY name",
+        SATELLITE_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "satellites": satellites
+    })))
+}
+
+pub async fn get_satellite(
+    State(state): State<AppState>,
+    Path(satellite_id): Path<String>,
+) -> Result<Json<Satellite>, StatusCode> {
+    find_satellite(&state, &satellite_id)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn update_satellite(
+    State(state): State<AppState>,
+    Path(satellite_id): Path<String>,
+    Json(payload): Json<UpdateSatelliteRequest>,
+) -> Result<Json<Satellite>, StatusCode> {
+    if let Some(name) = &payload.name {
+        if name.trim().is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let config = match &payload.config {
+        Some(config) => {
+            if let Err(e) = validate_config(config) {
+                warn!("Rejected config for satellite {}: {}", satellite_id, e);
+                return Err(StatusCode::UNPROCESSABLE_ENTITY);
+            }
+            Some(config.to_string())
+        }
+        None => None,
+    };
+
+    let satellite = sqlx::query_as::<_, Satellite>(&format!(
+        "UPDATE satellites SET name = COALESCE(?, name), ip_address = COALESCE(?, ip_address), config = COALESCE(?, config) WHERE id = ? RETURNING {}",
+        SATELLITE_COLUMNS
+    ))
+    .bind(payload.name.as_deref().map(str::trim))
+    .bind(&payload.ip_address)
+    .bind(&config)
+    .bind(&satellite_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    satellite.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn delete_satellite(
+    State(state): State<AppState>,
+    Path(satellite_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Keep the command history, just detach it from the satellite
+    sqlx::query("UPDATE command_history SET satellite_id = NULL WHERE satellite_id = ?")
+        .bind(&satellite_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let result = sqlx::query("DELETE FROM satellites WHERE id = ?")
+        .bind(&satellite_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Deleted satellite {}", satellite_id);
+    state.events.publish(Event::SatelliteStatus {
+        satellite_id,
+        status: "removed".to_string(),
+    });
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn restart_satellite(
+    State(state): State<AppState>,
+    Path(satellite_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    find_satellite(&state, &satellite_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mqtt = state.mqtt.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let payload = json!({
+        "command": "restart",
+        "requested_at": chrono::Utc::now(),
+    });
+
+    mqtt.publish_to_satellite(&satellite_id, "commands/restart", &payload.to_string())
+        .await
+        .map_err(|e| {
+            warn!("Failed to publish restart to satellite {}: {}", satellite_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    info!("Restart requested for satellite {}", satellite_id);
+    state.events.publish(Event::SatelliteStatus {
+        satellite_id: satellite_id.clone(),
+        status: "restarting".to_string(),
+    });
+    Ok(Json(json!({
+        "status": "restart_requested",
+        "satellite_id": satellite_id
+    })))
+}
+
+/// Online periods overlapping the last `hours` (default 24) and the share of
+/// that window the satellite was online.
+pub async fn get_satellite_uptime(
+    State(state): State<AppState>,
+    Path(satellite_id): Path<String>,
+    Query(query): Query<UptimeQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let hours = query.hours.unwrap_or(24);
+    if !(1..=24 * 90).contains(&hours) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let satellite = find_satellite(&state, &satellite_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let now = chrono::Utc::now();
+    let since = now - chrono::Duration::hours(hours);
+
+    let history = sqlx::query_as::<_, SatelliteUptime>(
+        "SELECT id, satellite_id, online_at, offline_at, offline_reason FROM satellite_uptime WHERE satellite_id = ? AND (offline_at IS NULL OR offline_at > ?) ORDER BY online_at DESC",
+    )
+    .bind(&satellite_id)
+    .bind(since)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let online_seconds: i64 = history
+        .iter()
+        .map(|period| {
+            let start = period.online_at.max(since);
+            let end = period.offline_at.unwrap_or(now).min(now);
+            (end - start).num_seconds().max(0)
+        })
+        .sum();
+    let online_since = history
+        .iter()
+        .find(|period| period.offline_at.is_none())
+        .map(|period| period.online_at);
+
+    Ok(Json(json!({
+        "satellite_id": satellite.id,
+        "status": satellite.status,
+        "last_seen": satellite.last_seen,
+        "online_since": online_since,
+        "hours": hours,
+        "uptime_ratio": online_seconds as f64 / (hours * 3600) as f64,
+        "history": history
+    })))
+}
+
+pub async fn list_pairing_requests(
+    State(state): State<AppState>,
+    Query(query): Query<PairingQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let filter = match query.state.as_deref() {
+        Some(value) => Some(value.parse::<PairingState>().map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+
+    let requests = pairing::list_requests(&state, filter)
+        .await
+        .map_err(pairing_error_status)?;
+
+    Ok(Json(json!({
+        "requests": requests
+    })))
+}
+
+/// Pairs the satellite. The secret is only ever returned here, once, to be
+/// provisioned on the satellite by hand.
+pub async fn approve_pairing_request(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(request_id): Path<String>,
+    Json(payload): Json<ApprovePairingRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let name = payload.name.as_deref().map(str::trim);
+    if name.is_some_and(str::is_empty) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let credentials = pairing::approve(&state, &request_id, name, &claims.sub)
+        .await
+        .map_err(pairing_error_status)?;
+
+    let satellite = find_satellite(&state, &credentials.satellite_id)
+        .await?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "satellite": satellite,
+        "secret": credentials.secret
+    })))
+}
+
+pub async fn reject_pairing_request(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(request_id): Path<String>,
+) -> Result<Json<PairingRequest>, StatusCode> {
+    pairing::reject(&state, &request_id, &claims.sub)
+        .await
+        .map(Json)
+        .map_err(pairing_error_status)
+}
+
+pub async fn delete_pairing_request(
+    State(state): State<AppState>,
+    Path(request_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    pairing::delete_request(&state, &request_id)
+        .await
+        .map_err(pairing_error_status)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn pairing_error_status(error: PairingError) -> StatusCode {
+    match error {
+        PairingError::NotFound => StatusCode::NOT_FOUND,
+        PairingError::InvalidMac(_) => StatusCode::BAD_REQUEST,
+        PairingError::InvalidTransition { .. } | PairingError::AlreadyPaired(_) => {
+            warn!("Pairing request rejected: {}", error);
+            StatusCode::CONFLICT
+        }
+        PairingError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn find_satellite(state: &AppState, satellite_id: &str) -> Result<Option<Satellite>, StatusCode> {
+    sqlx::query_as::<_, Satellite>(&format!(
+        "SELECT {} FROM satellites WHERE id = ?",
+        SATELLITE_COLUMNS
+    ))
+    .bind(satellite_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Checks a satellite config document before it is stored.
+///
+/// The config must be a JSON object. Unknown keys are kept as-is so newer
+/// satellite firmware can add settings, but the keys the satellites already
+/// understand must have the right type and range.
+pub fn validate_config(config: &Value) -> Result<(), String> {
+    let object = config
+        .as_object()
+        .ok_or_else(|| "config must be a JSON object".to_string())?;
+
+    for (key, value) in object {
+        match key.as_str() {
+            "volume" | "led_brightness" => match value.as_u64() {
+                Some(v) if v <= 100 => {}
+                _ => return Err(format!("{} must be an integer between 0 and 100", key)),
+            },
+            "wake_word_sensitivity" => match value.as_f64() {
+                Some(v) if (0.0..=1.0).contains(&v) => {}
+                _ => return Err(format!("{} must be a number between 0.0 and 1.0", key)),
+            },
+            // `user` is the username spoken commands from this satellite act as
+            "wake_word" | "room" | "user" => match value.as_str() {
+                Some(v) if !v.trim().is_empty() => {}
+                _ => return Err(format!("{} must be a non-empty string", key)),
+            },
+            "muted" if !value.is_boolean() => {
+                return Err(format!("{} must be a boolean", key));
+            }
+            // Overrides the home location for commands heard by this satellite
+            "location" => serde_json::from_value::<Location>(value.clone())
+                .map_err(|e| format!("location is invalid: {}", e))?
+                .validate()
+                .map_err(|e| format!("location is invalid: {}", e))?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::*;
+    use crate::test_support;
+
+    async fn uptime_period(state: &AppState, online_at: DateTime<Utc>, offline_at: Option<DateTime<Utc>>) {
+        sqlx::query("INSERT INTO satellite_uptime (id, satellite_id, online_at, offline_at) VALUES (?, 'kitchen', ?, ?)")
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(online_at)
+            .bind(offline_at)
+            .execute(&state.db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn uptime_covers_the_periods_overlapping_the_window() {
+        let state = test_support::state().await;
+        sqlx::query("INSERT INTO satellites (id, name, status) VALUES ('kitchen', 'Kitchen', 'online')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let now = Utc::now();
+        // Before the window, half an hour into it, and the last half hour
+        uptime_period(&state, now - Duration::hours(5), Some(now - Duration::hours(4))).await;
+        uptime_period(&state, now - Duration::hours(3), Some(now - Duration::minutes(90))).await;
+        uptime_period(&state, now - Duration::minutes(30), None).await;
+
+        let user_id = test_support::user(&state, "sam", "user").await;
+        let token = test_support::token(&state, &user_id, "user");
+        let (status, body) =
+            test_support::call(&state, Method::GET, "/api/satellites/kitchen/uptime?hours=2", Some(&token), Value::Null)
+                .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["hours"], 2);
+        assert_eq!(body["history"].as_array().unwrap().len(), 2);
+        assert!(body["online_since"].is_string());
+        let ratio = body["uptime_ratio"].as_f64().unwrap();
+        assert!((ratio - 0.5).abs() < 0.01, "{}", ratio);
+
+        for (uri, expected) in [
+            ("/api/satellites/kitchen/uptime?hours=0", StatusCode::BAD_REQUEST),
+            ("/api/satellites/kitchen/uptime?hours=10000", StatusCode::BAD_REQUEST),
+            ("/api/satellites/missing/uptime", StatusCode::NOT_FOUND),
+        ] {
+            let (status, _) = test_support::call(&state, Method::GET, uri, Some(&token), Value::Null).await;
+            assert_eq!(status, expected, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn known_settings_and_unknown_keys_are_accepted() {
+        for config in [
+            json!({}),
+            json!({"volume": 0, "led_brightness": 100, "wake_word_sensitivity": 0.5, "muted": false}),
+            json!({"wake_word_sensitivity": 1, "wake_word": "hey barnaby", "room": "kitchen", "user": "sam"}),
+            json!({"location": {"latitude": 51.5, "longitude": -0.13, "timezone": "Europe/London"}}),
+            // Settings from newer firmware are kept as they are
+            json!({"volume": 40, "equaliser": {"bass": 3}, "beta": null}),
+        ] {
+            assert_eq!(validate_config(&config), Ok(()), "{}", config);
+        }
+    }
+
+    #[test]
+    fn wrong_shapes_types_and_ranges_are_rejected() {
+        for (config, error) in [
+            (json!([]), "config must be a JSON object"),
+            (json!("volume=40"), "config must be a JSON object"),
+            (json!({"volume": 101}), "volume must be an integer between 0 and 100"),
+            (json!({"volume": -1}), "volume must be an integer between 0 and 100"),
+            (json!({"volume": 40.5}), "volume must be an integer between 0 and 100"),
+            (json!({"led_brightness": "50"}), "led_brightness must be an integer between 0 and 100"),
+            (json!({"wake_word_sensitivity": 1.5}), "wake_word_sensitivity must be a number between 0.0 and 1.0"),
+            (json!({"wake_word_sensitivity": "high"}), "wake_word_sensitivity must be a number between 0.0 and 1.0"),
+            (json!({"wake_word": " "}), "wake_word must be a non-empty string"),
+            (json!({"room": 3}), "room must be a non-empty string"),
+            (json!({"user": null}), "user must be a non-empty string"),
+            (json!({"muted": "yes"}), "muted must be a boolean"),
+        ] {
+            assert_eq!(validate_config(&config), Err(error.to_string()), "{}", config);
+        }
+
+        for location in [
+            json!("London"),
+            json!({"latitude": 51.5}),
+            json!({"latitude": 91.0, "longitude": 0.0}),
+            json!({"latitude": 51.5, "longitude": -0.13, "timezone": "London"}),
+        ] {
+            let error = validate_config(&json!({"location": location})).unwrap_err();
+            assert!(error.starts_with("location is invalid: "), "{}: {}", location, error);
+        }
+    }
+
+    /// The config column, which satellites are returned with as a string.
+    fn stored_config(satellite: &Value) -> Value {
+        serde_json::from_str(satellite["config"].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejected_configs_are_not_stored() {
+        let state = test_support::state().await;
+        sqlx::query("INSERT INTO satellites (id, name, config) VALUES ('kitchen', 'Kitchen', '{\"volume\": 40}')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let admin_id = test_support::user(&state, "ops", "admin").await;
+        let admin = test_support::token(&state, &admin_id, "admin");
+        let uri = "/api/satellites/kitchen";
+
+        let body = json!({"config": {"volume": 140}});
+        let (status, _) = test_support::call(&state, Method::PUT, uri, Some(&admin), body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, satellite) = test_support::call(&state, Method::GET, uri, Some(&admin), Value::Null).await;
+        assert_eq!(stored_config(&satellite), json!({"volume": 40}));
+
+        let body = json!({"config": {"volume": 60, "equaliser": "flat"}});
+        let (status, satellite) = test_support::call(&state, Method::PUT, uri, Some(&admin), body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stored_config(&satellite), json!({"volume": 60, "equaliser": "flat"}));
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{delete, get},
+    Extension, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    auth::{jwt::Claims, middleware::auth_middleware},
+    database::models::Timer,
+    timers::{self, TIMER_COLUMNS},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TimerQuery {
+    pub kind: Option<String>,
+}
+
+pub fn create_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_timers))
+        .route("/:id", delete(cancel_timer))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// Timers and alarms still to go off. Users see their own, admins see all of
+/// them, including the ones set through satellites.
+pub async fn list_timers(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(query): Query<TimerQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    if let Some(kind) = &query.kind {
+        kind.parse::<timers::TimerKind>().map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+
+    let timers = sqlx::query_as::<_, Timer>(&format!(
+        "SELECT {} FROM timers
+         WHERE status = 'pending' AND (? IS NULL OR kind = ?) AND (? = 'admin' OR user_id = ?)
+         ORDER BY fires_at",
+        TIMER_COLUMNS
+    ))
+    .bind(&query.kind)
+    .bind(&query.kind)
+    .bind(&claims.role)
+    .bind(&claims.sub)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "timers": timers
+    })))
+}
+
+pub async fn cancel_timer(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(timer_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let timer = timers::find_timer(&state, &timer_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if claims.role != "admin" && timer.user_id.as_deref() != Some(claims.sub.as_str()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let cancelled = timers::cancel(&state, &timer_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !cancelled {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
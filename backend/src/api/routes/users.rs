@@ -3,18 +3,21 @@ use axum::{
     http::StatusCode,
     middleware,
     response::Json,
-    routing::{get, post},
+    routing::{delete, get},
     Router,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use sqlx::Row;
 use uuid::Uuid;
 use bcrypt::{hash, DEFAULT_COST};
 
 use crate::{
-    auth::middleware::{admin_middleware, auth_middleware},
-    database::models::User,
+    auth::{
+        middleware::{admin_middleware, auth_middleware},
+        sessions,
+    },
+    database::models::{User, UserRole},
+    events::Event,
     AppState,
 };
 
@@ -32,10 +35,14 @@ pub struct UpdateUserRequest {
     pub role: String,
 }
 
-pub fn create_routes() -> Router<AppState> {
+pub fn create_routes(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/", get(list_users).post(create_user))
         .route("/:id", get(get_user).put(update_user))
+        .route("/:id/sessions", get(list_user_sessions).delete(revoke_user_sessions))
+        .route("/:id/sessions/:session_id", delete(revoke_user_session))
+        .route_layer(middleware::from_fn(admin_middleware))
+        .route_layer(middleware::from_fn_with_state(state, auth_middleware))
 }
 
 pub async fn list_users(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
@@ -70,6 +77,7 @@ pub async fn create_user(
     State(state): State<AppState>,
     Json(payload): Json<CreateUserRequest>,
 ) -> Result<Json<User>, StatusCode> {
+    let role = parse_role(&payload.role)?;
     let user_id = Uuid::new_v4().to_string();
     let password_hash = hash(&payload.password, DEFAULT_COST)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -81,11 +89,16 @@ pub async fn create_user(
     .bind(&payload.username)
     .bind(format!("{}@example.com", payload.username)) // Default email
     .bind(&password_hash)
-    .bind(&payload.role)
+    .bind(&role)
     .fetch_one(&state.db)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    state.events.publish(Event::UserChanged {
+        user_id: user.id.clone(),
+        action: "created".to_string(),
+    });
+
     Ok(Json(user))
 }
 
@@ -94,6 +107,8 @@ pub async fn update_user(
     Path(user_id): Path<String>,
     Json(payload): Json<UpdateUserRequest>,
 ) -> Result<Json<User>, StatusCode> {
+    let role = parse_role(&payload.role)?;
+
     if let Some(password) = &payload.password {
         let password_hash = hash(password, DEFAULT_COST)
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -103,24 +118,85 @@ pub async fn update_user(
         )
         .bind(&payload.username)
         .bind(&password_hash)
-        .bind(&payload.role)
+        .bind(&role)
         .bind(&user_id)
         .fetch_one(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        
+
+        sessions::revoke_user_sessions(&state.db, &user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        state.events.publish(Event::UserChanged {
+            user_id: user.id.clone(),
+            action: "updated".to_string(),
+        });
+
         Ok(Json(user))
     } else {
         let user = sqlx::query_as::<_, User>(
             "UPDATE users SET username = ?, role = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? RETURNING id, username, email, password_hash, role, created_at, updated_at",
         )
         .bind(&payload.username)
-        .bind(&payload.role)
+        .bind(&role)
         .bind(&user_id)
         .fetch_one(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        
+
+        state.events.publish(Event::UserChanged {
+            user_id: user.id.clone(),
+            action: "updated".to_string(),
+        });
+
         Ok(Json(user))
     }
-}
\ No newline at end of file
+}
+
+pub async fn list_user_sessions(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let sessions = sessions::list_active_sessions(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "sessions": sessions
+    })))
+}
+
+pub async fn revoke_user_sessions(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let revoked = sessions::revoke_user_sessions(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "sessions_revoked": revoked
+    })))
+}
+
+pub async fn revoke_user_session(
+    State(state): State<AppState>,
+    Path((user_id, session_id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = sessions::revoke_session_by_id(&state.db, &user_id, &session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if revoked {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+fn parse_role(role: &str) -> Result<String, StatusCode> {
+    role.parse::<UserRole>()
+        .map(|role| role.to_string())
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
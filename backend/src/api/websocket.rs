@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info};
+
+use crate::{
+    auth::jwt::{validate_token, Claims},
+    events::Event,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Browsers can't set headers on a WebSocket handshake, so the JWT may
+    /// also be passed as `?token=`.
+    pub token: Option<String>,
+    /// Comma separated event types to start with, e.g. `command_processed,nlu_fallback`.
+    pub types: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { types: Vec<String> },
+    Unsubscribe { types: Vec<String> },
+}
+
+pub fn create_routes() -> Router<AppState> {
+    Router::new().route("/ws/events", get(events_handler))
+}
+
+pub async fn events_handler(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or(query.token)
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let claims = validate_token(&token, &state.config.auth.jwt_secret)
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    // No filter means everything
+    let subscriptions = match query.types {
+        Some(types) => parse_types(types.split(',').map(str::trim))?,
+        None => Event::KINDS.iter().map(|kind| kind.to_string()).collect(),
+    };
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, claims, subscriptions)))
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: AppState,
+    claims: Claims,
+    mut subscriptions: HashSet<String>,
+) {
+    info!("Event stream opened for {}", claims.username);
+    let mut events = state.events.subscribe();
+
+    loop {
+        tokio::select! {
+            received = events.recv() => {
+                let outgoing = match received {
+                    Ok(envelope) => {
+                        if !subscriptions.contains(envelope.event.kind()) || !envelope.event.visible_to(&claims) {
+                            continue;
+                        }
+                        match serde_json::to_string(&envelope) {
+                            Ok(text) => text,
+                            Err(_) => continue,
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        json!({ "type": "lagged", "skipped": skipped }).to_string()
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                if socket.send(Message::Text(outgoing)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = match handle_client_message(&text, &mut subscriptions) {
+                            Ok(()) => {
+                                let mut types: Vec<_> = subscriptions.iter().cloned().collect();
+                                types.sort();
+                                json!({ "type": "subscribed", "types": types })
+                            }
+                            Err(error) => json!({ "type": "error", "message": error }),
+                        };
+                        if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    debug!("Event stream closed for {}", claims.username);
+}
+
+fn handle_client_message(text: &str, subscriptions: &mut HashSet<String>) -> Result<(), String> {
+    let message: ClientMessage = serde_json::from_str(text)
+        .map_err(|e| format!("Invalid message: {}", e))?;
+
+    match message {
+        ClientMessage::Subscribe { types } => {
+            let types = parse_types(types.iter().map(String::as_str))
+                .map_err(|_| "Unknown event type".to_string())?;
+            subscriptions.extend(types);
+        }
+        ClientMessage::Unsubscribe { types } => {
+            for kind in types {
+                subscriptions.remove(&kind);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_types<'a>(types: impl Iterator<Item = &'a str>) -> Result<HashSet<String>, StatusCode> {
+    types
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| {
+            if Event::KINDS.contains(&kind) {
+                Ok(kind.to_string())
+            } else {
+                Err(StatusCode::BAD_REQUEST)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::Value;
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    use super::*;
+    use crate::test_support;
+
+    fn sorted(subscriptions: &HashSet<String>) -> Vec<&str> {
+        let mut kinds: Vec<&str> = subscriptions.iter().map(String::as_str).collect();
+        kinds.sort();
+        kinds
+    }
+
+    fn timer_fired(user_id: &str) -> Event {
+        Event::TimerFired {
+            timer_id: format!("{}-timer", user_id),
+            kind: "timer".to_string(),
+            label: None,
+            user_id: Some(user_id.to_string()),
+            satellite_id: None,
+            text: "Your timer is done.".to_string(),
+        }
+    }
+
+    fn reminder_due(user_id: &str) -> Event {
+        Event::ReminderDue {
+            reminder_id: format!("{}-reminder", user_id),
+            user_id: user_id.to_string(),
+            satellite_id: None,
+            text: "Reminder: call mum.".to_string(),
+        }
+    }
+
+    #[test]
+    fn only_known_event_types_are_accepted() {
+        let types = parse_types(["timer_fired", "", "reminder_due"].into_iter()).unwrap();
+        assert_eq!(sorted(&types), ["reminder_due", "timer_fired"]);
+        assert_eq!(parse_types(["timer_fired", "everything"].into_iter()), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn clients_subscribe_and_unsubscribe() {
+        let mut subscriptions = parse_types(["timer_fired"].into_iter()).unwrap();
+
+        handle_client_message(r#"{"action": "subscribe", "types": ["reminder_due"]}"#, &mut subscriptions).unwrap();
+        assert_eq!(sorted(&subscriptions), ["reminder_due", "timer_fired"]);
+
+        let unsubscribe = r#"{"action": "unsubscribe", "types": ["timer_fired", "list_changed"]}"#;
+        handle_client_message(unsubscribe, &mut subscriptions).unwrap();
+        assert_eq!(sorted(&subscriptions), ["reminder_due"]);
+
+        let unknown = handle_client_message(r#"{"action": "subscribe", "types": ["secrets"]}"#, &mut subscriptions);
+        assert_eq!(unknown, Err("Unknown event type".to_string()));
+        assert!(handle_client_message(r#"{"action": "shout"}"#, &mut subscriptions).is_err());
+        assert_eq!(sorted(&subscriptions), ["reminder_due"]);
+    }
+
+    type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    async fn send(socket: &mut Socket, text: &str) -> Value {
+        socket.send(tungstenite::Message::text(text)).await.unwrap();
+        receive(socket).await
+    }
+
+    async fn receive(socket: &mut Socket) -> Value {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn streams_only_the_users_own_events_of_the_subscribed_types() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        let alex = test_support::user(&state, "alex", "user").await;
+        let token = test_support::token(&state, &sam, "user");
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = create_routes().with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("ws://{}/ws/events?token={}&types=timer_fired", address, token);
+        let (mut socket, _) = connect_async(url).await.unwrap();
+
+        // The reply also means the socket is listening on the bus
+        let reply = send(&mut socket, r#"{"action": "subscribe", "types": ["reminder_due"]}"#).await;
+        assert_eq!(reply, json!({"type": "subscribed", "types": ["reminder_due", "timer_fired"]}));
+
+        // Alex's events go first, so a leak would arrive before Sam's own
+        state.events.publish(timer_fired(&alex));
+        state.events.publish(reminder_due(&alex));
+        state.events.publish(timer_fired(&sam));
+        state.events.publish(reminder_due(&sam));
+        let timer = receive(&mut socket).await;
+        assert_eq!((&timer["type"], &timer["user_id"]), (&json!("timer_fired"), &json!(sam)));
+        let reminder = receive(&mut socket).await;
+        assert_eq!((&reminder["type"], &reminder["user_id"]), (&json!("reminder_due"), &json!(sam)));
+
+        let reply = send(&mut socket, r#"{"action": "unsubscribe", "types": ["timer_fired"]}"#).await;
+        assert_eq!(reply, json!({"type": "subscribed", "types": ["reminder_due"]}));
+        let reply = send(&mut socket, r#"{"action": "subscribe", "types": ["secrets"]}"#).await;
+        assert_eq!(reply, json!({"type": "error", "message": "Unknown event type"}));
+
+        state.events.publish(timer_fired(&sam));
+        state.events.publish(reminder_due(&sam));
+        assert_eq!(receive(&mut socket).await["type"], "reminder_due");
+    }
+
+    #[tokio::test]
+    async fn unknown_types_and_missing_tokens_are_refused() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        let token = test_support::token(&state, &sam, "user");
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = create_routes().with_state(state);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let refused = [
+            (format!("token={}&types=secrets", token), 400),
+            ("types=timer_fired".to_string(), 401),
+        ];
+        for (query, status) in refused {
+            let error = connect_async(format!("ws://{}/ws/events?{}", address, query)).await.unwrap_err();
+            match error {
+                tungstenite::Error::Http(response) => assert_eq!(response.status(), status, "{}", query),
+                other => panic!("{}: {:?}", query, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::io::Cursor;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+pub mod sessions;
+pub mod stt;
+pub mod tts;
+
+pub use stt::{SttEngine, Transcription};
+pub use tts::TtsEngine;
+
+/// Mono audio as normalised `f32` samples in the range -1.0..=1.0.
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl AudioBuffer {
+    pub fn duration_ms(&self) -> u64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        self.samples.len() as u64 * 1000 / self.sample_rate as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Linear resampling. Good enough for speech going into STT.
+    pub fn resampled(&self, sample_rate: u32) -> AudioBuffer {
+        if sample_rate == self.sample_rate || self.samples.is_empty() {
+            return AudioBuffer {
+                samples: self.samples.clone(),
+                sample_rate,
+            };
+        }
+
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let length = (self.samples.len() as f64 / ratio).floor() as usize;
+        let samples = (0..length)
+            .map(|i| {
+                let position = i as f64 * ratio;
+                let index = position.floor() as usize;
+                let fraction = (position - index as f64) as f32;
+                let current = self.samples[index];
+                let next = self.samples.get(index + 1).copied().unwrap_or(current);
+                current + (next - current) * fraction
+            })
+            .collect();
+
+        AudioBuffer {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Encodes the buffer as a 16-bit PCM mono WAV file.
+    pub fn to_wav(&self) -> Result<Vec<u8>> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+            for sample in &self.samples {
+                writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+            }
+            writer.finalize()?;
+        }
+
+        Ok(cursor.into_inner())
+    }
+}
+
+/// Decodes a base64 payload from a client or satellite.
+///
+/// WAV files are detected by their RIFF header and downmixed/resampled to
+/// `sample_rate`. Anything else is treated as raw 16-bit little-endian mono
+/// PCM already at `sample_rate`. A `data:...;base64,` prefix is accepted.
+pub fn decode_base64_audio(data: &str, sample_rate: u32) -> Result<AudioBuffer> {
+    let encoded = match data.split_once(";base64,") {
+        Some((prefix, encoded)) if prefix.starts_with("data:") => encoded,
+        _ => data,
+    };
+
+    let bytes = STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| anyhow!("Invalid base64 audio: {}", e))?;
+
+    decode_audio(&bytes, sample_rate)
+}
+
+pub fn decode_audio(bytes: &[u8], sample_rate: u32) -> Result<AudioBuffer> {
+    if bytes.starts_with(b"RIFF") {
+        decode_wav(bytes).map(|buffer| buffer.resampled(sample_rate))
+    } else {
+        Ok(AudioBuffer {
+            samples: pcm16_to_f32(bytes),
+            sample_rate,
+        })
+    }
+}
+
+pub fn pcm16_to_f32(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]) as f32 / i16::MAX as f32)
+        .collect()
+}
+
+fn decode_wav(bytes: &[u8]) -> Result<AudioBuffer> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes))
+        .map_err(|e| anyhow!("Invalid WAV audio: {}", e))?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    Ok(AudioBuffer {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(sample_rate: u32, samples: usize) -> AudioBuffer {
+        AudioBuffer {
+            samples: (0..samples).map(|i| ((i % 100) as f32 / 100.0) - 0.5).collect(),
+            sample_rate,
+        }
+    }
+
+    #[test]
+    fn wav_round_trips_through_base64() {
+        let audio = tone(16000, 1600);
+        let encoded = encode_base64(&audio.to_wav().unwrap());
+
+        let decoded = decode_base64_audio(&encoded, 16000).unwrap();
+        assert_eq!(decoded.sample_rate, 16000);
+        assert_eq!(decoded.samples.len(), 1600);
+        assert_eq!(decoded.duration_ms(), 100);
+        for (original, decoded) in audio.samples.iter().zip(&decoded.samples) {
+            assert!((original - decoded).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn wav_is_resampled_to_the_configured_rate() {
+        let encoded = encode_base64(&tone(48000, 4800).to_wav().unwrap());
+        let decoded = decode_base64_audio(&format!("data:audio/wav;base64,{}", encoded), 16000).unwrap();
+        assert_eq!(decoded.sample_rate, 16000);
+        assert_eq!(decoded.samples.len(), 1600);
+    }
+
+    #[test]
+    fn stereo_wav_is_downmixed() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+            for _ in 0..10 {
+                writer.write_sample(i16::MAX / 2).unwrap();
+                writer.write_sample(0i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let decoded = decode_audio(&cursor.into_inner(), 16000).unwrap();
+        assert_eq!(decoded.samples.len(), 10);
+        assert!((decoded.samples[0] - 0.25).abs() < 0.001);
+    }
+
+    #[test]
+    fn raw_pcm_is_taken_at_the_configured_rate() {
+        let pcm: Vec<u8> = [0i16, i16::MAX, -i16::MAX, 0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let decoded = decode_base64_audio(&encode_base64(&pcm), 8000).unwrap();
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.samples, vec![0.0, 1.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn invalid_base64_is_an_error() {
+        assert!(decode_base64_audio("not base64!", 16000).is_err());
+    }
+
+    #[test]
+    fn resampling_interpolates_between_samples() {
+        let audio = AudioBuffer {
+            samples: vec![0.0, 1.0, 0.0, -1.0],
+            sample_rate: 8000,
+        };
+        let upsampled = audio.resampled(16000);
+        assert_eq!(upsampled.sample_rate, 16000);
+        assert_eq!(upsampled.samples, vec![0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -1.0]);
+
+        let downsampled = audio.resampled(4000);
+        assert_eq!(downsampled.samples, vec![0.0, 0.0]);
+
+        let same = audio.resampled(8000);
+        assert_eq!(same.samples, audio.samples);
+    }
+
+    #[test]
+    fn to_wav_writes_16_bit_mono() {
+        let wav = tone(22050, 2205).to_wav().unwrap();
+        let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, 1);
+        assert_eq!(spec.sample_rate, 22050);
+        assert_eq!(spec.bits_per_sample, 16);
+        assert_eq!(reader.duration(), 2205);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::settings::AudioConfig;
+
+/// What happened to a chunk handed to [`AudioSessionManager::push_chunk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStatus {
+    Started,
+    Appended,
+    /// The utterance is longer than `audio.max_utterance_secs`; the audio
+    /// received so far has been dropped and the rest will be ignored.
+    Overflowed,
+    /// The chunk carried another credential than the one the stream started
+    /// with, and was dropped.
+    Rejected,
+}
+
+/// A satellite finished talking (`audio/end`).
+#[derive(Debug)]
+pub enum FinishedSession {
+    Complete { pcm: Vec<u8>, duration: Duration },
+    Overflowed,
+}
+
+struct AudioSession {
+    pcm: Vec<u8>,
+    started: Instant,
+    last_chunk: Instant,
+    overflowed: bool,
+    /// Whatever the satellite proved itself with when the stream started.
+    credential: String,
+}
+
+/// Reassembles 16-bit PCM chunks streamed by satellites, one session per
+/// satellite, until the satellite sends `audio/end` or goes quiet. Every
+/// chunk and the end of a stream must carry the credential it started with.
+pub struct AudioSessionManager {
+    sessions: Mutex<HashMap<String, AudioSession>>,
+    max_bytes: usize,
+    idle_timeout: Duration,
+}
+
+impl AudioSessionManager {
+    pub fn new(config: &AudioConfig) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_bytes: config.sample_rate as usize * 2 * config.max_utterance_secs as usize,
+            idle_timeout: Duration::from_millis(config.stream_timeout_ms),
+        }
+    }
+
+    pub fn has_session(&self, satellite_id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(satellite_id)
+    }
+
+    pub fn push_chunk(&self, satellite_id: &str, credential: &str, chunk: &[u8]) -> ChunkStatus {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let started = !sessions.contains_key(satellite_id);
+        let session = sessions
+            .entry(satellite_id.to_string())
+            .or_insert_with(|| AudioSession {
+                pcm: Vec::new(),
+                started: now,
+                last_chunk: now,
+                overflowed: false,
+                credential: credential.to_string(),
+            });
+        if session.credential != credential {
+            return ChunkStatus::Rejected;
+        }
+        session.last_chunk = now;
+
+        if session.overflowed {
+            return ChunkStatus::Overflowed;
+        }
+
+        if session.pcm.len() + chunk.len() > self.max_bytes {
+            session.overflowed = true;
+            session.pcm = Vec::new();
+            return ChunkStatus::Overflowed;
+        }
+
+        session.pcm.extend_from_slice(chunk);
+        if started {
+            ChunkStatus::Started
+        } else {
+            ChunkStatus::Appended
+        }
+    }
+
+    /// Ends the stream, unless there is none or `credential` isn't the one
+    /// it started with.
+    pub fn finish(&self, satellite_id: &str, credential: &str) -> Option<FinishedSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(satellite_id)?.credential != credential {
+            return None;
+        }
+        let session = sessions.remove(satellite_id)?;
+
+        if session.overflowed {
+            Some(FinishedSession::Overflowed)
+        } else {
+            Some(FinishedSession::Complete {
+                pcm: session.pcm,
+                duration: session.started.elapsed(),
+            })
+        }
+    }
+
+    /// Drops sessions that haven't received a chunk within the idle timeout
+    /// and returns their satellite ids.
+    pub fn expire_idle(&self) -> Vec<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| session.last_chunk.elapsed() > self.idle_timeout)
+            .map(|(satellite_id, _)| satellite_id.clone())
+            .collect();
+
+        for satellite_id in &expired {
+            sessions.remove(satellite_id);
+        }
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    /// Sessions of at most `max_bytes` that time out after `idle_ms`.
+    fn manager(max_bytes: usize, idle_ms: u64) -> AudioSessionManager {
+        let mut config = Settings::new().unwrap().audio;
+        config.sample_rate = 1000;
+        config.max_utterance_secs = 1;
+        config.stream_timeout_ms = idle_ms;
+        let mut manager = AudioSessionManager::new(&config);
+        manager.max_bytes = max_bytes;
+        manager
+    }
+
+    #[test]
+    fn chunks_are_reassembled_per_satellite() {
+        let sessions = manager(100, 5000);
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[1, 2]), ChunkStatus::Started);
+        assert_eq!(sessions.push_chunk("hall", "h", &[9]), ChunkStatus::Started);
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[3, 4]), ChunkStatus::Appended);
+        assert!(sessions.has_session("kitchen"));
+
+        match sessions.finish("kitchen", "k") {
+            Some(FinishedSession::Complete { pcm, .. }) => assert_eq!(pcm, vec![1, 2, 3, 4]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(!sessions.has_session("kitchen"));
+        assert!(sessions.finish("kitchen", "k").is_none());
+        assert!(sessions.has_session("hall"));
+    }
+
+    #[test]
+    fn max_bytes_follow_the_utterance_limit() {
+        let mut config = Settings::new().unwrap().audio;
+        config.sample_rate = 16000;
+        config.max_utterance_secs = 30;
+        assert_eq!(AudioSessionManager::new(&config).max_bytes, 16000 * 2 * 30);
+    }
+
+    #[test]
+    fn an_utterance_that_is_too_long_overflows() {
+        let sessions = manager(4, 5000);
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[0; 3]), ChunkStatus::Started);
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[0; 2]), ChunkStatus::Overflowed);
+        // The rest of the utterance is ignored, even chunks that would fit
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[0]), ChunkStatus::Overflowed);
+        assert!(matches!(sessions.finish("kitchen", "k"), Some(FinishedSession::Overflowed)));
+
+        // The next utterance starts afresh
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[0; 4]), ChunkStatus::Started);
+    }
+
+    #[test]
+    fn chunks_with_another_credential_are_rejected() {
+        let sessions = manager(100, 5000);
+        assert_eq!(sessions.push_chunk("kitchen", "k", &[1]), ChunkStatus::Started);
+        assert_eq!(sessions.push_chunk("kitchen", "forged", &[2]), ChunkStatus::Rejected);
+        assert!(sessions.finish("kitchen", "forged").is_none());
+        assert!(sessions.has_session("kitchen"));
+
+        match sessions.finish("kitchen", "k") {
+            Some(FinishedSession::Complete { pcm, .. }) => assert_eq!(pcm, vec![1]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let sessions = manager(100, 20);
+        sessions.push_chunk("kitchen", "k", &[1]);
+        assert!(sessions.expire_idle().is_empty());
+
+        std::thread::sleep(Duration::from_millis(40));
+        sessions.push_chunk("hall", "h", &[1]);
+        assert_eq!(sessions.expire_idle(), vec!["kitchen".to_string()]);
+        assert!(!sessions.has_session("kitchen"));
+        assert!(sessions.has_session("hall"));
+    }
+}
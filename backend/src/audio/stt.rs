@@ -0,0 +1,325 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::AudioBuffer;
+use crate::config::settings::AudioConfig;
+
+/// whisper.cpp models are trained on 16 kHz audio.
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+const WHISPER_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionSegment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transcription {
+    pub text: String,
+    pub confidence: f32,
+    pub segments: Vec<TranscriptionSegment>,
+}
+
+#[async_trait]
+pub trait SttEngine: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn transcribe(&self, audio: &AudioBuffer) -> Result<Transcription>;
+}
+
+/// Builds the engine selected by `audio.stt_engine`.
+pub fn create_engine(config: &AudioConfig) -> Result<Arc<dyn SttEngine>> {
+    match config.stt_engine.as_str() {
+        "whisper_cpp" => Ok(Arc::new(WhisperCppEngine::new(
+            &config.whisper_binary,
+            &config.stt_model_path,
+            &config.stt_language,
+        )?)),
+        "mock" => Ok(Arc::new(MockSttEngine::new(
+            config
+                .stt_mock_text
+                .clone()
+                .unwrap_or_else(|| MockSttEngine::DEFAULT_TEXT.to_string()),
+        ))),
+        other => Err(anyhow!("Unknown STT engine: {}", other)),
+    }
+}
+
+/// Runs a local whisper.cpp `whisper-cli` binary against a ggml model.
+pub struct WhisperCppEngine {
+    binary: String,
+    model_path: PathBuf,
+    language: String,
+}
+
+impl WhisperCppEngine {
+    pub fn new(binary: &str, model_path: &str, language: &str) -> Result<Self> {
+        let model_path = PathBuf::from(model_path);
+        if !model_path.exists() {
+            return Err(anyhow!("Whisper model not found at {}", model_path.display()));
+        }
+
+        info!("Using whisper.cpp model {}", model_path.display());
+        Ok(Self {
+            binary: binary.to_string(),
+            model_path,
+            language: language.to_string(),
+        })
+    }
+
+    async fn run(&self, wav_path: &Path, output_base: &Path) -> Result<WhisperOutput> {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("-m")
+            .arg(&self.model_path)
+            .arg("-f")
+            .arg(wav_path)
+            .arg("-l")
+            .arg(&self.language)
+            .arg("--output-json-full")
+            .arg("--output-file")
+            .arg(output_base)
+            .arg("--no-prints")
+            .kill_on_drop(true);
+
+        let output = tokio::time::timeout(WHISPER_TIMEOUT, command.output())
+            .await
+            .map_err(|_| anyhow!("whisper.cpp timed out"))?
+            .with_context(|| format!("Failed to run {}", self.binary))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "whisper.cpp failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let json = tokio::fs::read(output_base.with_extension("json")).await?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+#[async_trait]
+impl SttEngine for WhisperCppEngine {
+    fn name(&self) -> &'static str {
+        "whisper_cpp"
+    }
+
+    async fn transcribe(&self, audio: &AudioBuffer) -> Result<Transcription> {
+        if audio.is_empty() {
+            return Err(anyhow!("No audio to transcribe"));
+        }
+
+        let wav = audio.resampled(WHISPER_SAMPLE_RATE).to_wav()?;
+        let output_base = std::env::temp_dir().join(format!("barnaby-stt-{}", Uuid::new_v4()));
+        let wav_path = output_base.with_extension("wav");
+        tokio::fs::write(&wav_path, wav).await?;
+
+        let result = self.run(&wav_path, &output_base).await;
+
+        let _ = tokio::fs::remove_file(&wav_path).await;
+        let _ = tokio::fs::remove_file(output_base.with_extension("json")).await;
+
+        let output = result?;
+        debug!("whisper.cpp returned {} segments", output.transcription.len());
+        Ok(output.into_transcription())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperOutput {
+    transcription: Vec<WhisperSegment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperSegment {
+    offsets: WhisperOffsets,
+    text: String,
+    #[serde(default)]
+    tokens: Vec<WhisperToken>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperOffsets {
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperToken {
+    text: String,
+    p: Option<f32>,
+}
+
+impl WhisperOutput {
+    fn into_transcription(self) -> Transcription {
+        // Confidence is the mean probability of the spoken tokens; special
+        // tokens such as [_BEG_] and [_TT_150] are left out.
+        let probabilities: Vec<f32> = self
+            .transcription
+            .iter()
+            .flat_map(|segment| &segment.tokens)
+            .filter(|token| !token.text.starts_with("[_"))
+            .filter_map(|token| token.p)
+            .collect();
+        let confidence = if probabilities.is_empty() {
+            0.0
+        } else {
+            probabilities.iter().sum::<f32>() / probabilities.len() as f32
+        };
+
+        let segments: Vec<TranscriptionSegment> = self
+            .transcription
+            .into_iter()
+            .map(|segment| TranscriptionSegment {
+                start_ms: segment.offsets.from,
+                end_ms: segment.offsets.to,
+                text: segment.text.trim().to_string(),
+            })
+            .filter(|segment| !segment.text.is_empty())
+            .collect();
+
+        let text = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Transcription {
+            text,
+            confidence,
+            segments,
+        }
+    }
+}
+
+/// Deterministic engine for CI and development machines without a model.
+/// Any non-empty audio "transcribes" to the configured text.
+pub struct MockSttEngine {
+    text: String,
+}
+
+impl MockSttEngine {
+    pub const DEFAULT_TEXT: &'static str = "what time is it";
+
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+}
+
+#[async_trait]
+impl SttEngine for MockSttEngine {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn transcribe(&self, audio: &AudioBuffer) -> Result<Transcription> {
+        if audio.is_empty() {
+            return Err(anyhow!("No audio to transcribe"));
+        }
+
+        Ok(Transcription {
+            text: self.text.clone(),
+            confidence: 1.0,
+            segments: vec![TranscriptionSegment {
+                start_ms: 0,
+                end_ms: audio.duration_ms(),
+                text: self.text.clone(),
+            }],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whisper_output_skips_special_tokens_in_confidence() {
+        let output: WhisperOutput = serde_json::from_value(serde_json::json!({
+            "transcription": [
+                {
+                    "offsets": {"from": 0, "to": 1200},
+                    "text": " What time",
+                    "tokens": [
+                        {"text": "[_BEG_]", "p": 0.1},
+                        {"text": " What", "p": 0.9},
+                        {"text": " time", "p": 0.7},
+                        {"text": "[_TT_60]", "p": 0.05}
+                    ]
+                },
+                {
+                    "offsets": {"from": 1200, "to": 1800},
+                    "text": " is it? ",
+                    "tokens": [
+                        {"text": " is", "p": 0.8},
+                        {"text": " it", "p": 0.6},
+                        {"text": "?"}
+                    ]
+                },
+                {"offsets": {"from": 1800, "to": 2000}, "text": "  "}
+            ]
+        }))
+        .unwrap();
+
+        let transcription = output.into_transcription();
+        assert_eq!(transcription.text, "What time is it?");
+        assert!((transcription.confidence - 0.75).abs() < 1e-6);
+        assert_eq!(transcription.segments.len(), 2);
+        assert_eq!(transcription.segments[1].start_ms, 1200);
+        assert_eq!(transcription.segments[1].end_ms, 1800);
+    }
+
+    #[test]
+    fn whisper_output_without_tokens_has_no_confidence() {
+        let output: WhisperOutput = serde_json::from_value(serde_json::json!({
+            "transcription": [{"offsets": {"from": 0, "to": 500}, "text": " hello"}]
+        }))
+        .unwrap();
+        let transcription = output.into_transcription();
+        assert_eq!(transcription.text, "hello");
+        assert_eq!(transcription.confidence, 0.0);
+    }
+
+    #[tokio::test]
+    async fn mock_engine_transcribes_decoded_audio() {
+        let config = crate::config::Settings::new().unwrap().audio;
+        let audio = crate::audio::decode_audio(&[0u8; 3200], config.sample_rate).unwrap();
+
+        let engine = MockSttEngine::new("turn on the lights");
+        let transcription = engine.transcribe(&audio).await.unwrap();
+        assert_eq!(transcription.text, "turn on the lights");
+        assert_eq!(transcription.confidence, 1.0);
+        assert_eq!(transcription.segments[0].end_ms, audio.duration_ms());
+    }
+
+    #[tokio::test]
+    async fn mock_engine_needs_audio() {
+        let silence = AudioBuffer {
+            samples: Vec::new(),
+            sample_rate: 16000,
+        };
+        assert!(MockSttEngine::new("hello").transcribe(&silence).await.is_err());
+    }
+
+    #[test]
+    fn mock_engine_is_selected_by_config() {
+        let mut config = crate::config::Settings::new().unwrap().audio;
+        config.stt_engine = "mock".to_string();
+        config.stt_mock_text = None;
+        assert_eq!(create_engine(&config).unwrap().name(), "mock");
+
+        config.stt_engine = "nonsense".to_string();
+        assert!(create_engine(&config).is_err());
+    }
+}
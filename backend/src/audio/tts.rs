@@ -0,0 +1,289 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+use super::AudioBuffer;
+use crate::config::settings::AudioConfig;
+
+const TTS_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Voice {
+    pub id: String,
+    pub language: Option<String>,
+}
+
+#[async_trait]
+pub trait TtsEngine: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn voices(&self) -> Result<Vec<Voice>>;
+
+    /// Speaks `text` with `voice` and returns a WAV file.
+    async fn synthesize(&self, text: &str, voice: &str) -> Result<Vec<u8>>;
+}
+
+/// Builds the engine selected by `audio.tts_engine`.
+pub fn create_engine(config: &AudioConfig) -> Result<Arc<dyn TtsEngine>> {
+    match config.tts_engine.as_str() {
+        "piper" => Ok(Arc::new(PiperEngine::new(
+            &config.piper_binary,
+            &config.tts_voices_dir,
+        )?)),
+        "espeak" => Ok(Arc::new(EspeakEngine::new(&config.espeak_binary))),
+        "sine" => Ok(Arc::new(SineTtsEngine::new(config.sample_rate))),
+        other => Err(anyhow!("Unknown TTS engine: {}", other)),
+    }
+}
+
+async fn run_with_timeout(mut command: Command, stdin: Option<&str>) -> Result<Vec<u8>> {
+    command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn()?;
+    if let (Some(text), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(text.as_bytes()).await?;
+    }
+
+    let output = tokio::time::timeout(TTS_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow!("TTS process timed out"))??;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "TTS process failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Runs the Piper neural TTS binary. Each `<voice>.onnx` model in the voices
+/// directory is one voice, e.g. `en_US-lessac-medium`.
+pub struct PiperEngine {
+    binary: String,
+    voices_dir: PathBuf,
+}
+
+impl PiperEngine {
+    pub fn new(binary: &str, voices_dir: &str) -> Result<Self> {
+        let voices_dir = PathBuf::from(voices_dir);
+        if !voices_dir.is_dir() {
+            return Err(anyhow!("Piper voices directory not found at {}", voices_dir.display()));
+        }
+
+        info!("Using Piper voices from {}", voices_dir.display());
+        Ok(Self {
+            binary: binary.to_string(),
+            voices_dir,
+        })
+    }
+}
+
+#[async_trait]
+impl TtsEngine for PiperEngine {
+    fn name(&self) -> &'static str {
+        "piper"
+    }
+
+    async fn voices(&self) -> Result<Vec<Voice>> {
+        let mut voices = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.voices_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = file_name.strip_suffix(".onnx") {
+                voices.push(Voice {
+                    id: id.to_string(),
+                    language: id.split('-').next().map(str::to_string),
+                });
+            }
+        }
+        voices.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(voices)
+    }
+
+    async fn synthesize(&self, text: &str, voice: &str) -> Result<Vec<u8>> {
+        let model = self.voices_dir.join(format!("{}.onnx", voice));
+        if !model.exists() {
+            return Err(anyhow!("Unknown voice: {}", voice));
+        }
+
+        let output_path = std::env::temp_dir().join(format!("barnaby-tts-{}.wav", Uuid::new_v4()));
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("--model")
+            .arg(&model)
+            .arg("--output_file")
+            .arg(&output_path);
+
+        let result = run_with_timeout(command, Some(text))
+            .await
+            .with_context(|| format!("Failed to run {}", self.binary));
+        let wav = match result {
+            Ok(_) => tokio::fs::read(&output_path).await.map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_file(&output_path).await;
+        wav
+    }
+}
+
+/// Runs eSpeak NG, which writes a WAV file to stdout. Voices are eSpeak
+/// language codes such as `en-gb`.
+pub struct EspeakEngine {
+    binary: String,
+}
+
+impl EspeakEngine {
+    pub fn new(binary: &str) -> Self {
+        Self {
+            binary: binary.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsEngine for EspeakEngine {
+    fn name(&self) -> &'static str {
+        "espeak"
+    }
+
+    async fn voices(&self) -> Result<Vec<Voice>> {
+        let mut command = Command::new(&self.binary);
+        command.arg("--voices");
+        let output = run_with_timeout(command, None)
+            .await
+            .with_context(|| format!("Failed to run {}", self.binary))?;
+
+        // Columns: Pty Language Age/Gender VoiceName File Other Languages
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .map(|language| Voice {
+                id: language.to_string(),
+                language: Some(language.to_string()),
+            })
+            .collect())
+    }
+
+    async fn synthesize(&self, text: &str, voice: &str) -> Result<Vec<u8>> {
+        let mut command = Command::new(&self.binary);
+        command.arg("-v").arg(voice).arg("--stdout").arg("--stdin");
+
+        let wav = run_with_timeout(command, Some(text))
+            .await
+            .with_context(|| format!("Failed to run {}", self.binary))?;
+        if !wav.starts_with(b"RIFF") {
+            return Err(anyhow!("eSpeak did not return WAV audio"));
+        }
+        Ok(wav)
+    }
+}
+
+/// Test engine that "speaks" a 440 Hz tone whose length follows the text.
+pub struct SineTtsEngine {
+    sample_rate: u32,
+}
+
+impl SineTtsEngine {
+    const FREQUENCY: f32 = 440.0;
+    const MS_PER_CHARACTER: usize = 60;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+#[async_trait]
+impl TtsEngine for SineTtsEngine {
+    fn name(&self) -> &'static str {
+        "sine"
+    }
+
+    async fn voices(&self) -> Result<Vec<Voice>> {
+        Ok(vec![Voice {
+            id: "sine".to_string(),
+            language: None,
+        }])
+    }
+
+    async fn synthesize(&self, text: &str, _voice: &str) -> Result<Vec<u8>> {
+        let duration_ms = (text.chars().count() * Self::MS_PER_CHARACTER).max(200);
+        let length = self.sample_rate as usize * duration_ms / 1000;
+        let samples = (0..length)
+            .map(|i| {
+                let t = i as f32 / self.sample_rate as f32;
+                0.5 * (2.0 * std::f32::consts::PI * Self::FREQUENCY * t).sin()
+            })
+            .collect();
+
+        AudioBuffer {
+            samples,
+            sample_rate: self.sample_rate,
+        }
+        .to_wav()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sine_engine_returns_wav_at_its_rate() {
+        let engine = SineTtsEngine::new(22050);
+        let wav = engine.synthesize("hello there", "sine").await.unwrap();
+
+        let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, 1);
+        assert_eq!(spec.sample_rate, 22050);
+        // 11 characters at 60 ms each
+        assert_eq!(reader.duration(), 22050 * 660 / 1000);
+    }
+
+    #[tokio::test]
+    async fn sine_engine_speaks_short_text_for_a_minimum_time() {
+        let wav = SineTtsEngine::new(16000).synthesize("hi", "sine").await.unwrap();
+        let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        assert_eq!(reader.duration(), 16000 * 200 / 1000);
+    }
+
+    #[tokio::test]
+    async fn piper_lists_its_models_and_rejects_other_voices() {
+        let voices_dir = tempfile::tempdir().unwrap();
+        std::fs::write(voices_dir.path().join("en_GB-alan-low.onnx"), b"").unwrap();
+        std::fs::write(voices_dir.path().join("en_GB-alan-low.onnx.json"), b"{}").unwrap();
+        // The binary is never run for an unknown voice
+        let engine = PiperEngine::new("/nonexistent/piper", voices_dir.path().to_str().unwrap()).unwrap();
+
+        let voices = engine.voices().await.unwrap();
+        assert_eq!(voices.len(), 1);
+        assert_eq!(voices[0].id, "en_GB-alan-low");
+        assert_eq!(voices[0].language.as_deref(), Some("en_GB"));
+
+        let error = engine.synthesize("hello", "en_US-lessac-medium").await.unwrap_err();
+        assert!(error.to_string().contains("Unknown voice"), "{}", error);
+    }
+
+    #[test]
+    fn piper_needs_its_voices_directory() {
+        assert!(PiperEngine::new("piper", "/nonexistent/voices").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod jwt;
 pub mod middleware;
+pub mod sessions;
 
 pub use jwt::*;
 pub use middleware::*;
\ No newline at end of file
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::database::models::Session;
+
+/// Creates a new random refresh token. Only its hash is ever stored.
+pub fn generate_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Starts a session for the user and returns the plain refresh token.
+pub async fn create_session(db: &SqlitePool, user_id: &str, expiration: u64) -> Result<String> {
+    let refresh_token = generate_refresh_token();
+    let expires_at = Utc::now() + Duration::seconds(expiration as i64);
+
+    // Drop expired sessions while we're here so the table doesn't grow forever
+    sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    sqlx::query("INSERT INTO sessions (id, user_id, refresh_token, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(hash_refresh_token(&refresh_token))
+        .bind(expires_at)
+        .execute(db)
+        .await?;
+
+    Ok(refresh_token)
+}
+
+/// Exchanges a refresh token for a new one.
+///
+/// The old session is deleted in the same transaction, so every refresh token
+/// can be used exactly once. Returns the user id and the new refresh token, or
+/// `None` if the token is unknown or expired.
+pub async fn rotate_session(
+    db: &SqlitePool,
+    refresh_token: &str,
+    expiration: u64,
+) -> Result<Option<(String, String)>> {
+    let mut tx = db.begin().await?;
+
+    let session = sqlx::query_as::<_, Session>(
+        "DELETE FROM sessions WHERE refresh_token = ? RETURNING id, user_id, refresh_token, expires_at, created_at",
+    )
+    .bind(hash_refresh_token(refresh_token))
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let user_id = match session {
+        Some(Session { user_id: Some(user_id), expires_at, .. }) if expires_at > Utc::now() => user_id,
+        _ => {
+            tx.commit().await?;
+            return Ok(None);
+        }
+    };
+
+    let new_token = generate_refresh_token();
+    sqlx::query("INSERT INTO sessions (id, user_id, refresh_token, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(&user_id)
+        .bind(hash_refresh_token(&new_token))
+        .bind(Utc::now() + Duration::seconds(expiration as i64))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some((user_id, new_token)))
+}
+
+/// Revokes the session holding `refresh_token`, if it belongs to `user_id`.
+pub async fn revoke_session(db: &SqlitePool, user_id: &str, refresh_token: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM sessions WHERE refresh_token = ? AND user_id = ?")
+        .bind(hash_refresh_token(refresh_token))
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn revoke_session_by_id(db: &SqlitePool, user_id: &str, session_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn revoke_user_sessions(db: &SqlitePool, user_id: &str) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn list_active_sessions(db: &SqlitePool, user_id: &str) -> Result<Vec<Session>> {
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT id, user_id, refresh_token, expires_at, created_at FROM sessions WHERE user_id = ? AND expires_at > ? ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .bind(Utc::now())
+    .fetch_all(db)
+    .await?;
+
+    Ok(sessions)
+}
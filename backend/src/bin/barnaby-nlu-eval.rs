@@ -0,0 +1,322 @@
+//! Runs labelled utterances through the NLU engines and reports how well
+//! each understood them. By default the utterances are the Rasa training
+//! data and accepted feedback; Rasa has to be running to be evaluated.
+//!
+//! With `--baseline` it compares against an earlier JSON report and exits
+//! with 1 when a score dropped, so it can guard changes to the engines.
+
+use std::{
+    path::PathBuf,
+    process::exit,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use tracing::{error, warn, Level};
+
+use barnaby_server::config::Settings;
+use barnaby_server::database;
+use barnaby_server::nlu::eval::{self, Answer, Dataset, Report, Utterance};
+use barnaby_server::nlu::{patterns, LlmService, NluPipeline};
+
+const USAGE: &str = "\
+Usage: barnaby-nlu-eval [options]
+
+Options:
+  --engines LIST       engines to evaluate, of llm, rasa, rust_nlu and pipeline
+                       (default: the configured engines, and the pipeline)
+  --dataset FILE       Rasa NLU file of labelled utterances, repeatable
+                       (default: the Rasa training data)
+  --no-feedback        leave out accepted feedback from the database
+  --format FORMAT      markdown or json (default: markdown)
+  --output FILE        write the report to FILE instead of standard output
+  --baseline FILE      JSON report to compare with; exits with 1 on a regression
+  --tolerance SCORE    how far a score may drop below the baseline (default: 0.01)
+  --min-accuracy SCORE exits with 1 when an engine's accuracy is lower
+  --help               show this";
+
+/// The whole configured chain or ensemble, as opposed to one engine.
+const PIPELINE: &str = "pipeline";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Format {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    engines: Option<Vec<String>>,
+    datasets: Vec<PathBuf>,
+    feedback: bool,
+    format: Format,
+    output: Option<PathBuf>,
+    baseline: Option<PathBuf>,
+    tolerance: f64,
+    min_accuracy: Option<f64>,
+}
+
+impl Options {
+    /// `None` when help was asked for.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options {
+            engines: None,
+            datasets: Vec::new(),
+            feedback: true,
+            format: Format::Markdown,
+            output: None,
+            baseline: None,
+            tolerance: 0.01,
+            min_accuracy: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--engines" => {
+                    let engines = value()?
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|engine| !engine.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    options.engines = Some(engines);
+                }
+                "--dataset" => options.datasets.push(PathBuf::from(value()?)),
+                "--no-feedback" => options.feedback = false,
+                "--format" => {
+                    options.format = match value()?.as_str() {
+                        "markdown" | "md" => Format::Markdown,
+                        "json" => Format::Json,
+                        other => return Err(format!("Unknown format: {}", other)),
+                    }
+                }
+                "--output" => options.output = Some(PathBuf::from(value()?)),
+                "--baseline" => options.baseline = Some(PathBuf::from(value()?)),
+                "--tolerance" => options.tolerance = parse_score(&arg, &value()?)?,
+                "--min-accuracy" => options.min_accuracy = Some(parse_score(&arg, &value()?)?),
+                "--help" | "-h" => return Ok(None),
+                other => return Err(format!("Unknown option: {}", other)),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+fn parse_score(option: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|score| (0.0..=1.0).contains(score))
+        .ok_or_else(|| format!("{} takes a score from 0 to 1, not {}", option, value))
+}
+
+#[tokio::main]
+async fn main() {
+    // The report goes to standard output, so logs go elsewhere
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(Level::WARN)
+        .init();
+
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    let config = match Settings::new() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            exit(2);
+        }
+    };
+
+    let dataset = match load_dataset(&options, &config).await {
+        Ok(dataset) if !dataset.utterances().is_empty() => dataset,
+        Ok(_) => {
+            error!("No labelled utterances to evaluate on");
+            exit(2);
+        }
+        Err(e) => {
+            error!("Failed to load the dataset: {:#}", e);
+            exit(2);
+        }
+    };
+
+    let rust_nlu = match patterns::load_rust_nlu(&config.nlu) {
+        Ok(rust_nlu) => Arc::new(RwLock::new(rust_nlu)),
+        Err(e) => {
+            error!("Failed to load NLU patterns: {:#}", e);
+            exit(2);
+        }
+    };
+    let llm = load_llm().await;
+
+    let requested = options.engines.clone().unwrap_or_else(|| {
+        let mut engines: Vec<String> = config
+            .nlu
+            .engines
+            .split(',')
+            .map(str::trim)
+            .filter(|engine| !engine.is_empty())
+            .map(str::to_string)
+            .collect();
+        engines.push(PIPELINE.to_string());
+        engines
+    });
+    if llm.is_none() && requested.iter().any(|engine| engine == "llm") {
+        warn!("No LLM loaded (PICOLLM_MODEL_PATH), so it isn't evaluated");
+    }
+
+    // The engines by themselves, with their configured timeouts
+    let single: Vec<&str> = requested
+        .iter()
+        .map(String::as_str)
+        .filter(|engine| *engine != PIPELINE)
+        .collect();
+    let mut report = Report {
+        dataset: dataset.summary(),
+        engines: Vec::new(),
+    };
+    if !single.is_empty() {
+        let mut engines_config = config.nlu.clone();
+        engines_config.engines = single.join(",");
+        let engines = match NluPipeline::from_config(&engines_config, llm.clone(), rust_nlu.clone()) {
+            Ok(engines) => engines,
+            Err(e) => {
+                error!("Failed to set up NLU engines: {}", e);
+                exit(2);
+            }
+        };
+        for source in engines.engines() {
+            let mut answers = Vec::new();
+            for utterance in dataset.utterances() {
+                let started = Instant::now();
+                let result = engines
+                    .ask(source, &utterance.text)
+                    .await
+                    .expect("engine is in the pipeline");
+                answers.push(Answer {
+                    result: result.map_err(|e| format!("{:#}", e)),
+                    latency: started.elapsed(),
+                });
+            }
+            report.engines.push(score(source.as_str(), dataset.utterances(), &answers));
+        }
+    }
+
+    if requested.iter().any(|engine| engine == PIPELINE) {
+        let pipeline = match NluPipeline::from_config(&config.nlu, llm, rust_nlu) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                error!("Failed to set up NLU: {}", e);
+                exit(2);
+            }
+        };
+        let mut answers = Vec::new();
+        for utterance in dataset.utterances() {
+            let started = Instant::now();
+            let result = pipeline.understand(&utterance.text).await;
+            answers.push(Answer {
+                result: result.map(|outcome| outcome.result).map_err(|e| format!("{:#}", e)),
+                latency: started.elapsed(),
+            });
+        }
+        report.engines.push(score(PIPELINE, dataset.utterances(), &answers));
+    }
+
+    let rendered = match options.format {
+        Format::Markdown => report.to_markdown(),
+        Format::Json => serde_json::to_string_pretty(&report).expect("report serializes") + "\n",
+    };
+    match &options.output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, rendered) {
+                error!("Failed to write {}: {}", path.display(), e);
+                exit(2);
+            }
+        }
+        None => print!("{}", rendered),
+    }
+
+    let mut failures = Vec::new();
+    if let Some(path) = &options.baseline {
+        let baseline: Report = match std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                error!("Failed to read the baseline {}: {}", path.display(), e);
+                exit(2);
+            }
+        };
+        failures.extend(eval::regressions(&baseline, &report, options.tolerance));
+    }
+    if let Some(min_accuracy) = options.min_accuracy {
+        for engine in &report.engines {
+            if engine.accuracy < min_accuracy {
+                failures.push(format!(
+                    "{} accuracy {:.3} is below {:.3}",
+                    engine.engine, engine.accuracy, min_accuracy
+                ));
+            }
+        }
+    }
+    for failure in &failures {
+        eprintln!("Regression: {}", failure);
+    }
+    if !failures.is_empty() {
+        exit(1);
+    }
+}
+
+/// The dataset files given, or the Rasa training data, and then accepted
+/// feedback, which wins over the files.
+async fn load_dataset(options: &Options, config: &Settings) -> anyhow::Result<Dataset> {
+    let files = if options.datasets.is_empty() {
+        patterns::rasa_data_files(&config.nlu)?
+    } else {
+        options.datasets.clone()
+    };
+    let mut dataset = Dataset::default();
+    for path in files {
+        dataset.add_file(&path)?;
+    }
+    if options.feedback {
+        let db = database::create_pool(&config.database.url).await?;
+        dataset.add_feedback(&db).await?;
+    }
+    Ok(dataset)
+}
+
+/// The LLM, as the server loads it.
+async fn load_llm() -> Option<LlmService> {
+    let model_path = std::env::var("PICOLLM_MODEL_PATH").ok()?;
+    let llm = LlmService::new(model_path);
+    match llm.initialize().await {
+        Ok(_) => Some(llm),
+        Err(e) => {
+            warn!("picoLLM initialization failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Scores the answers, warning when an engine failed on everything, which
+/// usually means it isn't running.
+fn score(engine: &str, utterances: &[Utterance], answers: &[Answer]) -> eval::EngineReport {
+    if let Some(Err(e)) = answers.first().map(|answer| &answer.result) {
+        if answers.iter().all(|answer| answer.result.is_err()) {
+            warn!("{} failed on every utterance: {}", engine, e);
+        }
+    }
+    eval::score(engine, utterances, answers)
+}
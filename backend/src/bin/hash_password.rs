@@ -1,5 +1,3 @@
-use bcrypt;
-
 fn main() {
     let password = "admin123";
     let hash = bcrypt::hash(password, bcrypt::DEFAULT_COST).unwrap();
@@ -7,6 +7,10 @@ pub struct Settings {
     pub auth: AuthConfig,
     pub audio: AudioConfig,
     pub mqtt: MqttConfig,
+    pub home_automation: HomeAutomationConfig,
+    pub location: LocationConfig,
+    pub weather: WeatherConfig,
+    pub nlu: NluConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -24,35 +28,194 @@ pub struct DatabaseConfig {
 pub struct AuthConfig {
     pub jwt_secret: String,
     pub jwt_expiration: u64,
+    pub refresh_token_expiration: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AudioConfig {
     pub sample_rate: u32,
     pub chunk_size: usize,
+    pub stt_engine: String,
+    pub stt_model_path: String,
+    pub stt_language: String,
+    pub whisper_binary: String,
+    pub stt_mock_text: Option<String>,
+    pub tts_engine: String,
+    pub tts_voice: String,
+    pub tts_voices_dir: String,
+    pub piper_binary: String,
+    pub espeak_binary: String,
+    pub stream_timeout_ms: u64,
+    pub max_utterance_secs: u32,
+    pub max_concurrent_streams: usize,
+    pub processing_timeout_secs: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MqttConfig {
+    pub enabled: bool,
+    pub client_id: String,
     pub broker: String,
     pub port: u16,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub heartbeat_timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HomeAutomationConfig {
+    pub enabled: bool,
+    pub backend: String,
+    pub openhab_url: String,
+    pub openhab_token: Option<String>,
+    pub home_assistant_url: String,
+    pub home_assistant_token: Option<String>,
+    pub timeout_secs: u64,
+    pub sync_interval_secs: u64,
+}
+
+impl HomeAutomationConfig {
+    /// Base URL of the selected backend.
+    pub fn url(&self) -> &str {
+        match self.backend.as_str() {
+            "home_assistant" => &self.home_assistant_url,
+            _ => &self.openhab_url,
+        }
+    }
+}
+
+/// Where the household is, for weather and anything else that needs a place.
+/// Overridden by the location saved through the API.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LocationConfig {
+    pub name: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// IANA name, e.g. "Europe/London". The location's own when unset.
+    pub timezone: Option<String>,
+    /// Look the location up from our IP address (through ip-api.com) when
+    /// none is configured. Off by default, since it tells a third party
+    /// where we are.
+    pub ip_lookup: bool,
+}
+
+/// Open-Meteo endpoints, which can point at a self-hosted instance, and how
+/// long answers are kept.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WeatherConfig {
+    pub forecast_url: String,
+    pub geocoding_url: String,
+    pub ip_lookup_url: String,
+    pub timeout_secs: u64,
+    pub forecast_ttl_secs: u64,
+    pub geocoding_ttl_secs: u64,
+    /// How old a forecast may be, or how long past its ttl a place may be,
+    /// and still be used when the provider can't be reached.
+    pub stale_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NluConfig {
+    pub rasa_url: String,
+    /// The Rasa project, where training data is written and models are
+    /// trained.
+    pub rasa_dir: String,
+    /// RustNlu's pattern files, YAML or TOML.
+    pub patterns_dir: String,
+    /// How often pattern and training data files are checked for changes.
+    pub reload_interval_secs: u64,
+    /// The engines to ask, in order, comma separated: `llm`, `rasa` and
+    /// `rust_nlu`.
+    pub engines: String,
+    /// `chain` takes the first engine that is sure enough, `ensemble` asks
+    /// them all at once and takes the most confident after calibration.
+    pub mode: String,
+    pub llm: NluEngineConfig,
+    pub rasa: NluEngineConfig,
+    pub rust_nlu: NluEngineConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NluEngineConfig {
+    /// Results less confident than this are passed over.
+    pub threshold: f64,
+    pub timeout_ms: u64,
+    /// Scales the engine's confidence so engines compare fairly in ensemble
+    /// mode, e.g. below 1 for one that is sure of itself too easily.
+    pub calibration: f64,
+}
+
+impl NluConfig {
+    /// Where reviewed feedback is exported to, next to the other training data.
+    pub fn feedback_path(&self) -> std::path::PathBuf {
+        self.rasa_data_dir().join("feedback.yml")
+    }
+
+    pub fn rasa_data_dir(&self) -> std::path::PathBuf {
+        std::path::Path::new(&self.rasa_dir).join("data")
+    }
 }
 
 impl Settings {
     pub fn new() -> Result<Self, config::ConfigError> {
         let settings = config::Config::builder()
-            .add_source(config::Environment::with_prefix("BARNABY"))
+            // Nested keys use a double underscore, e.g. BARNABY_AUDIO__STT_ENGINE=mock
+            .add_source(config::Environment::with_prefix("BARNABY").prefix_separator("_").separator("__"))
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 3000)?
             .set_default("database.url", "sqlite:barnaby.db")?
             .set_default("auth.jwt_secret", "your-secret-key-change-in-production")?
             .set_default("auth.jwt_expiration", 3600)?
+            .set_default("auth.refresh_token_expiration", 2592000)?
             .set_default("audio.sample_rate", 16000)?
             .set_default("audio.chunk_size", 1024)?
+            .set_default("audio.stt_engine", "whisper_cpp")?
+            .set_default("audio.stt_model_path", "models/ggml-base.en.bin")?
+            .set_default("audio.stt_language", "en")?
+            .set_default("audio.whisper_binary", "whisper-cli")?
+            .set_default("audio.tts_engine", "piper")?
+            .set_default("audio.tts_voice", "en_US-lessac-medium")?
+            .set_default("audio.tts_voices_dir", "models/piper")?
+            .set_default("audio.piper_binary", "piper")?
+            .set_default("audio.espeak_binary", "espeak-ng")?
+            .set_default("audio.stream_timeout_ms", 5000)?
+            .set_default("audio.max_utterance_secs", 30)?
+            .set_default("audio.max_concurrent_streams", 2)?
+            .set_default("audio.processing_timeout_secs", 30)?
+            .set_default("mqtt.enabled", false)?
+            .set_default("mqtt.client_id", "barnaby-server")?
             .set_default("mqtt.broker", "localhost")?
             .set_default("mqtt.port", 1883)?
+            .set_default("mqtt.heartbeat_timeout_secs", 90)?
+            .set_default("home_automation.enabled", false)?
+            .set_default("home_automation.backend", "openhab")?
+            .set_default("home_automation.openhab_url", "http://localhost:8080")?
+            .set_default("home_automation.home_assistant_url", "http://localhost:8123")?
+            .set_default("home_automation.timeout_secs", 10)?
+            .set_default("home_automation.sync_interval_secs", 60)?
+            .set_default("location.ip_lookup", false)?
+            .set_default("weather.forecast_url", "https://api.open-meteo.com")?
+            .set_default("weather.geocoding_url", "https://geocoding-api.open-meteo.com")?
+            .set_default("weather.ip_lookup_url", "http://ip-api.com")?
+            .set_default("weather.timeout_secs", 10)?
+            .set_default("weather.forecast_ttl_secs", 900)?
+            .set_default("weather.geocoding_ttl_secs", 604800)?
+            .set_default("weather.stale_secs", 21600)?
+            .set_default("nlu.rasa_url", "http://localhost:5005")?
+            .set_default("nlu.rasa_dir", "../nlu/rasa")?
+            .set_default("nlu.patterns_dir", "../nlu/patterns")?
+            .set_default("nlu.reload_interval_secs", 2)?
+            .set_default("nlu.engines", "llm,rasa,rust_nlu")?
+            .set_default("nlu.mode", "chain")?
+            .set_default("nlu.llm.threshold", 0.5)?
+            .set_default("nlu.llm.timeout_ms", 5000)?
+            .set_default("nlu.llm.calibration", 1.0)?
+            .set_default("nlu.rasa.threshold", 0.3)?
+            .set_default("nlu.rasa.timeout_ms", 3000)?
+            .set_default("nlu.rasa.calibration", 1.0)?
+            .set_default("nlu.rust_nlu.threshold", 0.0)?
+            .set_default("nlu.rust_nlu.timeout_ms", 1000)?
+            .set_default("nlu.rust_nlu.calibration", 1.0)?
             .build()?;
 
         settings.try_deserialize()
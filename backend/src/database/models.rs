@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow};
 use chrono::{DateTime, Utc};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -50,6 +50,17 @@ pub struct CreateUser {
 }
 
 #[derive(Debug, Serialize, FromRow)]
+pub struct Session {
+    pub id: String,
+    pub user_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct Satellite {
     pub id: String,
     pub name: String,
@@ -61,6 +72,128 @@ pub struct Satellite {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SatelliteUptime {
+    pub id: String,
+    pub satellite_id: String,
+    pub online_at: DateTime<Utc>,
+    pub offline_at: Option<DateTime<Utc>>,
+    pub offline_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingRequest {
+    pub id: String,
+    pub mac_address: String,
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub state: String,
+    pub satellite_id: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decided_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Room {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub room_id: Option<String>,
+    pub device_type: String,
+    pub capabilities: Json<Vec<String>>,
+    pub backend: String,
+    pub external_id: Option<String>,
+    pub command_topic: Option<String>,
+    pub state_topic: Option<String>,
+    pub state: Option<Json<serde_json::Value>>,
+    pub state_updated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Timer {
+    pub id: String,
+    pub kind: String,
+    pub label: Option<String>,
+    pub user_id: Option<String>,
+    pub satellite_id: Option<String>,
+    pub duration_secs: Option<i64>,
+    pub fires_at: DateTime<Utc>,
+    pub status: String,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Reminder {
+    pub id: String,
+    pub user_id: String,
+    pub satellite_id: Option<String>,
+    pub text: String,
+    pub remind_at: DateTime<Utc>,
+    pub recurrence: Option<String>,
+    pub status: String,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A user's correction of the intent a command was understood as.
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct IntentFeedback {
+    pub id: String,
+    pub command_id: Option<String>,
+    pub user_id: Option<String>,
+    pub text: String,
+    pub predicted_intent: Option<String>,
+    pub correct_intent: String,
+    pub confidence: Option<f64>,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub exported_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A shopping or to-do list. Lists without a `user_id` are shared by the household.
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct List {
+    pub id: String,
+    pub name: String,
+    pub user_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ListItem {
+    pub id: String,
+    pub list_id: String,
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub checked: bool,
+    pub added_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, FromRow)]
 pub struct CommandHistory {
     pub id: String,
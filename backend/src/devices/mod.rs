@@ -0,0 +1,534 @@
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::types::Json;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::{
+    database::models::{Device, Room},
+    events::Event,
+    AppState,
+};
+
+pub const DEVICE_COLUMNS: &str =
+    "id, name, room_id, device_type, capabilities, backend, external_id, command_topic, state_topic, state, state_updated_at, created_at";
+
+const TOPIC_PREFIX: &str = "barnaby/devices/";
+
+/// `devices.backend` for devices Barnaby talks to directly over MQTT.
+pub const MQTT_BACKEND: &str = "mqtt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    OnOff,
+    Brightness,
+    Color,
+    Temperature,
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::OnOff => "on_off",
+            Capability::Brightness => "brightness",
+            Capability::Color => "color",
+            Capability::Temperature => "temperature",
+        }
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on_off" => Ok(Capability::OnOff),
+            "brightness" => Ok(Capability::Brightness),
+            "color" => Ok(Capability::Color),
+            "temperature" => Ok(Capability::Temperature),
+            _ => Err(format!("Invalid capability: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DeviceError {
+    #[error("device does not support {}", .0.as_str())]
+    Unsupported(Capability),
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("{0} is not available")]
+    Unavailable(String),
+    #[error("failed to send command to device: {0}")]
+    Command(anyhow::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A requested change of device state. Fields left out stay as they are.
+///
+/// Published to the device's command topic as
+/// `{"state": "ON", "brightness": 80, "color": "#ff8800"}`, with brightness
+/// in percent. Thermostats take a target `temperature` in degrees Celsius.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateChange {
+    pub on: Option<bool>,
+    pub brightness: Option<u8>,
+    pub color: Option<String>,
+    pub temperature: Option<f64>,
+}
+
+impl StateChange {
+    pub fn is_empty(&self) -> bool {
+        self.on.is_none() && self.brightness.is_none() && self.color.is_none() && self.temperature.is_none()
+    }
+
+    /// Checks the change against what the device can do.
+    pub fn validate(&self, capabilities: &[String]) -> Result<(), DeviceError> {
+        let supports = |capability: Capability| {
+            if capabilities.iter().any(|c| c == capability.as_str()) {
+                Ok(())
+            } else {
+                Err(DeviceError::Unsupported(capability))
+            }
+        };
+
+        if self.is_empty() {
+            return Err(DeviceError::InvalidValue("no state change requested".to_string()));
+        }
+        if self.on.is_some() {
+            supports(Capability::OnOff)?;
+        }
+        if let Some(brightness) = self.brightness {
+            supports(Capability::Brightness)?;
+            if brightness > 100 {
+                return Err(DeviceError::InvalidValue("brightness must be between 0 and 100".to_string()));
+            }
+        }
+        if let Some(color) = &self.color {
+            supports(Capability::Color)?;
+            if !is_hex_color(color) {
+                return Err(DeviceError::InvalidValue("color must look like #rrggbb".to_string()));
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            supports(Capability::Temperature)?;
+            if !(5.0..=35.0).contains(&temperature) {
+                return Err(DeviceError::InvalidValue("temperature must be between 5 and 35".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_payload(&self) -> Value {
+        let mut payload = Map::new();
+        if let Some(on) = self.on {
+            payload.insert("state".to_string(), json!(if on { "ON" } else { "OFF" }));
+        }
+        if let Some(brightness) = self.brightness {
+            payload.insert("brightness".to_string(), json!(brightness));
+        }
+        if let Some(color) = &self.color {
+            payload.insert("color".to_string(), json!(color.to_lowercase()));
+        }
+        if let Some(temperature) = self.temperature {
+            payload.insert("temperature".to_string(), json!(temperature));
+        }
+        Value::Object(payload)
+    }
+}
+
+pub fn is_hex_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub fn default_command_topic(device_id: &str) -> String {
+    format!("{}{}/set", TOPIC_PREFIX, device_id)
+}
+
+pub fn default_state_topic(device_id: &str) -> String {
+    format!("{}{}/state", TOPIC_PREFIX, device_id)
+}
+
+/// Turns a state report into `{"on": .., "brightness": .., "color": ..,
+/// "temperature": ..}`.
+/// Devices may report `"state": "ON"` like they are commanded, or `"on": true`.
+pub fn normalize_state(payload: &Value) -> Option<Value> {
+    let object = payload.as_object()?;
+    let mut state = Map::new();
+
+    let on = match object.get("state").and_then(Value::as_str) {
+        Some(value) => Some(value.eq_ignore_ascii_case("on")),
+        None => object.get("on").and_then(Value::as_bool),
+    };
+    if let Some(on) = on {
+        state.insert("on".to_string(), json!(on));
+    }
+    if let Some(brightness) = object.get("brightness").and_then(Value::as_u64) {
+        state.insert("brightness".to_string(), json!(brightness.min(100)));
+    }
+    if let Some(color) = object.get("color").and_then(Value::as_str).filter(|c| is_hex_color(c)) {
+        state.insert("color".to_string(), json!(color.to_lowercase()));
+    }
+    if let Some(temperature) = object.get("temperature").and_then(Value::as_f64) {
+        state.insert("temperature".to_string(), json!(temperature));
+    }
+
+    (!state.is_empty()).then_some(Value::Object(state))
+}
+
+pub async fn find_device(state: &AppState, device_id: &str) -> Result<Option<Device>, sqlx::Error> {
+    sqlx::query_as::<_, Device>(&format!("SELECT {} FROM devices WHERE id = ?", DEVICE_COLUMNS))
+        .bind(device_id)
+        .fetch_optional(&state.db)
+        .await
+}
+
+pub async fn find_room_by_name(state: &AppState, name: &str) -> Result<Option<Room>, sqlx::Error> {
+    sqlx::query_as::<_, Room>("SELECT id, name, created_at FROM rooms WHERE name = ?")
+        .bind(name.trim())
+        .fetch_optional(&state.db)
+        .await
+}
+
+pub async fn list_rooms(state: &AppState) -> Result<Vec<Room>, sqlx::Error> {
+    sqlx::query_as::<_, Room>("SELECT id, name, created_at FROM rooms ORDER BY name")
+        .fetch_all(&state.db)
+        .await
+}
+
+pub async fn devices_in_room(state: &AppState, room_id: &str) -> Result<Vec<Device>, sqlx::Error> {
+    sqlx::query_as::<_, Device>(&format!(
+        "SELECT {} FROM devices WHERE room_id = ? ORDER BY name",
+        DEVICE_COLUMNS
+    ))
+    .bind(room_id)
+    .fetch_all(&state.db)
+    .await
+}
+
+/// Sends a state change to a device through whichever backend owns it.
+///
+/// Devices that never report back (MQTT devices without a state topic) have
+/// their stored state updated straight away. Home automation devices are
+/// read back after the command.
+pub async fn set_state(state: &AppState, device: &Device, change: &StateChange) -> Result<(), DeviceError> {
+    change.validate(&device.capabilities)?;
+
+    if device.backend != MQTT_BACKEND {
+        return set_backend_state(state, device, change).await;
+    }
+
+    let mqtt = state
+        .mqtt
+        .as_ref()
+        .ok_or_else(|| DeviceError::Unavailable("MQTT".to_string()))?;
+    let topic = device
+        .command_topic
+        .as_deref()
+        .ok_or_else(|| DeviceError::InvalidValue("device has no command topic".to_string()))?;
+    mqtt.publish(topic, &change.to_payload().to_string())
+        .await
+        .map_err(DeviceError::Command)?;
+    info!("Sent {} to device {}", change.to_payload(), device.id);
+
+    if device.state_topic.is_none() {
+        record_requested_state(state, device, change).await?;
+    }
+    Ok(())
+}
+
+async fn set_backend_state(state: &AppState, device: &Device, change: &StateChange) -> Result<(), DeviceError> {
+    let backend = state
+        .home_automation
+        .as_ref()
+        .filter(|backend| backend.name() == device.backend)
+        .ok_or_else(|| DeviceError::Unavailable(device.backend.clone()))?;
+    let external_id = device
+        .external_id
+        .as_deref()
+        .ok_or_else(|| DeviceError::InvalidValue(format!("device has no {} id", device.backend)))?;
+
+    backend.apply(external_id, change).await.map_err(DeviceError::Command)?;
+    info!("Sent {} to {} device {}", change.to_payload(), device.backend, external_id);
+
+    match backend.read_state(external_id).await {
+        Ok(Some(reported)) => record_state(state, &device.id, &reported).await?,
+        Ok(None) => record_requested_state(state, device, change).await?,
+        Err(e) => {
+            warn!("Failed to read back {} device {}: {}", device.backend, external_id, e);
+            record_requested_state(state, device, change).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn record_requested_state(state: &AppState, device: &Device, change: &StateChange) -> Result<(), sqlx::Error> {
+    match normalize_state(&change.to_payload()) {
+        Some(requested) => record_state(state, &device.id, &requested).await,
+        None => Ok(()),
+    }
+}
+
+/// Merges reported state into what we know about the device.
+pub async fn record_state(state: &AppState, device_id: &str, reported: &Value) -> Result<(), sqlx::Error> {
+    let Some(device) = find_device(state, device_id).await? else {
+        return Ok(());
+    };
+
+    let mut merged = device
+        .state
+        .map(|Json(state)| state)
+        .filter(Value::is_object)
+        .unwrap_or_else(|| json!({}));
+    if let (Some(merged), Some(reported)) = (merged.as_object_mut(), reported.as_object()) {
+        for (key, value) in reported {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+
+    sqlx::query("UPDATE devices SET state = ?, state_updated_at = ? WHERE id = ?")
+        .bind(Json(&merged))
+        .bind(Utc::now())
+        .bind(device_id)
+        .execute(&state.db)
+        .await?;
+
+    state.events.publish(Event::DeviceState {
+        device_id: device_id.to_string(),
+        state: merged,
+    });
+    Ok(())
+}
+
+/// Handles a message on any topic that isn't a satellite's, updating the
+/// devices that report their state there.
+pub async fn handle_state_message(state: &AppState, topic: &str, payload: &[u8]) -> Result<(), sqlx::Error> {
+    let device_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM devices WHERE state_topic = ?")
+        .bind(topic)
+        .fetch_all(&state.db)
+        .await?;
+    if device_ids.is_empty() {
+        return Ok(());
+    }
+
+    let Some(reported) = serde_json::from_slice::<Value>(payload).ok().and_then(|p| normalize_state(&p)) else {
+        debug!("Ignoring unrecognised device state on {}", topic);
+        return Ok(());
+    };
+
+    for device_id in device_ids {
+        record_state(state, &device_id, &reported).await?;
+    }
+    Ok(())
+}
+
+/// Subscribes to the state topic of every device. Called at startup and
+/// whenever devices change.
+pub async fn sync_subscriptions(state: &AppState) {
+    let Some(mqtt) = &state.mqtt else { return };
+
+    let topics: Result<Vec<String>, _> =
+        sqlx::query_scalar("SELECT DISTINCT state_topic FROM devices WHERE state_topic IS NOT NULL")
+            .fetch_all(&state.db)
+            .await;
+
+    match topics {
+        Ok(topics) => {
+            if let Err(e) = mqtt.set_extra_topics(topics.into_iter().collect::<BTreeSet<_>>()).await {
+                warn!("Failed to subscribe to device state topics: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to load device state topics: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{home_automation::{DiscoveredDevice, HomeAutomationBackend}, test_support};
+
+    fn capabilities(capabilities: &[&str]) -> Vec<String> {
+        capabilities.iter().map(|c| c.to_string()).collect()
+    }
+
+    async fn stored_state(state: &AppState, device_id: &str) -> Option<Value> {
+        find_device(state, device_id).await.unwrap().unwrap().state.map(|Json(state)| state)
+    }
+
+    #[test]
+    fn changes_are_checked_against_capabilities() {
+        let light = capabilities(&["on_off", "brightness"]);
+        let change = |change: StateChange| change.validate(&light);
+
+        assert!(change(StateChange { on: Some(true), brightness: Some(100), ..Default::default() }).is_ok());
+        assert!(matches!(change(StateChange::default()), Err(DeviceError::InvalidValue(_))));
+        assert!(matches!(
+            change(StateChange { brightness: Some(101), ..Default::default() }),
+            Err(DeviceError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            change(StateChange { color: Some("#ff0000".to_string()), ..Default::default() }),
+            Err(DeviceError::Unsupported(Capability::Color))
+        ));
+        assert!(matches!(
+            change(StateChange { temperature: Some(21.0), ..Default::default() }),
+            Err(DeviceError::Unsupported(Capability::Temperature))
+        ));
+
+        let everything = capabilities(&["on_off", "brightness", "color", "temperature"]);
+        for invalid in [
+            StateChange { color: Some("red".to_string()), ..Default::default() },
+            StateChange { color: Some("#ff00zz".to_string()), ..Default::default() },
+            StateChange { temperature: Some(4.5), ..Default::default() },
+            StateChange { temperature: Some(35.5), ..Default::default() },
+        ] {
+            assert!(matches!(invalid.validate(&everything), Err(DeviceError::InvalidValue(_))), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn changes_are_published_like_devices_report_them() {
+        let change = StateChange {
+            on: Some(false),
+            brightness: Some(40),
+            color: Some("#FF8800".to_string()),
+            temperature: Some(20.5),
+        };
+        assert_eq!(
+            change.to_payload(),
+            json!({ "state": "OFF", "brightness": 40, "color": "#ff8800", "temperature": 20.5 })
+        );
+        assert_eq!(StateChange::default().to_payload(), json!({}));
+        assert_eq!(default_command_topic("lamp"), "barnaby/devices/lamp/set");
+        assert_eq!(default_state_topic("lamp"), "barnaby/devices/lamp/state");
+    }
+
+    #[test]
+    fn reported_state_is_normalised() {
+        assert_eq!(normalize_state(&json!({ "state": "on" })), Some(json!({ "on": true })));
+        assert_eq!(normalize_state(&json!({ "on": false })), Some(json!({ "on": false })));
+        assert_eq!(
+            normalize_state(&json!({ "state": "OFF", "brightness": 250, "color": "#00FF00", "temperature": 19 })),
+            Some(json!({ "on": false, "brightness": 100, "color": "#00ff00", "temperature": 19.0 }))
+        );
+        // Invalid values are left out, and nothing known is no state
+        assert_eq!(normalize_state(&json!({ "on": true, "color": "green" })), Some(json!({ "on": true })));
+        assert_eq!(normalize_state(&json!({ "battery": 80 })), None);
+        assert_eq!(normalize_state(&json!("ON")), None);
+    }
+
+    #[tokio::test]
+    async fn state_messages_update_devices_on_that_topic() {
+        let state = test_support::state().await;
+        test_support::light(&state, "lamp", None, &["on_off", "brightness"]).await;
+        test_support::light(&state, "other", None, &["on_off"]).await;
+        sqlx::query("UPDATE devices SET state_topic = 'home/lamp', state = '{\"brightness\": 20}' WHERE id = 'lamp'")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let mut events = state.events.subscribe();
+
+        handle_state_message(&state, "home/lamp", br#"{"state": "ON"}"#).await.unwrap();
+        assert_eq!(stored_state(&state, "lamp").await, Some(json!({ "on": true, "brightness": 20 })));
+        assert_eq!(stored_state(&state, "other").await, None);
+        match events.try_recv().unwrap().event {
+            Event::DeviceState { device_id, state } => {
+                assert_eq!(device_id, "lamp");
+                assert_eq!(state, json!({ "on": true, "brightness": 20 }));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Garbage and unknown topics change nothing
+        handle_state_message(&state, "home/lamp", b"not json").await.unwrap();
+        handle_state_message(&state, "home/elsewhere", br#"{"state": "OFF"}"#).await.unwrap();
+        assert_eq!(stored_state(&state, "lamp").await, Some(json!({ "on": true, "brightness": 20 })));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn mqtt_devices_are_commanded_over_mqtt() {
+        let mut state = test_support::state().await;
+        test_support::light(&state, "lamp", None, &["on_off", "brightness"]).await;
+        let lamp = find_device(&state, "lamp").await.unwrap().unwrap();
+        let change = StateChange { on: Some(true), brightness: Some(60), ..Default::default() };
+
+        assert!(matches!(set_state(&state, &lamp, &change).await, Err(DeviceError::Unavailable(_))));
+
+        state.mqtt = Some(test_support::offline_mqtt().await);
+        set_state(&state, &lamp, &change).await.unwrap();
+        // It doesn't report back, so what was asked for is what it is
+        assert_eq!(stored_state(&state, "lamp").await, Some(json!({ "on": true, "brightness": 60 })));
+
+        sqlx::query("UPDATE devices SET state_topic = 'home/lamp', state = NULL WHERE id = 'lamp'")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let lamp = find_device(&state, "lamp").await.unwrap().unwrap();
+        set_state(&state, &lamp, &change).await.unwrap();
+        assert_eq!(stored_state(&state, "lamp").await, None);
+    }
+
+    /// A backend that remembers what it was told and reports `reported`.
+    struct StubBackend {
+        applied: Mutex<Vec<(String, Value)>>,
+        reported: Option<Value>,
+    }
+
+    #[async_trait]
+    impl HomeAutomationBackend for StubBackend {
+        fn name(&self) -> &'static str {
+            "openhab"
+        }
+
+        async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+            Ok(Vec::new())
+        }
+
+        async fn apply(&self, external_id: &str, change: &StateChange) -> Result<()> {
+            self.applied.lock().unwrap().push((external_id.to_string(), change.to_payload()));
+            Ok(())
+        }
+
+        async fn read_state(&self, _external_id: &str) -> Result<Option<Value>> {
+            Ok(self.reported.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn backend_devices_are_commanded_and_read_back() {
+        let mut state = test_support::state().await;
+        sqlx::query(
+            "INSERT INTO devices (id, name, capabilities, backend, external_id) VALUES ('lamp', 'Lamp', '[\"on_off\", \"brightness\"]', 'openhab', 'Lamp_Item')",
+        )
+        .execute(&state.db)
+        .await
+        .unwrap();
+        let lamp = find_device(&state, "lamp").await.unwrap().unwrap();
+        let change = StateChange { brightness: Some(60), ..Default::default() };
+
+        assert!(matches!(set_state(&state, &lamp, &change).await, Err(DeviceError::Unavailable(_))));
+
+        let backend = Arc::new(StubBackend {
+            applied: Mutex::new(Vec::new()),
+            reported: Some(json!({ "on": true, "brightness": 55 })),
+        });
+        state.home_automation = Some(backend.clone());
+        set_state(&state, &lamp, &change).await.unwrap();
+        assert_eq!(*backend.applied.lock().unwrap(), [("Lamp_Item".to_string(), json!({ "brightness": 60 }))]);
+        // What the backend reports wins over what was asked for
+        assert_eq!(stored_state(&state, "lamp").await, Some(json!({ "on": true, "brightness": 55 })));
+    }
+}
@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::auth::jwt::Claims;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+/// Something that happened inside Barnaby that clients or other subsystems
+/// may want to react to.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    CommandProcessed {
+        command_id: String,
+        user_id: Option<String>,
+        satellite_id: Option<String>,
+        text: String,
+        intent: String,
+        confidence: f64,
+        response: String,
+    },
+    SatelliteStatus {
+        satellite_id: String,
+        status: String,
+    },
+    PairingRequested {
+        request_id: String,
+        mac_address: String,
+    },
+    DeviceState {
+        device_id: String,
+        state: Value,
+    },
+    TimerFired {
+        timer_id: String,
+        kind: String,
+        label: Option<String>,
+        user_id: Option<String>,
+        satellite_id: Option<String>,
+        text: String,
+    },
+    ReminderDue {
+        reminder_id: String,
+        user_id: String,
+        satellite_id: Option<String>,
+        text: String,
+    },
+    ListChanged {
+        list_id: String,
+        /// Owner of a personal list, `None` for household lists.
+        user_id: Option<String>,
+    },
+    UserChanged {
+        user_id: String,
+        action: String,
+    },
+    NluFallback {
+        text: String,
+        engine: String,
+        reason: String,
+    },
+}
+
+impl Event {
+    pub const KINDS: &'static [&'static str] = &[
+        "command_processed",
+        "satellite_status",
+        "pairing_requested",
+        "device_state",
+        "timer_fired",
+        "reminder_due",
+        "list_changed",
+        "user_changed",
+        "nlu_fallback",
+    ];
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::CommandProcessed { .. } => "command_processed",
+            Event::SatelliteStatus { .. } => "satellite_status",
+            Event::PairingRequested { .. } => "pairing_requested",
+            Event::DeviceState { .. } => "device_state",
+            Event::TimerFired { .. } => "timer_fired",
+            Event::ReminderDue { .. } => "reminder_due",
+            Event::ListChanged { .. } => "list_changed",
+            Event::UserChanged { .. } => "user_changed",
+            Event::NluFallback { .. } => "nlu_fallback",
+        }
+    }
+
+    /// Admins see everything; regular users only see their own commands,
+    /// timers and reminders, the lists they share, satellite status and
+    /// device state.
+    pub fn visible_to(&self, claims: &Claims) -> bool {
+        if claims.role == "admin" {
+            return true;
+        }
+
+        match self {
+            Event::CommandProcessed { user_id, .. } | Event::TimerFired { user_id, .. } => {
+                user_id.as_deref() == Some(claims.sub.as_str())
+            }
+            Event::ReminderDue { user_id, .. } => *user_id == claims.sub,
+            Event::ListChanged { user_id, .. } => user_id.as_deref().is_none_or(|owner| owner == claims.sub),
+            Event::SatelliteStatus { .. } | Event::DeviceState { .. } => true,
+            Event::PairingRequested { .. } | Event::UserChanged { .. } | Event::NluFallback { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventEnvelope {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Broadcast bus shared through `AppState`.
+///
+/// Publishing never blocks and never fails; events are simply dropped when
+/// nobody is listening, and slow receivers are told how many they missed.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<EventEnvelope>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        tracing::debug!("Publishing event: {}", event.kind());
+        let _ = self.sender.send(EventEnvelope {
+            timestamp: Utc::now(),
+            event,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(user_id: &str, role: &str) -> Claims {
+        Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            role: role.to_string(),
+            exp: 0,
+            iat: 0,
+        }
+    }
+
+    fn timer_fired(user_id: Option<&str>) -> Event {
+        Event::TimerFired {
+            timer_id: "t1".to_string(),
+            kind: "timer".to_string(),
+            label: None,
+            user_id: user_id.map(str::to_string),
+            satellite_id: None,
+            text: "Your timer is done.".to_string(),
+        }
+    }
+
+    fn reminder_due(user_id: &str) -> Event {
+        Event::ReminderDue {
+            reminder_id: "r1".to_string(),
+            user_id: user_id.to_string(),
+            satellite_id: None,
+            text: "Reminder: call mum.".to_string(),
+        }
+    }
+
+    #[test]
+    fn users_only_see_their_own_timers_and_reminders() {
+        let sam = claims("sam", "user");
+        assert!(timer_fired(Some("sam")).visible_to(&sam));
+        assert!(!timer_fired(Some("alex")).visible_to(&sam));
+        // Timers set on a satellite belong to no user
+        assert!(!timer_fired(None).visible_to(&sam));
+        assert!(reminder_due("sam").visible_to(&sam));
+        assert!(!reminder_due("alex").visible_to(&sam));
+
+        let command = |user_id: &str| Event::CommandProcessed {
+            command_id: "c1".to_string(),
+            user_id: Some(user_id.to_string()),
+            satellite_id: None,
+            text: "hello".to_string(),
+            intent: "greet".to_string(),
+            confidence: 1.0,
+            response: "Hi".to_string(),
+        };
+        assert!(command("sam").visible_to(&sam));
+        assert!(!command("alex").visible_to(&sam));
+    }
+
+    #[test]
+    fn users_see_shared_lists_and_the_house_but_not_admin_events() {
+        let sam = claims("sam", "user");
+        let list = |user_id: Option<&str>| Event::ListChanged {
+            list_id: "l1".to_string(),
+            user_id: user_id.map(str::to_string),
+        };
+        assert!(list(None).visible_to(&sam));
+        assert!(list(Some("sam")).visible_to(&sam));
+        assert!(!list(Some("alex")).visible_to(&sam));
+
+        let satellite = Event::SatelliteStatus {
+            satellite_id: "kitchen".to_string(),
+            status: "online".to_string(),
+        };
+        assert!(satellite.visible_to(&sam));
+        let fallback = Event::NluFallback {
+            text: "hmm".to_string(),
+            engine: "rasa".to_string(),
+            reason: "timed out".to_string(),
+        };
+        assert!(!fallback.visible_to(&sam));
+        let user_changed = Event::UserChanged {
+            user_id: "sam".to_string(),
+            action: "updated".to_string(),
+        };
+        assert!(!user_changed.visible_to(&sam));
+    }
+
+    #[test]
+    fn admins_see_everything() {
+        let admin = claims("ops", "admin");
+        assert!(timer_fired(Some("alex")).visible_to(&admin));
+        assert!(reminder_due("alex").visible_to(&admin));
+        assert!(Event::PairingRequested {
+            request_id: "p1".to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+        }
+        .visible_to(&admin));
+    }
+
+    #[test]
+    fn events_are_tagged_with_their_kind() {
+        let envelope = serde_json::to_value(EventEnvelope {
+            timestamp: Utc::now(),
+            event: reminder_due("sam"),
+        })
+        .unwrap();
+        assert_eq!(envelope["type"], "reminder_due");
+        assert_eq!(envelope["user_id"], "sam");
+    }
+}
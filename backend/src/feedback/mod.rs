@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::{
+    database::models::IntentFeedback,
+    nlu::{
+        patterns,
+        training::{self, TrainingExample},
+    },
+    AppState,
+};
+
+pub const FEEDBACK_COLUMNS: &str = "id, command_id, user_id, text, predicted_intent, correct_intent, confidence, status, \
+     reviewed_by, reviewed_at, exported_at, created_at";
+
+/// Intents Rasa is trained on that no skill handles.
+const CONVERSATION_INTENTS: &[&str] = &["affirm", "deny", "out_of_scope"];
+
+/// Whether feedback may name `intent` as the right one.
+pub fn is_known_intent(state: &AppState, intent: &str) -> bool {
+    CONVERSATION_INTENTS.contains(&intent)
+        || state.skills.intents().iter().any(|(_, spec)| spec.intent == intent)
+        || state.rust_nlu.read().unwrap().intents().contains(&intent)
+}
+
+/// What an export wrote.
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub examples: usize,
+    pub intents: usize,
+}
+
+/// Writes every accepted correction to the Rasa training data and teaches
+/// them to the running `RustNlu`. When the same text was corrected more than
+/// once, the latest review wins.
+pub async fn export(state: &AppState) -> anyhow::Result<ExportReport> {
+    // Only what was read is marked as exported, even if more is accepted
+    // while the training data is written
+    let mut tx = state.db.begin().await?;
+    let accepted = sqlx::query_as::<_, IntentFeedback>(&format!(
+        "SELECT {} FROM intent_feedback WHERE status = 'accepted' ORDER BY reviewed_at, created_at",
+        FEEDBACK_COLUMNS
+    ))
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut latest: HashMap<String, TrainingExample> = HashMap::new();
+    for feedback in &accepted {
+        let text = feedback.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        latest.insert(
+            text.to_lowercase(),
+            TrainingExample {
+                intent: feedback.correct_intent.clone(),
+                text,
+            },
+        );
+    }
+    let mut examples: Vec<TrainingExample> = latest.into_values().collect();
+    examples.sort_by(|a, b| (&a.intent, &a.text).cmp(&(&b.intent, &b.text)));
+
+    training::write_rasa_examples(
+        &state.config.nlu.feedback_path(),
+        &examples,
+        "Reviewed feedback, exported by Barnaby. Changes here are overwritten.",
+    )?;
+    patterns::reload(state)?;
+
+    for feedback in &accepted {
+        sqlx::query("UPDATE intent_feedback SET exported_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(&feedback.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    let mut intents: Vec<&str> = examples.iter().map(|example| example.intent.as_str()).collect();
+    intents.sort_unstable();
+    intents.dedup();
+    info!("Exported {} feedback examples for {} intents", examples.len(), intents.len());
+    Ok(ExportReport {
+        examples: examples.len(),
+        intents: intents.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Settings, test_support};
+
+    async fn feedback(state: &AppState, id: &str, text: &str, intent: &str, status: &str, reviewed_at: &str) {
+        sqlx::query(
+            "INSERT INTO intent_feedback (id, text, correct_intent, status, reviewed_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(text)
+        .bind(intent)
+        .bind(status)
+        .bind(reviewed_at)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    }
+
+    async fn exported(state: &AppState) -> Vec<String> {
+        sqlx::query_scalar("SELECT id FROM intent_feedback WHERE exported_at IS NOT NULL ORDER BY id")
+            .fetch_all(&state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepted_feedback_is_exported_as_rasa_training_data() {
+        let rasa_dir = tempfile::tempdir().unwrap();
+        let mut config = Settings::new().unwrap();
+        config.nlu.rasa_dir = rasa_dir.path().to_str().unwrap().to_string();
+        let state = test_support::state_with(config).await;
+
+        feedback(&state, "a", "Turn   on the lights", "get_weather", "accepted", "2026-01-01 10:00:00").await;
+        feedback(&state, "b", "turn on the lights", "lights_on", "accepted", "2026-01-02 10:00:00").await;
+        feedback(&state, "c", "what time is it [now]", "get_time", "accepted", "2026-01-01 09:00:00").await;
+        feedback(&state, "d", "add milk", "add_to_list", "pending", "2026-01-01 09:00:00").await;
+        feedback(&state, "e", "hello", "get_time", "rejected", "2026-01-01 09:00:00").await;
+
+        let report = export(&state).await.unwrap();
+        assert_eq!((report.examples, report.intents), (2, 2));
+
+        // The latest review of the same text wins, whatever its spacing or case
+        let written = std::fs::read_to_string(state.config.nlu.feedback_path()).unwrap();
+        assert_eq!(
+            written,
+            "# Reviewed feedback, exported by Barnaby. Changes here are overwritten.\n\
+             version: \"3.1\"\n\
+             \n\
+             nlu:\n\
+             - intent: get_time\n  examples: |\n    - what time is it now\n\n\
+             - intent: lights_on\n  examples: |\n    - turn on the lights\n\n"
+        );
+        assert_eq!(exported(&state).await, ["a", "b", "c"]);
+        assert_eq!(state.rust_nlu.read().unwrap().parse("turn on the lights").0.name, "lights_on");
+    }
+
+    #[tokio::test]
+    async fn the_latest_review_wins() {
+        let rasa_dir = tempfile::tempdir().unwrap();
+        let mut config = Settings::new().unwrap();
+        config.nlu.rasa_dir = rasa_dir.path().to_str().unwrap().to_string();
+        let state = test_support::state_with(config).await;
+
+        feedback(&state, "a", "is it cold", "get_weather", "accepted", "2026-01-02 10:00:00").await;
+        feedback(&state, "b", "Is it cold", "get_time", "accepted", "2026-01-01 10:00:00").await;
+        export(&state).await.unwrap();
+        let written = std::fs::read_to_string(state.config.nlu.feedback_path()).unwrap();
+        assert!(written.contains("- intent: get_weather\n  examples: |\n    - is it cold\n"), "{}", written);
+        assert!(!written.contains("get_time"), "{}", written);
+
+        // Reviewing it again later changes the answer
+        sqlx::query("UPDATE intent_feedback SET reviewed_at = '2026-01-03 10:00:00' WHERE id = 'b'")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        export(&state).await.unwrap();
+        let written = std::fs::read_to_string(state.config.nlu.feedback_path()).unwrap();
+        assert!(written.contains("- intent: get_time\n  examples: |\n    - Is it cold\n"), "{}", written);
+        assert!(!written.contains("get_weather"), "{}", written);
+    }
+}
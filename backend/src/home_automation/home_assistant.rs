@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Map, Value};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use super::{DiscoveredDevice, HomeAutomationBackend, StateUpdate};
+use crate::{
+    config::settings::HomeAutomationConfig,
+    devices::{Capability, StateChange},
+};
+
+/// Colour modes that take an RGB colour (`onoff` and `brightness` don't,
+/// `color_temp` and `white` only take shades of white).
+const COLOR_MODES: &[&str] = &["hs", "xy", "rgb", "rgbw", "rgbww"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntityState {
+    pub entity_id: String,
+    pub state: String,
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+}
+
+impl EntityState {
+    fn domain(&self) -> &str {
+        self.entity_id.split('.').next().unwrap_or_default()
+    }
+
+    fn display_name(&self) -> String {
+        self.attributes
+            .get("friendly_name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or(&self.entity_id)
+            .to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AreaEntry {
+    area_id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceEntry {
+    id: String,
+    area_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntityEntry {
+    entity_id: String,
+    area_id: Option<String>,
+    device_id: Option<String>,
+}
+
+/// A Home Assistant service call, e.g. `light.turn_on` with its data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceCall {
+    pub domain: &'static str,
+    pub service: &'static str,
+    pub data: Value,
+}
+
+/// Talks to Home Assistant over its REST API (`/api/states`,
+/// `/api/services`) and WebSocket API (area registry, state changes).
+pub struct HomeAssistantClient {
+    client: Client,
+    base_url: String,
+    token: String,
+    timeout: Duration,
+}
+
+impl HomeAssistantClient {
+    pub fn new(config: &HomeAutomationConfig) -> Result<Self> {
+        let token = config
+            .home_assistant_token
+            .clone()
+            .ok_or_else(|| anyhow!("home_automation.home_assistant_token is not set"))?;
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let client = Client::builder().timeout(timeout).build()?;
+
+        Ok(Self {
+            client,
+            base_url: config.home_assistant_url.trim_end_matches('/').to_string(),
+            token,
+            timeout,
+        })
+    }
+
+    pub async fn states(&self) -> Result<Vec<EntityState>> {
+        let url = format!("{}/api/states", self.base_url);
+        let response = self.client.get(&url).bearer_auth(&self.token).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Home Assistant returned {} for {}", response.status(), url));
+        }
+        Ok(response.json().await?)
+    }
+
+    pub async fn entity(&self, entity_id: &str) -> Result<EntityState> {
+        let url = format!("{}/api/states/{}", self.base_url, entity_id);
+        let response = self.client.get(&url).bearer_auth(&self.token).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Home Assistant returned {} for entity {}", response.status(), entity_id));
+        }
+        Ok(response.json().await?)
+    }
+
+    pub async fn call_service(&self, call: &ServiceCall) -> Result<()> {
+        let url = format!("{}/api/services/{}/{}", self.base_url, call.domain, call.service);
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.token)
+            .json(&call.data)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Home Assistant rejected {}.{}: {}",
+                call.domain,
+                call.service,
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Area names by entity id. Entities without an area of their own are in
+    /// the area of the device they belong to.
+    pub async fn areas(&self) -> Result<HashMap<String, String>> {
+        tokio::time::timeout(self.timeout, async {
+            let mut connection = self.connect().await?;
+            let areas: Vec<AreaEntry> = connection.registry("area").await?;
+            let devices: Vec<DeviceEntry> = connection.registry("device").await?;
+            let entities: Vec<EntityEntry> = connection.registry("entity").await?;
+            Ok(entity_areas(&areas, &devices, &entities))
+        })
+        .await?
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let url = websocket_url(&self.base_url);
+        let (stream, _) = connect_async(url.as_str()).await?;
+        let mut connection = Connection { stream, next_id: 1 };
+
+        // The server asks for auth first and answers with auth_ok or auth_invalid
+        connection.recv().await?;
+        connection
+            .send_raw(json!({ "type": "auth", "access_token": self.token }))
+            .await?;
+        let reply = connection.recv().await?;
+        match reply["type"].as_str() {
+            Some("auth_ok") => Ok(connection),
+            _ => Err(anyhow!(
+                "Home Assistant refused the access token: {}",
+                reply["message"].as_str().unwrap_or("no reason given")
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl HomeAutomationBackend for HomeAssistantClient {
+    fn name(&self) -> &'static str {
+        "home_assistant"
+    }
+
+    /// Entities are put in their area, or their device's area. Barnaby still
+    /// finds the devices when the area registry can't be read, just without
+    /// rooms.
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        let states = self.states().await?;
+        let areas = match self.areas().await {
+            Ok(areas) => areas,
+            Err(e) => {
+                warn!("Failed to read Home Assistant areas: {}", e);
+                HashMap::new()
+            }
+        };
+        Ok(map_entities(&states, &areas))
+    }
+
+    async fn apply(&self, entity_id: &str, change: &StateChange) -> Result<()> {
+        for call in service_calls(entity_id, change) {
+            self.call_service(&call).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_state(&self, entity_id: &str) -> Result<Option<Value>> {
+        Ok(normalize_state(&self.entity(entity_id).await?))
+    }
+
+    async fn subscribe(&self, updates: mpsc::Sender<StateUpdate>) -> Result<()> {
+        let mut connection = tokio::time::timeout(self.timeout, self.connect()).await??;
+        connection
+            .command(json!({ "type": "subscribe_events", "event_type": "state_changed" }))
+            .await?;
+        info!("Following Home Assistant state changes");
+
+        loop {
+            let message = connection.recv().await?;
+            if message["type"] != "event" {
+                continue;
+            }
+            let Some(new_state) = message["event"]["data"].get("new_state").cloned() else {
+                continue;
+            };
+            let Ok(entity) = serde_json::from_value::<EntityState>(new_state) else {
+                continue;
+            };
+            if entity_kind(&entity).is_none() {
+                continue;
+            }
+            if let Some(state) = normalize_state(&entity) {
+                let update = StateUpdate {
+                    external_id: entity.entity_id,
+                    state,
+                };
+                if updates.send(update).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// A WebSocket session. Every command carries an id that its result echoes.
+struct Connection {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl Connection {
+    async fn send_raw(&mut self, message: Value) -> Result<()> {
+        self.stream.send(Message::Text(message.to_string())).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Value> {
+        while let Some(message) = self.stream.next().await {
+            match message? {
+                Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        Err(anyhow!("Home Assistant closed the connection"))
+    }
+
+    async fn command(&mut self, mut message: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        message["id"] = json!(id);
+        self.send_raw(message).await?;
+
+        loop {
+            let reply = self.recv().await?;
+            if reply["type"] != "result" || reply["id"] != id {
+                continue;
+            }
+            if reply["success"] == true {
+                return Ok(reply["result"].clone());
+            }
+            return Err(anyhow!(
+                "Home Assistant command failed: {}",
+                reply["error"]["message"].as_str().unwrap_or("unknown error")
+            ));
+        }
+    }
+
+    async fn registry<T: DeserializeOwned>(&mut self, kind: &str) -> Result<Vec<T>> {
+        let entries = self
+            .command(json!({ "type": format!("config/{}_registry/list", kind) }))
+            .await?;
+        Ok(serde_json::from_value(entries)?)
+    }
+}
+
+fn websocket_url(base_url: &str) -> String {
+    let base = if let Some(rest) = base_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = base_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        base_url.to_string()
+    };
+    format!("{}/api/websocket", base)
+}
+
+fn entity_areas(
+    areas: &[AreaEntry],
+    devices: &[DeviceEntry],
+    entities: &[EntityEntry],
+) -> HashMap<String, String> {
+    let area_names: HashMap<&str, &str> = areas
+        .iter()
+        .map(|area| (area.area_id.as_str(), area.name.as_str()))
+        .collect();
+    let device_areas: HashMap<&str, &str> = devices
+        .iter()
+        .filter_map(|device| Some((device.id.as_str(), device.area_id.as_deref()?)))
+        .collect();
+
+    entities
+        .iter()
+        .filter_map(|entity| {
+            let area_id = entity
+                .area_id
+                .as_deref()
+                .or_else(|| device_areas.get(entity.device_id.as_deref()?).copied())?;
+            Some((entity.entity_id.clone(), area_names.get(area_id)?.to_string()))
+        })
+        .collect()
+}
+
+pub fn map_entities(states: &[EntityState], areas: &HashMap<String, String>) -> Vec<DiscoveredDevice> {
+    states
+        .iter()
+        .filter_map(|entity| {
+            let (device_type, capabilities) = entity_kind(entity)?;
+            Some(DiscoveredDevice {
+                external_id: entity.entity_id.clone(),
+                name: entity.display_name(),
+                room: areas.get(&entity.entity_id).cloned(),
+                device_type: device_type.to_string(),
+                capabilities: capabilities.iter().map(|c| c.as_str().to_string()).collect(),
+                state: normalize_state(entity),
+            })
+        })
+        .collect()
+}
+
+/// Lights get brightness and colour when their supported colour modes allow
+/// it. Climate entities become thermostats.
+fn entity_kind(entity: &EntityState) -> Option<(&'static str, Vec<Capability>)> {
+    match entity.domain() {
+        "light" => {
+            let modes: Vec<&str> = entity
+                .attributes
+                .get("supported_color_modes")
+                .and_then(Value::as_array)
+                .map(|modes| modes.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let mut capabilities = vec![Capability::OnOff];
+            if modes.iter().any(|mode| *mode != "onoff") {
+                capabilities.push(Capability::Brightness);
+            }
+            if modes.iter().any(|mode| COLOR_MODES.contains(mode)) {
+                capabilities.push(Capability::Color);
+            }
+            Some(("light", capabilities))
+        }
+        "switch" => Some(("switch", vec![Capability::OnOff])),
+        "climate" => Some(("thermostat", vec![Capability::OnOff, Capability::Temperature])),
+        _ => None,
+    }
+}
+
+/// Service calls for a change. `light.turn_on` takes brightness and colour in
+/// one go; thermostats are switched on before their target is set.
+pub fn service_calls(entity_id: &str, change: &StateChange) -> Vec<ServiceCall> {
+    let call = |domain, service, data: Value| ServiceCall { domain, service, data };
+    let target = json!({ "entity_id": entity_id });
+
+    match entity_id.split('.').next().unwrap_or_default() {
+        "light" => {
+            if change.on == Some(false) {
+                return vec![call("light", "turn_off", target)];
+            }
+            if change.on.is_none() && change.brightness.is_none() && change.color.is_none() {
+                return Vec::new();
+            }
+            let mut data = target;
+            if let Some(brightness) = change.brightness {
+                data["brightness_pct"] = json!(brightness);
+            }
+            if let Some(rgb) = change.color.as_deref().and_then(hex_to_rgb) {
+                data["rgb_color"] = json!(rgb);
+            }
+            vec![call("light", "turn_on", data)]
+        }
+        "switch" => match change.on {
+            Some(true) => vec![call("switch", "turn_on", target)],
+            Some(false) => vec![call("switch", "turn_off", target)],
+            None => Vec::new(),
+        },
+        "climate" => {
+            let mut calls = Vec::new();
+            match change.on {
+                Some(false) => return vec![call("climate", "turn_off", target)],
+                Some(true) => calls.push(call("climate", "turn_on", target.clone())),
+                None => {}
+            }
+            if let Some(temperature) = change.temperature {
+                let mut data = target;
+                data["temperature"] = json!(temperature);
+                calls.push(call("climate", "set_temperature", data));
+            }
+            calls
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Turns an entity state into Barnaby's device state. Brightness is reported
+/// as 0-255 and becomes a percentage. `unavailable` and `unknown` mean Home
+/// Assistant doesn't know.
+pub fn normalize_state(entity: &EntityState) -> Option<Value> {
+    if entity.state == "unavailable" || entity.state == "unknown" {
+        return None;
+    }
+
+    let attributes = &entity.attributes;
+    let mut normalized = Map::new();
+    match entity.domain() {
+        "light" => {
+            normalized.insert("on".to_string(), json!(entity.state == "on"));
+            if let Some(brightness) = attributes.get("brightness").and_then(Value::as_f64) {
+                let percent = (brightness / 255.0 * 100.0).round().clamp(0.0, 100.0) as u32;
+                normalized.insert("brightness".to_string(), json!(percent));
+            }
+            let rgb: Option<Vec<u8>> = attributes
+                .get("rgb_color")
+                .and_then(|rgb| serde_json::from_value(rgb.clone()).ok());
+            if let Some([r, g, b]) = rgb.as_deref() {
+                normalized.insert("color".to_string(), json!(format!("#{:02x}{:02x}{:02x}", r, g, b)));
+            }
+        }
+        "switch" => {
+            normalized.insert("on".to_string(), json!(entity.state == "on"));
+        }
+        "climate" => {
+            normalized.insert("on".to_string(), json!(entity.state != "off"));
+            if let Some(temperature) = attributes.get("temperature").and_then(Value::as_f64) {
+                normalized.insert("temperature".to_string(), json!(temperature));
+            }
+        }
+        _ => return None,
+    }
+    Some(Value::Object(normalized))
+}
+
+fn hex_to_rgb(hex: &str) -> Option<[u8; 3]> {
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(1)?, channel(3)?, channel(5)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(value: Value) -> EntityState {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn states() -> Vec<EntityState> {
+        [
+            json!({ "entity_id": "light.desk", "state": "on", "attributes": {
+                "friendly_name": "Desk lamp", "supported_color_modes": ["onoff"] } }),
+            json!({ "entity_id": "light.ceiling", "state": "off", "attributes": {
+                "friendly_name": "Ceiling", "supported_color_modes": ["color_temp"] } }),
+            json!({ "entity_id": "light.strip", "state": "on", "attributes": {
+                "friendly_name": " ", "supported_color_modes": ["xy", "color_temp"],
+                "brightness": 128, "rgb_color": [255, 0, 64] } }),
+            json!({ "entity_id": "switch.kettle", "state": "off" }),
+            json!({ "entity_id": "climate.hallway", "state": "heat", "attributes": { "temperature": 20.5 } }),
+            json!({ "entity_id": "sensor.outside", "state": "12.5" }),
+            json!({ "entity_id": "media_player.tv", "state": "playing" }),
+        ]
+        .into_iter()
+        .map(entity)
+        .collect()
+    }
+
+    fn capabilities(device: &DiscoveredDevice) -> Vec<&str> {
+        device.capabilities.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn entities_are_mapped_by_domain() {
+        let areas = HashMap::from([("light.desk".to_string(), "Office".to_string())]);
+        let devices = map_entities(&states(), &areas);
+        let kinds: Vec<(&str, &str)> = devices
+            .iter()
+            .map(|device| (device.external_id.as_str(), device.device_type.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("light.desk", "light"),
+                ("light.ceiling", "light"),
+                ("light.strip", "light"),
+                ("switch.kettle", "switch"),
+                ("climate.hallway", "thermostat"),
+            ]
+        );
+
+        assert_eq!(devices[0].name, "Desk lamp");
+        assert_eq!(devices[0].room.as_deref(), Some("Office"));
+        assert_eq!(devices[1].room, None);
+        // A blank friendly name falls back to the entity id
+        assert_eq!(devices[2].name, "light.strip");
+    }
+
+    #[test]
+    fn light_capabilities_follow_their_colour_modes() {
+        let devices = map_entities(&states(), &HashMap::new());
+        assert_eq!(capabilities(&devices[0]), ["on_off"]);
+        // Shades of white can be dimmed but not coloured
+        assert_eq!(capabilities(&devices[1]), ["on_off", "brightness"]);
+        assert_eq!(capabilities(&devices[2]), ["on_off", "brightness", "color"]);
+        assert_eq!(capabilities(&devices[3]), ["on_off"]);
+        assert_eq!(capabilities(&devices[4]), ["on_off", "temperature"]);
+
+        let no_modes = entity(json!({ "entity_id": "light.old", "state": "on" }));
+        assert_eq!(entity_kind(&no_modes), Some(("light", vec![Capability::OnOff])));
+    }
+
+    #[test]
+    fn states_are_normalized() {
+        let devices = map_entities(&states(), &HashMap::new());
+        let normalized: Vec<Option<Value>> = devices.into_iter().map(|device| device.state).collect();
+        assert_eq!(
+            normalized,
+            [
+                Some(json!({ "on": true })),
+                Some(json!({ "on": false })),
+                Some(json!({ "on": true, "brightness": 50, "color": "#ff0040" })),
+                Some(json!({ "on": false })),
+                Some(json!({ "on": true, "temperature": 20.5 })),
+            ]
+        );
+
+        for unknown in ["unavailable", "unknown"] {
+            let light = entity(json!({ "entity_id": "light.desk", "state": unknown }));
+            assert_eq!(normalize_state(&light), None);
+        }
+        let thermostat = entity(json!({ "entity_id": "climate.loft", "state": "off" }));
+        assert_eq!(normalize_state(&thermostat), Some(json!({ "on": false })));
+        let sensor = entity(json!({ "entity_id": "sensor.outside", "state": "12.5" }));
+        assert_eq!(normalize_state(&sensor), None);
+    }
+
+    #[test]
+    fn light_changes_go_in_one_service_call() {
+        let change = StateChange {
+            on: Some(true),
+            brightness: Some(40),
+            color: Some("#ff8000".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            service_calls("light.strip", &change),
+            [ServiceCall {
+                domain: "light",
+                service: "turn_on",
+                data: json!({ "entity_id": "light.strip", "brightness_pct": 40, "rgb_color": [255, 128, 0] }),
+            }]
+        );
+
+        // Turning off ignores the rest, and dimming alone turns the light on
+        let off = StateChange {
+            on: Some(false),
+            brightness: Some(40),
+            ..Default::default()
+        };
+        assert_eq!(service_calls("light.strip", &off)[0].service, "turn_off");
+        let dim = StateChange {
+            brightness: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            service_calls("light.strip", &dim)[0].data,
+            json!({ "entity_id": "light.strip", "brightness_pct": 10 })
+        );
+        assert!(service_calls("light.strip", &StateChange::default()).is_empty());
+    }
+
+    #[test]
+    fn switch_and_thermostat_service_calls() {
+        let on = StateChange {
+            on: Some(true),
+            ..Default::default()
+        };
+        let calls = service_calls("switch.kettle", &on);
+        assert_eq!((calls[0].domain, calls[0].service), ("switch", "turn_on"));
+
+        let heat = StateChange {
+            on: Some(true),
+            temperature: Some(21.0),
+            ..Default::default()
+        };
+        let calls: Vec<(&str, &str, Value)> = service_calls("climate.hallway", &heat)
+            .into_iter()
+            .map(|call| (call.domain, call.service, call.data))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("climate", "turn_on", json!({ "entity_id": "climate.hallway" })),
+                ("climate", "set_temperature", json!({ "entity_id": "climate.hallway", "temperature": 21.0 })),
+            ]
+        );
+
+        assert!(service_calls("switch.kettle", &StateChange::default()).is_empty());
+        assert!(service_calls("sensor.outside", &on).is_empty());
+    }
+
+    #[test]
+    fn hex_colours_become_rgb() {
+        assert_eq!(hex_to_rgb("#ff8000"), Some([255, 128, 0]));
+        assert_eq!(hex_to_rgb("#FFFFFF"), Some([255, 255, 255]));
+        assert_eq!(hex_to_rgb("#fff"), None);
+        assert_eq!(hex_to_rgb("#gg0000"), None);
+    }
+
+    #[test]
+    fn the_websocket_url_follows_the_http_scheme() {
+        assert_eq!(websocket_url("http://ha.local:8123"), "ws://ha.local:8123/api/websocket");
+        assert_eq!(websocket_url("https://ha.example.com"), "wss://ha.example.com/api/websocket");
+        assert_eq!(websocket_url("ws://ha.local:8123"), "ws://ha.local:8123/api/websocket");
+    }
+
+    #[test]
+    fn entities_take_their_devices_area_unless_they_have_their_own() {
+        let areas = [
+            AreaEntry { area_id: "kitchen".to_string(), name: "Kitchen".to_string() },
+            AreaEntry { area_id: "hall".to_string(), name: "Hall".to_string() },
+        ];
+        let devices = [DeviceEntry { id: "hub".to_string(), area_id: Some("kitchen".to_string()) }];
+        let entry = |entity_id: &str, area_id: Option<&str>, device_id: Option<&str>| EntityEntry {
+            entity_id: entity_id.to_string(),
+            area_id: area_id.map(str::to_string),
+            device_id: device_id.map(str::to_string),
+        };
+        let entities = [
+            entry("light.hob", None, Some("hub")),
+            entry("light.porch", Some("hall"), Some("hub")),
+            entry("switch.loose", None, None),
+            entry("switch.gone", Some("garage"), None),
+        ];
+        let found = entity_areas(&areas, &devices, &entities);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["light.hob"], "Kitchen");
+        assert_eq!(found["light.porch"], "Hall");
+    }
+}
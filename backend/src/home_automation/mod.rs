@@ -0,0 +1,252 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::types::Json;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::settings::HomeAutomationConfig,
+    devices::{self, StateChange},
+    events::Event,
+    AppState,
+};
+
+pub mod home_assistant;
+pub mod openhab;
+
+pub use home_assistant::HomeAssistantClient;
+pub use openhab::OpenHabClient;
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
+/// A home automation system whose devices Barnaby imports and controls.
+#[async_trait]
+pub trait HomeAutomationBackend: Send + Sync {
+    /// Stored in `devices.backend` for every device the backend owns.
+    fn name(&self) -> &'static str;
+
+    /// Controllable things, each mapped to a device type, its capabilities and
+    /// the room it is in.
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>>;
+
+    async fn apply(&self, external_id: &str, change: &StateChange) -> Result<()>;
+
+    async fn read_state(&self, external_id: &str) -> Result<Option<Value>>;
+
+    /// Forwards state changes until the connection drops. Backends that can't
+    /// push changes never return and are kept up to date by the periodic sync.
+    async fn subscribe(&self, _updates: mpsc::Sender<StateUpdate>) -> Result<()> {
+        std::future::pending().await
+    }
+}
+
+/// Builds the backend selected by `home_automation.backend`.
+pub fn create_backend(config: &HomeAutomationConfig) -> Result<Arc<dyn HomeAutomationBackend>> {
+    match config.backend.as_str() {
+        "openhab" => Ok(Arc::new(OpenHabClient::new(config)?)),
+        "home_assistant" => Ok(Arc::new(HomeAssistantClient::new(config)?)),
+        other => Err(anyhow!("Unknown home automation backend: {}", other)),
+    }
+}
+
+/// A controllable thing found in the home automation system.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredDevice {
+    pub external_id: String,
+    pub name: String,
+    pub room: Option<String>,
+    pub device_type: String,
+    pub capabilities: Vec<String>,
+    pub state: Option<Value>,
+}
+
+/// New state reported for a device, keyed by its id in the backend.
+#[derive(Debug, Clone)]
+pub struct StateUpdate {
+    pub external_id: String,
+    pub state: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub devices: usize,
+    pub rooms_created: usize,
+    pub devices_removed: usize,
+}
+
+/// Imports everything the backend knows into Barnaby's rooms and devices.
+///
+/// Rooms are matched by name and created when missing. A device keeps the
+/// room it was given in Barnaby if the backend doesn't put it in one.
+/// Devices that disappeared from the backend are removed.
+pub async fn sync(state: &AppState) -> Result<SyncReport> {
+    let Some(backend) = &state.home_automation else {
+        return Ok(SyncReport::default());
+    };
+
+    let discovered = backend.discover().await?;
+    let mut report = SyncReport {
+        devices: discovered.len(),
+        ..Default::default()
+    };
+
+    let mut changed = Vec::new();
+    let mut tx = state.db.begin().await?;
+    for device in &discovered {
+        let room_id = match &device.room {
+            Some(name) => {
+                let existing: Option<String> = sqlx::query_scalar("SELECT id FROM rooms WHERE name = ?")
+                    .bind(name)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                match existing {
+                    Some(id) => Some(id),
+                    None => {
+                        let id = Uuid::new_v4().to_string();
+                        sqlx::query("INSERT INTO rooms (id, name) VALUES (?, ?)")
+                            .bind(&id)
+                            .bind(name)
+                            .execute(&mut *tx)
+                            .await?;
+                        report.rooms_created += 1;
+                        Some(id)
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let existing: Option<(String, Option<Json<Value>>)> =
+            sqlx::query_as("SELECT id, state FROM devices WHERE backend = ? AND external_id = ?")
+                .bind(backend.name())
+                .bind(&device.external_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let (device_id, previous_state) = match existing {
+            Some((id, state)) => (id, state.map(|Json(state)| state)),
+            None => (Uuid::new_v4().to_string(), None),
+        };
+        let new_state = device
+            .state
+            .as_ref()
+            .filter(|current| previous_state.as_ref() != Some(*current));
+        if let Some(current) = new_state {
+            changed.push((device_id.clone(), current.clone()));
+        }
+
+        sqlx::query(
+            "INSERT INTO devices (id, name, room_id, device_type, capabilities, backend, external_id, state, state_updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(backend, external_id) DO UPDATE SET
+                 name = excluded.name,
+                 room_id = COALESCE(excluded.room_id, devices.room_id),
+                 device_type = excluded.device_type,
+                 capabilities = excluded.capabilities,
+                 state = COALESCE(excluded.state, devices.state),
+                 state_updated_at = COALESCE(excluded.state_updated_at, devices.state_updated_at)",
+        )
+        .bind(&device_id)
+        .bind(&device.name)
+        .bind(&room_id)
+        .bind(&device.device_type)
+        .bind(Json(&device.capabilities))
+        .bind(backend.name())
+        .bind(&device.external_id)
+        .bind(device.state.as_ref().map(Json))
+        .bind(new_state.map(|_| chrono::Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let known: Vec<String> = sqlx::query_scalar("SELECT external_id FROM devices WHERE backend = ?")
+        .bind(backend.name())
+        .fetch_all(&mut *tx)
+        .await?;
+    for external_id in known {
+        if !discovered.iter().any(|device| device.external_id == external_id) {
+            sqlx::query("DELETE FROM devices WHERE backend = ? AND external_id = ?")
+                .bind(backend.name())
+                .bind(&external_id)
+                .execute(&mut *tx)
+                .await?;
+            report.devices_removed += 1;
+        }
+    }
+    tx.commit().await?;
+
+    for (device_id, state_value) in changed {
+        state.events.publish(Event::DeviceState {
+            device_id,
+            state: state_value,
+        });
+    }
+
+    info!(
+        "Synced {} devices from {} ({} new rooms, {} removed)",
+        report.devices,
+        backend.name(),
+        report.rooms_created,
+        report.devices_removed
+    );
+    Ok(report)
+}
+
+/// Keeps devices and their state in step with the backend. State changes are
+/// published as `device_state` events.
+pub async fn watch(state: AppState) {
+    let Some(backend) = state.home_automation.clone() else {
+        return;
+    };
+    tokio::spawn(follow_state_changes(state.clone(), backend.clone()));
+
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        state.config.home_automation.sync_interval_secs,
+    ));
+    loop {
+        interval.tick().await;
+        if let Err(e) = sync(&state).await {
+            warn!("Failed to sync with {}: {}", backend.name(), e);
+        }
+    }
+}
+
+/// Records pushed state changes as they arrive, resubscribing whenever the
+/// backend drops the connection.
+async fn follow_state_changes(state: AppState, backend: Arc<dyn HomeAutomationBackend>) {
+    loop {
+        let (tx, mut rx) = mpsc::channel(64);
+        let record = async {
+            while let Some(update) = rx.recv().await {
+                if let Err(e) = record_update(&state, backend.name(), &update).await {
+                    warn!("Failed to record state of {}: {}", update.external_id, e);
+                }
+            }
+        };
+
+        let (result, ()) = tokio::join!(backend.subscribe(tx), record);
+        match result {
+            Ok(()) => info!("{} closed the state subscription", backend.name()),
+            Err(e) => warn!("Lost state subscription to {}: {}", backend.name(), e),
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn record_update(state: &AppState, backend: &str, update: &StateUpdate) -> Result<(), sqlx::Error> {
+    let device_id: Option<String> =
+        sqlx::query_scalar("SELECT id FROM devices WHERE backend = ? AND external_id = ?")
+            .bind(backend)
+            .bind(&update.external_id)
+            .fetch_optional(&state.db)
+            .await?;
+
+    match device_id {
+        Some(device_id) => devices::record_state(state, &device_id, &update.state).await,
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::{DiscoveredDevice, HomeAutomationBackend};
+use crate::{
+    config::settings::HomeAutomationConfig,
+    devices::{Capability, StateChange},
+};
+
+/// Semantic model tags that mark a Group item as a room.
+const LOCATION_TAGS: &[&str] = &[
+    "Location", "Indoor", "Room", "Apartment", "Floor", "Bathroom", "Bedroom", "BoilerRoom", "Cellar",
+    "DiningRoom", "Entry", "FamilyRoom", "GuestRoom", "Kitchen", "LaundryRoom", "LivingRoom", "Office",
+    "Veranda", "Garage", "Garden", "Terrace",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub name: String,
+    pub label: Option<String>,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub state: Option<String>,
+    #[serde(default)]
+    pub group_names: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Item {
+    fn is_location(&self) -> bool {
+        self.item_type == "Group" && self.tags.iter().any(|tag| LOCATION_TAGS.contains(&tag.as_str()))
+    }
+
+    fn display_name(&self) -> String {
+        self.label
+            .as_deref()
+            .filter(|label| !label.trim().is_empty())
+            .unwrap_or(&self.name)
+            .to_string()
+    }
+}
+
+/// Talks to the OpenHAB REST API (`/rest/items`).
+pub struct OpenHabClient {
+    client: Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl OpenHabClient {
+    pub fn new(config: &HomeAutomationConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: config.openhab_url.trim_end_matches('/').to_string(),
+            token: config.openhab_token.clone(),
+        })
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    pub async fn items(&self) -> Result<Vec<Item>> {
+        let url = format!("{}/rest/items?recursive=false", self.base_url);
+        let response = self.request(self.client.get(&url)).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("OpenHAB returned {} for {}", response.status(), url));
+        }
+        Ok(response.json().await?)
+    }
+
+    pub async fn item(&self, name: &str) -> Result<Item> {
+        let url = format!("{}/rest/items/{}", self.base_url, name);
+        let response = self.request(self.client.get(&url)).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("OpenHAB returned {} for item {}", response.status(), name));
+        }
+        Ok(response.json().await?)
+    }
+
+    pub async fn send_command(&self, item: &str, command: &str) -> Result<()> {
+        let url = format!("{}/rest/items/{}", self.base_url, item);
+        let response = self
+            .request(self.client.post(&url))
+            .header("Content-Type", "text/plain")
+            .body(command.to_string())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("OpenHAB rejected {} for item {}: {}", command, item, response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HomeAutomationBackend for OpenHabClient {
+    fn name(&self) -> &'static str {
+        "openhab"
+    }
+
+    /// Items are put in the room whose location group they belong to.
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        Ok(map_items(&self.items().await?))
+    }
+
+    /// Sends a state change to an item, as the one command its type expects.
+    async fn apply(&self, item: &str, change: &StateChange) -> Result<()> {
+        for command in commands_for(change) {
+            self.send_command(item, &command).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_state(&self, name: &str) -> Result<Option<Value>> {
+        let item = self.item(name).await?;
+        Ok(item
+            .state
+            .as_deref()
+            .and_then(|state| normalize_state(&item.item_type, state)))
+    }
+}
+
+pub fn map_items(items: &[Item]) -> Vec<DiscoveredDevice> {
+    let rooms: HashMap<&str, String> = items
+        .iter()
+        .filter(|item| item.is_location())
+        .map(|item| (item.name.as_str(), item.display_name()))
+        .collect();
+
+    items
+        .iter()
+        .filter_map(|item| {
+            let (device_type, capabilities) = device_kind(item)?;
+            Some(DiscoveredDevice {
+                external_id: item.name.clone(),
+                name: item.display_name(),
+                room: item
+                    .group_names
+                    .iter()
+                    .find_map(|group| rooms.get(group.as_str()).cloned()),
+                device_type: device_type.to_string(),
+                capabilities: capabilities.iter().map(|c| c.as_str().to_string()).collect(),
+                state: item
+                    .state
+                    .as_deref()
+                    .and_then(|state| normalize_state(&item.item_type, state)),
+            })
+        })
+        .collect()
+}
+
+/// Switches are lights only when tagged as such; dimmers and colour items
+/// always are.
+fn device_kind(item: &Item) -> Option<(&'static str, &'static [Capability])> {
+    let is_light = item.tags.iter().any(|tag| tag == "Light" || tag == "Lightbulb");
+    match item.item_type.as_str() {
+        "Switch" if is_light => Some(("light", &[Capability::OnOff])),
+        "Switch" => Some(("switch", &[Capability::OnOff])),
+        "Dimmer" => Some(("light", &[Capability::OnOff, Capability::Brightness])),
+        "Color" => Some(("light", &[Capability::OnOff, Capability::Brightness, Capability::Color])),
+        _ => None,
+    }
+}
+
+/// OpenHAB commands for a change. Colour and brightness commands switch the
+/// item on by themselves, so `ON` is only sent when nothing else is.
+pub fn commands_for(change: &StateChange) -> Vec<String> {
+    if change.on == Some(false) {
+        return vec!["OFF".to_string()];
+    }
+
+    if let Some((hue, saturation, value)) = change.color.as_deref().and_then(hex_to_hsb) {
+        let brightness = change.brightness.map(u32::from).unwrap_or(value);
+        return vec![format!("{},{},{}", hue, saturation, brightness)];
+    }
+
+    match (change.brightness, change.on) {
+        (Some(brightness), _) => vec![brightness.to_string()],
+        (None, Some(true)) => vec!["ON".to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Turns an item state such as `ON`, `42` or `120,100,50` into Barnaby's
+/// device state. `NULL` and `UNDEF` mean OpenHAB doesn't know.
+pub fn normalize_state(item_type: &str, state: &str) -> Option<Value> {
+    let state = state.trim();
+    if state == "NULL" || state == "UNDEF" {
+        return None;
+    }
+
+    let mut normalized = Map::new();
+    match item_type {
+        "Switch" => {
+            normalized.insert("on".to_string(), json!(state == "ON"));
+        }
+        "Dimmer" => {
+            let brightness = parse_percent(state)?;
+            normalized.insert("on".to_string(), json!(brightness > 0));
+            normalized.insert("brightness".to_string(), json!(brightness));
+        }
+        "Color" => {
+            let parts: Vec<f64> = state.split(',').filter_map(|part| part.trim().parse().ok()).collect();
+            let [hue, saturation, brightness] = parts[..] else {
+                return None;
+            };
+            normalized.insert("on".to_string(), json!(brightness > 0.0));
+            normalized.insert("brightness".to_string(), json!(brightness.round() as u32));
+            normalized.insert("color".to_string(), json!(hsb_to_hex(hue, saturation, 100.0)));
+        }
+        _ => return None,
+    }
+    Some(Value::Object(normalized))
+}
+
+fn parse_percent(state: &str) -> Option<u32> {
+    state.parse::<f64>().ok().map(|value| value.round().clamp(0.0, 100.0) as u32)
+}
+
+/// `#rrggbb` to hue (0-360), saturation and brightness (0-100).
+pub fn hex_to_hsb(hex: &str) -> Option<(u32, u32, u32)> {
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|c| c as f64 / 255.0);
+    let (r, g, b) = (channel(1)?, channel(3)?, channel(5)?);
+
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta).rem_euclid(6.0))
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    Some((
+        hue.round() as u32 % 360,
+        (saturation * 100.0).round() as u32,
+        (max * 100.0).round() as u32,
+    ))
+}
+
+pub fn hsb_to_hex(hue: f64, saturation: f64, brightness: f64) -> String {
+    let (s, v) = (saturation / 100.0, brightness / 100.0);
+    let c = v * s;
+    let x = c * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue.rem_euclid(360.0) / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let to_byte = |channel: f64| ((channel + m) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", to_byte(r), to_byte(g), to_byte(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::{Path, State},
+        http::{HeaderMap, StatusCode},
+        routing::get,
+        Json, Router,
+    };
+
+    use super::*;
+    use crate::config::Settings;
+
+    /// Commands the mock server received: item, body and authorization.
+    type Commands = Arc<Mutex<Vec<(String, String, Option<String>)>>>;
+
+    fn items() -> Value {
+        json!([
+            { "name": "LivingRoom", "label": "Living Room", "type": "Group", "tags": ["LivingRoom"] },
+            { "name": "Lamp", "label": "Floor lamp", "type": "Dimmer", "state": "40", "groupNames": ["LivingRoom"] },
+            { "name": "Ceiling", "label": " ", "type": "Switch", "state": "NULL", "tags": ["Lightbulb"], "groupNames": ["LivingRoom"] },
+            { "name": "Fan", "type": "Switch", "state": "ON", "groupNames": ["Appliances"] },
+            { "name": "Bulb", "label": "Bulb", "type": "Color", "state": "120,100,50" },
+            { "name": "Outside", "type": "Number", "state": "12.5" },
+            { "name": "Appliances", "type": "Group", "tags": ["Equipment"] },
+        ])
+    }
+
+    /// Serves `/rest/items` from [`items`] on a free port. Commands for
+    /// `Broken` fail with a 500.
+    async fn start_openhab() -> (OpenHabClient, Commands) {
+        async fn item(Path(name): Path<String>) -> Result<Json<Value>, StatusCode> {
+            let items = items();
+            let item = items.as_array().unwrap().iter().find(|item| item["name"] == name.as_str());
+            item.cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+        }
+
+        async fn command(
+            State(commands): State<Commands>,
+            Path(name): Path<String>,
+            headers: HeaderMap,
+            body: String,
+        ) -> StatusCode {
+            if name == "Broken" {
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            let auth = headers
+                .get("authorization")
+                .map(|value| value.to_str().unwrap().to_string());
+            commands.lock().unwrap().push((name, body, auth));
+            StatusCode::OK
+        }
+
+        let commands = Commands::default();
+        let app = Router::new()
+            .route("/rest/items", get(|| async { Json(items()) }))
+            .route("/rest/items/:name", get(item).post(command))
+            .with_state(commands.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = Settings::new().unwrap().home_automation;
+        config.openhab_url = format!("http://{}/", address);
+        config.openhab_token = Some("token".to_string());
+        (OpenHabClient::new(&config).unwrap(), commands)
+    }
+
+    #[tokio::test]
+    async fn items_are_discovered_with_their_room_and_type() {
+        let (openhab, _) = start_openhab().await;
+        let devices = openhab.discover().await.unwrap();
+        let names: Vec<&str> = devices.iter().map(|device| device.external_id.as_str()).collect();
+        assert_eq!(names, ["Lamp", "Ceiling", "Fan", "Bulb"]);
+
+        let lamp = &devices[0];
+        assert_eq!(lamp.name, "Floor lamp");
+        assert_eq!(lamp.room.as_deref(), Some("Living Room"));
+        assert_eq!(lamp.device_type, "light");
+        assert_eq!(lamp.capabilities, ["on_off", "brightness"]);
+        assert_eq!(lamp.state, Some(json!({ "on": true, "brightness": 40 })));
+
+        // A blank label falls back to the item name, and NULL is no state
+        let ceiling = &devices[1];
+        assert_eq!(ceiling.name, "Ceiling");
+        assert_eq!(ceiling.device_type, "light");
+        assert_eq!(ceiling.state, None);
+
+        // Equipment groups aren't rooms, and untagged switches aren't lights
+        let fan = &devices[2];
+        assert_eq!(fan.room, None);
+        assert_eq!(fan.device_type, "switch");
+        assert_eq!(fan.capabilities, ["on_off"]);
+
+        let bulb = &devices[3];
+        assert_eq!(bulb.capabilities, ["on_off", "brightness", "color"]);
+        assert_eq!(bulb.state, Some(json!({ "on": true, "brightness": 50, "color": "#00ff00" })));
+    }
+
+    #[tokio::test]
+    async fn state_changes_are_sent_as_commands() {
+        let (openhab, commands) = start_openhab().await;
+        let change = StateChange {
+            on: Some(true),
+            brightness: Some(30),
+            color: Some("#ff0000".to_string()),
+            ..Default::default()
+        };
+        openhab.apply("Bulb", &change).await.unwrap();
+        let off = StateChange {
+            on: Some(false),
+            ..Default::default()
+        };
+        openhab.apply("Fan", &off).await.unwrap();
+
+        assert_eq!(
+            *commands.lock().unwrap(),
+            [
+                ("Bulb".to_string(), "0,100,30".to_string(), Some("Bearer token".to_string())),
+                ("Fan".to_string(), "OFF".to_string(), Some("Bearer token".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn item_state_is_read() {
+        let (openhab, _) = start_openhab().await;
+        assert_eq!(openhab.read_state("Fan").await.unwrap(), Some(json!({ "on": true })));
+        assert_eq!(openhab.read_state("Ceiling").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn missing_items_are_an_error() {
+        let (openhab, _) = start_openhab().await;
+        let error = openhab.read_state("Missing").await.unwrap_err().to_string();
+        assert!(error.contains("404"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejected_commands_are_an_error() {
+        let (openhab, commands) = start_openhab().await;
+        let change = StateChange {
+            on: Some(true),
+            ..Default::default()
+        };
+        let error = openhab.apply("Broken", &change).await.unwrap_err().to_string();
+        assert!(error.contains("ON") && error.contains("500"), "{}", error);
+        assert!(commands.lock().unwrap().is_empty());
+    }
+}
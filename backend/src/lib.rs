@@ -0,0 +1,50 @@
+pub mod api;
+pub mod audio;
+pub mod auth;
+pub mod config;
+pub mod database;
+pub mod devices;
+pub mod events;
+pub mod feedback;
+pub mod home_automation;
+pub mod lists;
+pub mod location;
+pub mod middleware;
+pub mod mqtt;
+pub mod nlu;
+pub mod pipeline;
+pub mod reminders;
+pub mod services;
+pub mod skills;
+pub mod timers;
+
+use sqlx::SqlitePool;
+use std::sync::{Arc, RwLock};
+
+use audio::{SttEngine, TtsEngine};
+use config::Settings;
+use events::EventBus;
+use home_automation::HomeAutomationBackend;
+use mqtt::MqttService;
+use nlu::{NluPipeline, RasaManager, RustNlu};
+use services::weather::WeatherService;
+use skills::SkillRegistry;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: SqlitePool,
+    pub config: Settings,
+    pub mqtt: Option<MqttService>,
+    pub nlu: Arc<NluPipeline>,
+    pub events: EventBus,
+    pub skills: Arc<SkillRegistry>,
+    pub stt: Option<Arc<dyn SttEngine>>,
+    pub tts: Option<Arc<dyn TtsEngine>>,
+    pub home_automation: Option<Arc<dyn HomeAutomationBackend>>,
+    pub weather: Arc<WeatherService>,
+    pub rust_nlu: Arc<RwLock<RustNlu>>,
+    pub rasa: Arc<tokio::sync::Mutex<RasaManager>>,
+}
+
+#[cfg(test)]
+mod test_support;
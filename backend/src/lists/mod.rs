@@ -0,0 +1,347 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    auth::jwt::Claims,
+    database::models::{List, ListItem},
+    events::Event,
+    nlu::datetime::{parse_number, NUMBER_PATTERN},
+    AppState,
+};
+
+pub const LIST_COLUMNS: &str = "id, name, user_id, created_at, updated_at";
+pub const ITEM_COLUMNS: &str = "id, list_id, name, quantity, unit, checked, added_by, created_at, updated_at";
+
+/// Where items go when the command doesn't name a list.
+pub const DEFAULT_LIST: &str = "shopping";
+
+/// Spoken units and the name they are stored under.
+const UNITS: &[(&str, &str)] = &[
+    ("g|grams?|grammes?", "gram"),
+    ("kg|kilos?|kilograms?", "kilo"),
+    ("ml|millilitres?|milliliters?", "millilitre"),
+    ("l|litres?|liters?", "litre"),
+    ("pints?", "pint"),
+    ("bottles?", "bottle"),
+    ("cans?", "can"),
+    ("packs?|packets?", "pack"),
+    ("bags?", "bag"),
+    ("box(?:es)?", "box"),
+    ("loaf|loaves", "loaf"),
+    ("jars?", "jar"),
+    ("tins?", "tin"),
+    ("bunch(?:es)?", "bunch"),
+    ("cartons?", "carton"),
+];
+
+static FILLER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:some|the|more)\s+").unwrap());
+static COUPLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:a\s+)?couple\s+of\b").unwrap());
+
+/// Number, dozen, unit, "of" and the name.
+static ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    let units: Vec<&str> = UNITS.iter().map(|(pattern, _)| *pattern).collect();
+    Regex::new(&format!(
+        r"^(?:({})\s*)?(dozen\s+)?(?:({})\s+|\b)(of\s+)?(.+)$",
+        NUMBER_PATTERN,
+        units.join("|")
+    ))
+    .unwrap()
+});
+
+static UNIT_NAMES: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    UNITS
+        .iter()
+        .map(|(pattern, unit)| (Regex::new(&format!("^(?:{})$", pattern)).unwrap(), *unit))
+        .collect()
+});
+
+/// An item as said: "2 litres of milk", "a dozen eggs", "bread".
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedItem {
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+}
+
+impl ParsedItem {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        let text = FILLER.replace(&text, "");
+        let text = COUPLE.replace(&text, "2").replace("half a dozen", "6");
+
+        let captures = ITEM.captures(&text)?;
+
+        let name = captures[5].trim().to_string();
+        if name.is_empty() {
+            return None;
+        }
+        // "tin foil" is not a tin of foil
+        let counted = captures.get(1).is_some() || captures.get(2).is_some();
+        if !counted && captures.get(4).is_none() {
+            return Some(Self {
+                name: text.trim().to_string(),
+                quantity: None,
+                unit: None,
+            });
+        }
+        let unit = captures.get(3).and_then(|said| {
+            UNIT_NAMES
+                .iter()
+                .find(|(pattern, _)| pattern.is_match(said.as_str()))
+                .map(|(_, unit)| unit.to_string())
+        });
+        let mut quantity = captures.get(1).and_then(|number| parse_number(number.as_str()));
+        if captures.get(2).is_some() {
+            quantity = Some(quantity.unwrap_or(1.0) * 12.0);
+        }
+        // "an apple" is just an apple, but "a bag of ice" is one bag
+        if unit.is_none() && captures.get(2).is_none() && matches!(captures.get(1).map(|m| m.as_str()), Some("a" | "an"))
+        {
+            quantity = None;
+        }
+        if unit.is_some() && quantity.is_none() {
+            quantity = Some(1.0);
+        }
+
+        Some(Self { name, quantity, unit })
+    }
+}
+
+/// What makes two items the same one: "Eggs" and "egg", "tomatoes" and "tomato".
+pub fn item_key(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if ["ches", "shes", "xes", "sses", "oes"].iter().any(|suffix| name.ends_with(suffix)) {
+        name[..name.len() - 2].to_string()
+    } else if name.ends_with('s') && !name.ends_with("ss") {
+        name[..name.len() - 1].to_string()
+    } else {
+        name
+    }
+}
+
+/// "2 litres of milk", "12 eggs", "bread", the way Barnaby reads an item out.
+pub fn describe_item(item: &ListItem) -> String {
+    let Some(quantity) = item.quantity else {
+        return item.name.clone();
+    };
+    let count = if quantity.fract() == 0.0 {
+        format!("{}", quantity as i64)
+    } else {
+        format!("{}", quantity)
+    };
+
+    match item.unit.as_deref() {
+        Some(unit) => {
+            let unit = if quantity == 1.0 {
+                unit.to_string()
+            } else {
+                match unit {
+                    "loaf" => "loaves".to_string(),
+                    "box" | "bunch" => format!("{}es", unit),
+                    _ => format!("{}s", unit),
+                }
+            };
+            format!("{} {} of {}", count, unit, item.name)
+        }
+        None => format!("{} {}", count, item.name),
+    }
+}
+
+/// "shopping", "to-do", "christmas": the name a list is kept under.
+pub fn normalize_list_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let name = name.strip_suffix(" list").unwrap_or(&name).trim();
+    match name {
+        "todo" | "to do" | "to-do" | "to dos" | "to-dos" | "todos" => "to-do".to_string(),
+        "grocery" | "groceries" => DEFAULT_LIST.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Who can see a list: everyone for household lists, the owner (and
+/// admins) for personal ones.
+pub fn can_access(list: &List, claims: &Claims) -> bool {
+    claims.role == "admin" || list.user_id.as_deref().is_none_or(|owner| owner == claims.sub)
+}
+
+/// The list a command means by `name`: the user's own list of that name
+/// if they have one, otherwise the household's.
+pub async fn find_by_name(state: &AppState, name: &str, user_id: Option<&str>) -> Result<Option<List>, sqlx::Error> {
+    sqlx::query_as::<_, List>(&format!(
+        "SELECT {} FROM lists WHERE name = ? AND (user_id IS NULL OR user_id = ?)
+         ORDER BY user_id IS NULL LIMIT 1",
+        LIST_COLUMNS
+    ))
+    .bind(normalize_list_name(name))
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await
+}
+
+pub async fn find_list(state: &AppState, list_id: &str) -> Result<Option<List>, sqlx::Error> {
+    sqlx::query_as::<_, List>(&format!("SELECT {} FROM lists WHERE id = ?", LIST_COLUMNS))
+        .bind(list_id)
+        .fetch_optional(&state.db)
+        .await
+}
+
+/// Creates a list, for the household when there is no `user_id`.
+pub async fn create_list(state: &AppState, name: &str, user_id: Option<&str>) -> Result<List, sqlx::Error> {
+    let list = sqlx::query_as::<_, List>(&format!(
+        "INSERT INTO lists (id, name, user_id) VALUES (?, ?, ?) RETURNING {}",
+        LIST_COLUMNS
+    ))
+    .bind(Uuid::new_v4().to_string())
+    .bind(normalize_list_name(name))
+    .bind(user_id)
+    .fetch_one(&state.db)
+    .await?;
+
+    info!("Created list '{}' ({})", list.name, list.id);
+    Ok(list)
+}
+
+/// Items on a list, unticked first, oldest first.
+pub async fn items(state: &AppState, list_id: &str) -> Result<Vec<ListItem>, sqlx::Error> {
+    sqlx::query_as::<_, ListItem>(&format!(
+        "SELECT {} FROM list_items WHERE list_id = ? ORDER BY checked, created_at",
+        ITEM_COLUMNS
+    ))
+    .bind(list_id)
+    .fetch_all(&state.db)
+    .await
+}
+
+/// An item once it is on the list, and whether it was already there.
+#[derive(Debug)]
+pub struct AddedItem {
+    pub item: ListItem,
+    pub merged: bool,
+}
+
+/// Puts an item on the list. An item that is already on it is merged: the
+/// quantities are added up when they are in the same unit, and one that
+/// was ticked off goes back on with the new quantity.
+pub async fn add_item(
+    state: &AppState,
+    list: &List,
+    parsed: &ParsedItem,
+    added_by: Option<&str>,
+) -> Result<AddedItem, sqlx::Error> {
+    let key = item_key(&parsed.name);
+    let existing = items(state, &list.id)
+        .await?
+        .into_iter()
+        .find(|item| item_key(&item.name) == key && item.unit == parsed.unit);
+
+    let (item, merged) = match existing {
+        Some(existing) => {
+            let quantity = match (existing.checked, existing.quantity, parsed.quantity) {
+                (true, _, quantity) => quantity,
+                (false, None, None) => None,
+                // "milk" then "2 milk" is three
+                (false, had, adding) => Some(had.unwrap_or(1.0) + adding.unwrap_or(1.0)),
+            };
+            let item = sqlx::query_as::<_, ListItem>(&format!(
+                "UPDATE list_items SET quantity = ?, checked = FALSE, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ? RETURNING {}",
+                ITEM_COLUMNS
+            ))
+            .bind(quantity)
+            .bind(&existing.id)
+            .fetch_one(&state.db)
+            .await?;
+            (item, !existing.checked)
+        }
+        None => {
+            let item = sqlx::query_as::<_, ListItem>(&format!(
+                "INSERT INTO list_items (id, list_id, name, quantity, unit, added_by) VALUES (?, ?, ?, ?, ?, ?) RETURNING {}",
+                ITEM_COLUMNS
+            ))
+            .bind(Uuid::new_v4().to_string())
+            .bind(&list.id)
+            .bind(&parsed.name)
+            .bind(parsed.quantity)
+            .bind(&parsed.unit)
+            .bind(added_by)
+            .fetch_one(&state.db)
+            .await?;
+            (item, false)
+        }
+    };
+
+    touch(state, list).await?;
+    Ok(AddedItem { item, merged })
+}
+
+/// Ticks off the unticked item called `name`. `None` when there is none.
+pub async fn check_item(state: &AppState, list: &List, name: &str) -> Result<Option<ListItem>, sqlx::Error> {
+    let key = item_key(&ParsedItem::parse(name).map_or_else(|| name.to_string(), |parsed| parsed.name));
+    let Some(item) = items(state, &list.id)
+        .await?
+        .into_iter()
+        .find(|item| !item.checked && item_key(&item.name) == key)
+    else {
+        return Ok(None);
+    };
+
+    let item = sqlx::query_as::<_, ListItem>(&format!(
+        "UPDATE list_items SET checked = TRUE, updated_at = CURRENT_TIMESTAMP WHERE id = ? RETURNING {}",
+        ITEM_COLUMNS
+    ))
+    .bind(&item.id)
+    .fetch_one(&state.db)
+    .await?;
+
+    touch(state, list).await?;
+    Ok(Some(item))
+}
+
+/// Marks the list as changed and tells clients to refresh it.
+pub async fn touch(state: &AppState, list: &List) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE lists SET updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(&list.id)
+        .execute(&state.db)
+        .await?;
+
+    state.events.publish(Event::ListChanged {
+        list_id: list.id.clone(),
+        user_id: list.user_id.clone(),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, quantity: Option<f64>, unit: Option<&str>) -> Option<ParsedItem> {
+        Some(ParsedItem {
+            name: name.to_string(),
+            quantity,
+            unit: unit.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn items_are_parsed_with_quantity_and_unit() {
+        assert_eq!(ParsedItem::parse("2 litres of milk"), item("milk", Some(2.0), Some("litre")));
+        assert_eq!(ParsedItem::parse("a dozen eggs"), item("eggs", Some(12.0), None));
+        assert_eq!(ParsedItem::parse("half a dozen eggs"), item("eggs", Some(6.0), None));
+        assert_eq!(ParsedItem::parse("a couple of lemons"), item("lemons", Some(2.0), None));
+        assert_eq!(ParsedItem::parse("a bag of ice"), item("ice", Some(1.0), Some("bag")));
+        assert_eq!(ParsedItem::parse("some Bread"), item("bread", None, None));
+        assert_eq!(ParsedItem::parse("an apple"), item("apple", None, None));
+    }
+
+    #[test]
+    fn units_need_a_number_or_of() {
+        assert_eq!(ParsedItem::parse("tin foil"), item("tin foil", None, None));
+        assert_eq!(ParsedItem::parse("tin of tomatoes"), item("tomatoes", Some(1.0), Some("tin")));
+    }
+}
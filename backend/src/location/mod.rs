@@ -0,0 +1,246 @@
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{config::settings::LocationConfig, AppState};
+
+/// Key of the home location saved through the API in the config table.
+const CONFIG_KEY: &str = "home_location";
+
+/// A place on the map, e.g. the household's home or a satellite's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub name: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// IANA name, e.g. "Europe/London".
+    pub timezone: Option<String>,
+}
+
+impl Location {
+    /// The configured location, if both coordinates are set.
+    pub fn from_settings(config: &LocationConfig) -> Option<Self> {
+        Some(Self {
+            name: config.name.clone().filter(|name| !name.trim().is_empty()),
+            latitude: config.latitude?,
+            longitude: config.longitude?,
+            timezone: config.timezone.clone().filter(|timezone| !timezone.trim().is_empty()),
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            return Err("latitude must be between -90 and 90".to_string());
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            return Err("longitude must be between -180 and 180".to_string());
+        }
+        if self.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+            return Err("name must not be empty".to_string());
+        }
+        if self.timezone.is_some() && self.tz().is_none() {
+            return Err("timezone must be an IANA name like Europe/London".to_string());
+        }
+        Ok(())
+    }
+
+    /// The timezone, if it is set and a known IANA name.
+    pub fn tz(&self) -> Option<Tz> {
+        self.timezone.as_deref()?.parse().ok()
+    }
+}
+
+/// Where a home location comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocationSource {
+    /// Saved through the API.
+    Saved,
+    Settings,
+}
+
+impl LocationSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LocationSource::Saved => "saved",
+            LocationSource::Settings => "settings",
+        }
+    }
+}
+
+/// The household's home: the one saved through the API, otherwise the one
+/// in the settings. `None` when neither is set.
+pub async fn home(state: &AppState) -> Result<Option<(Location, LocationSource)>, sqlx::Error> {
+    let saved: Option<(String,)> = sqlx::query_as("SELECT value FROM config WHERE key = ?")
+        .bind(CONFIG_KEY)
+        .fetch_optional(&state.db)
+        .await?;
+
+    if let Some((value,)) = saved {
+        match serde_json::from_str::<Location>(&value) {
+            Ok(location) => return Ok(Some((location, LocationSource::Saved))),
+            Err(e) => warn!("Ignoring invalid saved home location: {}", e),
+        }
+    }
+    Ok(Location::from_settings(&state.config.location).map(|location| (location, LocationSource::Settings)))
+}
+
+/// Saves the home location, or forgets it with `None` so the settings apply
+/// again.
+pub async fn save_home(state: &AppState, location: Option<&Location>) -> Result<(), sqlx::Error> {
+    match location {
+        Some(location) => {
+            sqlx::query(
+                "INSERT INTO config (key, value) VALUES (?, ?)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+            )
+            .bind(CONFIG_KEY)
+            .bind(sqlx::types::Json(location))
+            .execute(&state.db)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM config WHERE key = ?")
+                .bind(CONFIG_KEY)
+                .execute(&state.db)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Where a command heard by `satellite_id` is about: the satellite's own
+/// location when its config has one, e.g. for a satellite at a holiday
+/// home, otherwise the household's home.
+pub async fn for_satellite(state: &AppState, satellite_id: Option<&str>) -> Result<Option<Location>, sqlx::Error> {
+    if let Some(satellite_id) = satellite_id {
+        let config: Option<(Option<String>,)> = sqlx::query_as("SELECT config FROM satellites WHERE id = ?")
+            .bind(satellite_id)
+            .fetch_optional(&state.db)
+            .await?;
+
+        let location = config
+            .and_then(|(config,)| config)
+            .and_then(|config| serde_json::from_str::<Value>(&config).ok())
+            .and_then(|mut config| config.get_mut("location").map(Value::take))
+            .and_then(|location| serde_json::from_value::<Location>(location).ok());
+        if location.is_some() {
+            return Ok(location);
+        }
+    }
+
+    Ok(home(state).await?.map(|(location, _)| location))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Settings, test_support};
+
+    fn place(latitude: f64, longitude: f64, timezone: Option<&str>) -> Location {
+        Location {
+            name: None,
+            latitude,
+            longitude,
+            timezone: timezone.map(str::to_string),
+        }
+    }
+
+    fn london() -> LocationConfig {
+        LocationConfig {
+            name: Some("London".to_string()),
+            latitude: Some(51.5),
+            longitude: Some(-0.13),
+            timezone: Some("Europe/London".to_string()),
+            ..Settings::new().unwrap().location
+        }
+    }
+
+    #[test]
+    fn coordinates_and_timezones_are_validated() {
+        for valid in [
+            place(90.0, -180.0, None),
+            place(-33.9, 151.2, Some("Australia/Sydney")),
+            place(0.0, 0.0, Some("UTC")),
+        ] {
+            assert_eq!(valid.validate(), Ok(()), "{:?}", valid);
+        }
+
+        for (invalid, error) in [
+            (place(90.5, 0.0, None), "latitude must be between -90 and 90"),
+            (place(f64::NAN, 0.0, None), "latitude must be between -90 and 90"),
+            (place(0.0, -180.1, None), "longitude must be between -180 and 180"),
+            (place(0.0, 0.0, Some("Mars/Olympus_Mons")), "timezone must be an IANA name like Europe/London"),
+            (place(0.0, 0.0, Some("")), "timezone must be an IANA name like Europe/London"),
+            (Location { name: Some(" ".to_string()), ..place(0.0, 0.0, None) }, "name must not be empty"),
+        ] {
+            assert_eq!(invalid.validate(), Err(error.to_string()), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn settings_need_both_coordinates() {
+        assert_eq!(
+            Location::from_settings(&london()),
+            Some(Location { name: Some("London".to_string()), ..place(51.5, -0.13, Some("Europe/London")) })
+        );
+
+        let blank = LocationConfig { name: Some("".to_string()), timezone: Some(" ".to_string()), ..london() };
+        assert_eq!(Location::from_settings(&blank), Some(place(51.5, -0.13, None)));
+
+        assert_eq!(Location::from_settings(&LocationConfig { latitude: None, ..london() }), None);
+        assert_eq!(Location::from_settings(&LocationConfig { longitude: None, ..london() }), None);
+    }
+
+    #[tokio::test]
+    async fn satellites_override_the_saved_home_which_overrides_the_settings() {
+        let mut config = Settings::new().unwrap();
+        config.location = london();
+        let state = test_support::state_with(config).await;
+        let satellites = [
+            ("tokyo", Some(r#"{"room": "hall", "location": {"latitude": 35.7, "longitude": 139.7}}"#)),
+            ("hall", Some(r#"{"room": "hall"}"#)),
+            ("broken", Some(r#"{"location": {"latitude": "north"}}"#)),
+            ("bare", None),
+        ];
+        for (id, config) in satellites {
+            sqlx::query("INSERT INTO satellites (id, name, config) VALUES (?, ?, ?)")
+                .bind(id)
+                .bind(id)
+                .bind(config)
+                .execute(&state.db)
+                .await
+                .unwrap();
+        }
+
+        let settings = Location::from_settings(&london()).unwrap();
+        assert_eq!(home(&state).await.unwrap(), Some((settings.clone(), LocationSource::Settings)));
+
+        let tokyo = place(35.7, 139.7, None);
+        for satellite in [None, Some("hall"), Some("broken"), Some("bare"), Some("missing")] {
+            assert_eq!(for_satellite(&state, satellite).await.unwrap().as_ref(), Some(&settings), "{:?}", satellite);
+        }
+        assert_eq!(for_satellite(&state, Some("tokyo")).await.unwrap().as_ref(), Some(&tokyo));
+
+        let saved = Location { name: Some("Cottage".to_string()), ..place(50.1, -5.5, Some("Europe/London")) };
+        save_home(&state, Some(&saved)).await.unwrap();
+        assert_eq!(home(&state).await.unwrap(), Some((saved.clone(), LocationSource::Saved)));
+        assert_eq!(for_satellite(&state, None).await.unwrap(), Some(saved.clone()));
+        assert_eq!(for_satellite(&state, Some("hall")).await.unwrap(), Some(saved));
+        assert_eq!(for_satellite(&state, Some("tokyo")).await.unwrap(), Some(tokyo));
+
+        save_home(&state, None).await.unwrap();
+        assert_eq!(for_satellite(&state, None).await.unwrap(), Some(settings));
+    }
+
+    #[tokio::test]
+    async fn there_is_no_home_without_coordinates() {
+        let mut config = Settings::new().unwrap();
+        config.location = LocationConfig { latitude: None, ..london() };
+        let state = test_support::state_with(config).await;
+
+        assert_eq!(home(&state).await.unwrap(), None);
+        assert_eq!(for_satellite(&state, Some("missing")).await.unwrap(), None);
+    }
+}
@@ -1,36 +1,22 @@
-mod api;
-mod auth;
-mod config;
-mod database;
-mod middleware;
-mod mqtt;
-mod nlu;
-mod services;
-
 use axum::{
     routing::get,
     Router,
     response::Json,
 };
 use serde_json::{json, Value};
-use sqlx::SqlitePool;
+use std::sync::{Arc, RwLock};
 use tower_http::cors::CorsLayer;
 use tracing::{error, info, Level};
-use tracing_subscriber;
-
-use config::Settings;
-use mqtt::MqttService;
-use nlu::RasaManager;
-use services::llm::LlmService;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub db: SqlitePool,
-    pub config: Settings,
-    pub mqtt: Option<MqttService>,
-    pub nlu_url: String,
-    pub llm_service: Option<LlmService>,
-}
+
+use barnaby_server::{
+    api, audio, database, devices, home_automation, middleware, mqtt, nlu, reminders, timers, AppState,
+};
+use barnaby_server::config::Settings;
+use barnaby_server::events::EventBus;
+use barnaby_server::mqtt::MqttService;
+use barnaby_server::nlu::{NluPipeline, RasaManager};
+use barnaby_server::services::{llm::LlmService, weather::WeatherService};
+use barnaby_server::skills::SkillRegistry;
 
 
 
@@ -62,14 +48,27 @@ async fn main() {
     };
 
     // Start Rasa NLU server
-    let mut rasa_manager = RasaManager::new();
+    let mut rasa_manager = RasaManager::new(&config.nlu);
     if let Err(e) = rasa_manager.start().await {
         error!("Failed to start Rasa NLU: {}. Falling back to Rust NLU.", e);
     }
 
-    // MQTT client disabled for now - can be enabled when broker is available
-    info!("MQTT client disabled - enable when broker is configured");
-    let mqtt = None;
+    // MQTT is optional; satellites need a broker to reach the server
+    let (mqtt, mqtt_messages) = if config.mqtt.enabled {
+        match MqttService::new(&config.mqtt).await {
+            Ok((mqtt, messages)) => {
+                info!("MQTT client connecting to {}:{}", config.mqtt.broker, config.mqtt.port);
+                (Some(mqtt), Some(messages))
+            }
+            Err(e) => {
+                error!("Failed to create MQTT client: {}. Satellites will be unavailable.", e);
+                (None, None)
+            }
+        }
+    } else {
+        info!("MQTT client disabled - set BARNABY_MQTT__ENABLED=true when a broker is configured");
+        (None, None)
+    };
 
     // Initialize LLM service (optional)
     let llm_service = if let Ok(model_path) = std::env::var("PICOLLM_MODEL_PATH") {
@@ -90,20 +89,106 @@ async fn main() {
         None
     };
 
+    // Initialize speech-to-text (optional)
+    let stt = match audio::stt::create_engine(&config.audio) {
+        Ok(engine) => {
+            info!("Speech-to-text engine: {}", engine.name());
+            Some(engine)
+        }
+        Err(e) => {
+            info!("Speech-to-text unavailable: {}, only text commands will work", e);
+            None
+        }
+    };
+
+    // Initialize text-to-speech (optional)
+    let tts = match audio::tts::create_engine(&config.audio) {
+        Ok(engine) => {
+            info!("Text-to-speech engine: {}", engine.name());
+            Some(engine)
+        }
+        Err(e) => {
+            info!("Text-to-speech unavailable: {}, responses will be text only", e);
+            None
+        }
+    };
+
+    // Initialize home automation (optional)
+    let home_automation = if config.home_automation.enabled {
+        match home_automation::create_backend(&config.home_automation) {
+            Ok(backend) => {
+                info!("Home automation backend: {} at {}", backend.name(), config.home_automation.url());
+                Some(backend)
+            }
+            Err(e) => {
+                error!("Failed to set up home automation: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let weather = match WeatherService::new(&config.weather) {
+        Ok(weather) => Arc::new(weather),
+        Err(e) => {
+            error!("Failed to create weather service: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let rust_nlu = match nlu::patterns::load_rust_nlu(&config.nlu) {
+        Ok(rust_nlu) => Arc::new(RwLock::new(rust_nlu)),
+        Err(e) => {
+            error!("Failed to load NLU patterns: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let nlu = match NluPipeline::from_config(&config.nlu, llm_service, rust_nlu.clone()) {
+        Ok(nlu) => {
+            let engines: Vec<&str> = nlu.engines().iter().map(|engine| engine.as_str()).collect();
+            info!("NLU engines: {} ({} mode)", engines.join(", "), config.nlu.mode);
+            Arc::new(nlu)
+        }
+        Err(e) => {
+            error!("Failed to set up NLU: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Create application state
     let state = AppState {
         db,
         config: config.clone(),
         mqtt,
-        nlu_url: "http://localhost:5005".to_string(),
-        llm_service,
+        nlu,
+        events: EventBus::new(),
+        skills: Arc::new(SkillRegistry::with_builtin_skills()),
+        stt,
+        tts,
+        home_automation,
+        weather,
+        rust_nlu,
+        rasa: Arc::new(tokio::sync::Mutex::new(rasa_manager)),
     };
 
+    tokio::spawn(mqtt::presence::watch(state.clone()));
+    tokio::spawn(timers::run(state.clone()));
+    tokio::spawn(reminders::run(state.clone()));
+    tokio::spawn(nlu::patterns::watch(state.clone()));
+    if state.home_automation.is_some() {
+        tokio::spawn(home_automation::watch(state.clone()));
+    }
+    if let Some(messages) = mqtt_messages {
+        tokio::spawn(mqtt::dispatch::run(state.clone(), messages));
+        devices::sync_subscriptions(&state).await;
+    }
+
     // Build application with routes
     let app = Router::new()
         .route("/", get(root))
         .route("/health", get(health_check))
-        .merge(api::create_routes())
+        .merge(api::create_routes(state.clone()))
         .layer(axum::middleware::from_fn(middleware::logging_middleware))
         .layer(CorsLayer::permissive())
         .with_state(state);
@@ -0,0 +1,498 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, info, warn};
+
+use super::{pairing, presence, IncomingMessage, SatelliteMessage};
+use crate::{
+    audio::{
+        self,
+        sessions::{AudioSessionManager, ChunkStatus, FinishedSession},
+    },
+    devices, pipeline, AppState,
+};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest secret accepted in front of an audio chunk.
+const MAX_SECRET_LEN: usize = 128;
+
+/// The body of `audio/end`.
+#[derive(Debug, Default, serde::Deserialize)]
+struct AudioEnd {
+    secret: Option<String>,
+}
+
+/// Routes satellite messages to the subsystems that handle them.
+pub async fn run(state: AppState, mut messages: mpsc::Receiver<IncomingMessage>) {
+    let sessions = AudioSessionManager::new(&state.config.audio);
+    let processing = Arc::new(Semaphore::new(state.config.audio.max_concurrent_streams));
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+
+    info!("MQTT dispatcher started");
+    loop {
+        tokio::select! {
+            message = messages.recv() => {
+                let message = match message {
+                    Some(IncomingMessage::Satellite(message)) => message,
+                    Some(IncomingMessage::PairingAnnounce(payload)) => {
+                        handle_pairing_announce(&state, &payload).await;
+                        continue;
+                    }
+                    Some(IncomingMessage::Other { topic, payload }) => {
+                        if let Err(e) = devices::handle_state_message(&state, &topic, &payload).await {
+                            warn!("Failed to record device state from {}: {}", topic, e);
+                        }
+                        continue;
+                    }
+                    None => break,
+                };
+                match message.topic.as_str() {
+                    "audio/stream" => handle_audio_chunk(&state, &sessions, message).await,
+                    "audio/end" => handle_audio_end(&state, &sessions, &processing, message).await,
+                    "status/heartbeat" | "status/online" | "status/offline" => {
+                        handle_status(&state, message).await
+                    }
+                    other => debug!("Ignoring {} from satellite {}", other, message.satellite_id),
+                }
+            }
+            _ = sweep.tick() => {
+                for satellite_id in sessions.expire_idle() {
+                    warn!("Audio stream from satellite {} timed out", satellite_id);
+                }
+            }
+        }
+    }
+    info!("MQTT dispatcher stopped");
+}
+
+async fn handle_pairing_announce(state: &AppState, payload: &[u8]) {
+    let announcement = match serde_json::from_slice::<pairing::Announcement>(payload) {
+        Ok(announcement) => announcement,
+        Err(e) => {
+            debug!("Ignoring malformed pairing announcement: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = pairing::announce(state, announcement).await {
+        warn!("Failed to handle pairing announcement: {}", e);
+    }
+}
+
+async fn handle_status(state: &AppState, message: SatelliteMessage) {
+    let result = if message.topic == "status/offline" {
+        let goodbye: presence::Goodbye = parse_optional_json(&message.payload);
+        presence::say_goodbye(state, &message.satellite_id, &goodbye).await
+    } else {
+        let heartbeat: presence::Heartbeat = parse_optional_json(&message.payload);
+        presence::record_seen(state, &message.satellite_id, &heartbeat).await
+    };
+
+    if let Err(e) = result {
+        warn!("Failed to update presence of satellite {}: {}", message.satellite_id, e);
+    }
+}
+
+/// Status payloads are optional, so an empty or malformed body means defaults.
+fn parse_optional_json<T: serde::de::DeserializeOwned + Default>(payload: &[u8]) -> T {
+    serde_json::from_slice(payload).unwrap_or_default()
+}
+
+/// Splits an `audio/stream` payload, `<secret>\n<pcm>`, into the satellite's
+/// secret and the audio.
+fn split_secret(payload: &[u8]) -> Option<(&str, &[u8])> {
+    let newline = payload.iter().take(MAX_SECRET_LEN + 1).position(|&byte| byte == b'\n')?;
+    let secret = std::str::from_utf8(&payload[..newline]).ok().filter(|secret| !secret.is_empty())?;
+    Some((secret, &payload[newline + 1..]))
+}
+
+async fn handle_audio_chunk(state: &AppState, sessions: &AudioSessionManager, message: SatelliteMessage) {
+    let Some((secret, pcm)) = split_secret(&message.payload) else {
+        debug!("Ignoring audio without a secret from satellite {}", message.satellite_id);
+        return;
+    };
+    let credential = pairing::hash_secret(secret);
+
+    // Only online satellites with the right secret get a buffer; later chunks
+    // must carry the same secret
+    if !sessions.has_session(&message.satellite_id) && !may_stream(state, &message.satellite_id, secret).await {
+        debug!("Ignoring audio from unpaired or offline satellite {}", message.satellite_id);
+        return;
+    }
+
+    match sessions.push_chunk(&message.satellite_id, &credential, pcm) {
+        ChunkStatus::Started => debug!("Audio stream started for satellite {}", message.satellite_id),
+        ChunkStatus::Appended => {}
+        ChunkStatus::Overflowed => debug!("Audio stream from satellite {} is too long", message.satellite_id),
+        ChunkStatus::Rejected => debug!("Ignoring audio with the wrong secret for satellite {}", message.satellite_id),
+    }
+}
+
+async fn handle_audio_end(
+    state: &AppState,
+    sessions: &AudioSessionManager,
+    processing: &Arc<Semaphore>,
+    message: SatelliteMessage,
+) {
+    let satellite_id = message.satellite_id;
+    let end: AudioEnd = parse_optional_json(&message.payload);
+    let Some(secret) = end.secret else {
+        debug!("Ignoring the end of audio without a secret from satellite {}", satellite_id);
+        return;
+    };
+
+    let pcm = match sessions.finish(&satellite_id, &pairing::hash_secret(&secret)) {
+        Some(FinishedSession::Complete { pcm, duration }) => {
+            debug!("Audio stream from satellite {} ended after {:?}", satellite_id, duration);
+            pcm
+        }
+        Some(FinishedSession::Overflowed) => {
+            reply(state, &satellite_id, "Sorry, that was too long for me to follow.", None).await;
+            return;
+        }
+        None => return,
+    };
+
+    let Ok(permit) = processing.clone().try_acquire_owned() else {
+        warn!("Too many commands in flight, rejecting audio from satellite {}", satellite_id);
+        reply(state, &satellite_id, "I'm a little busy right now, please try again.", None).await;
+        return;
+    };
+
+    let state = state.clone();
+    let timeout = Duration::from_secs(state.config.audio.processing_timeout_secs);
+    tokio::spawn(async move {
+        if tokio::time::timeout(timeout, handle_utterance(&state, &satellite_id, pcm))
+            .await
+            .is_err()
+        {
+            warn!("Processing audio from satellite {} timed out", satellite_id);
+            reply(&state, &satellite_id, "Sorry, that took too long. Please try again.", None).await;
+        }
+        drop(permit);
+    });
+}
+
+async fn handle_utterance(state: &AppState, satellite_id: &str, pcm: Vec<u8>) {
+    let Some(stt) = &state.stt else {
+        warn!("Received audio from satellite {} but no STT engine is configured", satellite_id);
+        return;
+    };
+
+    let buffer = audio::AudioBuffer {
+        samples: audio::pcm16_to_f32(&pcm),
+        sample_rate: state.config.audio.sample_rate,
+    };
+
+    let text = match stt.transcribe(&buffer).await {
+        Ok(transcription) if !transcription.text.trim().is_empty() => transcription.text,
+        Ok(_) => {
+            reply(state, satellite_id, "Sorry, I didn't catch that.", None).await;
+            return;
+        }
+        Err(e) => {
+            warn!("Transcription for satellite {} failed: {}", satellite_id, e);
+            reply(state, satellite_id, "Sorry, I didn't catch that.", None).await;
+            return;
+        }
+    };
+
+    let user_id = match satellite_user(state, satellite_id).await {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            warn!("Failed to look up the user of satellite {}: {}", satellite_id, e);
+            None
+        }
+    };
+
+    match pipeline::process_text(state, &text, user_id.as_deref(), Some(satellite_id)).await {
+        Ok(outcome) => {
+            let audio = Some(outcome.audio_response).filter(|audio| !audio.is_empty());
+            debug!("Replying to satellite {} for command {}", satellite_id, outcome.command_id);
+            reply(state, satellite_id, &outcome.response, audio).await;
+        }
+        Err(e) => warn!("Command pipeline failed for satellite {}: {}", satellite_id, e),
+    }
+}
+
+/// Sends a spoken reply to `commands/tts`. Satellites play `audio` when it is
+/// present and fall back to their own TTS for `text` otherwise.
+async fn reply(state: &AppState, satellite_id: &str, text: &str, audio: Option<String>) {
+    let Some(mqtt) = &state.mqtt else { return };
+
+    let payload = json!({
+        "text": text,
+        "audio": audio,
+    });
+
+    if let Err(e) = mqtt.publish_to_satellite(satellite_id, "commands/tts", &payload.to_string()).await {
+        warn!("Failed to send reply to satellite {}: {}", satellite_id, e);
+    }
+}
+
+/// The user commands heard by the satellite act as, named by `user` in its
+/// config. Without one, skills that need a user turn the command down.
+async fn satellite_user(state: &AppState, satellite_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let config: Option<Option<String>> = sqlx::query_scalar("SELECT config FROM satellites WHERE id = ?")
+        .bind(satellite_id)
+        .fetch_optional(&state.db)
+        .await?;
+    let Some(username) = config
+        .flatten()
+        .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok())
+        .and_then(|config| config.get("user")?.as_str().map(str::to_string))
+    else {
+        return Ok(None);
+    };
+
+    let user_id = sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+        .bind(&username)
+        .fetch_optional(&state.db)
+        .await?;
+    if user_id.is_none() {
+        warn!("Satellite {} belongs to unknown user {}", satellite_id, username);
+    }
+    Ok(user_id)
+}
+
+/// Whether the satellite is online and `secret` is its own.
+async fn may_stream(state: &AppState, satellite_id: &str, secret: &str) -> bool {
+    let online = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM satellites WHERE id = ? AND status = 'online'")
+        .bind(satellite_id)
+        .fetch_one(&state.db)
+        .await
+        .map(|count| count > 0)
+        .unwrap_or(false);
+
+    online && pairing::verify_secret(state, satellite_id, Some(secret)).await.unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        audio::{stt::MockSttEngine, SttEngine, Transcription},
+        config::Settings,
+        mqtt::MqttService,
+        test_support,
+    };
+
+    const SATELLITE: &str = "kitchen";
+    const SECRET: &str = "kitchen-secret";
+
+    /// A satellite talking to a server that runs the dispatcher, through a
+    /// real broker.
+    struct Satellite {
+        client: AsyncClient,
+        replies: mpsc::Receiver<Value>,
+        db: sqlx::SqlitePool,
+    }
+
+    impl Satellite {
+        async fn start(stt: Arc<dyn SttEngine>, configure: impl FnOnce(&mut Settings)) -> Self {
+            let port = test_support::start_broker();
+            let mut config = Settings::new().unwrap();
+            config.mqtt.enabled = true;
+            config.mqtt.broker = "127.0.0.1".to_string();
+            config.mqtt.port = port;
+            configure(&mut config);
+
+            let mut state = test_support::state_with(config).await;
+            sqlx::query("INSERT INTO satellites (id, name, status, secret_hash) VALUES (?, 'Kitchen', 'online', ?)")
+                .bind(SATELLITE)
+                .bind(pairing::hash_secret(SECRET))
+                .execute(&state.db)
+                .await
+                .unwrap();
+            let (mqtt, messages) = MqttService::new(&state.config.mqtt).await.unwrap();
+            state.mqtt = Some(mqtt);
+            state.stt = Some(stt);
+            let db = state.db.clone();
+            tokio::spawn(run(state, messages));
+
+            let mut options = MqttOptions::new("satellite-kitchen", "127.0.0.1", port);
+            options.set_max_packet_size(1024 * 1024, 1024 * 1024);
+            let (client, mut eventloop) = AsyncClient::new(options, 100);
+            let (sender, replies) = mpsc::channel(10);
+            tokio::spawn(async move {
+                while let Ok(event) = eventloop.poll().await {
+                    if let Event::Incoming(Packet::Publish(publish)) = event {
+                        let _ = sender.send(serde_json::from_slice(&publish.payload).unwrap()).await;
+                    }
+                }
+            });
+            client
+                .subscribe(format!("barnaby/satellites/{}/commands/tts", SATELLITE), QoS::AtLeastOnce)
+                .await
+                .unwrap();
+            // Give the server time to connect and subscribe
+            tokio::time::sleep(Duration::from_millis(500)).await;
+
+            Self { client, replies, db }
+        }
+
+        async fn publish(&self, topic: &str, payload: Vec<u8>) {
+            self.client
+                .publish(format!("barnaby/satellites/{}/{}", SATELLITE, topic), QoS::AtLeastOnce, false, payload)
+                .await
+                .unwrap();
+        }
+
+        /// Streams `pcm` in chunks and ends the utterance, signed with `secret`.
+        async fn say_with(&self, secret: &str, pcm: &[u8]) {
+            for chunk in pcm.chunks(1024) {
+                let mut payload = format!("{}\n", secret).into_bytes();
+                payload.extend_from_slice(chunk);
+                self.publish("audio/stream", payload).await;
+            }
+            self.publish("audio/end", json!({ "secret": secret }).to_string().into_bytes()).await;
+        }
+
+        async fn say(&self, pcm: &[u8]) {
+            self.say_with(SECRET, pcm).await;
+        }
+
+        async fn reply(&mut self) -> String {
+            let reply = tokio::time::timeout(Duration::from_secs(10), self.replies.recv())
+                .await
+                .expect("no reply on commands/tts")
+                .unwrap();
+            reply["text"].as_str().unwrap().to_string()
+        }
+
+        async fn no_reply(&mut self) {
+            let reply = tokio::time::timeout(Duration::from_secs(1), self.replies.recv()).await;
+            assert!(reply.is_err(), "unexpected reply {:?}", reply);
+        }
+    }
+
+    /// Half a second of 16 kHz silence.
+    fn speech() -> Vec<u8> {
+        vec![0; 16000]
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spoken_commands_are_answered_on_commands_tts() {
+        let mut satellite = Satellite::start(Arc::new(MockSttEngine::new("what time is it")), |_| {}).await;
+        satellite.say(&speech()).await;
+        let reply = satellite.reply().await;
+        assert!(reply.starts_with("The current time is"), "{}", reply);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reminders_belong_to_the_satellite_user() {
+        let stt = Arc::new(MockSttEngine::new("remind me to water the plants in 10 minutes"));
+        let mut satellite = Satellite::start(stt, |_| {}).await;
+        satellite.say(&speech()).await;
+        assert_eq!(
+            satellite.reply().await,
+            "Sorry, I can only set reminders on a satellite that belongs to someone."
+        );
+
+        sqlx::query("INSERT INTO users (id, username, email, password_hash, role) VALUES ('u1', 'sam', 'sam@example.com', 'x', 'user')")
+            .execute(&satellite.db)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE satellites SET config = '{\"user\": \"sam\"}' WHERE id = ?")
+            .bind(SATELLITE)
+            .execute(&satellite.db)
+            .await
+            .unwrap();
+        satellite.say(&speech()).await;
+        let reply = satellite.reply().await;
+        assert!(reply.starts_with("OK, I'll remind you"), "{}", reply);
+
+        let owner: (String, String) = sqlx::query_as("SELECT user_id, satellite_id FROM reminders")
+            .fetch_one(&satellite.db)
+            .await
+            .unwrap();
+        assert_eq!(owner, ("u1".to_string(), SATELLITE.to_string()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn audio_without_the_satellite_secret_is_ignored() {
+        let mut satellite = Satellite::start(Arc::new(MockSttEngine::new("what time is it")), |_| {}).await;
+        satellite.say_with("guessed", &speech()).await;
+        satellite.no_reply().await;
+
+        // Unsigned audio
+        satellite.publish("audio/stream", speech()).await;
+        satellite.publish("audio/end", Vec::new()).await;
+        satellite.no_reply().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_stream_cannot_be_ended_with_another_secret() {
+        let mut satellite = Satellite::start(Arc::new(MockSttEngine::new("what time is it")), |_| {}).await;
+        let mut payload = format!("{}\n", SECRET).into_bytes();
+        payload.extend_from_slice(&speech());
+        satellite.publish("audio/stream", payload).await;
+        satellite.publish("audio/end", br#"{"secret": "guessed"}"#.to_vec()).await;
+        satellite.no_reply().await;
+
+        satellite.publish("audio/end", json!({ "secret": SECRET }).to_string().into_bytes()).await;
+        assert!(satellite.reply().await.starts_with("The current time is"));
+    }
+
+    #[test]
+    fn secrets_are_split_from_audio_chunks() {
+        assert_eq!(split_secret(b"abc\n\x01\x02"), Some(("abc", &[1u8, 2][..])));
+        assert_eq!(split_secret(b"abc\n"), Some(("abc", &[][..])));
+        assert_eq!(split_secret(b"\n\x01"), None);
+        assert_eq!(split_secret(b"\x01\x02"), None);
+        assert_eq!(split_secret(&[b'a'; MAX_SECRET_LEN + 1]), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn utterances_over_the_limit_are_refused() {
+        let mut satellite = Satellite::start(Arc::new(MockSttEngine::new("what time is it")), |config| {
+            config.audio.max_utterance_secs = 0;
+        })
+        .await;
+        satellite.say(&speech()).await;
+        assert_eq!(satellite.reply().await, "Sorry, that was too long for me to follow.");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn utterances_beyond_the_concurrency_limit_are_turned_away() {
+        let mut satellite = Satellite::start(Arc::new(MockSttEngine::new("what time is it")), |config| {
+            config.audio.max_concurrent_streams = 0;
+        })
+        .await;
+        satellite.say(&speech()).await;
+        assert_eq!(satellite.reply().await, "I'm a little busy right now, please try again.");
+    }
+
+    /// An engine that never finishes in time.
+    struct SlowSttEngine;
+
+    #[async_trait]
+    impl SttEngine for SlowSttEngine {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        async fn transcribe(&self, _audio: &audio::AudioBuffer) -> Result<Transcription> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            unreachable!()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn slow_processing_times_out() {
+        let mut satellite = Satellite::start(Arc::new(SlowSttEngine), |config| {
+            config.audio.processing_timeout_secs = 1;
+        })
+        .await;
+        satellite.say(&speech()).await;
+        assert_eq!(satellite.reply().await, "Sorry, that took too long. Please try again.");
+    }
+}
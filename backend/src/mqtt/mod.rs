@@ -1,30 +1,132 @@
-use rumqttc::{AsyncClient, MqttOptions, QoS};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, SubscribeFilter};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{info, warn};
 use anyhow::Result;
 
 use crate::config::settings::MqttConfig;
 
+pub mod dispatch;
+pub mod pairing;
+pub mod presence;
+
+const TOPIC_PREFIX: &str = "barnaby/satellites/";
+
+/// Satellites without an id announce themselves here to be paired.
+const PAIRING_ANNOUNCE_TOPIC: &str = "barnaby/pairing/announce";
+
+/// Satellite topics the server listens on, relative to `barnaby/satellites/{id}/`.
+const SATELLITE_TOPICS: &[&str] = &[
+    "audio/stream",
+    "audio/end",
+    "status/heartbeat",
+    "status/online",
+    "status/offline",
+];
+
+/// Incoming messages are handed to the dispatcher through a bounded channel.
+/// When it is full the MQTT event loop stops polling, which pushes back on
+/// the broker instead of buffering audio without limit.
+const INCOMING_CAPACITY: usize = 256;
+
+/// Requests (publishes, subscriptions) queued for the MQTT event loop.
+const REQUEST_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone)]
+pub enum IncomingMessage {
+    Satellite(SatelliteMessage),
+    PairingAnnounce(Vec<u8>),
+    /// Anything else, e.g. a device reporting its state.
+    Other { topic: String, payload: Vec<u8> },
+}
+
+impl IncomingMessage {
+    pub fn parse(topic: &str, payload: &[u8]) -> Self {
+        if topic == PAIRING_ANNOUNCE_TOPIC {
+            return IncomingMessage::PairingAnnounce(payload.to_vec());
+        }
+
+        match SatelliteMessage::parse(topic, payload) {
+            Some(message) => IncomingMessage::Satellite(message),
+            None => IncomingMessage::Other {
+                topic: topic.to_string(),
+                payload: payload.to_vec(),
+            },
+        }
+    }
+}
+
+/// A message published by a satellite, with the topic prefix stripped.
+#[derive(Debug, Clone)]
+pub struct SatelliteMessage {
+    pub satellite_id: String,
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+impl SatelliteMessage {
+    pub fn parse(topic: &str, payload: &[u8]) -> Option<Self> {
+        let (satellite_id, topic) = topic.strip_prefix(TOPIC_PREFIX)?.split_once('/')?;
+        if satellite_id.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            satellite_id: satellite_id.to_string(),
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct MqttService {
     client: AsyncClient,
+    /// Topics subscribed on behalf of other subsystems, replayed on reconnect.
+    extra_topics: Arc<Mutex<BTreeSet<String>>>,
 }
 
 impl MqttService {
-    pub async fn new(config: &MqttConfig) -> Result<Self> {
-        let mut mqttoptions = MqttOptions::new("barnaby-server", &config.broker, config.port);
-        
+    pub async fn new(config: &MqttConfig) -> Result<(Self, mpsc::Receiver<IncomingMessage>)> {
+        let mut mqttoptions = MqttOptions::new(&config.client_id, &config.broker, config.port);
+        mqttoptions.set_keep_alive(Duration::from_secs(30));
+        // Audio chunks can be larger than the 10 KiB default
+        mqttoptions.set_max_packet_size(1024 * 1024, 1024 * 1024);
+
         if let (Some(username), Some(password)) = (&config.username, &config.password) {
             mqttoptions.set_credentials(username, password);
         }
 
-        let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+        let (client, mut eventloop) = AsyncClient::new(mqttoptions, REQUEST_CAPACITY);
+        let (sender, receiver) = mpsc::channel(INCOMING_CAPACITY);
+        let service = Self {
+            client,
+            extra_topics: Arc::new(Mutex::new(BTreeSet::new())),
+        };
+        let subscriber = service.clone();
 
         // Spawn task to handle MQTT events
         tokio::spawn(async move {
             let mut connection_attempts = 0;
             loop {
                 match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        connection_attempts = 0;
+                        info!("Connected to MQTT broker");
+                        // Subscriptions don't survive a clean-session reconnect
+                        if let Err(e) = subscriber.subscribe_to_satellites() {
+                            warn!("Failed to subscribe to satellite topics: {}", e);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        let message = IncomingMessage::parse(&publish.topic, &publish.payload);
+                        if sender.send(message).await.is_err() {
+                            info!("MQTT dispatcher stopped, closing event loop");
+                            break;
+                        }
+                    }
                     Ok(event) => {
                         connection_attempts = 0; // Reset on successful event
                         tracing::debug!("MQTT Event: {:?}", event);
@@ -40,27 +142,130 @@ impl MqttService {
             }
         });
 
-        Ok(Self { client })
+        Ok((service, receiver))
     }
 
-    pub async fn subscribe_to_satellites(&self) -> Result<()> {
-        self.client
-            .subscribe("barnaby/satellites/+/audio/stream", QoS::AtLeastOnce)
-            .await?;
-        
-        self.client
-            .subscribe("barnaby/satellites/+/status/heartbeat", QoS::AtLeastOnce)
-            .await?;
+    /// Non-blocking so it can be called from inside the event loop task. All
+    /// topics go in one request, however many devices there are.
+    pub fn subscribe_to_satellites(&self) -> Result<()> {
+        let mut topics: Vec<SubscribeFilter> = SATELLITE_TOPICS
+            .iter()
+            .map(|topic| format!("{}+/{}", TOPIC_PREFIX, topic))
+            .chain([PAIRING_ANNOUNCE_TOPIC.to_string()])
+            .map(|topic| SubscribeFilter::new(topic, QoS::AtLeastOnce))
+            .collect();
+        topics.extend(
+            self.extra_topics
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|topic| SubscribeFilter::new(topic.clone(), QoS::AtLeastOnce)),
+        );
+        self.client.try_subscribe_many(topics)?;
 
         info!("Subscribed to satellite topics");
         Ok(())
     }
 
     pub async fn publish_to_satellite(&self, satellite_id: &str, topic: &str, payload: &str) -> Result<()> {
-        let full_topic = format!("barnaby/satellites/{}/{}", satellite_id, topic);
+        self.publish(&format!("{}{}/{}", TOPIC_PREFIX, satellite_id, topic), payload)
+            .await
+    }
+
+    /// Makes `topics` the set of extra topics the server is subscribed to,
+    /// subscribing and unsubscribing only what changed.
+    pub async fn set_extra_topics(&self, topics: BTreeSet<String>) -> Result<()> {
+        let (added, removed) = {
+            let mut current = self.extra_topics.lock().unwrap();
+            let added: Vec<String> = topics.difference(&current).cloned().collect();
+            let removed: Vec<String> = current.difference(&topics).cloned().collect();
+            *current = topics;
+            (added, removed)
+        };
+
+        if !added.is_empty() {
+            let filters = added.into_iter().map(|topic| SubscribeFilter::new(topic, QoS::AtLeastOnce));
+            self.client.subscribe_many(filters).await?;
+        }
+        for topic in removed {
+            self.client.unsubscribe(topic).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn publish(&self, topic: &str, payload: &str) -> Result<()> {
         self.client
-            .publish(full_topic, QoS::AtLeastOnce, false, payload)
+            .publish(topic, QoS::AtLeastOnce, false, payload)
             .await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Settings, test_support};
+
+    fn config(port: u16) -> MqttConfig {
+        let mut config = Settings::new().unwrap().mqtt;
+        config.broker = "127.0.0.1".to_string();
+        config.port = port;
+        config
+    }
+
+    fn device_topics(count: usize) -> BTreeSet<String> {
+        (0..count).map(|i| format!("barnaby/devices/light-{}/state", i)).collect()
+    }
+
+    #[test]
+    fn topics_are_routed_by_prefix() {
+        assert!(matches!(
+            IncomingMessage::parse("barnaby/pairing/announce", b"{}"),
+            IncomingMessage::PairingAnnounce(_)
+        ));
+        match IncomingMessage::parse("barnaby/satellites/kitchen/audio/end", b"x") {
+            IncomingMessage::Satellite(message) => {
+                assert_eq!((message.satellite_id.as_str(), message.topic.as_str()), ("kitchen", "audio/end"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            IncomingMessage::parse("barnaby/satellites//audio/end", b""),
+            IncomingMessage::Other { .. }
+        ));
+        assert!(matches!(
+            IncomingMessage::parse("barnaby/devices/lamp/state", b""),
+            IncomingMessage::Other { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn resubscribing_takes_one_request_however_many_devices_there_are() {
+        // Nothing listens there, so queued requests are never taken
+        let mqtt = test_support::offline_mqtt().await;
+        *mqtt.extra_topics.lock().unwrap() = device_topics(200);
+
+        for _ in 0..REQUEST_CAPACITY / 2 {
+            mqtt.subscribe_to_satellites().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn every_device_state_topic_is_subscribed() {
+        let port = test_support::start_broker();
+        let (mqtt, mut messages) = MqttService::new(&config(port)).await.unwrap();
+        mqtt.set_extra_topics(device_topics(50)).await.unwrap();
+        // Give the server time to connect and subscribe
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        mqtt.publish("barnaby/devices/light-49/state", r#"{"state": "ON"}"#).await.unwrap();
+        let message = tokio::time::timeout(Duration::from_secs(10), messages.recv())
+            .await
+            .expect("no device state received")
+            .unwrap();
+        match message {
+            IncomingMessage::Other { topic, .. } => assert_eq!(topic, "barnaby/devices/light-49/state"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
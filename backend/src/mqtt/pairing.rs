@@ -0,0 +1,534 @@
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{database::models::PairingRequest, events::Event, AppState};
+
+const PAIRING_COLUMNS: &str =
+    "id, mac_address, name, ip_address, state, satellite_id, requested_at, decided_at, decided_by";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingState {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl PairingState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PairingState::Pending => "pending",
+            PairingState::Approved => "approved",
+            PairingState::Rejected => "rejected",
+        }
+    }
+}
+
+impl std::str::FromStr for PairingState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(PairingState::Pending),
+            "approved" => Ok(PairingState::Approved),
+            "rejected" => Ok(PairingState::Rejected),
+            _ => Err(format!("Invalid pairing state: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingAction {
+    /// The satellite published `barnaby/pairing/announce`.
+    Announce,
+    Approve,
+    Reject,
+}
+
+impl PairingAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PairingAction::Announce => "announce",
+            PairingAction::Approve => "approve",
+            PairingAction::Reject => "reject",
+        }
+    }
+}
+
+/// What the server has to do after a transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingEffect {
+    /// Store or refresh the request and tell the satellite to wait.
+    AwaitApproval,
+    /// Create the satellite and issue its credentials.
+    Pair,
+    Reject,
+    /// Only remind the satellite of the existing decision.
+    Remind,
+}
+
+#[derive(Debug, Error)]
+pub enum PairingError {
+    #[error("pairing request not found")]
+    NotFound,
+    #[error("invalid MAC address '{0}'")]
+    InvalidMac(String),
+    #[error("cannot {} a request that is {}", action.as_str(), from.as_str())]
+    InvalidTransition { from: PairingState, action: PairingAction },
+    #[error("a satellite with MAC address {0} already exists")]
+    AlreadyPaired(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// The pairing state machine. `current` is `None` for a MAC address the
+/// server has never heard of.
+///
+/// Re-announcing never changes a decision, and a rejected satellite can still
+/// be approved later. Once approved, a satellite is unpaired by deleting it.
+pub fn transition(
+    current: Option<PairingState>,
+    action: PairingAction,
+) -> Result<(PairingState, PairingEffect), PairingError> {
+    use PairingAction::*;
+    use PairingState::*;
+
+    match (current, action) {
+        (None | Some(Pending), Announce) => Ok((Pending, PairingEffect::AwaitApproval)),
+        (Some(state @ (Approved | Rejected)), Announce) => Ok((state, PairingEffect::Remind)),
+        (Some(Pending | Rejected), Approve) => Ok((Approved, PairingEffect::Pair)),
+        (Some(Pending), Reject) => Ok((Rejected, PairingEffect::Reject)),
+        (None, _) => Err(PairingError::NotFound),
+        (Some(from), action) => Err(PairingError::InvalidTransition { from, action }),
+    }
+}
+
+/// Normalises a MAC address to lower case `aa:bb:cc:dd:ee:ff`. Colons,
+/// dashes or no separators are accepted.
+pub fn normalize_mac(mac: &str) -> Result<String, PairingError> {
+    let hex: String = mac.chars().filter(|c| *c != ':' && *c != '-').collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(PairingError::InvalidMac(mac.to_string()));
+    }
+
+    let hex = hex.to_ascii_lowercase();
+    Ok((0..6).map(|i| &hex[i * 2..i * 2 + 2]).collect::<Vec<_>>().join(":"))
+}
+
+/// Replies go to `barnaby/pairing/{mac without colons}` since the satellite
+/// has no id yet.
+pub fn reply_topic(mac: &str) -> String {
+    format!("barnaby/pairing/{}", mac.replace(':', ""))
+}
+
+pub fn generate_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Body of `barnaby/pairing/announce`.
+#[derive(Debug, Deserialize)]
+pub struct Announcement {
+    pub mac_address: String,
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// Credentials handed to a satellite once. It has to send `secret` with its
+/// heartbeats and audio; only the hash is stored. The secret is never sent
+/// over MQTT, where anyone on the broker could read it: the admin who
+/// approved the pairing provisions it on the satellite by hand.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub satellite_id: String,
+    pub secret: String,
+}
+
+pub async fn find_request(state: &AppState, request_id: &str) -> Result<Option<PairingRequest>, PairingError> {
+    Ok(sqlx::query_as::<_, PairingRequest>(&format!(
+        "SELECT {} FROM pairing_requests WHERE id = ?",
+        PAIRING_COLUMNS
+    ))
+    .bind(request_id)
+    .fetch_optional(&state.db)
+    .await?)
+}
+
+pub async fn list_requests(state: &AppState, filter: Option<PairingState>) -> Result<Vec<PairingRequest>, PairingError> {
+    Ok(sqlx::query_as::<_, PairingRequest>(&format!(
+        "SELECT {} FROM pairing_requests WHERE ? IS NULL OR state = ? ORDER BY requested_at DESC",
+        PAIRING_COLUMNS
+    ))
+    .bind(filter.map(|state| state.as_str()))
+    .bind(filter.map(|state| state.as_str()))
+    .fetch_all(&state.db)
+    .await?)
+}
+
+/// Handles an announcement from a satellite that wants to be paired.
+pub async fn announce(state: &AppState, announcement: Announcement) -> Result<(), PairingError> {
+    let mac = normalize_mac(&announcement.mac_address)?;
+
+    let existing = sqlx::query_as::<_, PairingRequest>(&format!(
+        "SELECT {} FROM pairing_requests WHERE mac_address = ?",
+        PAIRING_COLUMNS
+    ))
+    .bind(&mac)
+    .fetch_optional(&state.db)
+    .await?;
+    let current = existing.as_ref().map(|request| parse_state(&request.state));
+
+    let reply = match transition(current, PairingAction::Announce)? {
+        (_, PairingEffect::AwaitApproval) => {
+            let request_id = existing
+                .as_ref()
+                .map(|request| request.id.clone())
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+            sqlx::query(
+                "INSERT INTO pairing_requests (id, mac_address, name, ip_address, state, requested_at) VALUES (?, ?, ?, ?, 'pending', ?)
+                 ON CONFLICT(mac_address) DO UPDATE SET
+                     name = COALESCE(excluded.name, pairing_requests.name),
+                     ip_address = COALESCE(excluded.ip_address, pairing_requests.ip_address),
+                     requested_at = excluded.requested_at",
+            )
+            .bind(&request_id)
+            .bind(&mac)
+            .bind(&announcement.name)
+            .bind(&announcement.ip_address)
+            .bind(Utc::now())
+            .execute(&state.db)
+            .await?;
+
+            if existing.is_none() {
+                info!("Satellite {} asked to be paired", mac);
+                state.events.publish(Event::PairingRequested {
+                    request_id,
+                    mac_address: mac.clone(),
+                });
+            }
+            json!({ "status": "pending" })
+        }
+        (decided, _) => json!({
+            "status": decided.as_str(),
+            "satellite_id": existing.and_then(|request| request.satellite_id),
+        }),
+    };
+
+    publish_reply(state, &mac, &reply).await;
+    Ok(())
+}
+
+/// Approves a request and creates the satellite. The satellite is only told
+/// its id; the secret is returned for the admin to provision.
+pub async fn approve(
+    state: &AppState,
+    request_id: &str,
+    name: Option<&str>,
+    admin_id: &str,
+) -> Result<Credentials, PairingError> {
+    let request = find_request(state, request_id).await?.ok_or(PairingError::NotFound)?;
+    transition(Some(parse_state(&request.state)), PairingAction::Approve)?;
+
+    let name = name
+        .or(request.name.as_deref())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Satellite {}", &request.mac_address[9..]));
+
+    let mut tx = state.db.begin().await?;
+    // A satellite from before pairing existed keeps its id and history
+    let unpaired: Option<String> =
+        sqlx::query_scalar("SELECT id FROM satellites WHERE mac_address = ? AND secret_hash IS NULL")
+            .bind(&request.mac_address)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let credentials = Credentials {
+        satellite_id: unpaired.clone().unwrap_or_else(|| Uuid::new_v4().to_string()),
+        secret: generate_secret(),
+    };
+    if unpaired.is_some() {
+        sqlx::query(
+            "UPDATE satellites SET name = ?, ip_address = COALESCE(?, ip_address), secret_hash = ? WHERE id = ?",
+        )
+        .bind(&name)
+        .bind(&request.ip_address)
+        .bind(hash_secret(&credentials.secret))
+        .bind(&credentials.satellite_id)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        sqlx::query(
+            "INSERT INTO satellites (id, name, mac_address, ip_address, status, secret_hash) VALUES (?, ?, ?, ?, 'offline', ?)",
+        )
+        .bind(&credentials.satellite_id)
+        .bind(&name)
+        .bind(&request.mac_address)
+        .bind(&request.ip_address)
+        .bind(hash_secret(&credentials.secret))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                PairingError::AlreadyPaired(request.mac_address.clone())
+            }
+            e => e.into(),
+        })?;
+    }
+
+    sqlx::query(
+        "UPDATE pairing_requests SET state = 'approved', satellite_id = ?, decided_at = ?, decided_by = ? WHERE id = ?",
+    )
+    .bind(&credentials.satellite_id)
+    .bind(Utc::now())
+    .bind(admin_id)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    info!("Satellite {} paired as {}", request.mac_address, credentials.satellite_id);
+    publish_reply(
+        state,
+        &request.mac_address,
+        &json!({
+            "status": "approved",
+            "satellite_id": credentials.satellite_id,
+        }),
+    )
+    .await;
+    state.events.publish(Event::SatelliteStatus {
+        satellite_id: credentials.satellite_id.clone(),
+        status: "paired".to_string(),
+    });
+
+    Ok(credentials)
+}
+
+pub async fn reject(state: &AppState, request_id: &str, admin_id: &str) -> Result<PairingRequest, PairingError> {
+    let request = find_request(state, request_id).await?.ok_or(PairingError::NotFound)?;
+    transition(Some(parse_state(&request.state)), PairingAction::Reject)?;
+
+    let request = sqlx::query_as::<_, PairingRequest>(&format!(
+        "UPDATE pairing_requests SET state = 'rejected', decided_at = ?, decided_by = ? WHERE id = ? RETURNING {}",
+        PAIRING_COLUMNS
+    ))
+    .bind(Utc::now())
+    .bind(admin_id)
+    .bind(request_id)
+    .fetch_one(&state.db)
+    .await?;
+
+    info!("Pairing of satellite {} rejected", request.mac_address);
+    publish_reply(state, &request.mac_address, &json!({ "status": "rejected" })).await;
+    Ok(request)
+}
+
+/// Forgets a request so the satellite can announce itself again. The
+/// satellite created by an approved request is kept.
+pub async fn delete_request(state: &AppState, request_id: &str) -> Result<(), PairingError> {
+    let result = sqlx::query("DELETE FROM pairing_requests WHERE id = ?")
+        .bind(request_id)
+        .execute(&state.db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(PairingError::NotFound);
+    }
+    Ok(())
+}
+
+/// Checks the secret a satellite sent. Satellites without a secret, such as
+/// those added before pairing existed, count as unpaired until they pair.
+pub async fn verify_secret(state: &AppState, satellite_id: &str, secret: Option<&str>) -> Result<bool, PairingError> {
+    let stored: Option<Option<String>> = sqlx::query_scalar("SELECT secret_hash FROM satellites WHERE id = ?")
+        .bind(satellite_id)
+        .fetch_optional(&state.db)
+        .await?;
+
+    Ok(match stored.flatten() {
+        Some(hash) => secret.is_some_and(|secret| hash_secret(secret) == hash),
+        None => false,
+    })
+}
+
+fn parse_state(state: &str) -> PairingState {
+    state.parse().unwrap_or(PairingState::Pending)
+}
+
+async fn publish_reply(state: &AppState, mac: &str, payload: &Value) {
+    let Some(mqtt) = &state.mqtt else { return };
+
+    if let Err(e) = mqtt.publish(&reply_topic(mac), &payload.to_string()).await {
+        warn!("Failed to send pairing reply to {}: {}", mac, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{config::Settings, mqtt::MqttService, test_support};
+
+    use PairingAction::*;
+    use PairingState::*;
+
+    #[test]
+    fn announcing_asks_for_approval_until_decided() {
+        assert_eq!(transition(None, Announce).unwrap(), (Pending, PairingEffect::AwaitApproval));
+        assert_eq!(transition(Some(Pending), Announce).unwrap(), (Pending, PairingEffect::AwaitApproval));
+        assert_eq!(transition(Some(Approved), Announce).unwrap(), (Approved, PairingEffect::Remind));
+        assert_eq!(transition(Some(Rejected), Announce).unwrap(), (Rejected, PairingEffect::Remind));
+    }
+
+    #[test]
+    fn pending_and_rejected_requests_can_be_approved() {
+        assert_eq!(transition(Some(Pending), Approve).unwrap(), (Approved, PairingEffect::Pair));
+        assert_eq!(transition(Some(Rejected), Approve).unwrap(), (Approved, PairingEffect::Pair));
+    }
+
+    #[test]
+    fn pending_requests_can_be_rejected() {
+        assert_eq!(transition(Some(Pending), Reject).unwrap(), (Rejected, PairingEffect::Reject));
+    }
+
+    #[test]
+    fn decisions_cannot_be_repeated_or_undone() {
+        for (from, action) in [(Approved, Approve), (Approved, Reject), (Rejected, Reject)] {
+            match transition(Some(from), action) {
+                Err(PairingError::InvalidTransition { from: f, action: a }) => assert_eq!((f, a), (from, action)),
+                other => panic!("{:?} {:?} gave {:?}", from, action, other),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_requests_cannot_be_decided() {
+        assert!(matches!(transition(None, Approve), Err(PairingError::NotFound)));
+        assert!(matches!(transition(None, Reject), Err(PairingError::NotFound)));
+    }
+
+    #[test]
+    fn mac_addresses_are_normalised() {
+        assert_eq!(normalize_mac("AA-BB-CC-DD-EE-FF").unwrap(), "aa:bb:cc:dd:ee:ff");
+        assert_eq!(normalize_mac("aabbccddeeff").unwrap(), "aa:bb:cc:dd:ee:ff");
+        assert!(normalize_mac("aa:bb:cc:dd:ee").is_err());
+        assert!(normalize_mac("aa:bb:cc:dd:ee:gg").is_err());
+    }
+
+    #[tokio::test]
+    async fn satellites_without_a_secret_are_unpaired() {
+        let state = test_support::state().await;
+        sqlx::query("INSERT INTO satellites (id, name, mac_address, status) VALUES ('old', 'Old', 'aa:bb:cc:dd:ee:ff', 'online')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        assert!(!verify_secret(&state, "old", None).await.unwrap());
+        assert!(!verify_secret(&state, "old", Some("anything")).await.unwrap());
+        assert!(!verify_secret(&state, "missing", Some("anything")).await.unwrap());
+
+        // Pairing again issues a secret to the same satellite
+        let admin = test_support::user(&state, "ops", "admin").await;
+        announce(
+            &state,
+            Announcement {
+                mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
+                name: None,
+                ip_address: None,
+            },
+        )
+        .await
+        .unwrap();
+        let request = list_requests(&state, Some(Pending)).await.unwrap().remove(0);
+        let credentials = approve(&state, &request.id, None, &admin).await.unwrap();
+        assert_eq!(credentials.satellite_id, "old");
+        assert!(verify_secret(&state, "old", Some(&credentials.secret)).await.unwrap());
+        assert!(!verify_secret(&state, "old", Some("anything")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn paired_satellites_cannot_be_paired_twice() {
+        let state = test_support::state().await;
+        sqlx::query("INSERT INTO satellites (id, name, mac_address, status, secret_hash) VALUES ('new', 'New', 'aa:bb:cc:dd:ee:ff', 'online', 'hash')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let admin = test_support::user(&state, "ops", "admin").await;
+        announce(
+            &state,
+            Announcement {
+                mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+                name: None,
+                ip_address: None,
+            },
+        )
+        .await
+        .unwrap();
+        let request = list_requests(&state, Some(Pending)).await.unwrap().remove(0);
+        assert!(matches!(
+            approve(&state, &request.id, None, &admin).await,
+            Err(PairingError::AlreadyPaired(_))
+        ));
+    }
+
+    async fn reply(replies: &mut mpsc::Receiver<String>) -> String {
+        tokio::time::timeout(Duration::from_secs(10), replies.recv())
+            .await
+            .expect("no pairing reply")
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_secret_is_never_published() {
+        let port = test_support::start_broker();
+        let mut config = Settings::new().unwrap();
+        config.mqtt.enabled = true;
+        config.mqtt.broker = "127.0.0.1".to_string();
+        config.mqtt.port = port;
+        let mut state = test_support::state_with(config).await;
+        let (mqtt, _messages) = MqttService::new(&state.config.mqtt).await.unwrap();
+        state.mqtt = Some(mqtt);
+
+        // Anyone on the broker can listen to the satellite's replies
+        let (client, mut eventloop) = AsyncClient::new(MqttOptions::new("eavesdropper", "127.0.0.1", port), 10);
+        client.subscribe(reply_topic("aa:bb:cc:dd:ee:ff"), QoS::AtLeastOnce).await.unwrap();
+        let (sender, mut replies) = mpsc::channel(10);
+        tokio::spawn(async move {
+            while let Ok(event) = eventloop.poll().await {
+                if let MqttEvent::Incoming(Packet::Publish(publish)) = event {
+                    let _ = sender.send(String::from_utf8(publish.payload.to_vec()).unwrap()).await;
+                }
+            }
+        });
+        // Give both clients time to connect and subscribe
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let announcement = Announcement {
+            mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
+            name: Some("Kitchen".to_string()),
+            ip_address: None,
+        };
+        announce(&state, announcement).await.unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&reply(&mut replies).await).unwrap(), json!({"status": "pending"}));
+
+        let admin = test_support::user(&state, "ops", "admin").await;
+        let request = list_requests(&state, Some(Pending)).await.unwrap().remove(0);
+        let credentials = approve(&state, &request.id, None, &admin).await.unwrap();
+        let approved = reply(&mut replies).await;
+        assert!(!approved.contains(&credentials.secret), "{}", approved);
+        assert_eq!(
+            serde_json::from_str::<Value>(&approved).unwrap(),
+            json!({"status": "approved", "satellite_id": credentials.satellite_id})
+        );
+    }
+}
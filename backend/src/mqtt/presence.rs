@@ -0,0 +1,303 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::pairing;
+use crate::{events::Event, AppState};
+
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Body of `status/heartbeat` and `status/online`. `secret` is the one
+/// issued when the satellite was paired.
+#[derive(Debug, Default, Deserialize)]
+pub struct Heartbeat {
+    pub secret: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// Body of `status/offline`. Satellites should register that topic as their
+/// MQTT last will with `{"secret": "...", "reason": "connection_lost"}` so the
+/// broker reports them gone without waiting for the heartbeat timeout.
+#[derive(Debug, Default, Deserialize)]
+pub struct Goodbye {
+    pub secret: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Records that a paired satellite was heard from and marks it online.
+/// Heartbeats from unknown satellites or with the wrong secret are ignored.
+pub async fn record_seen(state: &AppState, satellite_id: &str, heartbeat: &Heartbeat) -> Result<()> {
+    if !pairing::verify_secret(state, satellite_id, heartbeat.secret.as_deref()).await? {
+        debug!("Ignoring heartbeat from unpaired satellite {}", satellite_id);
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let mut tx = state.db.begin().await?;
+
+    // Satellites added before statuses were tracked have none
+    let previous: Option<String> = sqlx::query_scalar("SELECT status FROM satellites WHERE id = ?")
+        .bind(satellite_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        "UPDATE satellites SET ip_address = COALESCE(?, ip_address), status = 'online', last_seen = ? WHERE id = ?",
+    )
+    .bind(&heartbeat.ip_address)
+    .bind(now)
+    .bind(satellite_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let came_online = previous.as_deref() != Some("online");
+    if came_online {
+        sqlx::query("INSERT INTO satellite_uptime (id, satellite_id, online_at) VALUES (?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(satellite_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    if came_online {
+        info!("Satellite {} is online", satellite_id);
+        state.events.publish(Event::SatelliteStatus {
+            satellite_id: satellite_id.to_string(),
+            status: "online".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Handles `status/offline`, including the broker delivering a last will.
+pub async fn say_goodbye(state: &AppState, satellite_id: &str, goodbye: &Goodbye) -> Result<()> {
+    if !pairing::verify_secret(state, satellite_id, goodbye.secret.as_deref()).await? {
+        debug!("Ignoring offline notice from unpaired satellite {}", satellite_id);
+        return Ok(());
+    }
+
+    let reason = goodbye.reason.as_deref().unwrap_or("offline");
+    mark_offline(state, satellite_id, reason, Utc::now()).await
+}
+
+/// Marks a satellite offline as of `at` and closes its open uptime period.
+/// Does nothing if the satellite is unknown or already offline.
+pub async fn mark_offline(state: &AppState, satellite_id: &str, reason: &str, at: DateTime<Utc>) -> Result<()> {
+    let mut tx = state.db.begin().await?;
+
+    let result = sqlx::query("UPDATE satellites SET status = 'offline' WHERE id = ? AND status = 'online'")
+        .bind(satellite_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "UPDATE satellite_uptime SET offline_at = ?, offline_reason = ? WHERE satellite_id = ? AND offline_at IS NULL",
+    )
+    .bind(at)
+    .bind(reason)
+    .bind(satellite_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    info!("Satellite {} is offline ({})", satellite_id, reason);
+    state.events.publish(Event::SatelliteStatus {
+        satellite_id: satellite_id.to_string(),
+        status: "offline".to_string(),
+    });
+    Ok(())
+}
+
+/// Marks satellites offline that haven't sent a heartbeat within
+/// `mqtt.heartbeat_timeout_secs`. Their uptime ends when they were last seen.
+///
+/// This also cleans up satellites left online when the server last stopped.
+pub async fn expire_silent(state: &AppState) -> Result<()> {
+    let cutoff = Utc::now() - Duration::seconds(state.config.mqtt.heartbeat_timeout_secs as i64);
+
+    let online: Vec<(String, Option<DateTime<Utc>>)> =
+        sqlx::query_as("SELECT id, last_seen FROM satellites WHERE status = 'online'")
+            .fetch_all(&state.db)
+            .await?;
+
+    for (satellite_id, last_seen) in online {
+        match last_seen {
+            Some(last_seen) if last_seen > cutoff => {}
+            Some(last_seen) => mark_offline(state, &satellite_id, "timeout", last_seen).await?,
+            None => mark_offline(state, &satellite_id, "timeout", Utc::now()).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically runs [`expire_silent`]. Runs even without a broker so
+/// satellites left online by a previous run are closed off.
+pub async fn watch(state: AppState) {
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        sweep.tick().await;
+        if let Err(e) = expire_silent(&state).await {
+            warn!("Failed to check satellite heartbeats: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const SECRET: &str = "s3cret";
+
+    async fn satellite(state: &AppState, id: &str, status: Option<&str>) {
+        sqlx::query("INSERT INTO satellites (id, name, status, secret_hash) VALUES (?, ?, ?, ?)")
+            .bind(id)
+            .bind(id)
+            .bind(status)
+            .bind(pairing::hash_secret(SECRET))
+            .execute(&state.db)
+            .await
+            .unwrap();
+    }
+
+    fn heartbeat(secret: &str) -> Heartbeat {
+        Heartbeat {
+            secret: Some(secret.to_string()),
+            ip_address: Some("192.168.1.20".to_string()),
+        }
+    }
+
+    async fn status(state: &AppState, id: &str) -> Option<String> {
+        sqlx::query_scalar("SELECT status FROM satellites WHERE id = ?")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap()
+    }
+
+    /// Uptime periods as (offline yet, reason), oldest first.
+    async fn periods(state: &AppState, id: &str) -> Vec<(bool, Option<String>)> {
+        sqlx::query_as(
+            "SELECT offline_at IS NOT NULL, offline_reason FROM satellite_uptime WHERE satellite_id = ? ORDER BY online_at",
+        )
+        .bind(id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap()
+    }
+
+    fn status_events(events: &mut tokio::sync::broadcast::Receiver<crate::events::EventEnvelope>) -> Vec<String> {
+        std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|envelope| match envelope.event {
+                Event::SatelliteStatus { status, .. } => Some(status),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn the_first_heartbeat_brings_a_satellite_online() {
+        let state = test_support::state().await;
+        satellite(&state, "kitchen", Some("offline")).await;
+        let mut events = state.events.subscribe();
+
+        record_seen(&state, "kitchen", &heartbeat(SECRET)).await.unwrap();
+        record_seen(&state, "kitchen", &heartbeat(SECRET)).await.unwrap();
+
+        assert_eq!(status(&state, "kitchen").await.as_deref(), Some("online"));
+        assert_eq!(periods(&state, "kitchen").await, [(false, None)]);
+        assert_eq!(status_events(&mut events), ["online"]);
+        let ip: Option<String> = sqlx::query_scalar("SELECT ip_address FROM satellites WHERE id = 'kitchen'")
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(ip.as_deref(), Some("192.168.1.20"));
+    }
+
+    #[tokio::test]
+    async fn satellites_without_a_status_come_online() {
+        let state = test_support::state().await;
+        satellite(&state, "legacy", None).await;
+
+        record_seen(&state, "legacy", &heartbeat(SECRET)).await.unwrap();
+        assert_eq!(status(&state, "legacy").await.as_deref(), Some("online"));
+        assert_eq!(periods(&state, "legacy").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn heartbeats_with_the_wrong_secret_are_ignored() {
+        let state = test_support::state().await;
+        satellite(&state, "kitchen", Some("offline")).await;
+
+        record_seen(&state, "kitchen", &heartbeat("guess")).await.unwrap();
+        record_seen(&state, "kitchen", &Heartbeat::default()).await.unwrap();
+        record_seen(&state, "unknown", &heartbeat(SECRET)).await.unwrap();
+        assert_eq!(status(&state, "kitchen").await.as_deref(), Some("offline"));
+        assert!(periods(&state, "kitchen").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn going_offline_closes_the_uptime_period_once() {
+        let state = test_support::state().await;
+        satellite(&state, "kitchen", Some("offline")).await;
+        record_seen(&state, "kitchen", &heartbeat(SECRET)).await.unwrap();
+        let mut events = state.events.subscribe();
+
+        let goodbye = Goodbye {
+            secret: Some(SECRET.to_string()),
+            reason: Some("connection_lost".to_string()),
+        };
+        say_goodbye(&state, "kitchen", &goodbye).await.unwrap();
+        mark_offline(&state, "kitchen", "timeout", Utc::now()).await.unwrap();
+
+        assert_eq!(status(&state, "kitchen").await.as_deref(), Some("offline"));
+        assert_eq!(periods(&state, "kitchen").await, [(true, Some("connection_lost".to_string()))]);
+        assert_eq!(status_events(&mut events), ["offline"]);
+
+        // Back again starts a new period
+        record_seen(&state, "kitchen", &heartbeat(SECRET)).await.unwrap();
+        assert_eq!(periods(&state, "kitchen").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn silent_satellites_expire_as_of_when_they_were_last_seen() {
+        let state = test_support::state().await;
+        satellite(&state, "quiet", Some("offline")).await;
+        satellite(&state, "chatty", Some("offline")).await;
+        satellite(&state, "restarted", Some("online")).await;
+        record_seen(&state, "quiet", &heartbeat(SECRET)).await.unwrap();
+        record_seen(&state, "chatty", &heartbeat(SECRET)).await.unwrap();
+
+        let last_seen = Utc::now() - Duration::hours(1);
+        sqlx::query("UPDATE satellites SET last_seen = ? WHERE id = 'quiet'")
+            .bind(last_seen)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        expire_silent(&state).await.unwrap();
+
+        assert_eq!(status(&state, "quiet").await.as_deref(), Some("offline"));
+        assert_eq!(status(&state, "chatty").await.as_deref(), Some("online"));
+        // Left online by a previous run, never seen
+        assert_eq!(status(&state, "restarted").await.as_deref(), Some("offline"));
+        let offline_at: DateTime<Utc> =
+            sqlx::query_scalar("SELECT offline_at FROM satellite_uptime WHERE satellite_id = 'quiet'")
+                .fetch_one(&state.db)
+                .await
+                .unwrap();
+        assert_eq!(offline_at, last_seen);
+        assert_eq!(periods(&state, "quiet").await, [(true, Some("timeout".to_string()))]);
+    }
+}
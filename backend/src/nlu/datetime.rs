@@ -0,0 +1,656 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Weekday};
+use regex::Regex;
+
+/// Numbers as they come out of speech-to-text, digits or words.
+pub const NUMBER_PATTERN: &str = r"\d+(?:\.\d+)?|(?:twenty|thirty|forty|fifty)(?:[- ](?:one|two|three|four|five|six|seven|eight|nine))?|an?|one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|thirteen|fourteen|fifteen|sixteen|seventeen|eighteen|nineteen|sixty|ninety";
+const UNIT_PATTERN: &str = r"hours?|hrs?|minutes?|mins?|seconds?|secs?";
+const WEEKDAY_PATTERN: &str = r"monday|tuesday|wednesday|thursday|friday|saturday|sunday";
+const MONTH_PATTERN: &str =
+    r"january|february|march|april|may|june|july|august|september|october|november|december";
+const CLOCK_TIME_PATTERN: &str =
+    r"noon|midday|midnight|\d{1,2}(?:[:.]\d{2})?\s*[ap]\.?\s?m\b\.?|\d{1,2}:\d{2}|\d{1,2}\s+o'?clock";
+
+/// Time of day for a reminder that only says which day, e.g. "tomorrow".
+const DEFAULT_HOUR: u32 = 9;
+
+static DURATION_PART: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b({})\s*({})\b(\s+and\s+a\s+half)?",
+        NUMBER_PATTERN, UNIT_PATTERN
+    ))
+    .unwrap()
+});
+static HALF_DURATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bhalf an? (hour|minute)\b").unwrap());
+
+static WITH_MERIDIEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2})(?:[:.](\d{2}))?\s*([ap])\.?\s?m\b\.?").unwrap());
+static CLOCK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(\d{1,2}):(\d{2})\b").unwrap());
+/// "at 7", "7 o'clock", or a bare "7" as found by `find_clock_time`.
+static HOUR_ONLY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:at|for)\s+(\d{1,2})\b|\b(\d{1,2})\s+o'?clock\b|^\s*(\d{1,2})\s*$").unwrap()
+});
+static CLOCK_TIME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(?:at|for)\s+({}|\d{{1,2}}\b)|\b({})",
+        CLOCK_TIME_PATTERN, CLOCK_TIME_PATTERN
+    ))
+    .unwrap()
+});
+
+static MORNING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bmorning\b").unwrap());
+static AFTERNOON: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bafternoon\b").unwrap());
+static EVENING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(evening|tonight|night)\b").unwrap());
+
+static TODAY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(today|tonight|this (morning|afternoon|evening))\b").unwrap());
+static WEEKDAY: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!(r"\b({})s?\b", WEEKDAY_PATTERN)).unwrap());
+static MONTH_DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"\b(\d{{1,2}})(?:st|nd|rd|th)?\s+(?:of\s+)?({months})\b|\b({months})\s+(?:the\s+)?(\d{{1,2}})(?:st|nd|rd|th)?\b",
+        months = MONTH_PATTERN
+    ))
+    .unwrap()
+});
+static WEEKEND: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bweekend\b").unwrap());
+static DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(?:the\s+)?day\s+after\s+tomorrow\b|\b(?:today|tonight|tomorrow)(?:\s+(?:morning|afternoon|evening|night))?\b|\b(?:this|next|over\s+the|at\s+the)\s+weekend\b|\bweekend\b|\bthis\s+(?:morning|afternoon|evening)\b|\b(?:(?:on|this|next)\s+)?(?:{weekdays})\b|\b(?:on\s+)?(?:the\s+)?\d{{1,2}}(?:st|nd|rd|th)?\s+(?:of\s+)?(?:{months})\b|\b(?:on\s+)?(?:{months})\s+(?:the\s+)?\d{{1,2}}(?:st|nd|rd|th)?\b",
+        weekdays = WEEKDAY_PATTERN,
+        months = MONTH_PATTERN
+    ))
+    .unwrap()
+});
+
+static EVERY_WEEKDAY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(every\s+(weekday|work\s*day)|weekdays|on\s+work\s*days)\b").unwrap());
+static EVERY_WEEK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"\b(every\s+(week|{})|weekly|each\s+week)\b", WEEKDAY_PATTERN)).unwrap()
+});
+static EVERY_DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(every\s+(day|morning|afternoon|evening|night)|daily|each\s+day)\b").unwrap()
+});
+
+/// Date, time and recurrence phrases `strip_date_time` takes out, in order.
+static DATE_TIME_PHRASES: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        format!(r"\bin\s+(?:{n})\s*(?:{u})(?:\s+and\s+(?:a\s+half|(?:{n})\s*(?:{u})))?", n = NUMBER_PATTERN, u = UNIT_PATTERN),
+        r"\bin\s+half\s+an?\s+(?:hour|minute)".to_string(),
+        format!(r"\b(?:(?:at|by|around|for)\s+)?(?:{})", CLOCK_TIME_PATTERN),
+        r"\b(?:at|by|around)\s+\d{1,2}\b".to_string(),
+        r"\b(?:the\s+)?day\s+after\s+tomorrow\b".to_string(),
+        r"\b(?:tomorrow|today|tonight)\b".to_string(),
+        r"\b(?:this|in\s+the|every|each)\s+(?:morning|afternoon|evening|night)\b".to_string(),
+        r"\b(?:(?:this|next|over\s+the|at\s+the|on\s+the)\s+)?weekend\b".to_string(),
+        r"\b(?:morning|afternoon|evening)\b".to_string(),
+        format!(r"\b(?:(?:on|next|this|every|each)\s+)?(?:{})s?\b", WEEKDAY_PATTERN),
+        format!(
+            r"\b(?:on\s+)?(?:the\s+)?\d{{1,2}}(?:st|nd|rd|th)?\s+(?:of\s+)?(?:{m})\b|\b(?:on\s+)?(?:{m})\s+(?:the\s+)?\d{{1,2}}(?:st|nd|rd|th)?\b",
+            m = MONTH_PATTERN
+        ),
+        r"\b(?:every\s+(?:day|week|weekday|work\s*day)|each\s+(?:day|week)|daily|weekly|(?:on\s+)?weekdays|on\s+work\s*days)\b".to_string(),
+    ]
+    .iter()
+    .map(|phrase| Regex::new(&format!("(?i){}", phrase)).unwrap())
+    .collect()
+});
+
+/// The value of a number as written in `NUMBER_PATTERN`.
+pub fn parse_number(word: &str) -> Option<f64> {
+    let word = word.to_lowercase();
+    if let Ok(value) = word.parse() {
+        return Some(value);
+    }
+
+    // "twenty five" and "twenty-five" add up their parts
+    word.split([' ', '-']).map(word_value).sum()
+}
+
+fn word_value(word: &str) -> Option<f64> {
+    let value = match word {
+        "a" | "an" | "one" => 1,
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" => 12,
+        "thirteen" => 13,
+        "fourteen" => 14,
+        "fifteen" => 15,
+        "sixteen" => 16,
+        "seventeen" => 17,
+        "eighteen" => 18,
+        "nineteen" => 19,
+        "twenty" => 20,
+        "thirty" => 30,
+        "forty" => 40,
+        "fifty" => 50,
+        "sixty" => 60,
+        "ninety" => 90,
+        _ => return None,
+    };
+    Some(value as f64)
+}
+
+fn unit_seconds(unit: &str) -> f64 {
+    match unit.to_lowercase().chars().next() {
+        Some('h') => 3600.0,
+        Some('m') => 60.0,
+        _ => 1.0,
+    }
+}
+
+/// Adds up every "<number> <unit>" in the text, so "1 hour and 30 minutes",
+/// "an hour and a half" and "half an hour" all work.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text
+        .to_lowercase()
+        .replace("half an hour", "30 minutes")
+        .replace("half a minute", "30 seconds");
+
+    let mut seconds = 0.0;
+    for captures in DURATION_PART.captures_iter(&text) {
+        let count = parse_number(&captures[1])?;
+        let half = if captures.get(3).is_some() { 0.5 } else { 0.0 };
+        seconds += (count + half) * unit_seconds(&captures[2]);
+    }
+
+    (seconds >= 1.0).then(|| Duration::seconds(seconds.round() as i64))
+}
+
+/// Where the duration is in the text, from its first part to its last.
+pub fn find_duration(text: &str) -> Option<(usize, usize)> {
+    let start = DURATION_PART.find_iter(text).chain(HALF_DURATION.find_iter(text)).map(|m| m.start()).min()?;
+    let end = DURATION_PART.find_iter(text).chain(HALF_DURATION.find_iter(text)).map(|m| m.end()).max()?;
+    Some((start, end))
+}
+
+/// "1 hour and 30 minutes", "5 minutes", "45 seconds". Seconds are left out
+/// once a duration runs into hours.
+pub fn describe_duration(duration: Duration) -> String {
+    let total = duration.num_seconds().max(0);
+    let (hours, minutes, seconds) = (total / 3600, total % 3600 / 60, total % 60);
+
+    let mut parts = Vec::new();
+    for (count, unit) in [(hours, "hour"), (minutes, "minute"), (seconds, "second")] {
+        if count == 0 || (unit == "second" && hours > 0) {
+            continue;
+        }
+        parts.push(format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" }));
+    }
+
+    match parts.len() {
+        0 => "0 seconds".to_string(),
+        1 => parts.remove(0),
+        _ => {
+            let last = parts.pop().unwrap_or_default();
+            format!("{} and {}", parts.join(", "), last)
+        }
+    }
+}
+
+/// Clock times the text could mean: "7:30 pm" and "19:30" are one, "at 7" is
+/// either 7:00 or 19:00.
+fn clock_times(text: &str) -> Vec<NaiveTime> {
+    // "for 10 minutes" is a duration, not ten o'clock
+    let text = DURATION_PART.replace_all(&text.to_lowercase(), " ").into_owned();
+    if text.contains("noon") || text.contains("midday") {
+        return NaiveTime::from_hms_opt(12, 0, 0).into_iter().collect();
+    }
+    if text.contains("midnight") {
+        return NaiveTime::from_hms_opt(0, 0, 0).into_iter().collect();
+    }
+
+    if let Some(captures) = WITH_MERIDIEM.captures(&text) {
+        let hour: u32 = captures[1].parse().unwrap_or(0);
+        let minute: u32 = captures.get(2).map_or(Some(0), |m| m.as_str().parse().ok()).unwrap_or(0);
+        if !(1..=12).contains(&hour) {
+            return Vec::new();
+        }
+        let hour = match &captures[3] {
+            "a" => hour % 12,
+            _ => hour % 12 + 12,
+        };
+        return NaiveTime::from_hms_opt(hour, minute, 0).into_iter().collect();
+    }
+
+    let (hour, minute) = if let Some(captures) = CLOCK.captures(&text) {
+        (captures[1].parse().unwrap_or(99), captures[2].parse().unwrap_or(99))
+    } else if let Some(captures) = HOUR_ONLY.captures(&text) {
+        let hour = captures.get(1).or_else(|| captures.get(2)).or_else(|| captures.get(3)).map(|m| m.as_str());
+        (hour.and_then(|h| h.parse().ok()).unwrap_or(99), 0)
+    } else {
+        return Vec::new();
+    };
+
+    let Some(time) = NaiveTime::from_hms_opt(hour, minute, 0) else {
+        return Vec::new();
+    };
+    if (1..=12).contains(&hour) {
+        let evening = NaiveTime::from_hms_opt(hour % 12 + 12, minute, 0);
+        let morning = NaiveTime::from_hms_opt(hour % 12, minute, 0);
+        return morning.into_iter().chain(evening).collect();
+    }
+    vec![time]
+}
+
+/// The next moment the clock shows the time in `text`, e.g. "7am",
+/// "7:30 pm", "19:45", "noon" or "6 o'clock". Without am/pm it is whichever
+/// of the morning or evening time comes first. "tomorrow" skips today.
+pub fn parse_clock_time(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    next_clock_time(text, text.to_lowercase().contains("tomorrow"), now)
+}
+
+/// The next moment the clock shows `time`, e.g. a `time` entity such as
+/// "7:30 pm" or "7", skipping today when it is for `tomorrow`.
+pub fn next_clock_time(time: &str, tomorrow: bool, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let today = now.date_naive();
+
+    clock_times(time)
+        .into_iter()
+        .filter_map(|time| {
+            let date = if tomorrow { today.succ_opt()? } else { today };
+            let at = Local.from_local_datetime(&date.and_time(time)).earliest()?;
+            if at > now {
+                Some(at)
+            } else {
+                Local
+                    .from_local_datetime(&date.succ_opt()?.and_time(time))
+                    .earliest()
+            }
+        })
+        .min()
+}
+
+/// Where a clock time is in the text.
+pub fn find_clock_time(text: &str) -> Option<(usize, usize)> {
+    let durations: Vec<(usize, usize)> = DURATION_PART
+        .find_iter(text)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+
+    let found = CLOCK_TIME
+        .captures_iter(text)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|found| (found.start(), found.end()))
+        .find(|(start, end)| !durations.iter().any(|(from, to)| start < to && from < end));
+    found
+}
+
+/// "7:30 AM", the way Barnaby reads a time out loud.
+pub fn describe_clock_time(at: DateTime<Local>) -> String {
+    at.format("%-I:%M %p").to_string()
+}
+
+/// Part of the day a command mentions, used to settle "at 7" and to stand in
+/// for a time when there is none, as in "tomorrow morning".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayPeriod {
+    Morning,
+    Afternoon,
+    Evening,
+}
+
+impl DayPeriod {
+    pub fn find(text: &str) -> Option<Self> {
+        if MORNING.is_match(text) {
+            Some(DayPeriod::Morning)
+        } else if AFTERNOON.is_match(text) {
+            Some(DayPeriod::Afternoon)
+        } else if EVENING.is_match(text) {
+            Some(DayPeriod::Evening)
+        } else {
+            None
+        }
+    }
+
+    fn contains(&self, time: &NaiveTime) -> bool {
+        match self {
+            DayPeriod::Morning => time.hour() < 12,
+            DayPeriod::Afternoon => (12..18).contains(&time.hour()),
+            DayPeriod::Evening => time.hour() >= 17,
+        }
+    }
+
+    /// The hours a forecast for this part of the day covers.
+    pub fn hours(&self) -> std::ops::Range<u32> {
+        match self {
+            DayPeriod::Morning => 6..12,
+            DayPeriod::Afternoon => 12..18,
+            DayPeriod::Evening => 18..24,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DayPeriod::Morning => "morning",
+            DayPeriod::Afternoon => "afternoon",
+            DayPeriod::Evening => "evening",
+        }
+    }
+
+    fn default_time(&self) -> NaiveTime {
+        let hour = match self {
+            DayPeriod::Morning => DEFAULT_HOUR,
+            DayPeriod::Afternoon => 15,
+            DayPeriod::Evening => 19,
+        };
+        NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or_default()
+    }
+}
+
+/// "in 20 minutes", "in an hour and a half".
+fn relative_delay(text: &str) -> Option<Duration> {
+    let (start, end) = find_duration(text)?;
+    let before = text[..start].trim_end();
+    (before == "in" || before.ends_with(" in")).then(|| parse_duration(&text[start..end]))?
+}
+
+/// Days the text could mean, soonest first. A weekday that is today could
+/// also mean the same day next week.
+fn named_days(text: &str, today: NaiveDate) -> Vec<NaiveDate> {
+    if text.contains("day after tomorrow") {
+        return today.checked_add_days(chrono::Days::new(2)).into_iter().collect();
+    }
+    if text.contains("tomorrow") {
+        return today.succ_opt().into_iter().collect();
+    }
+    if TODAY.is_match(text) {
+        return vec![today];
+    }
+
+    if let Some(day) = WEEKDAY.captures(text).and_then(|captures| captures[1].parse::<Weekday>().ok()) {
+        let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        let next_week = if ahead == 0 { vec![7] } else { Vec::new() };
+        return std::iter::once(ahead)
+            .chain(next_week)
+            .filter_map(|days| today.checked_add_days(chrono::Days::new(days.into())))
+            .collect();
+    }
+
+    if let Some((month, day)) = month_day(text) {
+        // "3rd of March" in December means next year's
+        return [today.year(), today.year() + 1]
+            .into_iter()
+            .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+            .filter(|date| *date >= today)
+            .take(1)
+            .collect();
+    }
+
+    Vec::new()
+}
+
+/// "12th of March", "march 12" as (month, day).
+fn month_day(text: &str) -> Option<(u32, u32)> {
+    let captures = MONTH_DAY.captures(text)?;
+    let day = captures.get(1).or_else(|| captures.get(4))?.as_str().parse().ok()?;
+    let month = captures.get(2).or_else(|| captures.get(3))?.as_str();
+    let month = MONTH_PATTERN.split('|').position(|name| name == month)? as u32 + 1;
+    Some((month, day))
+}
+
+/// The days a question like "will it rain this weekend?" is about: the
+/// coming Saturday and Sunday for the weekend, otherwise the day named as
+/// for reminders. Empty when no day is named.
+pub fn parse_days(text: &str, today: NaiveDate) -> Vec<NaiveDate> {
+    let text = text.to_lowercase();
+    if WEEKEND.is_match(&text) {
+        let ahead = (Weekday::Sat.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        return match today.weekday() {
+            Weekday::Sun => vec![today],
+            _ => (ahead..ahead + 2)
+                .filter_map(|days| today.checked_add_days(chrono::Days::new(days.into())))
+                .collect(),
+        };
+    }
+    named_days(&text, today).into_iter().take(1).collect()
+}
+
+/// Where a day is named in the text: "tomorrow", "on Saturday", "this weekend",
+/// "the 3rd of March".
+pub fn find_date(text: &str) -> Option<(usize, usize)> {
+    DATE.find(text).map(|found| (found.start(), found.end()))
+}
+
+/// When a reminder should go off: "at 7pm tomorrow", "in 20 minutes",
+/// "on Friday morning", "12th of March at 9:30" or just "at 6". A day without
+/// a time means 9 AM, a time without a day its next occurrence. An ambiguous
+/// hour on a named day is taken during waking hours. May be in the past,
+/// e.g. "today at 7am" said in the evening.
+pub fn parse_date_time(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let text = text.to_lowercase();
+    if let Some(delay) = relative_delay(&text) {
+        return Some(now + delay);
+    }
+
+    let mut times = clock_times(&text);
+    if let Some(period) = DayPeriod::find(&text) {
+        let in_period: Vec<NaiveTime> = times.iter().copied().filter(|time| period.contains(time)).collect();
+        if !in_period.is_empty() {
+            times = in_period;
+        }
+        if times.is_empty() {
+            times.push(period.default_time());
+        }
+    }
+
+    let days = named_days(&text, now.date_naive());
+    if days.is_empty() {
+        // Same as an alarm: the next time the clock shows it
+        return times
+            .into_iter()
+            .filter_map(|time| next_occurrence(time, now))
+            .min();
+    }
+
+    if times.is_empty() {
+        times.extend(NaiveTime::from_hms_opt(DEFAULT_HOUR, 0, 0));
+    }
+    days.iter()
+        .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+        .filter_map(|at| Local.from_local_datetime(&at).earliest())
+        .min_by_key(|at| (*at <= now, !(7..22).contains(&at.hour()), *at))
+}
+
+fn next_occurrence(time: NaiveTime, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let today = now.date_naive();
+    let at = Local.from_local_datetime(&today.and_time(time)).earliest()?;
+    if at > now {
+        Some(at)
+    } else {
+        Local.from_local_datetime(&today.succ_opt()?.and_time(time)).earliest()
+    }
+}
+
+/// How often the text says something should repeat: "daily", "weekly" or
+/// "weekdays".
+pub fn parse_recurrence(text: &str) -> Option<&'static str> {
+    let text = text.to_lowercase();
+    if EVERY_WEEKDAY.is_match(&text) {
+        Some("weekdays")
+    } else if EVERY_WEEK.is_match(&text) {
+        Some("weekly")
+    } else if EVERY_DAY.is_match(&text) {
+        Some("daily")
+    } else {
+        None
+    }
+}
+
+/// The text with every date, time and recurrence phrase taken out, leaving
+/// what the command is about.
+pub fn strip_date_time(text: &str) -> String {
+    let mut stripped = text.to_string();
+    for phrase in DATE_TIME_PHRASES.iter() {
+        stripped = phrase.replace_all(&stripped, " ").into_owned();
+    }
+
+    let words: Vec<&str> = stripped.split_whitespace().collect();
+    words.join(" ").trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace()).to_string()
+}
+
+/// "today at 7:00 PM", "tomorrow at 9:00 AM", "on Friday at 6:30 PM" or
+/// "on 12 March at 9:00 AM".
+pub fn describe_date_time(at: DateTime<Local>, now: DateTime<Local>) -> String {
+    let days = (at.date_naive() - now.date_naive()).num_days();
+    let day = match days {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        2..=6 => at.format("on %A").to_string(),
+        _ => at.format("on %-d %B").to_string(),
+    };
+    format!("{} at {}", day, describe_clock_time(at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wednesday, 4 March 2026, 10:00.
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 4, 10, 0, 0).unwrap()
+    }
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_when_reminders_go_off() {
+        let cases = [
+            ("at 7pm tomorrow", at(3, 5, 19, 0)),
+            ("in 20 minutes", at(3, 4, 10, 20)),
+            ("in an hour and a half", at(3, 4, 11, 30)),
+            ("on Friday morning", at(3, 6, 9, 0)),
+            ("on friday evening", at(3, 6, 19, 0)),
+            ("12th of March", at(3, 12, 9, 0)),
+            ("march 12 at 9:30", at(3, 12, 9, 30)),
+            ("tomorrow at noon", at(3, 5, 12, 0)),
+            // 7 AM has gone, so the next 7 on the clock is this evening
+            ("at 7", at(3, 4, 19, 0)),
+            // On a named day an ambiguous hour is taken in waking hours
+            ("tomorrow at 7", at(3, 5, 7, 0)),
+            ("tomorrow at 3", at(3, 5, 15, 0)),
+            ("tomorrow evening at 7", at(3, 5, 19, 0)),
+            // Today is a Wednesday and 9 AM has passed, so it's next week's
+            ("on wednesday", at(3, 11, 9, 0)),
+            ("on wednesday at 6pm", at(3, 4, 18, 0)),
+            ("on tuesday", at(3, 10, 9, 0)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_date_time(text, now()), Some(expected), "{}", text);
+        }
+        assert_eq!(parse_date_time("take the bins out", now()), None);
+    }
+
+    #[test]
+    fn dates_that_have_passed_this_year_are_next_years() {
+        let december = Local.with_ymd_and_hms(2026, 12, 20, 10, 0, 0).unwrap();
+        assert_eq!(
+            parse_date_time("on the 3rd of march", december),
+            Local.with_ymd_and_hms(2027, 3, 3, 9, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn parses_clock_times_for_alarms() {
+        let cases = [
+            ("7am", at(3, 5, 7, 0)),
+            ("7:30 pm", at(3, 4, 19, 30)),
+            ("19:45", at(3, 4, 19, 45)),
+            ("midnight", at(3, 5, 0, 0)),
+            ("6 o'clock", at(3, 4, 18, 0)),
+            ("11 tomorrow", at(3, 5, 11, 0)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_clock_time(&format!("at {}", text), now()), Some(expected), "{}", text);
+        }
+        assert_eq!(parse_clock_time("for 10 minutes", now()), None);
+        assert_eq!(next_clock_time("7", false, now()), Some(at(3, 4, 19, 0)));
+        assert_eq!(next_clock_time("7", true, now()), Some(at(3, 5, 7, 0)));
+        assert_eq!(next_clock_time("7:30 pm", true, now()), Some(at(3, 5, 19, 30)));
+        assert_eq!(parse_clock_time("at 13pm", now()), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        let cases = [
+            ("5 minutes", 300),
+            ("1 hour and 30 minutes", 5400),
+            ("an hour and a half", 5400),
+            ("half an hour", 1800),
+            ("twenty five seconds", 25),
+            ("2 hrs", 7200),
+        ];
+        for (text, seconds) in cases {
+            assert_eq!(parse_duration(text), Some(Duration::seconds(seconds)), "{}", text);
+        }
+        assert_eq!(parse_duration("a while"), None);
+        assert_eq!(find_duration("set a timer for 1 hour and 30 minutes please"), Some((16, 37)));
+        assert_eq!(describe_duration(Duration::seconds(5430)), "1 hour and 30 minutes");
+        assert_eq!(describe_duration(Duration::seconds(125)), "2 minutes and 5 seconds");
+    }
+
+    #[test]
+    fn finds_days_for_weather_questions() {
+        let today = now().date_naive();
+        let day = |day| NaiveDate::from_ymd_opt(2026, 3, day).unwrap();
+        assert_eq!(parse_days("will it rain this weekend", today), vec![day(7), day(8)]);
+        assert_eq!(parse_days("what about tomorrow", today), vec![day(5)]);
+        assert_eq!(parse_days("and on Monday", today), vec![day(9)]);
+        assert!(parse_days("what's the weather like", today).is_empty());
+        assert_eq!(
+            parse_days("this weekend", day(8)),
+            vec![day(8)],
+            "on a Sunday the weekend is today"
+        );
+    }
+
+    #[test]
+    fn parses_recurrence() {
+        let cases = [
+            ("every weekday at 7am", Some("weekdays")),
+            ("on work days", Some("weekdays")),
+            ("every monday", Some("weekly")),
+            ("each week", Some("weekly")),
+            ("every morning", Some("daily")),
+            ("daily", Some("daily")),
+            ("tomorrow at 7", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_recurrence(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn strips_dates_and_times_from_the_subject() {
+        let cases = [
+            ("take the bins out at 7pm tomorrow", "take the bins out"),
+            ("water the plants every day at 9am", "water the plants"),
+            ("call mum in 20 minutes.", "call mum"),
+            ("pay rent on the 12th of March", "pay rent"),
+            ("stretch on weekdays at 6:30 pm", "stretch"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(strip_date_time(text), expected, "{}", text);
+        }
+        assert_eq!(find_date("remind me on Friday morning"), Some((10, 19)));
+        assert_eq!(find_clock_time("wake me at 7:30 am"), Some((11, 18)));
+    }
+
+    #[test]
+    fn describes_date_times_relative_to_now() {
+        assert_eq!(describe_date_time(at(3, 4, 19, 0), now()), "today at 7:00 PM");
+        assert_eq!(describe_date_time(at(3, 5, 9, 0), now()), "tomorrow at 9:00 AM");
+        assert_eq!(describe_date_time(at(3, 6, 18, 30), now()), "on Friday at 6:30 PM");
+        assert_eq!(describe_date_time(at(3, 12, 9, 0), now()), "on 12 March at 9:00 AM");
+    }
+}
@@ -0,0 +1,496 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use tracing::{debug, info};
+
+use super::{merge_entities, rust_nlu_entities, LlmService, NluResult, NluSource, RasaClient, RustNlu};
+use crate::config::settings::{NluConfig, NluEngineConfig};
+
+/// Intents engines answer with when they didn't understand, whatever their
+/// confidence. Rasa's `FallbackClassifier` says `nlu_fallback`.
+const NOT_UNDERSTOOD: &[&str] = &["unknown", "nlu_fallback"];
+
+#[async_trait]
+pub trait NluEngine: Send + Sync {
+    fn source(&self) -> NluSource;
+
+    /// What `text` means, as far as this engine can tell.
+    async fn understand(&self, text: &str) -> Result<NluResult>;
+}
+
+#[async_trait]
+impl NluEngine for LlmService {
+    fn source(&self) -> NluSource {
+        NluSource::Llm
+    }
+
+    async fn understand(&self, text: &str) -> Result<NluResult> {
+        if !self.is_available().await {
+            bail!("Model not loaded");
+        }
+        Ok(NluResult::from_llm(text, self.parse_intent(text).await?))
+    }
+}
+
+#[async_trait]
+impl NluEngine for RasaClient {
+    fn source(&self) -> NluSource {
+        NluSource::Rasa
+    }
+
+    async fn understand(&self, text: &str) -> Result<NluResult> {
+        self.parse(text).await
+    }
+}
+
+#[async_trait]
+impl NluEngine for RustNlu {
+    fn source(&self) -> NluSource {
+        NluSource::RustNlu
+    }
+
+    async fn understand(&self, text: &str) -> Result<NluResult> {
+        let (intent, entities) = self.parse(text);
+        Ok(NluResult::from_rust_nlu(intent, entities))
+    }
+}
+
+/// The shared `RustNlu`, which feedback exports replace while we run.
+#[async_trait]
+impl NluEngine for RwLock<RustNlu> {
+    fn source(&self) -> NluSource {
+        NluSource::RustNlu
+    }
+
+    async fn understand(&self, text: &str) -> Result<NluResult> {
+        let (intent, entities) = self.read().unwrap().parse(text);
+        Ok(NluResult::from_rust_nlu(intent, entities))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NluMode {
+    /// Engines are asked in order until one is sure enough.
+    Chain,
+    /// All engines are asked at once and the most confident wins.
+    Ensemble,
+}
+
+struct Stage {
+    engine: Arc<dyn NluEngine>,
+    config: NluEngineConfig,
+}
+
+impl Stage {
+    async fn ask(&self, text: &str) -> Result<NluResult> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let mut result = tokio::time::timeout(timeout, self.engine.understand(text))
+            .await
+            .map_err(|_| anyhow!("timed out after {} ms", self.config.timeout_ms))??;
+        // Skills only know "unknown"
+        if NOT_UNDERSTOOD.contains(&result.intent.as_str()) {
+            result.intent = "unknown".to_string();
+        }
+        Ok(result)
+    }
+
+    /// Why `result` isn't good enough, if it isn't.
+    fn rejects(&self, result: &NluResult) -> Option<String> {
+        if result.intent == "unknown" {
+            Some("didn't understand".to_string())
+        } else if result.confidence < self.config.threshold {
+            Some(format!(
+                "{} at {:.2}, below {:.2}",
+                result.intent, result.confidence, self.config.threshold
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn calibrated(&self, result: &NluResult) -> f64 {
+        (result.confidence * self.config.calibration).clamp(0.0, 1.0)
+    }
+}
+
+/// What the pipeline understood.
+#[derive(Debug)]
+pub struct NluOutcome {
+    pub result: NluResult,
+    /// Engines that failed, and in chain mode those passed over before the
+    /// winner, with the reason.
+    pub passed_over: Vec<String>,
+}
+
+/// The NLU engines to ask and how to pick between them. Built once at
+/// startup.
+pub struct NluPipeline {
+    stages: Vec<Stage>,
+    mode: NluMode,
+    /// Fills in entities the winning engine didn't find.
+    rust_nlu: Arc<RwLock<RustNlu>>,
+}
+
+impl NluPipeline {
+    /// Builds the engines listed in `nlu.engines`. The LLM is left out when
+    /// no model is loaded.
+    pub fn from_config(config: &NluConfig, llm: Option<LlmService>, rust_nlu: Arc<RwLock<RustNlu>>) -> Result<Self> {
+        let mode = match config.mode.as_str() {
+            "chain" => NluMode::Chain,
+            "ensemble" => NluMode::Ensemble,
+            other => bail!("Unknown NLU mode: {}", other),
+        };
+
+        let mut stages: Vec<Stage> = Vec::new();
+        for name in config.engines.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let (engine, engine_config): (Arc<dyn NluEngine>, _) = match name {
+                "llm" => match &llm {
+                    Some(llm) => (Arc::new(llm.clone()), &config.llm),
+                    None => {
+                        info!("No LLM loaded, leaving it out of NLU");
+                        continue;
+                    }
+                },
+                "rasa" => (Arc::new(RasaClient::new(config.rasa_url.clone())), &config.rasa),
+                "rust_nlu" => (rust_nlu.clone(), &config.rust_nlu),
+                other => bail!("Unknown NLU engine: {}", other),
+            };
+            if stages.iter().any(|stage| stage.engine.source() == engine.source()) {
+                bail!("NLU engine {} is listed twice", name);
+            }
+            stages.push(Stage {
+                engine,
+                config: engine_config.clone(),
+            });
+        }
+        if stages.is_empty() {
+            bail!("No NLU engines configured");
+        }
+
+        Ok(Self { stages, mode, rust_nlu })
+    }
+
+    pub fn engines(&self) -> Vec<NluSource> {
+        self.stages.iter().map(|stage| stage.engine.source()).collect()
+    }
+
+    /// What one of the engines makes of `text` by itself, with its timeout
+    /// and without RustNlu's entities. `None` when it isn't configured.
+    pub async fn ask(&self, source: NluSource, text: &str) -> Option<Result<NluResult>> {
+        let stage = self.stages.iter().find(|stage| stage.engine.source() == source)?;
+        Some(stage.ask(text).await)
+    }
+
+    /// Asks the engines what `text` means. When none is sure enough, the
+    /// most confident answer is taken anyway; it fails only if no engine
+    /// answered at all.
+    pub async fn understand(&self, text: &str) -> Result<NluOutcome> {
+        let mut passed_over = Vec::new();
+        let mut best: Option<(f64, NluResult)> = None;
+        let mut winner = None;
+
+        match self.mode {
+            NluMode::Chain => {
+                for stage in &self.stages {
+                    match stage.ask(text).await {
+                        Ok(result) => match stage.rejects(&result) {
+                            Some(reason) => {
+                                passed_over.push(format!("{}: {}", stage.engine.source().as_str(), reason));
+                                consider(&mut best, stage.calibrated(&result), result);
+                            }
+                            None => {
+                                winner = Some(result);
+                                break;
+                            }
+                        },
+                        Err(e) => passed_over.push(format!("{}: {}", stage.engine.source().as_str(), e)),
+                    }
+                }
+            }
+            NluMode::Ensemble => {
+                let answers = join_all(self.stages.iter().map(|stage| stage.ask(text))).await;
+                let mut accepted: Option<(f64, NluResult)> = None;
+                for (stage, answer) in self.stages.iter().zip(answers) {
+                    match answer {
+                        Ok(result) => {
+                            let calibrated = stage.calibrated(&result);
+                            debug!(
+                                "{} understood {} at {:.2}, {:.2} calibrated",
+                                stage.engine.source().as_str(),
+                                result.intent,
+                                result.confidence,
+                                calibrated
+                            );
+                            if stage.rejects(&result).is_none() {
+                                consider(&mut accepted, calibrated, result);
+                            } else {
+                                consider(&mut best, calibrated, result);
+                            }
+                        }
+                        Err(e) => passed_over.push(format!("{}: {}", stage.engine.source().as_str(), e)),
+                    }
+                }
+                winner = accepted.map(|(_, result)| result);
+            }
+        }
+
+        let mut result = match winner.or(best.map(|(_, result)| result)) {
+            Some(result) => result,
+            None => bail!("No NLU engine answered: {}", passed_over.join("; ")),
+        };
+        if result.source != NluSource::RustNlu {
+            // RustNlu's entities carry the confidence of the intent they are for
+            let fallback = self.rust_nlu.read().unwrap().extract_entities(text, &result.intent);
+            result.entities = merge_entities(result.entities, rust_nlu_entities(fallback, result.confidence));
+        }
+        Ok(NluOutcome { result, passed_over })
+    }
+}
+
+/// Keeps `result` if it is more confident than the best so far. Any intent
+/// beats not understanding, and ties go to the engine asked first.
+fn consider(best: &mut Option<(f64, NluResult)>, calibrated: f64, result: NluResult) {
+    let understood = |result: &NluResult| result.intent != "unknown";
+    let better = match best {
+        Some((confidence, best)) => {
+            (understood(&result), calibrated) > (understood(best), *confidence)
+        }
+        None => true,
+    };
+    if better {
+        *best = Some((calibrated, result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::Settings;
+
+    /// Answers every command with the same intent, after `delay`.
+    struct StubEngine {
+        source: NluSource,
+        intent: &'static str,
+        confidence: f64,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl NluEngine for StubEngine {
+        fn source(&self) -> NluSource {
+            self.source
+        }
+
+        async fn understand(&self, _text: &str) -> Result<NluResult> {
+            tokio::time::sleep(self.delay).await;
+            if self.intent == "error" {
+                bail!("connection refused");
+            }
+            Ok(NluResult {
+                intent: self.intent.to_string(),
+                confidence: self.confidence,
+                entities: Vec::new(),
+                source: self.source,
+            })
+        }
+    }
+
+    fn stage(source: NluSource, intent: &'static str, confidence: f64, threshold: f64) -> Stage {
+        Stage {
+            engine: Arc::new(StubEngine {
+                source,
+                intent,
+                confidence,
+                delay: Duration::ZERO,
+            }),
+            config: NluEngineConfig {
+                threshold,
+                timeout_ms: 100,
+                calibration: 1.0,
+            },
+        }
+    }
+
+    fn pipeline(mode: NluMode, stages: Vec<Stage>) -> NluPipeline {
+        NluPipeline {
+            stages,
+            mode,
+            rust_nlu: Arc::new(RwLock::new(RustNlu::new())),
+        }
+    }
+
+    #[tokio::test]
+    async fn chain_takes_the_first_engine_sure_enough() {
+        let nlu = pipeline(
+            NluMode::Chain,
+            vec![
+                stage(NluSource::Llm, "get_time", 0.6, 0.7),
+                stage(NluSource::Rasa, "get_weather", 0.8, 0.7),
+                stage(NluSource::RustNlu, "get_news", 0.99, 0.5),
+            ],
+        );
+        let outcome = nlu.understand("what's it like out").await.unwrap();
+        assert_eq!(outcome.result.intent, "get_weather");
+        assert_eq!(outcome.result.source, NluSource::Rasa);
+        assert_eq!(outcome.passed_over, vec!["llm: get_time at 0.60, below 0.70"]);
+    }
+
+    #[tokio::test]
+    async fn thresholds_are_per_engine() {
+        let nlu = pipeline(
+            NluMode::Chain,
+            vec![
+                stage(NluSource::Rasa, "get_weather", 0.6, 0.9),
+                stage(NluSource::RustNlu, "get_time", 0.6, 0.5),
+            ],
+        );
+        let outcome = nlu.understand("what time is it").await.unwrap();
+        assert_eq!(outcome.result.intent, "get_time");
+    }
+
+    #[tokio::test]
+    async fn engines_that_time_out_or_fail_are_passed_over() {
+        let mut slow = stage(NluSource::Llm, "get_weather", 0.99, 0.5);
+        slow.engine = Arc::new(StubEngine {
+            source: NluSource::Llm,
+            intent: "get_weather",
+            confidence: 0.99,
+            delay: Duration::from_secs(5),
+        });
+        let nlu = pipeline(
+            NluMode::Chain,
+            vec![
+                slow,
+                stage(NluSource::Rasa, "error", 0.0, 0.5),
+                stage(NluSource::RustNlu, "get_time", 0.8, 0.5),
+            ],
+        );
+        let outcome = nlu.understand("what time is it").await.unwrap();
+        assert_eq!(outcome.result.intent, "get_time");
+        assert_eq!(
+            outcome.passed_over,
+            vec!["llm: timed out after 100 ms", "rasa: connection refused"]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_most_confident_answer_is_taken_when_none_is_sure_enough() {
+        let nlu = pipeline(
+            NluMode::Chain,
+            vec![
+                stage(NluSource::Llm, "nlu_fallback", 0.95, 0.5),
+                stage(NluSource::Rasa, "get_weather", 0.4, 0.7),
+                stage(NluSource::RustNlu, "get_time", 0.3, 0.5),
+            ],
+        );
+        let outcome = nlu.understand("hmm").await.unwrap();
+        // Any intent beats not understanding, however confident
+        assert_eq!(outcome.result.intent, "get_weather");
+        assert_eq!(outcome.passed_over.len(), 3);
+        assert_eq!(outcome.passed_over[0], "llm: didn't understand");
+
+        let nlu = pipeline(NluMode::Chain, vec![stage(NluSource::Rasa, "nlu_fallback", 0.95, 0.5)]);
+        assert_eq!(nlu.understand("hmm").await.unwrap().result.intent, "unknown");
+    }
+
+    #[tokio::test]
+    async fn fails_only_when_no_engine_answers() {
+        let nlu = pipeline(
+            NluMode::Ensemble,
+            vec![stage(NluSource::Rasa, "error", 0.0, 0.5), stage(NluSource::Llm, "error", 0.0, 0.5)],
+        );
+        let error = nlu.understand("hello").await.unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "No NLU engine answered: rasa: connection refused; llm: connection refused"
+        );
+    }
+
+    #[tokio::test]
+    async fn ensemble_takes_the_most_confident_after_calibration() {
+        let mut overconfident = stage(NluSource::Llm, "get_news", 0.95, 0.5);
+        overconfident.config.calibration = 0.5;
+        let nlu = pipeline(
+            NluMode::Ensemble,
+            vec![
+                overconfident,
+                stage(NluSource::Rasa, "get_weather", 0.8, 0.5),
+                stage(NluSource::RustNlu, "get_time", 0.7, 0.5),
+            ],
+        );
+        let outcome = nlu.understand("what's new").await.unwrap();
+        assert_eq!(outcome.result.intent, "get_weather");
+        assert!(outcome.passed_over.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ensemble_prefers_accepted_answers_and_ties_go_to_the_first_engine() {
+        // Above its threshold beats more confident but below it
+        let nlu = pipeline(
+            NluMode::Ensemble,
+            vec![
+                stage(NluSource::Llm, "get_news", 0.85, 0.9),
+                stage(NluSource::Rasa, "get_weather", 0.6, 0.5),
+            ],
+        );
+        assert_eq!(nlu.understand("news").await.unwrap().result.intent, "get_weather");
+
+        let nlu = pipeline(
+            NluMode::Ensemble,
+            vec![
+                stage(NluSource::Rasa, "get_weather", 0.8, 0.5),
+                stage(NluSource::RustNlu, "get_time", 0.8, 0.5),
+            ],
+        );
+        assert_eq!(nlu.understand("what's up").await.unwrap().result.source, NluSource::Rasa);
+    }
+
+    #[tokio::test]
+    async fn rust_nlu_fills_in_entities_for_other_engines() {
+        let nlu = pipeline(NluMode::Chain, vec![stage(NluSource::Rasa, "control_lights", 0.9, 0.5)]);
+        let outcome = nlu.understand("turn on the kitchen lights").await.unwrap();
+        let entities: Vec<(&str, &str, NluSource)> = outcome
+            .result
+            .entities
+            .iter()
+            .map(|entity| (entity.name.as_str(), entity.value.as_str(), entity.source))
+            .collect();
+        assert_eq!(
+            entities,
+            vec![("room", "kitchen", NluSource::RustNlu), ("state", "on", NluSource::RustNlu)]
+        );
+        assert!(outcome.result.entities.iter().all(|entity| entity.confidence == 0.9));
+    }
+
+    #[tokio::test]
+    async fn ask_uses_one_engine_by_itself() {
+        let nlu = pipeline(NluMode::Chain, vec![stage(NluSource::Rasa, "control_lights", 0.2, 0.5)]);
+        let result = nlu.ask(NluSource::Rasa, "turn on the kitchen lights").await.unwrap().unwrap();
+        assert_eq!(result.intent, "control_lights");
+        assert!(result.entities.is_empty());
+        assert!(nlu.ask(NluSource::Llm, "hello").await.is_none());
+    }
+
+    #[test]
+    fn builds_the_configured_engines() {
+        let mut config = Settings::new().unwrap().nlu;
+        let rust_nlu = Arc::new(RwLock::new(RustNlu::new()));
+
+        config.engines = "llm, rasa, rust_nlu".to_string();
+        config.mode = "ensemble".to_string();
+        let nlu = NluPipeline::from_config(&config, None, rust_nlu.clone()).unwrap();
+        assert_eq!(nlu.engines(), vec![NluSource::Rasa, NluSource::RustNlu]);
+        assert_eq!(nlu.mode, NluMode::Ensemble);
+
+        config.mode = "vote".to_string();
+        assert!(NluPipeline::from_config(&config, None, rust_nlu.clone()).is_err());
+        config.mode = "chain".to_string();
+        for engines in ["rasa,rasa", "rasa,spacy", "llm", ""] {
+            config.engines = engines.to_string();
+            assert!(NluPipeline::from_config(&config, None, rust_nlu.clone()).is_err(), "{}", engines);
+        }
+    }
+}
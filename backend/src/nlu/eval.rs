@@ -0,0 +1,671 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::{
+    training::{self, LabelledEntity},
+    NluResult,
+};
+
+/// Where utterances from reviewed feedback come from, in reports.
+const FEEDBACK_SOURCE: &str = "feedback";
+
+/// What a failed engine is counted as having understood.
+const FAILED: &str = "(error)";
+
+/// An utterance and what it should be understood as.
+#[derive(Debug, Clone)]
+pub struct Utterance {
+    pub text: String,
+    pub intent: String,
+    /// Only the training data has entities annotated.
+    pub entities: Vec<LabelledEntity>,
+    /// The file it is from, or `feedback`.
+    pub source: String,
+}
+
+/// Labelled utterances to evaluate engines on. When the same text is
+/// labelled twice, the label added last is kept, as feedback corrects the
+/// training data.
+#[derive(Debug, Default)]
+pub struct Dataset {
+    utterances: Vec<Utterance>,
+}
+
+impl Dataset {
+    /// Adds the examples in a Rasa NLU file.
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let source = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for example in training::read_labelled_examples(path)? {
+            self.add(Utterance {
+                text: example.text,
+                intent: example.intent,
+                entities: example.entities,
+                source: source.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds accepted feedback: what was said, with the intent a reviewer
+    /// says it should have been understood as.
+    pub async fn add_feedback(&mut self, db: &SqlitePool) -> Result<()> {
+        let accepted: Vec<(String, String)> = sqlx::query_as(
+            "SELECT text, correct_intent FROM intent_feedback WHERE status = 'accepted' ORDER BY reviewed_at, created_at",
+        )
+        .fetch_all(db)
+        .await?;
+        for (text, intent) in accepted {
+            self.add(Utterance {
+                text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                intent,
+                entities: Vec::new(),
+                source: FEEDBACK_SOURCE.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, utterance: Utterance) {
+        if utterance.text.is_empty() {
+            return;
+        }
+        self.utterances
+            .retain(|known| !known.text.eq_ignore_ascii_case(&utterance.text));
+        self.utterances.push(utterance);
+    }
+
+    pub fn utterances(&self) -> &[Utterance] {
+        &self.utterances
+    }
+
+    pub fn summary(&self) -> DatasetSummary {
+        let mut sources: BTreeMap<String, usize> = BTreeMap::new();
+        for utterance in &self.utterances {
+            *sources.entry(utterance.source.clone()).or_default() += 1;
+        }
+        let intents: BTreeSet<&str> = self.utterances.iter().map(|utterance| utterance.intent.as_str()).collect();
+        DatasetSummary {
+            utterances: self.utterances.len(),
+            intents: intents.len(),
+            entities: self.utterances.iter().map(|utterance| utterance.entities.len()).sum(),
+            sources,
+        }
+    }
+}
+
+/// What an engine answered for an utterance, and how long it took.
+#[derive(Debug)]
+pub struct Answer {
+    pub result: Result<NluResult, String>,
+    pub latency: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub dataset: DatasetSummary,
+    pub engines: Vec<EngineReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetSummary {
+    pub utterances: usize,
+    pub intents: usize,
+    /// Annotated entities.
+    pub entities: usize,
+    /// Utterances from each file, and from feedback.
+    pub sources: BTreeMap<String, usize>,
+}
+
+/// How well an engine did. Scores are from 0 to 1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EngineReport {
+    pub engine: String,
+    /// Utterances understood as the intent they are labelled with.
+    pub accuracy: f64,
+    /// The F1 score of each labelled intent, averaged.
+    pub macro_f1: f64,
+    /// Utterances the engine failed on, e.g. because it timed out.
+    pub errors: usize,
+    pub intents: BTreeMap<String, IntentScore>,
+    /// How often each intent was understood as each other one: labelled
+    /// intent first, then what was understood.
+    pub confusion: BTreeMap<String, BTreeMap<String, usize>>,
+    pub entities: EntityScore,
+    pub latency: LatencyScore,
+    pub misses: Vec<Miss>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntentScore {
+    /// Utterances labelled with the intent.
+    pub support: usize,
+    /// Utterances understood as the intent.
+    pub predicted: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+/// Entities found in utterances that have entities annotated. The name and
+/// value have to match, ignoring case; where they were found doesn't matter.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityScore {
+    pub expected: usize,
+    pub found: usize,
+    pub correct: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    /// Utterances where all the entities, and nothing else, were found.
+    pub exact: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LatencyScore {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+/// An utterance understood as the wrong intent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Miss {
+    pub text: String,
+    pub expected: String,
+    pub understood: String,
+    /// `None` when the engine failed.
+    pub confidence: Option<f64>,
+}
+
+/// Scores an engine's answers, one for each of the `utterances`.
+pub fn score(engine: &str, utterances: &[Utterance], answers: &[Answer]) -> EngineReport {
+    let mut confusion: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    let mut misses = Vec::new();
+    let mut entities = EntityCounts::default();
+    let mut errors = 0;
+
+    for (utterance, answer) in utterances.iter().zip(answers) {
+        let (understood, confidence) = match &answer.result {
+            Ok(result) => (result.intent.clone(), Some(result.confidence)),
+            Err(_) => {
+                errors += 1;
+                (FAILED.to_string(), None)
+            }
+        };
+        if understood != utterance.intent {
+            misses.push(Miss {
+                text: utterance.text.clone(),
+                expected: utterance.intent.clone(),
+                understood: understood.clone(),
+                confidence,
+            });
+        }
+        *confusion
+            .entry(utterance.intent.clone())
+            .or_default()
+            .entry(understood)
+            .or_default() += 1;
+
+        if !utterance.entities.is_empty() {
+            let found = answer.result.as_ref().map(|result| result.entities.as_slice()).unwrap_or(&[]);
+            entities.count(&utterance.entities, found.iter().map(|entity| (&entity.name, &entity.value)));
+        }
+    }
+
+    let mut intents = BTreeMap::new();
+    for (intent, understood) in &confusion {
+        let support: usize = understood.values().sum();
+        let correct = understood.get(intent).copied().unwrap_or(0);
+        let predicted: usize = confusion
+            .values()
+            .filter_map(|understood| understood.get(intent))
+            .sum();
+        let precision = ratio(correct, predicted);
+        let recall = ratio(correct, support);
+        intents.insert(
+            intent.clone(),
+            IntentScore {
+                support,
+                predicted,
+                precision,
+                recall,
+                f1: f1(precision, recall),
+            },
+        );
+    }
+
+    let macro_f1 = if intents.is_empty() {
+        0.0
+    } else {
+        intents.values().map(|score| score.f1).sum::<f64>() / intents.len() as f64
+    };
+    EngineReport {
+        engine: engine.to_string(),
+        accuracy: 1.0 - ratio(misses.len(), answers.len()),
+        macro_f1,
+        errors,
+        intents,
+        confusion,
+        entities: entities.score(),
+        latency: latency(answers),
+        misses,
+    }
+}
+
+#[derive(Default)]
+struct EntityCounts {
+    expected: usize,
+    found: usize,
+    correct: usize,
+    utterances: usize,
+    exact: usize,
+}
+
+impl EntityCounts {
+    fn count<'a>(&mut self, expected: &[LabelledEntity], found: impl Iterator<Item = (&'a String, &'a String)>) {
+        let key = |name: &str, value: &str| (name.to_string(), value.trim().to_lowercase());
+        let mut missing: Vec<(String, String)> = expected.iter().map(|entity| key(&entity.name, &entity.value)).collect();
+        let mut extra = 0;
+        for (name, value) in found {
+            self.found += 1;
+            match missing.iter().position(|expected| *expected == key(name, value)) {
+                Some(index) => {
+                    missing.swap_remove(index);
+                    self.correct += 1;
+                }
+                None => extra += 1,
+            }
+        }
+        self.expected += expected.len();
+        self.utterances += 1;
+        if missing.is_empty() && extra == 0 {
+            self.exact += 1;
+        }
+    }
+
+    fn score(&self) -> EntityScore {
+        let precision = ratio(self.correct, self.found);
+        let recall = ratio(self.correct, self.expected);
+        EntityScore {
+            expected: self.expected,
+            found: self.found,
+            correct: self.correct,
+            precision,
+            recall,
+            f1: f1(precision, recall),
+            exact: ratio(self.exact, self.utterances),
+        }
+    }
+}
+
+fn latency(answers: &[Answer]) -> LatencyScore {
+    let mut latencies: Vec<f64> = answers
+        .iter()
+        .map(|answer| answer.latency.as_secs_f64() * 1000.0)
+        .collect();
+    if latencies.is_empty() {
+        return LatencyScore {
+            mean_ms: 0.0,
+            p50_ms: 0.0,
+            p95_ms: 0.0,
+            max_ms: 0.0,
+        };
+    }
+    latencies.sort_by(f64::total_cmp);
+    // Nearest rank
+    let percentile = |p: f64| latencies[((p * latencies.len() as f64).ceil() as usize).max(1) - 1];
+    LatencyScore {
+        mean_ms: latencies.iter().sum::<f64>() / latencies.len() as f64,
+        p50_ms: percentile(0.5),
+        p95_ms: percentile(0.95),
+        max_ms: latencies[latencies.len() - 1],
+    }
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+fn f1(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0.0 {
+        0.0
+    } else {
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+/// Where `report` does worse than `baseline`: scores that dropped by more
+/// than `tolerance`, for engines both have. Latency isn't compared, as it
+/// depends on the machine.
+pub fn regressions(baseline: &Report, report: &Report, tolerance: f64) -> Vec<String> {
+    let mut regressions = Vec::new();
+    for engine in &report.engines {
+        let Some(before) = baseline.engines.iter().find(|before| before.engine == engine.engine) else {
+            continue;
+        };
+        let scores = [
+            ("accuracy", before.accuracy, engine.accuracy),
+            ("macro F1", before.macro_f1, engine.macro_f1),
+            ("entity F1", before.entities.f1, engine.entities.f1),
+        ];
+        for (name, before, now) in scores {
+            if now < before - tolerance {
+                regressions.push(format!("{} {} dropped from {:.3} to {:.3}", engine.engine, name, before, now));
+            }
+        }
+    }
+    regressions
+}
+
+impl Report {
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let sources: Vec<String> = self
+            .dataset
+            .sources
+            .iter()
+            .map(|(source, count)| format!("{} from {}", count, source))
+            .collect();
+        let _ = writeln!(out, "# NLU evaluation\n");
+        let _ = writeln!(
+            out,
+            "{} utterances of {} intents ({}), with {} entities annotated.\n",
+            self.dataset.utterances,
+            self.dataset.intents,
+            sources.join(", "),
+            self.dataset.entities
+        );
+
+        let _ = writeln!(out, "| Engine | Accuracy | Macro F1 | Entity F1 | Errors | Mean ms | p95 ms |");
+        let _ = writeln!(out, "|---|---:|---:|---:|---:|---:|---:|");
+        for engine in &self.engines {
+            let _ = writeln!(
+                out,
+                "| {} | {:.3} | {:.3} | {:.3} | {} | {:.1} | {:.1} |",
+                engine.engine,
+                engine.accuracy,
+                engine.macro_f1,
+                engine.entities.f1,
+                engine.errors,
+                engine.latency.mean_ms,
+                engine.latency.p95_ms
+            );
+        }
+
+        for engine in &self.engines {
+            engine.write_markdown(&mut out);
+        }
+        out
+    }
+}
+
+impl EngineReport {
+    fn write_markdown(&self, out: &mut String) {
+        let _ = writeln!(out, "\n## {}\n", self.engine);
+        let _ = writeln!(
+            out,
+            "Latency: {:.1} ms mean, {:.1} ms p50, {:.1} ms p95, {:.1} ms max.\n",
+            self.latency.mean_ms, self.latency.p50_ms, self.latency.p95_ms, self.latency.max_ms
+        );
+
+        let _ = writeln!(out, "### Intents\n");
+        let _ = writeln!(out, "| Intent | Support | Precision | Recall | F1 |");
+        let _ = writeln!(out, "|---|---:|---:|---:|---:|");
+        for (intent, score) in &self.intents {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:.3} | {:.3} | {:.3} |",
+                intent, score.support, score.precision, score.recall, score.f1
+            );
+        }
+
+        self.write_confusion(out);
+
+        let entities = &self.entities;
+        let _ = writeln!(out, "\n### Entities\n");
+        let _ = writeln!(
+            out,
+            "{} of {} annotated entities found, with {} found in all: precision {:.3}, recall {:.3}, F1 {:.3}. \
+             Exactly right in {:.1}% of the utterances with entities.",
+            entities.correct,
+            entities.expected,
+            entities.found,
+            entities.precision,
+            entities.recall,
+            entities.f1,
+            entities.exact * 100.0
+        );
+
+        if !self.misses.is_empty() {
+            let _ = writeln!(out, "\n### Misses\n");
+            for miss in &self.misses {
+                let confidence = miss
+                    .confidence
+                    .map(|confidence| format!(" ({:.2})", confidence))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "- \"{}\": {}, understood as {}{}",
+                    miss.text, miss.expected, miss.understood, confidence
+                );
+            }
+        }
+    }
+
+    /// The confusion matrix, cut down to the intents that were mixed up.
+    fn write_confusion(&self, out: &mut String) {
+        let rows: Vec<&String> = self
+            .confusion
+            .iter()
+            .filter(|(intent, understood)| understood.keys().any(|other| other != *intent))
+            .map(|(intent, _)| intent)
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+        let mut columns: BTreeSet<&String> = rows.iter().copied().collect();
+        for intent in &rows {
+            columns.extend(self.confusion[*intent].keys());
+        }
+
+        let _ = writeln!(out, "\n### Confusion matrix\n");
+        let _ = writeln!(out, "Labelled intents down, understood across; intents never mixed up are left out.\n");
+        let header: Vec<&str> = columns.iter().map(|column| column.as_str()).collect();
+        let _ = writeln!(out, "| | {} |", header.join(" | "));
+        let _ = writeln!(out, "|---|{}", "---:|".repeat(columns.len()));
+        for intent in rows {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|column| match self.confusion[intent].get(*column) {
+                    Some(count) => count.to_string(),
+                    None => String::new(),
+                })
+                .collect();
+            let _ = writeln!(out, "| {} | {} |", intent, cells.join(" | "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nlu::{NluEntity, NluSource};
+
+    fn utterance(text: &str, intent: &str, entities: &[(&str, &str)]) -> Utterance {
+        Utterance {
+            text: text.to_string(),
+            intent: intent.to_string(),
+            entities: entities
+                .iter()
+                .map(|(name, value)| LabelledEntity {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    start: 0,
+                    end: 0,
+                })
+                .collect(),
+            source: "fixture.yml".to_string(),
+        }
+    }
+
+    fn understood(intent: &str, entities: &[(&str, &str)], ms: u64) -> Answer {
+        Answer {
+            result: Ok(NluResult {
+                intent: intent.to_string(),
+                confidence: 0.9,
+                entities: entities
+                    .iter()
+                    .map(|(name, value)| NluEntity {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                        start: None,
+                        end: None,
+                        confidence: 0.9,
+                        source: NluSource::RustNlu,
+                    })
+                    .collect(),
+                source: NluSource::RustNlu,
+            }),
+            latency: Duration::from_millis(ms),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, what: &str) {
+        assert!((actual - expected).abs() < 1e-9, "{}: {} != {}", what, actual, expected);
+    }
+
+    /// Seven utterances of three intents, three of them missed:
+    ///
+    /// | labelled \ understood | control_lights | get_weather | greet | (error) |
+    /// |---|---:|---:|---:|---:|
+    /// | control_lights | 2 | 1 | | |
+    /// | get_weather | | 1 | | 1 |
+    /// | greet | 1 | | 1 | |
+    fn fixture() -> EngineReport {
+        let utterances = [
+            utterance("lights on in the kitchen", "control_lights", &[("room", "kitchen")]),
+            utterance("lights off", "control_lights", &[]),
+            utterance("dim the lights", "control_lights", &[]),
+            utterance("weather today", "get_weather", &[("date", "today")]),
+            utterance("is it raining", "get_weather", &[("weather", "rain")]),
+            utterance("hello", "greet", &[]),
+            utterance("hi there", "greet", &[]),
+        ];
+        let answers = [
+            understood("control_lights", &[("room", "Kitchen "), ("state", "on")], 10),
+            understood("control_lights", &[], 20),
+            understood("get_weather", &[], 30),
+            understood("get_weather", &[("date", "today")], 40),
+            Answer {
+                result: Err("timed out".to_string()),
+                latency: Duration::from_millis(50),
+            },
+            understood("greet", &[], 60),
+            understood("control_lights", &[], 70),
+        ];
+        score("rust_nlu", &utterances, &answers)
+    }
+
+    #[test]
+    fn intents_are_scored_from_the_confusion_matrix() {
+        let report = fixture();
+
+        let row = |intent: &str| -> Vec<(&str, usize)> {
+            report.confusion[intent].iter().map(|(understood, count)| (understood.as_str(), *count)).collect()
+        };
+        assert_eq!(row("control_lights"), [("control_lights", 2), ("get_weather", 1)]);
+        assert_eq!(row("get_weather"), [(FAILED, 1), ("get_weather", 1)]);
+        assert_eq!(row("greet"), [("control_lights", 1), ("greet", 1)]);
+
+        // support, predicted, precision, recall, F1
+        let expected = [
+            ("control_lights", 3, 3, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0),
+            ("get_weather", 2, 2, 0.5, 0.5, 0.5),
+            ("greet", 2, 1, 1.0, 0.5, 2.0 / 3.0),
+        ];
+        assert_eq!(report.intents.len(), expected.len());
+        for (intent, support, predicted, precision, recall, f1) in expected {
+            let score = &report.intents[intent];
+            assert_eq!((score.support, score.predicted), (support, predicted), "{}", intent);
+            assert_close(score.precision, precision, intent);
+            assert_close(score.recall, recall, intent);
+            assert_close(score.f1, f1, intent);
+        }
+
+        assert_close(report.accuracy, 4.0 / 7.0, "accuracy");
+        assert_close(report.macro_f1, 11.0 / 18.0, "macro F1");
+        assert_eq!(report.errors, 1);
+        let misses: Vec<_> = report
+            .misses
+            .iter()
+            .map(|miss| (miss.text.as_str(), miss.understood.as_str(), miss.confidence))
+            .collect();
+        assert_eq!(
+            misses,
+            [
+                ("dim the lights", "get_weather", Some(0.9)),
+                ("is it raining", FAILED, None),
+                ("hi there", "control_lights", Some(0.9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn entities_and_latency_are_scored() {
+        let report = fixture();
+
+        // Found: kitchen (right despite case and spacing), state (extra) and today
+        let entities = &report.entities;
+        assert_eq!((entities.expected, entities.found, entities.correct), (3, 3, 2));
+        assert_close(entities.precision, 2.0 / 3.0, "precision");
+        assert_close(entities.recall, 2.0 / 3.0, "recall");
+        assert_close(entities.f1, 2.0 / 3.0, "F1");
+        assert_close(entities.exact, 1.0 / 3.0, "exact");
+
+        let latency = &report.latency;
+        assert_eq!((latency.mean_ms, latency.p50_ms, latency.p95_ms, latency.max_ms), (40.0, 40.0, 70.0, 70.0));
+    }
+
+    #[test]
+    fn nothing_to_score_is_all_zeros() {
+        let report = score("rust_nlu", &[], &[]);
+        assert!(report.intents.is_empty());
+        assert_eq!((report.macro_f1, report.entities.f1, report.latency.max_ms), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn the_confusion_matrix_shows_only_mixed_up_intents() {
+        let mut report = fixture();
+        report.confusion.insert("goodbye".to_string(), BTreeMap::from([("goodbye".to_string(), 4)]));
+
+        let mut out = String::new();
+        report.write_confusion(&mut out);
+        let table: Vec<&str> = out.lines().skip_while(|line| !line.starts_with('|')).collect();
+        assert_eq!(
+            table,
+            [
+                "| | (error) | control_lights | get_weather | greet |",
+                "|---|---:|---:|---:|---:|",
+                "| control_lights |  | 2 | 1 |  |",
+                "| get_weather | 1 |  | 1 |  |",
+                "| greet |  | 1 |  | 1 |",
+            ]
+        );
+    }
+}
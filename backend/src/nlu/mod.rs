@@ -1,11 +1,17 @@
 use serde::{Deserialize, Serialize};
 use reqwest;
 
+pub mod datetime;
+mod engine;
+pub mod eval;
+pub mod patterns;
 mod rasa_manager;
 mod rust_nlu;
+pub mod training;
+pub use engine::{NluEngine, NluPipeline};
 pub use rasa_manager::RasaManager;
 pub use rust_nlu::{RustNlu, Entity as RustEntity};
-pub use crate::services::llm::{LlmService, LlmIntent, LlmEntity};
+pub use crate::services::llm::{LlmService, LlmIntent};
 
 #[derive(Debug, Serialize)]
 pub struct NluRequest {
@@ -14,6 +20,8 @@ pub struct NluRequest {
 
 #[derive(Debug, Deserialize)]
 pub struct NluResponse {
+    #[serde(default)]
+    pub text: String,
     pub intent: Intent,
     pub entities: Vec<Entity>,
 }
@@ -24,79 +32,375 @@ pub struct Intent {
     pub confidence: f64,
 }
 
+/// An entity as Rasa reports it. Values can be numbers, e.g. from Duckling.
 #[derive(Debug, Deserialize)]
 pub struct Entity {
     pub entity: String,
+    pub value: serde_json::Value,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// DIET reports `confidence_entity`, rule-based extractors nothing.
+    #[serde(alias = "confidence_entity")]
+    pub confidence: Option<f64>,
+}
+
+/// Confidence given to LLM entities, which come without one.
+const LLM_ENTITY_CONFIDENCE: f64 = 0.9;
+
+/// Entities the winning engine is less sure of than this are left out, and
+/// RustNlu's are used instead if it has them.
+const MIN_ENTITY_CONFIDENCE: f64 = 0.5;
+
+/// The engine that understood a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NluSource {
+    Llm,
+    Rasa,
+    RustNlu,
+}
+
+impl NluSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NluSource::Llm => "llm",
+            NluSource::Rasa => "rasa",
+            NluSource::RustNlu => "rust_nlu",
+        }
+    }
+}
+
+/// An entity from any engine. Spans are byte offsets into the text, and
+/// missing when the engine doesn't say where it found the value.
+#[derive(Debug, Clone, Serialize)]
+pub struct NluEntity {
+    pub name: String,
     pub value: String,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
     pub confidence: f64,
+    pub source: NluSource,
 }
 
-pub struct NluService {
-    rasa_url: String,
-    client: reqwest::Client,
-    llm_service: Option<LlmService>,
+impl NluEntity {
+    fn overlaps(&self, other: &NluEntity) -> bool {
+        match (self.start, self.end, other.start, other.end) {
+            (Some(start), Some(end), Some(other_start), Some(other_end)) => start < other_end && other_start < end,
+            _ => false,
+        }
+    }
 }
 
-impl NluService {
-    pub fn new(rasa_url: String) -> Self {
+/// What an engine understood: the intent and the entities that go with it.
+#[derive(Debug, Clone, Serialize)]
+pub struct NluResult {
+    pub intent: String,
+    pub confidence: f64,
+    pub entities: Vec<NluEntity>,
+    pub source: NluSource,
+}
+
+impl NluResult {
+    /// RustNlu's entities carry the confidence of its intent.
+    pub fn from_rust_nlu(intent: rust_nlu::Intent, entities: Vec<RustEntity>) -> Self {
+        let confidence = intent.confidence;
         Self {
-            rasa_url,
-            client: reqwest::Client::new(),
-            llm_service: None,
+            intent: intent.name,
+            confidence,
+            entities: rust_nlu_entities(entities, confidence),
+            source: NluSource::RustNlu,
         }
     }
 
-    pub fn with_llm(mut self, llm_service: LlmService) -> Self {
-        self.llm_service = Some(llm_service);
-        self
-    }
-
-    pub async fn parse_intent_with_llm(&self, text: &str) -> Result<NluResponse, Box<dyn std::error::Error>> {
-        // Try LLM first if available
-        if let Some(llm) = &self.llm_service {
-            if llm.is_available().await {
-                match llm.parse_intent(text).await {
-                    Ok(llm_result) => {
-                        return Ok(NluResponse {
-                            intent: Intent {
-                                name: llm_result.intent,
-                                confidence: llm_result.confidence,
-                            },
-                            entities: llm_result.entities.into_iter().map(|e| Entity {
-                                entity: e.name,
-                                value: e.value,
-                                confidence: 0.9,
-                            }).collect(),
-                        });
-                    }
-                    Err(e) => {
-                        tracing::warn!("LLM parsing failed: {}, falling back to Rasa", e);
+    /// Skills read values as text, so Duckling's numbers (a duration in
+    /// seconds, say) are replaced by the words they were found in.
+    fn from_rasa(response: NluResponse) -> Self {
+        let text = response.text;
+        Self {
+            intent: response.intent.name,
+            confidence: response.intent.confidence,
+            entities: response
+                .entities
+                .into_iter()
+                .map(|entity| NluEntity {
+                    name: entity.entity,
+                    value: match entity.value {
+                        serde_json::Value::String(value) => value,
+                        value => entity
+                            .start
+                            .zip(entity.end)
+                            .and_then(|(start, end)| text.get(start..end))
+                            .map(str::to_string)
+                            .unwrap_or_else(|| value.to_string()),
+                    },
+                    start: entity.start,
+                    end: entity.end,
+                    confidence: entity.confidence.unwrap_or(1.0),
+                    source: NluSource::Rasa,
+                })
+                .collect(),
+            source: NluSource::Rasa,
+        }
+    }
+
+    /// The LLM doesn't give spans, so they are looked up in the text.
+    fn from_llm(text: &str, intent: LlmIntent) -> Self {
+        let lowercase = text.to_lowercase();
+        Self {
+            intent: intent.intent,
+            confidence: intent.confidence,
+            entities: intent
+                .entities
+                .into_iter()
+                .map(|entity| {
+                    // Lowercasing keeps byte offsets for ASCII; anything else goes without a span
+                    let start = (lowercase.len() == text.len())
+                        .then(|| lowercase.find(&entity.value.to_lowercase()))
+                        .flatten();
+                    NluEntity {
+                        start,
+                        end: start.map(|start| start + entity.value.len()),
+                        name: entity.name,
+                        value: entity.value,
+                        confidence: LLM_ENTITY_CONFIDENCE,
+                        source: NluSource::Llm,
                     }
-                }
-            }
+                })
+                .collect(),
+            source: NluSource::Llm,
+        }
+    }
+}
+
+/// RustNlu's entities, as found for an intent it was `confidence` sure of.
+pub fn rust_nlu_entities(entities: Vec<RustEntity>, confidence: f64) -> Vec<NluEntity> {
+    entities
+        .into_iter()
+        .map(|entity| NluEntity {
+            name: entity.name,
+            value: entity.value,
+            start: Some(entity.start),
+            end: Some(entity.end),
+            confidence,
+            source: NluSource::RustNlu,
+        })
+        .collect()
+}
+
+/// Combines the winning engine's entities with RustNlu's for the same
+/// intent:
+///
+/// - the winner's entities are kept, unless it is unsure of them, and
+///   repeats of the same value are dropped;
+/// - RustNlu fills in entities the winner has none of, e.g. a date Rasa
+///   wasn't trained to find, except where they overlap one that was kept.
+pub fn merge_entities(winner: Vec<NluEntity>, fallback: Vec<NluEntity>) -> Vec<NluEntity> {
+    let mut merged: Vec<NluEntity> = Vec::new();
+    for entity in winner {
+        let repeated = merged
+            .iter()
+            .any(|kept| kept.name == entity.name && kept.value.eq_ignore_ascii_case(&entity.value));
+        if entity.confidence >= MIN_ENTITY_CONFIDENCE && !entity.value.trim().is_empty() && !repeated {
+            merged.push(entity);
+        }
+    }
+
+    let provided: Vec<String> = merged.iter().map(|entity| entity.name.clone()).collect();
+    for entity in fallback {
+        if !provided.contains(&entity.name) && !merged.iter().any(|kept| kept.overlaps(&entity)) {
+            merged.push(entity);
         }
+    }
+    merged
+}
+
+/// Asks the Rasa server `RasaManager` runs. One client is shared by all
+/// requests.
+pub struct RasaClient {
+    rasa_url: String,
+    client: reqwest::Client,
+}
 
-        // Fallback to Rasa
-        self.parse_intent(text).await
+impl RasaClient {
+    pub fn new(rasa_url: String) -> Self {
+        Self {
+            rasa_url,
+            client: reqwest::Client::new(),
+        }
     }
 
-    pub async fn parse_intent(&self, text: &str) -> Result<NluResponse, Box<dyn std::error::Error>> {
+    pub async fn parse(&self, text: &str) -> anyhow::Result<NluResult> {
         let request = NluRequest {
             text: text.to_string(),
         };
 
         let response = self
             .client
-            .post(&format!("{}/model/parse", self.rasa_url))
+            .post(format!("{}/model/parse", self.rasa_url))
             .json(&request)
             .send()
             .await?;
 
         if response.status().is_success() {
             let nlu_response: NluResponse = response.json().await?;
-            Ok(nlu_response)
+            Ok(NluResult::from_rasa(nlu_response))
         } else {
-            Err(format!("Rasa API error: {}", response.status()).into())
+            Err(anyhow::anyhow!("Rasa API error: {}", response.status()))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm::LlmEntity;
+
+    fn entity(name: &str, value: &str, span: Option<(usize, usize)>, confidence: f64, source: NluSource) -> NluEntity {
+        NluEntity {
+            name: name.to_string(),
+            value: value.to_string(),
+            start: span.map(|(start, _)| start),
+            end: span.map(|(_, end)| end),
+            confidence,
+            source,
+        }
+    }
+
+    fn names_and_sources(entities: &[NluEntity]) -> Vec<(&str, &str, NluSource)> {
+        entities
+            .iter()
+            .map(|entity| (entity.name.as_str(), entity.value.as_str(), entity.source))
+            .collect()
+    }
+
+    #[test]
+    fn the_winners_entities_take_precedence() {
+        // "turn the kitchen lights red"
+        let winner = vec![entity("room", "kitchen", Some((9, 16)), 0.9, NluSource::Rasa)];
+        let fallback = vec![
+            entity("room", "kitchen", Some((9, 16)), 0.8, NluSource::RustNlu),
+            entity("color", "red", Some((24, 27)), 0.8, NluSource::RustNlu),
+        ];
+        assert_eq!(
+            names_and_sources(&merge_entities(winner, fallback)),
+            vec![("room", "kitchen", NluSource::Rasa), ("color", "red", NluSource::RustNlu)]
+        );
+    }
+
+    #[test]
+    fn conflicting_entities_keep_the_winners_value() {
+        let winner = vec![entity("room", "living room", Some((12, 23)), 0.9, NluSource::Llm)];
+        let fallback = vec![entity("room", "office", Some((30, 36)), 0.8, NluSource::RustNlu)];
+        assert_eq!(
+            names_and_sources(&merge_entities(winner, fallback)),
+            vec![("room", "living room", NluSource::Llm)]
+        );
+    }
+
+    #[test]
+    fn fallback_entities_overlapping_a_kept_one_are_dropped() {
+        // "remind me at 7 to call mum": Rasa took "7 to call" as the time
+        let winner = vec![entity("time", "7 to call", Some((13, 22)), 0.9, NluSource::Rasa)];
+        let fallback = vec![
+            entity("reminder", "call mum", Some((18, 26)), 0.8, NluSource::RustNlu),
+            entity("recurrence", "daily", None, 0.8, NluSource::RustNlu),
+        ];
+        assert_eq!(
+            names_and_sources(&merge_entities(winner, fallback)),
+            vec![("time", "7 to call", NluSource::Rasa), ("recurrence", "daily", NluSource::RustNlu)]
+        );
+    }
+
+    #[test]
+    fn entities_without_spans_never_overlap() {
+        let winner = vec![entity("location", "paris", None, 0.9, NluSource::Llm)];
+        let fallback = vec![entity("date", "tomorrow", Some((0, 8)), 0.8, NluSource::RustNlu)];
+        assert_eq!(merge_entities(winner, fallback).len(), 2);
+    }
+
+    #[test]
+    fn unsure_empty_and_repeated_entities_are_left_out() {
+        let winner = vec![
+            entity("item", "milk", Some((4, 8)), 0.9, NluSource::Rasa),
+            entity("item", "Milk", Some((10, 14)), 0.9, NluSource::Rasa),
+            entity("item", "eggs", Some((20, 24)), 0.3, NluSource::Rasa),
+            entity("list", " ", Some((30, 31)), 0.9, NluSource::Rasa),
+        ];
+        let fallback = vec![entity("list", "shopping", Some((35, 43)), 0.8, NluSource::RustNlu)];
+        assert_eq!(
+            names_and_sources(&merge_entities(winner, fallback)),
+            vec![("item", "milk", NluSource::Rasa), ("list", "shopping", NluSource::RustNlu)]
+        );
+    }
+
+    #[test]
+    fn rasa_numbers_are_replaced_by_the_words_they_were_found_in() {
+        let response: NluResponse = serde_json::from_value(serde_json::json!({
+            "text": "set a timer for 5 minutes in the kitchen",
+            "intent": {"name": "set_timer", "confidence": 0.93},
+            "entities": [
+                {"entity": "duration", "value": 300, "start": 16, "end": 25},
+                {"entity": "number", "value": 5},
+                {"entity": "room", "value": "kitchen", "start": 33, "end": 40, "confidence_entity": 0.7},
+            ],
+        }))
+        .unwrap();
+        let result = NluResult::from_rasa(response);
+        assert_eq!(result.intent, "set_timer");
+        assert_eq!(result.source, NluSource::Rasa);
+        let values: Vec<(&str, Option<usize>, f64)> = result
+            .entities
+            .iter()
+            .map(|entity| (entity.value.as_str(), entity.start, entity.confidence))
+            .collect();
+        assert_eq!(
+            values,
+            vec![("5 minutes", Some(16), 1.0), ("5", None, 1.0), ("kitchen", Some(33), 0.7)]
+        );
+    }
+
+    #[test]
+    fn llm_entities_are_found_in_the_text() {
+        let intent = LlmIntent {
+            intent: "get_weather".to_string(),
+            confidence: 0.8,
+            entities: vec![
+                LlmEntity {
+                    name: "location".to_string(),
+                    value: "paris".to_string(),
+                },
+                LlmEntity {
+                    name: "date".to_string(),
+                    value: "next week".to_string(),
+                },
+            ],
+        };
+        let result = NluResult::from_llm("Weather in Paris tomorrow?", intent);
+        assert_eq!(result.source, NluSource::Llm);
+        let spans: Vec<(&str, Option<usize>, Option<usize>, f64)> = result
+            .entities
+            .iter()
+            .map(|entity| (entity.name.as_str(), entity.start, entity.end, entity.confidence))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![("location", Some(11), Some(16), LLM_ENTITY_CONFIDENCE), ("date", None, None, LLM_ENTITY_CONFIDENCE)]
+        );
+    }
+
+    #[test]
+    fn llm_entities_in_non_ascii_text_have_no_span() {
+        let intent = LlmIntent {
+            intent: "get_weather".to_string(),
+            confidence: 0.8,
+            entities: vec![LlmEntity {
+                name: "location".to_string(),
+                value: "İstanbul".to_string(),
+            }],
+        };
+        // "İ" lowercases to more bytes, so offsets into the lowercase text would be off
+        let result = NluResult::from_llm("Weather in İSTANBUL", intent);
+        assert_eq!(result.entities[0].start, None);
+        assert_eq!(result.entities[0].value, "İstanbul");
+    }
+}
@@ -0,0 +1,509 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{training, RustNlu};
+use crate::{config::settings::NluConfig, AppState};
+
+/// Where patterns for intents no file has yet are written.
+const NEW_INTENTS_FILE: &str = "custom.yml";
+
+/// A pattern file, e.g.
+///
+/// ```yaml
+/// intents:
+/// - intent: get_time
+///   patterns:
+///     - 'what.{0,10}time'
+/// ```
+///
+/// or the same as TOML, with `[[intents]]` tables.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PatternFile {
+    #[serde(default)]
+    pub intents: Vec<IntentPatterns>,
+}
+
+/// Case-insensitive regular expressions for an intent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntentPatterns {
+    pub intent: String,
+    pub patterns: Vec<String>,
+}
+
+/// Everything RustNlu knows about an intent, and where it comes from.
+#[derive(Debug, Serialize)]
+pub struct IntentSummary {
+    pub intent: String,
+    /// The pattern file, `None` when there are only examples.
+    pub file: Option<String>,
+    pub patterns: Vec<String>,
+    /// Examples from the Rasa training data, feedback included.
+    pub examples: Vec<String>,
+}
+
+pub fn read_pattern_file(path: &Path) -> Result<PatternFile> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: PatternFile = if is_toml(path) {
+        toml::from_str(&contents).with_context(|| format!("Invalid patterns in {}", path.display()))?
+    } else {
+        serde_yaml::from_str(&contents).with_context(|| format!("Invalid patterns in {}", path.display()))?
+    };
+    Ok(file)
+}
+
+/// Replaces the file as a whole, in the format its extension says.
+pub fn write_pattern_file(path: &Path, file: &PatternFile) -> Result<()> {
+    let contents = if is_toml(path) {
+        toml::to_string_pretty(file)?
+    } else {
+        serde_yaml::to_string(file)?
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let partial = path.with_extension("tmp");
+    fs::write(&partial, contents).with_context(|| format!("Failed to write {}", partial.display()))?;
+    fs::rename(&partial, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "toml")
+}
+
+/// Checks that every pattern compiles, naming the first that doesn't.
+pub fn validate(patterns: &[String]) -> std::result::Result<(), String> {
+    if patterns.iter().all(|pattern| pattern.trim().is_empty()) {
+        return Err("At least one pattern is needed".to_string());
+    }
+    for pattern in patterns {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+    }
+    Ok(())
+}
+
+/// The pattern files, in name order. A missing directory has none.
+pub fn pattern_files(config: &NluConfig) -> Result<Vec<PathBuf>> {
+    files_in(Path::new(&config.patterns_dir), &["yml", "yaml", "toml"])
+}
+
+/// The Rasa training data files, with exported feedback last so that it
+/// wins over the examples it corrects.
+pub fn rasa_data_files(config: &NluConfig) -> Result<Vec<PathBuf>> {
+    let feedback = config.feedback_path();
+    let mut files: Vec<PathBuf> = files_in(&config.rasa_data_dir(), &["yml", "yaml"])?
+        .into_iter()
+        .filter(|path| *path != feedback)
+        .collect();
+    if feedback.exists() {
+        files.push(feedback);
+    }
+    Ok(files)
+}
+
+fn files_in(dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let listed = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extensions.contains(&extension));
+        if listed && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Pattern files with the intents in each. An intent's patterns have to be
+/// in one file, so there is one place to edit them.
+fn read_pattern_files(config: &NluConfig) -> Result<Vec<(PathBuf, PatternFile)>> {
+    let mut files: Vec<(PathBuf, PatternFile)> = Vec::new();
+    for path in pattern_files(config)? {
+        let file = read_pattern_file(&path)?;
+        for patterns in &file.intents {
+            let defined_in = files
+                .iter()
+                .find(|(_, other)| other.intents.iter().any(|other| other.intent == patterns.intent));
+            if let Some((other_path, _)) = defined_in {
+                bail!(
+                    "Intent '{}' has patterns in both {} and {}",
+                    patterns.intent,
+                    other_path.display(),
+                    path.display()
+                );
+            }
+        }
+        files.push((path, file));
+    }
+    Ok(files)
+}
+
+/// RustNlu with the patterns from the pattern files and the examples from
+/// the Rasa training data.
+pub fn load_rust_nlu(config: &NluConfig) -> Result<RustNlu> {
+    let mut nlu = RustNlu::new();
+    let files = read_pattern_files(config)?;
+    if files.is_empty() {
+        warn!("No pattern files in {}", config.patterns_dir);
+    }
+    for (path, file) in files {
+        for patterns in file.intents {
+            nlu.add_patterns(&patterns.intent, &patterns.patterns)
+                .map_err(|e| anyhow!("Invalid pattern for '{}' in {}: {}", patterns.intent, path.display(), e))?;
+        }
+    }
+    for path in rasa_data_files(config)? {
+        nlu.add_examples(&training::read_rasa_examples(&path)?);
+    }
+    Ok(nlu)
+}
+
+/// Every intent with patterns or examples, by name.
+pub fn list_intents(config: &NluConfig) -> Result<Vec<IntentSummary>> {
+    let mut intents: BTreeMap<String, IntentSummary> = BTreeMap::new();
+    for (path, file) in read_pattern_files(config)? {
+        for patterns in file.intents {
+            intents.insert(
+                patterns.intent.clone(),
+                IntentSummary {
+                    intent: patterns.intent,
+                    file: Some(file_name(&path)),
+                    patterns: patterns.patterns,
+                    examples: Vec::new(),
+                },
+            );
+        }
+    }
+    for path in rasa_data_files(config)? {
+        for example in training::read_rasa_examples(&path)? {
+            intents
+                .entry(example.intent.clone())
+                .or_insert_with(|| IntentSummary {
+                    intent: example.intent,
+                    file: None,
+                    patterns: Vec::new(),
+                    examples: Vec::new(),
+                })
+                .examples
+                .push(example.text);
+        }
+    }
+    Ok(intents.into_values().collect())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Replaces an intent's patterns in the file that has them, or adds them to
+/// `custom.yml`. Patterns must have been validated.
+pub fn set_patterns(config: &NluConfig, intent: &str, patterns: Vec<String>) -> Result<()> {
+    let files = read_pattern_files(config)?;
+    let defined_in = files
+        .into_iter()
+        .find(|(_, file)| file.intents.iter().any(|patterns| patterns.intent == intent));
+    let (path, mut file) = match defined_in {
+        Some(defined_in) => defined_in,
+        None => {
+            let path = Path::new(&config.patterns_dir).join(NEW_INTENTS_FILE);
+            let file = if path.exists() {
+                read_pattern_file(&path)?
+            } else {
+                PatternFile::default()
+            };
+            (path, file)
+        }
+    };
+
+    let patterns: Vec<String> = patterns.into_iter().filter(|pattern| !pattern.trim().is_empty()).collect();
+    match file.intents.iter_mut().find(|existing| existing.intent == intent) {
+        Some(existing) => existing.patterns = patterns,
+        None => file.intents.push(IntentPatterns {
+            intent: intent.to_string(),
+            patterns,
+        }),
+    }
+    write_pattern_file(&path, &file)?;
+    info!("Patterns for '{}' written to {}", intent, path.display());
+    Ok(())
+}
+
+/// Takes an intent's patterns out of its file. `false` when it has none.
+pub fn remove_patterns(config: &NluConfig, intent: &str) -> Result<bool> {
+    for (path, mut file) in read_pattern_files(config)? {
+        let before = file.intents.len();
+        file.intents.retain(|patterns| patterns.intent != intent);
+        if file.intents.len() != before {
+            write_pattern_file(&path, &file)?;
+            info!("Patterns for '{}' removed from {}", intent, path.display());
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Rebuilds the running RustNlu. When a file is broken, the one running is
+/// kept.
+pub fn reload(state: &AppState) -> Result<()> {
+    let nlu = load_rust_nlu(&state.config.nlu)?;
+    *state.rust_nlu.write().unwrap() = nlu;
+    Ok(())
+}
+
+/// What the files RustNlu is built from look like now: which there are,
+/// and when each last changed.
+fn fingerprint(config: &NluConfig) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let files = pattern_files(config)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(rasa_data_files(config).unwrap_or_default());
+    files
+        .map(|path| {
+            let metadata = fs::metadata(&path).ok();
+            let modified = metadata.as_ref().and_then(|metadata| metadata.modified().ok());
+            let len = metadata.map(|metadata| metadata.len()).unwrap_or(0);
+            (path, modified, len)
+        })
+        .collect()
+}
+
+/// Reloads RustNlu whenever a pattern file or the Rasa training data
+/// changes, whoever changed it.
+pub async fn watch(state: AppState) {
+    let config = state.config.nlu.clone();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.reload_interval_secs.max(1)));
+    let mut last = fingerprint(&config);
+    loop {
+        interval.tick().await;
+        let current = fingerprint(&config);
+        if current == last {
+            continue;
+        }
+        last = current;
+        match reload(&state) {
+            Ok(()) => info!("Reloaded NLU patterns and examples"),
+            Err(e) => warn!("Keeping the NLU patterns we have: {:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Settings, test_support};
+
+    /// NLU settings reading patterns and Rasa data from `dir`.
+    fn config(dir: &Path) -> NluConfig {
+        let mut config = Settings::new().unwrap().nlu;
+        config.patterns_dir = dir.join("patterns").to_str().unwrap().to_string();
+        config.rasa_dir = dir.join("rasa").to_str().unwrap().to_string();
+        config
+    }
+
+    fn write(config: &NluConfig, name: &str, contents: &str) {
+        let dir = Path::new(&config.patterns_dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(name), contents).unwrap();
+    }
+
+    fn write_examples(config: &NluConfig, name: &str, contents: &str) {
+        let dir = config.rasa_data_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name), contents).unwrap();
+    }
+
+    const TIME: &str = "intents:\n- intent: get_time\n  patterns:\n    - 'what.{0,10}time'\n";
+
+    fn understood(nlu: &RustNlu, text: &str) -> String {
+        nlu.parse(text).0.name
+    }
+
+    #[test]
+    fn rust_nlu_is_built_from_patterns_and_examples() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write(&config, "time.yml", TIME);
+        write(
+            &config,
+            "lights.toml",
+            "[[intents]]\nintent = \"control_lights\"\npatterns = ['turn (on|off)']\n",
+        );
+        write(&config, "notes.txt", "not patterns");
+        write_examples(
+            &config,
+            "nlu.yml",
+            "version: \"3.1\"\nnlu:\n- intent: get_weather\n  examples: |\n    - is it going to rain in [Paris](location)\n",
+        );
+
+        let nlu = load_rust_nlu(&config).unwrap();
+        assert_eq!(understood(&nlu, "what's the time"), "get_time");
+        assert_eq!(understood(&nlu, "Turn ON the lamp"), "control_lights");
+        assert_eq!(understood(&nlu, "is it going to rain in Paris"), "get_weather");
+
+        let files: Vec<String> = pattern_files(&config).unwrap().iter().map(|path| file_name(path)).collect();
+        assert_eq!(files, ["lights.toml", "time.yml"]);
+    }
+
+    #[test]
+    fn missing_directories_have_no_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        assert!(pattern_files(&config).unwrap().is_empty());
+        assert!(rasa_data_files(&config).unwrap().is_empty());
+        assert_eq!(understood(&load_rust_nlu(&config).unwrap(), "what's the time"), "unknown");
+    }
+
+    #[test]
+    fn feedback_is_read_after_the_other_training_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write_examples(&config, "feedback.yml", "nlu: []\n");
+        write_examples(&config, "zzz.yml", "nlu: []\n");
+        let files: Vec<String> = rasa_data_files(&config).unwrap().iter().map(|path| file_name(path)).collect();
+        assert_eq!(files, ["zzz.yml", "feedback.yml"]);
+    }
+
+    #[test]
+    fn an_intent_can_only_have_patterns_in_one_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write(&config, "a.yml", TIME);
+        write(&config, "b.yml", TIME);
+        let error = load_rust_nlu(&config).err().unwrap().to_string();
+        assert!(error.contains("'get_time' has patterns in both"), "{}", error);
+        assert!(list_intents(&config).is_err());
+    }
+
+    #[test]
+    fn broken_patterns_name_their_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write(&config, "time.yml", "intents:\n- intent: get_time\n  patterns:\n    - 'what (time'\n");
+        let error = load_rust_nlu(&config).err().unwrap().to_string();
+        assert!(error.contains("'get_time'") && error.contains("time.yml"), "{}", error);
+
+        assert!(validate(&["what (time".to_string()]).is_err());
+        assert!(validate(&[" ".to_string()]).is_err());
+        assert!(validate(&["what.{0,10}time".to_string(), String::new()]).is_ok());
+    }
+
+    #[test]
+    fn setting_patterns_edits_the_file_that_has_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write(&config, "time.toml", "[[intents]]\nintent = \"get_time\"\npatterns = ['what.{0,10}time']\n");
+
+        set_patterns(&config, "get_time", vec!["clock".to_string(), " ".to_string()]).unwrap();
+        set_patterns(&config, "say_hello", vec!["^hello".to_string()]).unwrap();
+
+        let time = read_pattern_file(&Path::new(&config.patterns_dir).join("time.toml")).unwrap();
+        assert_eq!(time.intents.len(), 1);
+        assert_eq!(time.intents[0].patterns, ["clock"]);
+        let custom = read_pattern_file(&Path::new(&config.patterns_dir).join(NEW_INTENTS_FILE)).unwrap();
+        assert_eq!(custom.intents[0].intent, "say_hello");
+
+        let intents = list_intents(&config).unwrap();
+        let files: Vec<(&str, Option<&str>)> = intents
+            .iter()
+            .map(|summary| (summary.intent.as_str(), summary.file.as_deref()))
+            .collect();
+        assert_eq!(files, [("get_time", Some("time.toml")), ("say_hello", Some("custom.yml"))]);
+
+        assert!(remove_patterns(&config, "get_time").unwrap());
+        assert!(!remove_patterns(&config, "get_time").unwrap());
+        let time = read_pattern_file(&Path::new(&config.patterns_dir).join("time.toml")).unwrap();
+        assert!(time.intents.is_empty());
+    }
+
+    #[test]
+    fn pattern_files_round_trip_as_yaml_and_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = PatternFile {
+            intents: vec![IntentPatterns {
+                intent: "get_time".to_string(),
+                patterns: vec!["what.{0,10}time".to_string(), r"\bclock\b".to_string()],
+            }],
+        };
+        for name in ["patterns.yml", "patterns.toml"] {
+            let path = dir.path().join(name);
+            write_pattern_file(&path, &file).unwrap();
+            let read = read_pattern_file(&path).unwrap();
+            assert_eq!(read.intents[0].intent, "get_time", "{}", name);
+            assert_eq!(read.intents[0].patterns, file.intents[0].patterns, "{}", name);
+            assert!(!path.with_extension("tmp").exists());
+        }
+        let toml = fs::read_to_string(dir.path().join("patterns.toml")).unwrap();
+        assert!(toml.contains("[[intents]]"), "{}", toml);
+    }
+
+    #[test]
+    fn the_fingerprint_changes_with_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        write(&config, "time.yml", TIME);
+        let first = fingerprint(&config);
+        assert_eq!(first, fingerprint(&config));
+
+        write(&config, "time.yml", &format!("{}    - 'clock'\n", TIME));
+        let edited = fingerprint(&config);
+        assert_ne!(edited, first);
+
+        write_examples(&config, "nlu.yml", "nlu: []\n");
+        assert_ne!(fingerprint(&config), edited);
+    }
+
+    #[tokio::test]
+    async fn edited_files_are_picked_up_and_broken_ones_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::new().unwrap();
+        settings.nlu = config(dir.path());
+        settings.nlu.reload_interval_secs = 1;
+        write(&settings.nlu, "time.yml", TIME);
+        let state = test_support::state_with(settings).await;
+        let watcher = tokio::spawn(watch(state.clone()));
+        // Lets the watcher take its first look before anything changes
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let understands = |text: &str, intent: &str| {
+            let state = state.clone();
+            let (text, intent) = (text.to_string(), intent.to_string());
+            async move {
+                for _ in 0..50 {
+                    if understood(&state.rust_nlu.read().unwrap(), &text) == intent {
+                        return true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                false
+            }
+        };
+
+        write(&state.config.nlu, "hello.yml", "intents:\n- intent: say_hello\n  patterns:\n    - '^hello'\n");
+        assert!(understands("hello there", "say_hello").await);
+
+        // A broken file leaves the running patterns alone
+        write(&state.config.nlu, "broken.yml", "intents:\n- intent: oops\n  patterns:\n    - '('\n");
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(understood(&state.rust_nlu.read().unwrap(), "hello there"), "say_hello");
+        assert!(reload(&state).is_err());
+        watcher.abort();
+    }
+}
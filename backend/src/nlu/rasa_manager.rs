@@ -1,19 +1,43 @@
 use std::process::{Child, Command, Stdio};
-use std::path::Path;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
 use tokio::time::{sleep, Duration};
 use tracing::{info, warn, error};
 
+use crate::config::settings::NluConfig;
+
 pub struct RasaManager {
     process: Option<Child>,
+    rasa_dir: PathBuf,
+    rasa_url: String,
+    pub last_trained_at: Option<DateTime<Utc>>,
+    /// Why the last retraining failed, cleared when one succeeds.
+    pub last_error: Option<String>,
 }
 
 impl RasaManager {
-    pub fn new() -> Self {
-        Self { process: None }
+    pub fn new(config: &NluConfig) -> Self {
+        Self {
+            process: None,
+            rasa_dir: PathBuf::from(&config.rasa_dir),
+            rasa_url: config.rasa_url.trim_end_matches('/').to_string(),
+            last_trained_at: None,
+            last_error: None,
+        }
+    }
+
+    /// Port the server is run on, taken from the configured URL.
+    fn port(&self) -> String {
+        self.rasa_url
+            .rsplit(':')
+            .next()
+            .filter(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or("5005")
+            .to_string()
     }
 
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let rasa_dir = Path::new("../nlu/rasa");
+        let rasa_dir = self.rasa_dir.as_path();
         
         if !rasa_dir.exists() {
             return Err("Rasa directory not found".into());
@@ -55,7 +79,7 @@ impl RasaManager {
         if !models_dir.exists() {
             info!("Training Rasa model...");
             let train_output = Command::new("rasa")
-                .args(&["train", "nlu"])
+                .args(["train", "nlu"])
                 .current_dir(rasa_dir)
                 .output()?;
 
@@ -67,8 +91,9 @@ impl RasaManager {
         }
 
         // Start Rasa server
+        let port = self.port();
         let child = Command::new("rasa")
-            .args(&["run", "--enable-api", "--cors", "*", "--port", "5005"])
+            .args(["run", "--enable-api", "--cors", "*", "--port", &port])
             .current_dir(rasa_dir)
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -80,7 +105,7 @@ impl RasaManager {
         info!("Waiting for Rasa to start...");
         for _ in 0..30 {
             if self.is_healthy().await {
-                info!("Rasa NLU server started successfully on port 5005");
+                info!("Rasa NLU server started successfully on port {}", port);
                 return Ok(());
             }
             sleep(Duration::from_secs(1)).await;
@@ -90,8 +115,43 @@ impl RasaManager {
         Ok(())
     }
 
+    /// Trains a model on the current training data, feedback included, and
+    /// restarts the server with it. Takes minutes.
+    pub async fn retrain(&mut self) -> Result<(), String> {
+        info!("Retraining Rasa model...");
+        let result = self.train_and_restart().await;
+        match &result {
+            Ok(()) => {
+                info!("Rasa model retrained");
+                self.last_trained_at = Some(Utc::now());
+                self.last_error = None;
+            }
+            Err(e) => {
+                error!("Rasa retraining failed: {}", e);
+                self.last_error = Some(e.clone());
+            }
+        }
+        result
+    }
+
+    async fn train_and_restart(&mut self) -> Result<(), String> {
+        let output = tokio::process::Command::new("rasa")
+            .args(["train", "nlu"])
+            .current_dir(&self.rasa_dir)
+            .output()
+            .await
+            .map_err(|e| format!("Could not run rasa: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("Training failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        // The server loads the newest model when it starts
+        self.stop();
+        self.start().await.map_err(|e| e.to_string())
+    }
+
     async fn is_healthy(&self) -> bool {
-        match reqwest::get("http://localhost:5005/status").await {
+        match reqwest::get(format!("{}/status", self.rasa_url)).await {
             Ok(response) => response.status().is_success(),
             Err(_) => false,
         }
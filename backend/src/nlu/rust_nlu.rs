@@ -1,6 +1,81 @@
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+use tracing::debug;
+
+use super::{datetime, training::TrainingExample};
+
+const TIMER_INTENTS: &[&str] = &["set_timer", "cancel_timer", "list_timers", "set_alarm", "cancel_alarm", "list_alarms"];
+const REMINDER_INTENTS: &[&str] = &["set_reminder", "cancel_reminder", "list_reminders"];
+const LIST_INTENTS: &[&str] = &["add_to_list", "remove_from_list", "read_list", "clear_list"];
+
+/// Where a pattern matches, the intent is at least this likely.
+const PATTERN_CONFIDENCE: f64 = 0.8;
+
+/// Intents whose examples match less well than this don't count.
+const MIN_EXAMPLE_SCORE: f64 = 0.5;
+
+/// How much more missing words count than extra ones.
+const RECALL_WEIGHT: f64 = 2.0;
+
+/// Words shorter than this are too easily confused to match fuzzily.
+const MIN_FUZZY_WORD_LEN: usize = 4;
+const MIN_WORD_SIMILARITY: f64 = 0.75;
+
+/// Words next to "timer" or "alarm" that don't name it.
+const NOT_A_LABEL: &[&str] = &[
+    "a", "an", "the", "my", "new", "other", "next", "last", "all", "current", "every", "each", "second", "minute",
+    "hour", "tomorrow",
+];
+
+static ROOM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(living room|bedroom|kitchen|bathroom|office)").unwrap());
+static LIGHT_STATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(on|off)\b").unwrap());
+static BRIGHTNESS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(\d{1,3})\s*(?:%|percent)").unwrap());
+static COLOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(warm white|white|red|green|blue|yellow|orange|purple|pink)\b").unwrap()
+});
+
+static LABEL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:a|an|the|my|minute|hour|second)s?\s+([a-z]+)\s+(?:timer|alarm)\b|\b(?:timer|alarm)\s+(?:for|called|named)\s+(?:the\s+|my\s+)?([a-z]+)\b",
+    )
+    .unwrap()
+});
+
+static REMINDER_SUBJECT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:remind(?:ers?)?(?:\s+me)?|don'?t let me forget)\b(.*)$").unwrap()
+});
+static REMINDER_LEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(?:(?:to|about|that|of|for|me)\s+)+").unwrap());
+
+/// List names are a word or two: "shopping", "to-do", "hardware store".
+const LIST_NAME_PATTERN: &str = r"(?:the\s+|my\s+|our\s+)?([a-z][a-z-]*(?:\s+[a-z-]+)?)\s+list\b";
+const LIST_VERB_PATTERN: &str = r"(?i)\b(?:add|put|remove|take|delete|cross|tick|check)\s+(?:off\s+)?";
+static LIST: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(?:to|on|onto|from|off|in)\s+{}|\b(?:the|my|our)\s+([a-z][a-z-]*(?:\s+[a-z-]+)?)\s+list\b",
+        LIST_NAME_PATTERN
+    ))
+    .unwrap()
+});
+static LIST_ITEMS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"{}(.+)\s+(?:to|on|onto|off|from)\s+(?:the\s+|my\s+|our\s+)?(?:[a-z][a-z-]*(?:\s+[a-z-]+)?\s+)?list\b",
+        LIST_VERB_PATTERN
+    ))
+    .unwrap()
+});
+static BARE_LIST_ITEMS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"{}(.+?)[.!?]?$", LIST_VERB_PATTERN)).unwrap());
+static ITEM_SEPARATOR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\s*,\s*(?:and\s+)?|\s+and\s+").unwrap());
+
+static LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:in|for|at)\s+([a-zA-Z0-9\s,]+?)(?:\?|$)").unwrap());
+static LOCATION_FILLER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:(?:the|this|next|on)\s+)+|(?:\s+(?:the|this|next|on))+$").unwrap()
+});
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Intent {
@@ -20,100 +95,146 @@ pub struct Entity {
 pub struct IntentPattern {
     pub intent: String,
     pub patterns: Vec<Regex>,
-    pub entities: Vec<String>,
 }
 
+#[derive(Clone, Default)]
 pub struct RustNlu {
     patterns: Vec<IntentPattern>,
+    examples: Vec<Example>,
+    /// How telling each word in the examples is of an intent.
+    weights: HashMap<String, f64>,
+    /// The words in each intent's examples.
+    vocabularies: HashMap<String, HashSet<String>>,
+}
+
+/// A training example utterance.
+#[derive(Debug, Clone)]
+struct Example {
+    intent: String,
+    words: Vec<String>,
 }
 
 impl RustNlu {
+    /// An engine that knows no intents yet. `patterns::load_rust_nlu` builds
+    /// the one we run with.
     pub fn new() -> Self {
-        let mut nlu = Self {
-            patterns: Vec::new(),
-        };
-        nlu.load_patterns();
-        nlu
-    }
-
-    fn load_patterns(&mut self) {
-        // Time patterns
-        self.add_intent("get_time", vec![
-            r"(?i)what.{0,10}time",
-            r"(?i)current.{0,5}time",
-            r"(?i)tell.{0,10}time",
-            r"(?i)time.{0,5}(is|please)",
-            r"(?i)^time$",
-            r"(?i)clock",
-        ]);
-
-        // Weather patterns  
-        self.add_intent("get_weather", vec![
-            r"(?i)weather",
-            r"(?i)temperature",
-            r"(?i)forecast",
-            r"(?i)(how.{0,5}|what.{0,5})(hot|cold|warm)",
-            r"(?i)raining",
-            r"(?i)sunny",
-        ]);
-
-        // Light control patterns
-        self.add_intent("control_lights", vec![
-            r"(?i)turn.{0,5}(on|off).{0,10}light",
-            r"(?i)light.{0,5}(on|off)",
-            r"(?i)(switch|dim|brighten).{0,10}light",
-            r"(?i)lights.{0,5}(on|off)",
-        ]);
-
-        // Greeting patterns
-        self.add_intent("greet", vec![
-            r"(?i)^(hi|hello|hey)($|\s)",
-            r"(?i)good.{0,5}(morning|evening|afternoon)",
-            r"(?i)greetings",
-        ]);
-
-        // Goodbye patterns
-        self.add_intent("goodbye", vec![
-            r"(?i)(bye|goodbye|farewell)",
-            r"(?i)see.{0,5}you",
-            r"(?i)talk.{0,5}later",
-        ]);
-    }
-
-    fn add_intent(&mut self, intent: &str, patterns: Vec<&str>) {
-        let regex_patterns: Vec<Regex> = patterns
-            .into_iter()
-            .filter_map(|p| Regex::new(p).ok())
-            .collect();
+        Self::default()
+    }
+
+    /// Adds case-insensitive regular expressions for `intent`.
+    pub fn add_patterns(&mut self, intent: &str, patterns: &[String]) -> Result<(), regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+            .collect::<Result<Vec<_>, _>>()?;
 
         self.patterns.push(IntentPattern {
             intent: intent.to_string(),
-            patterns: regex_patterns,
-            entities: Vec::new(),
+            patterns,
         });
+        Ok(())
+    }
+
+    /// Learns example utterances, e.g. Rasa's training data or reviewed
+    /// feedback. An example that repeats an earlier one replaces it, so later
+    /// sources win, and an exact match wins over any pattern.
+    pub fn add_examples(&mut self, examples: &[TrainingExample]) {
+        for example in examples {
+            let words = words(&example.text);
+            if words.is_empty() {
+                continue;
+            }
+            match self.examples.iter_mut().find(|known| known.words == words) {
+                Some(known) => known.intent = example.intent.clone(),
+                None => self.examples.push(Example {
+                    intent: example.intent.clone(),
+                    words,
+                }),
+            }
+        }
+        self.weigh_words();
+    }
+
+    /// Gives each word the BM25 inverse document frequency, with an intent's
+    /// examples as one document: "weather" says a lot, "the" next to nothing.
+    fn weigh_words(&mut self) {
+        self.vocabularies.clear();
+        for example in &self.examples {
+            self.vocabularies
+                .entry(example.intent.clone())
+                .or_default()
+                .extend(example.words.iter().cloned());
+        }
+        let mut intents_with: HashMap<&str, usize> = HashMap::new();
+        for words in self.vocabularies.values() {
+            for word in words {
+                *intents_with.entry(word).or_default() += 1;
+            }
+        }
+        let intents = self.vocabularies.len() as f64;
+
+        self.weights = intents_with
+            .into_iter()
+            .map(|(word, with)| {
+                let with = with as f64;
+                (word.to_string(), (1.0 + (intents - with + 0.5) / (with + 0.5)).ln())
+            })
+            .collect();
+    }
+
+    /// Every intent there is a pattern or example for.
+    pub fn intents(&self) -> Vec<&str> {
+        let mut intents: Vec<&str> = self
+            .patterns
+            .iter()
+            .map(|pattern| pattern.intent.as_str())
+            .chain(self.examples.iter().map(|example| example.intent.as_str()))
+            .collect();
+        intents.sort_unstable();
+        intents.dedup();
+        intents
     }
 
+    /// The most likely intent for `text`. Confidences are comparable
+    /// across intents: 1 for a known example, at least
+    /// `PATTERN_CONFIDENCE` where a pattern matches, and otherwise how
+    /// closely the text matches the intent's examples.
     pub fn parse(&self, text: &str) -> (Intent, Vec<Entity>) {
+        let query = self.query(text);
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for example in &self.examples {
+            let score = self.score(&query, example);
+            let best = scores.entry(&example.intent).or_insert(0.0);
+            *best = best.max(score);
+        }
+
         let mut best_intent = Intent {
             name: "unknown".to_string(),
             confidence: 0.0,
         };
+        let mut consider = |intent: &str, confidence: f64| {
+            if confidence > best_intent.confidence {
+                best_intent = Intent {
+                    name: intent.to_string(),
+                    confidence,
+                };
+            }
+        };
 
         for pattern in &self.patterns {
-            for regex in &pattern.patterns {
-                if let Some(matches) = regex.find(text) {
-                    let confidence = self.calculate_confidence(text, matches.as_str());
-                    if confidence > best_intent.confidence {
-                        best_intent = Intent {
-                            name: pattern.intent.clone(),
-                            confidence,
-                        };
-                    }
-                }
+            if pattern.patterns.iter().any(|regex| regex.is_match(text)) {
+                let score = scores.get(pattern.intent.as_str()).copied().unwrap_or(0.0);
+                consider(&pattern.intent, PATTERN_CONFIDENCE + (1.0 - PATTERN_CONFIDENCE) * score);
+            }
+        }
+        for example in &self.examples {
+            let score = scores[example.intent.as_str()];
+            if score >= MIN_EXAMPLE_SCORE {
+                consider(&example.intent, score);
             }
         }
 
-        // If no pattern matched, set low confidence
+        // If nothing matched, set low confidence
         if best_intent.confidence == 0.0 {
             best_intent.confidence = 0.1;
         }
@@ -122,30 +243,85 @@ impl RustNlu {
         (best_intent, entities)
     }
 
-    fn calculate_confidence(&self, text: &str, matched_part: &str) -> f64 {
-        let text_len = text.len() as f64;
-        let match_len = matched_part.len() as f64;
-        
-        // Base confidence on match coverage
-        let coverage = match_len / text_len;
-        
-        // Boost confidence for exact matches
-        let exactness_bonus = if text.trim().to_lowercase() == matched_part.to_lowercase() {
-            0.3
-        } else {
-            0.0
+    /// The words in `text`, each with the known words it could be and how
+    /// likely: 1 when spelled the same, less for a near miss such as
+    /// "wether" or "alarm" for "alarms". Words no example has anything like,
+    /// such as most places, are left out.
+    fn query(&self, text: &str) -> Vec<HashMap<&str, f64>> {
+        words(text)
+            .iter()
+            .map(|word| {
+                self.weights
+                    .keys()
+                    .filter_map(|known| {
+                        let similarity = word_similarity(word, known);
+                        (similarity > 0.0).then_some((known.as_str(), similarity))
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .filter(|candidates| !candidates.is_empty())
+            .collect()
+    }
+
+    /// How well `query` matches `example`, from 0 to 1, weighing words by
+    /// how telling they are: how much of the example is in the query, and
+    /// how much of the query the example's intent has words for. Missing
+    /// words of the example count for more than extra words in the query,
+    /// so that "could you tell me the time please" still matches "tell me
+    /// the time".
+    fn score(&self, query: &[HashMap<&str, f64>], example: &Example) -> f64 {
+        let Some(vocabulary) = self.vocabularies.get(&example.intent) else {
+            return 0.0;
+        };
+        let weight = |word: &str| self.weights.get(word).copied().unwrap_or(0.0);
+        // What a word of the query is worth, as the best word it could be
+        let worth = |candidates: &HashMap<&str, f64>, known: &dyn Fn(&str) -> bool| {
+            candidates
+                .iter()
+                .filter(|(word, _)| known(word))
+                .map(|(word, similarity)| weight(word) * similarity)
+                .fold(0.0, f64::max)
         };
 
-        (coverage * 0.7 + exactness_bonus).min(0.95)
+        let example_words: HashSet<&str> = example.words.iter().map(String::as_str).collect();
+        let example_weight: f64 = example_words.iter().map(|word| weight(word)).sum();
+        let query_weight: f64 = query.iter().map(|candidates| worth(candidates, &|_| true)).sum();
+        if example_weight == 0.0 || query_weight == 0.0 {
+            return 0.0;
+        }
+
+        let found: f64 = example_words
+            .iter()
+            .map(|word| {
+                let similarity = query
+                    .iter()
+                    .filter_map(|candidates| candidates.get(word).copied())
+                    .fold(0.0, f64::max);
+                weight(word) * similarity
+            })
+            .sum();
+        let explained: f64 = query
+            .iter()
+            .map(|candidates| worth(candidates, &|word| vocabulary.contains(word)))
+            .sum();
+
+        let recall = found / example_weight;
+        let precision = explained / query_weight;
+        if recall == 0.0 || precision == 0.0 {
+            return 0.0;
+        }
+        let beta2 = RECALL_WEIGHT * RECALL_WEIGHT;
+        (1.0 + beta2) * precision * recall / (beta2 * precision + recall)
     }
 
-    fn extract_entities(&self, text: &str, intent: &str) -> Vec<Entity> {
+    /// The entities `intent` takes, found in `text`. Also used to fill in
+    /// for another engine that understood the intent.
+    pub fn extract_entities(&self, text: &str, intent: &str) -> Vec<Entity> {
         let mut entities = Vec::new();
 
         // Extract room entities for light control
         if intent == "control_lights" {
-            let room_regex = Regex::new(r"(?i)(living room|bedroom|kitchen|bathroom|office)").unwrap();
-            if let Some(matches) = room_regex.find(text) {
+            if let Some(matches) = ROOM.find(text) {
                 entities.push(Entity {
                     name: "room".to_string(),
                     value: matches.as_str().to_lowercase(),
@@ -153,28 +329,299 @@ impl RustNlu {
                     end: matches.end(),
                 });
             }
+
+            if let Some(matches) = LIGHT_STATE.find(text) {
+                entities.push(Entity {
+                    name: "state".to_string(),
+                    value: matches.as_str().to_lowercase(),
+                    start: matches.start(),
+                    end: matches.end(),
+                });
+            }
+
+            if let Some(level) = BRIGHTNESS.captures(text).and_then(|captures| captures.get(1)) {
+                entities.push(Entity {
+                    name: "brightness".to_string(),
+                    value: level.as_str().to_string(),
+                    start: level.start(),
+                    end: level.end(),
+                });
+            }
+
+            if let Some(matches) = COLOR.find(text) {
+                entities.push(Entity {
+                    name: "color".to_string(),
+                    value: matches.as_str().to_lowercase(),
+                    start: matches.start(),
+                    end: matches.end(),
+                });
+            }
+        }
+
+        // Extract durations, clock times and labels for timers and alarms
+        if TIMER_INTENTS.contains(&intent) {
+            let duration = datetime::find_duration(text);
+            let time = datetime::find_clock_time(text);
+
+            if let Some((start, end)) = duration {
+                entities.push(Entity {
+                    name: "duration".to_string(),
+                    value: text[start..end].to_string(),
+                    start,
+                    end,
+                });
+            }
+
+            if let Some((start, end)) = time {
+                entities.push(Entity {
+                    name: "time".to_string(),
+                    value: text[start..end].to_string(),
+                    start,
+                    end,
+                });
+            }
+
+            let label = LABEL
+                .captures_iter(text)
+                .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+                .filter(|label| !NOT_A_LABEL.contains(&label.as_str().to_lowercase().as_str()))
+                .find(|label| {
+                    // "timer for five minutes" and "alarm for noon" aren't names
+                    [duration, time]
+                        .into_iter()
+                        .flatten()
+                        .all(|(start, end)| label.end() <= start || end <= label.start())
+                });
+            if let Some(label) = label {
+                entities.push(Entity {
+                    name: "label".to_string(),
+                    value: label.as_str().to_lowercase(),
+                    start: label.start(),
+                    end: label.end(),
+                });
+            }
+        }
+
+        // Extract what to be reminded about and how often
+        if REMINDER_INTENTS.contains(&intent) {
+            if let Some(subject) = REMINDER_SUBJECT.captures(text).and_then(|captures| captures.get(1)) {
+                let stripped = datetime::strip_date_time(subject.as_str());
+                let value = REMINDER_LEADING.replace(&stripped, "").trim().to_string();
+                if !value.is_empty() {
+                    entities.push(Entity {
+                        name: "reminder".to_string(),
+                        value,
+                        start: subject.start(),
+                        end: subject.end(),
+                    });
+                }
+            }
+
+            if let Some(recurrence) = datetime::parse_recurrence(text) {
+                entities.push(Entity {
+                    name: "recurrence".to_string(),
+                    value: recurrence.to_string(),
+                    start: 0,
+                    end: text.len(),
+                });
+            }
+        }
+
+        // Extract the list and the items on it
+        if LIST_INTENTS.contains(&intent) {
+            let list = LIST
+                .captures_iter(text)
+                .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+                .find(|list| !["the", "my", "our"].contains(&list.as_str().to_lowercase().as_str()));
+            if let Some(list) = list {
+                entities.push(Entity {
+                    name: "list".to_string(),
+                    value: list.as_str().to_lowercase(),
+                    start: list.start(),
+                    end: list.end(),
+                });
+            }
+
+            // Longest first, so "add go to the gym to my to-do list" keeps "to the gym"
+            let items = LIST_ITEMS
+                .captures(text)
+                .or_else(|| BARE_LIST_ITEMS.captures(text))
+                .and_then(|captures| captures.get(1));
+            if let Some(items) = items {
+                // "milk, eggs and bread" is three items
+                let mut start = items.start();
+                let ends = ITEM_SEPARATOR
+                    .find_iter(items.as_str())
+                    .map(|found| (items.start() + found.start(), items.start() + found.end()))
+                    .chain(std::iter::once((items.end(), items.end())));
+                for (end, next) in ends {
+                    let value = text[start..end].trim();
+                    if !value.is_empty() && !value.eq_ignore_ascii_case("it") {
+                        entities.push(Entity {
+                            name: "item".to_string(),
+                            value: value.to_string(),
+                            start,
+                            end,
+                        });
+                    }
+                    start = next;
+                }
+            }
         }
 
         // Extract location entities for weather
         if intent == "get_weather" {
-            println!("Extracting location from text: '{}'", text);
-            let location_regex = Regex::new(r"(?i)(?:in|for|at)\s+([a-zA-Z\s,]+?)(?:\?|$)").unwrap();
-            if let Some(captures) = location_regex.captures(text) {
+            debug!("Extracting location from text: '{}'", text);
+            if let Some(captures) = LOCATION.captures(text) {
                 if let Some(location_match) = captures.get(1) {
-                    let location_value = location_match.as_str().trim().to_string();
-                    println!("Found location: '{}'", location_value);
-                    entities.push(Entity {
-                        name: "location".to_string(),
-                        value: location_value,
-                        start: location_match.start(),
-                        end: location_match.end(),
-                    });
+                    // "in Paris on Saturday" is about Paris, "for tomorrow" isn't a place
+                    let location_value = LOCATION_FILLER
+                        .replace_all(&datetime::strip_date_time(location_match.as_str()), "")
+                        .trim()
+                        .to_string();
+                    debug!("Found location: '{}'", location_value);
+                    if !location_value.is_empty()
+                        && !location_value.chars().any(|c| c.is_ascii_digit())
+                        && !["the", "this", "next", "on"].contains(&location_value.to_lowercase().as_str())
+                    {
+                        // The span covers the place alone, not the date after it
+                        let start = location_match.start()
+                            + location_match.as_str().find(location_value.as_str()).unwrap_or(0);
+                        let end = (start + location_value.len()).min(location_match.end());
+                        entities.push(Entity {
+                            name: "location".to_string(),
+                            value: location_value,
+                            start,
+                            end,
+                        });
+                    }
                 }
             } else {
-                println!("No location match found in: '{}'", text);
+                debug!("No location match found in: '{}'", text);
+            }
+
+            if let Some((start, end)) = datetime::find_date(text) {
+                entities.push(Entity {
+                    name: "date".to_string(),
+                    value: text[start..end].to_lowercase(),
+                    start,
+                    end,
+                });
             }
         }
 
         entities
     }
-}
\ No newline at end of file
+}
+
+/// The lowercased words in `text`.
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// 1 for the same word, less for a near miss such as "wether", 0 otherwise.
+/// Short words have to match exactly, STT gets those right or wrong
+/// entirely.
+fn word_similarity(word: &str, other: &str) -> f64 {
+    if word == other {
+        return 1.0;
+    }
+    let (length, other_length) = (word.chars().count(), other.chars().count());
+    let longest = length.max(other_length);
+    if length.min(other_length) < MIN_FUZZY_WORD_LEN
+        || (longest - length.min(other_length)) as f64 > longest as f64 * (1.0 - MIN_WORD_SIMILARITY)
+    {
+        return 0.0;
+    }
+    let similarity = 1.0 - edit_distance(word, other) as f64 / longest as f64;
+    if similarity >= MIN_WORD_SIMILARITY {
+        similarity
+    } else {
+        0.0
+    }
+}
+
+/// Edit distance in characters, with swapping two neighbours as one edit:
+/// "cancle" is one away from "cancel".
+fn edit_distance(word: &str, other: &str) -> usize {
+    let word: Vec<char> = word.chars().collect();
+    let other: Vec<char> = other.chars().collect();
+    let mut rows = vec![vec![0; other.len() + 1]; word.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in rows[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=word.len() {
+        for j in 1..=other.len() {
+            let substitution = usize::from(word[i - 1] != other[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && word[i - 1] == other[j - 2] && word[i - 2] == other[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[word.len()][other.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::RustNlu;
+    use crate::nlu::{patterns, training};
+
+    /// Each Rasa example is held out in turn and understood by a RustNlu
+    /// built from the pattern files and every other example.
+    #[test]
+    fn understands_held_out_rasa_examples() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../nlu");
+        let examples = training::read_rasa_examples(&root.join("rasa/data/nlu.yml")).unwrap();
+        let mut pattern_files: Vec<_> = fs::read_dir(root.join("patterns"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        pattern_files.sort();
+
+        let mut with_patterns = RustNlu::new();
+        for path in &pattern_files {
+            for intent in patterns::read_pattern_file(path).unwrap().intents {
+                with_patterns.add_patterns(&intent.intent, &intent.patterns).unwrap();
+            }
+        }
+
+        let mut misses = Vec::new();
+        for (held_out, example) in examples.iter().enumerate() {
+            let mut nlu = with_patterns.clone();
+            let others: Vec<_> = examples
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != held_out)
+                .map(|(_, example)| example.clone())
+                .collect();
+            nlu.add_examples(&others);
+
+            let (intent, _) = nlu.parse(&example.text);
+            if intent.name != example.intent {
+                misses.push(format!("'{}': {} as {} ({:.2})", example.text, example.intent, intent.name, intent.confidence));
+            }
+        }
+
+        let accuracy = 1.0 - misses.len() as f64 / examples.len() as f64;
+        assert!(
+            accuracy >= 0.85,
+            "Accuracy {:.1}% of {} examples is below 85%, missed:\n  {}",
+            accuracy * 100.0,
+            examples.len(),
+            misses.join("\n  ")
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{collections::BTreeMap, fs, path::Path, sync::LazyLock};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// An utterance labelled with the intent it should be understood as.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrainingExample {
+    pub intent: String,
+    pub text: String,
+}
+
+/// The parts of a Rasa NLU training data file we use.
+#[derive(Debug, Deserialize)]
+struct RasaNluData {
+    #[serde(default)]
+    nlu: Vec<RasaNluItem>,
+}
+
+/// Synonyms, regexes and lookup tables have no `intent` and are skipped.
+#[derive(Debug, Deserialize)]
+struct RasaNluItem {
+    intent: Option<String>,
+    examples: Option<String>,
+}
+
+/// A training example with the entities annotated in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledExample {
+    pub intent: String,
+    pub text: String,
+    pub entities: Vec<LabelledEntity>,
+}
+
+/// An annotated entity. The value is the words annotated, and the span is
+/// in byte offsets into the text without annotations.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledEntity {
+    pub name: String,
+    pub value: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// An entity annotation in an example: `[text](...)` or `[text]{...}`.
+static ANNOTATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\](\([^)]*\)|\{[^}]*\})").unwrap());
+
+/// Reads the intent examples in a Rasa NLU file, with entity annotations
+/// taken out: "weather in [Paris](location)" is "weather in Paris".
+pub fn read_rasa_examples(path: &Path) -> Result<Vec<TrainingExample>> {
+    Ok(read_labelled_examples(path)?
+        .into_iter()
+        .map(|example| TrainingExample {
+            intent: example.intent,
+            text: example.text,
+        })
+        .collect())
+}
+
+/// Reads the intent examples in a Rasa NLU file with the entities they
+/// annotate, as `[Paris](location)`, `[Paris](location:paris)` or
+/// `[Paris]{"entity": "location"}`.
+pub fn read_labelled_examples(path: &Path) -> Result<Vec<LabelledExample>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let data: RasaNluData =
+        serde_yaml::from_str(&contents).with_context(|| format!("Invalid Rasa training data in {}", path.display()))?;
+
+    let mut examples = Vec::new();
+    for item in data.nlu {
+        let (Some(intent), Some(lines)) = (item.intent, item.examples) else {
+            continue;
+        };
+        for line in lines.lines() {
+            let Some(line) = line.trim().strip_prefix("- ") else {
+                continue;
+            };
+            let (text, entities) = strip_annotations(line.trim());
+            if !text.is_empty() {
+                examples.push(LabelledExample {
+                    intent: intent.clone(),
+                    text,
+                    entities,
+                });
+            }
+        }
+    }
+    Ok(examples)
+}
+
+/// The example without annotations, and the entities they name.
+fn strip_annotations(line: &str) -> (String, Vec<LabelledEntity>) {
+    let mut text = String::new();
+    let mut entities = Vec::new();
+    let mut copied = 0;
+    for captures in ANNOTATION.captures_iter(line) {
+        let whole = captures.get(0).unwrap();
+        text.push_str(&line[copied..whole.start()]);
+        let start = text.len();
+        text.push_str(&captures[1]);
+        copied = whole.end();
+
+        let label = &captures[2];
+        let name = if label.starts_with('(') {
+            // `(location)`, or `(location:paris)` for a synonym
+            label[1..label.len() - 1].split(':').next().map(str::to_string)
+        } else {
+            serde_json::from_str::<serde_json::Value>(label)
+                .ok()
+                .and_then(|label| label.get("entity")?.as_str().map(str::to_string))
+        };
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            entities.push(LabelledEntity {
+                name,
+                value: captures[1].to_string(),
+                start,
+                end: text.len(),
+            });
+        }
+    }
+    text.push_str(&line[copied..]);
+    (text, entities)
+}
+
+/// Writes examples as a Rasa NLU file, grouped by intent. The file is
+/// replaced as a whole, so readers never see half of it.
+pub fn write_rasa_examples(path: &Path, examples: &[TrainingExample], comment: &str) -> Result<()> {
+    let mut by_intent: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for example in examples {
+        // One example per line, and brackets would read as entity annotations
+        let text = example.text.split_whitespace().collect::<Vec<_>>().join(" ").replace(['[', ']'], "");
+        if !text.is_empty() {
+            by_intent.entry(&example.intent).or_default().push(text);
+        }
+    }
+
+    let mut contents = format!("# {}\nversion: \"3.1\"\n\nnlu:\n", comment);
+    for (intent, texts) in by_intent {
+        contents.push_str(&format!("- intent: {}\n  examples: |\n", intent));
+        for text in texts {
+            contents.push_str(&format!("    - {}\n", text));
+        }
+        contents.push('\n');
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let partial = path.with_extension("yml.tmp");
+    fs::write(&partial, contents).with_context(|| format!("Failed to write {}", partial.display()))?;
+    fs::rename(&partial, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use serde_json::Value;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    audio,
+    events::Event,
+    nlu::{NluEntity, NluSource},
+    skills::SkillRequest,
+    AppState,
+};
+
+/// Result of running one command through NLU, skills and TTS.
+#[derive(Debug, Clone)]
+pub struct CommandOutcome {
+    pub command_id: String,
+    pub transcription: String,
+    pub intent: String,
+    /// What the command was understood as, by which engine.
+    pub nlu_source: NluSource,
+    pub entities: Vec<NluEntity>,
+    pub response: String,
+    /// Base64 WAV of the spoken response, empty when no TTS engine is available.
+    pub audio_response: String,
+    pub error: Option<Value>,
+}
+
+/// Speaks `text` with the configured TTS engine, as base64 WAV. `None` when
+/// there is no engine or synthesis fails.
+pub async fn synthesize(state: &AppState, text: &str) -> Option<String> {
+    let tts = state.tts.as_ref()?;
+    match tts.synthesize(text, &state.config.audio.tts_voice).await {
+        Ok(wav) => Some(audio::encode_base64(&wav)),
+        Err(e) => {
+            warn!("Synthesis with {} failed: {}", tts.name(), e);
+            None
+        }
+    }
+}
+
+/// Runs already transcribed text through the command pipeline and logs it.
+///
+/// Shared by the REST API and satellites streaming audio over MQTT.
+pub async fn process_text(
+    state: &AppState,
+    transcription: &str,
+    user_id: Option<&str>,
+    satellite_id: Option<&str>,
+) -> Result<CommandOutcome> {
+    let started = Instant::now();
+    info!("Transcription: {}", transcription);
+
+    // 1. Intent parsing with the configured NLU engines
+    let outcome = state.nlu.understand(transcription).await?;
+    let nlu = outcome.result;
+    if !outcome.passed_over.is_empty() {
+        let reason = outcome.passed_over.join("; ");
+        info!("NLU fell back to {}: {}", nlu.source.as_str(), reason);
+        state.events.publish(Event::NluFallback {
+            text: transcription.to_string(),
+            engine: nlu.source.as_str().to_string(),
+            reason,
+        });
+    }
+    info!("NLU response: intent={}, confidence={:.2}", nlu.intent, nlu.confidence);
+    info!("Extracted entities: {:?}", nlu.entities);
+
+    // 2. Command execution
+    let intent = nlu.intent.clone();
+    let confidence = nlu.confidence;
+    let request = SkillRequest {
+        intent: intent.clone(),
+        text: transcription.to_string(),
+        entities: nlu.entities.clone(),
+        user_id: user_id.map(str::to_string),
+        satellite_id: satellite_id.map(str::to_string),
+    };
+    let (response, error) = match state.skills.execute(state, &request).await {
+        Ok(response) => (response, None),
+        Err(e) => {
+            info!("Command not executed: {}", e);
+            (e.spoken_response(), Some(e.to_json()))
+        }
+    };
+    info!("Generated response: {}", response);
+
+    // 3. TTS: Convert response to audio
+    let audio_response = synthesize(state, &response).await.unwrap_or_default();
+
+    // 4. Log command
+    let command_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO command_history (id, user_id, satellite_id, command_text, intent, response, confidence, processing_time_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&command_id)
+    .bind(user_id)
+    .bind(satellite_id)
+    .bind(transcription)
+    .bind(&intent)
+    .bind(&response)
+    .bind(confidence as f32)
+    .bind(started.elapsed().as_millis() as i32)
+    .execute(&state.db)
+    .await?;
+
+    state.events.publish(Event::CommandProcessed {
+        command_id: command_id.clone(),
+        user_id: user_id.map(str::to_string),
+        satellite_id: satellite_id.map(str::to_string),
+        text: transcription.to_string(),
+        intent: intent.clone(),
+        confidence,
+        response: response.clone(),
+    });
+
+    Ok(CommandOutcome {
+        command_id,
+        transcription: transcription.to_string(),
+        intent,
+        nlu_source: nlu.source,
+        entities: nlu.entities,
+        response,
+        audio_response,
+        error,
+    })
+}
@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, TimeZone, Utc, Weekday};
+use serde_json::json;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    database::models::Reminder,
+    events::Event,
+    nlu::datetime::{describe_clock_time, describe_date_time},
+    pipeline, AppState,
+};
+
+pub const REMINDER_COLUMNS: &str =
+    "id, user_id, satellite_id, text, remind_at, recurrence, status, delivered_at, created_at, updated_at";
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Intent recorded in `command_history` for a delivered reminder.
+const DELIVERY_INTENT: &str = "reminder";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Weekdays,
+}
+
+impl Recurrence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+            Recurrence::Weekdays => "weekdays",
+        }
+    }
+
+    /// The first time a reminder repeating like this goes off, given when it
+    /// was asked for. Weekday reminders set for a weekend start on Monday.
+    pub fn first(&self, at: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Recurrence::Weekdays if is_weekend(at.weekday()) => self.following(at),
+            _ => at,
+        }
+    }
+
+    /// The next time after `at`, keeping the same time on the clock.
+    pub fn following(&self, at: DateTime<Local>) -> DateTime<Local> {
+        let mut date = at.date_naive();
+        loop {
+            date = match self {
+                Recurrence::Weekly => date + chrono::Duration::days(7),
+                _ => date + chrono::Duration::days(1),
+            };
+            if *self == Recurrence::Weekdays && is_weekend(date.weekday()) {
+                continue;
+            }
+            // Skips a day whose time falls into a daylight saving gap
+            if let Some(next) = Local.from_local_datetime(&date.and_time(at.time())).earliest() {
+                return next;
+            }
+        }
+    }
+
+    fn describe(&self, at: DateTime<Local>) -> String {
+        match self {
+            Recurrence::Daily => format!("every day at {}", describe_clock_time(at)),
+            Recurrence::Weekly => format!("every {} at {}", at.format("%A"), describe_clock_time(at)),
+            Recurrence::Weekdays => format!("every weekday at {}", describe_clock_time(at)),
+        }
+    }
+}
+
+impl std::str::FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Recurrence::Daily),
+            "weekly" => Ok(Recurrence::Weekly),
+            "weekdays" => Ok(Recurrence::Weekdays),
+            _ => Err(format!("Invalid recurrence: {}", s)),
+        }
+    }
+}
+
+fn is_weekend(day: Weekday) -> bool {
+    matches!(day, Weekday::Sat | Weekday::Sun)
+}
+
+pub async fn create(
+    state: &AppState,
+    user_id: &str,
+    satellite_id: Option<&str>,
+    text: &str,
+    remind_at: DateTime<Utc>,
+    recurrence: Option<Recurrence>,
+) -> Result<Reminder, sqlx::Error> {
+    let reminder = sqlx::query_as::<_, Reminder>(&format!(
+        "INSERT INTO reminders (id, user_id, satellite_id, text, remind_at, recurrence) VALUES (?, ?, ?, ?, ?, ?) RETURNING {}",
+        REMINDER_COLUMNS
+    ))
+    .bind(Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(satellite_id)
+    .bind(text)
+    .bind(remind_at)
+    .bind(recurrence.map(|recurrence| recurrence.as_str()))
+    .fetch_one(&state.db)
+    .await?;
+
+    info!("Set reminder {} for {}", reminder.id, remind_at);
+    Ok(reminder)
+}
+
+/// The user's reminders still to go off, soonest first.
+pub async fn pending(state: &AppState, user_id: &str) -> Result<Vec<Reminder>, sqlx::Error> {
+    sqlx::query_as::<_, Reminder>(&format!(
+        "SELECT {} FROM reminders WHERE status = 'pending' AND user_id = ? ORDER BY remind_at",
+        REMINDER_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await
+}
+
+pub async fn find_reminder(state: &AppState, reminder_id: &str) -> Result<Option<Reminder>, sqlx::Error> {
+    sqlx::query_as::<_, Reminder>(&format!("SELECT {} FROM reminders WHERE id = ?", REMINDER_COLUMNS))
+        .bind(reminder_id)
+        .fetch_optional(&state.db)
+        .await
+}
+
+/// Returns false when the reminder had already been delivered or cancelled.
+pub async fn cancel(state: &AppState, reminder_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE reminders SET status = 'cancelled', updated_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'pending'",
+    )
+    .bind(reminder_id)
+    .execute(&state.db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// "take the bins out tomorrow at 7:00 PM", "water the plants every day at 9:00 AM".
+pub fn describe(reminder: &Reminder) -> String {
+    let at = reminder.remind_at.with_timezone(&Local);
+    let when = match reminder.recurrence.as_deref().and_then(|r| r.parse::<Recurrence>().ok()) {
+        Some(recurrence) => recurrence.describe(at),
+        None => describe_date_time(at, Local::now()),
+    };
+    format!("{} {}", reminder.text, when)
+}
+
+/// Delivers reminders as they come due. Reminders that came due while
+/// Barnaby was down are still delivered, once, when it is back.
+pub async fn run(state: AppState) {
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        sweep.tick().await;
+        if let Err(e) = deliver_due(&state).await {
+            warn!("Failed to check reminders: {}", e);
+        }
+    }
+}
+
+async fn deliver_due(state: &AppState) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let due = sqlx::query_as::<_, Reminder>(&format!(
+        "SELECT {} FROM reminders WHERE status = 'pending' AND remind_at <= ? ORDER BY remind_at",
+        REMINDER_COLUMNS
+    ))
+    .bind(now)
+    .fetch_all(&state.db)
+    .await?;
+
+    for reminder in due {
+        let recurrence = reminder.recurrence.as_deref().and_then(|r| r.parse::<Recurrence>().ok());
+
+        // Recurring reminders move on to their next time instead of being done
+        let claimed = match recurrence {
+            Some(recurrence) => {
+                let mut next = recurrence.following(reminder.remind_at.with_timezone(&Local));
+                while next <= now {
+                    next = recurrence.following(next);
+                }
+                sqlx::query(
+                    "UPDATE reminders SET remind_at = ?, delivered_at = ?, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ? AND status = 'pending' AND remind_at = ?",
+                )
+                .bind(next.with_timezone(&Utc))
+                .bind(now)
+                .bind(&reminder.id)
+                .bind(reminder.remind_at)
+                .execute(&state.db)
+                .await?
+            }
+            None => {
+                sqlx::query(
+                    "UPDATE reminders SET status = 'delivered', delivered_at = ?, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ? AND status = 'pending'",
+                )
+                .bind(now)
+                .bind(&reminder.id)
+                .execute(&state.db)
+                .await?
+            }
+        };
+
+        // Only whoever claims the reminder delivers it, in case it was edited meanwhile
+        if claimed.rows_affected() > 0 {
+            deliver(state, &reminder).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The satellite the user last spoke to, if they last used one and it is
+/// still online. Otherwise the reminder goes to their app sessions only.
+async fn last_used_satellite(state: &AppState, user_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let satellite_id = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT satellite_id FROM command_history
+         WHERE user_id = ? AND (intent IS NULL OR intent != ?)
+         ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(DELIVERY_INTENT)
+    .fetch_optional(&state.db)
+    .await?
+    .flatten();
+
+    let Some(satellite_id) = satellite_id else {
+        return Ok(None);
+    };
+    let online = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM satellites WHERE id = ? AND status = 'online'")
+        .bind(&satellite_id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok((online > 0).then_some(satellite_id))
+}
+
+async fn deliver(state: &AppState, reminder: &Reminder) -> Result<(), sqlx::Error> {
+    let text = format!("Reminder: {}.", reminder.text);
+    let satellite_id = last_used_satellite(state, &reminder.user_id).await?;
+    info!(
+        "Reminder {} for user {} delivered to {}: {}",
+        reminder.id,
+        reminder.user_id,
+        satellite_id.as_deref().unwrap_or("app sessions"),
+        text
+    );
+
+    sqlx::query(
+        "INSERT INTO command_history (id, user_id, satellite_id, command_text, intent, response) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&reminder.user_id)
+    .bind(&satellite_id)
+    .bind(&reminder.text)
+    .bind(DELIVERY_INTENT)
+    .bind(&text)
+    .execute(&state.db)
+    .await?;
+
+    state.events.publish(Event::ReminderDue {
+        reminder_id: reminder.id.clone(),
+        user_id: reminder.user_id.clone(),
+        satellite_id: satellite_id.clone(),
+        text: text.clone(),
+    });
+
+    let (Some(mqtt), Some(satellite_id)) = (&state.mqtt, satellite_id) else {
+        return Ok(());
+    };
+    let payload = json!({
+        "reminder_id": reminder.id,
+        "text": text,
+        "audio": pipeline::synthesize(state, &text).await,
+    });
+    if let Err(e) = mqtt
+        .publish_to_satellite(&satellite_id, "reminders/due", &payload.to_string())
+        .await
+    {
+        warn!("Failed to tell satellite {} about reminder {}: {}", satellite_id, reminder.id, e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        // 2 March 2026 is a Monday
+        Local.with_ymd_and_hms(2026, 3, day, hour, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn weekday_reminders_set_for_a_weekend_start_on_monday() {
+        assert_eq!(Recurrence::Weekdays.first(at(7, 8)), at(9, 8));
+        assert_eq!(Recurrence::Weekdays.first(at(8, 8)), at(9, 8));
+        assert_eq!(Recurrence::Weekdays.first(at(4, 8)), at(4, 8));
+        assert_eq!(Recurrence::Daily.first(at(7, 8)), at(7, 8));
+        assert_eq!(Recurrence::Weekly.first(at(8, 8)), at(8, 8));
+    }
+
+    #[test]
+    fn following_keeps_the_time_on_the_clock() {
+        assert_eq!(Recurrence::Daily.following(at(6, 7)), at(7, 7));
+        assert_eq!(Recurrence::Daily.following(at(7, 7)), at(8, 7));
+        assert_eq!(Recurrence::Weekly.following(at(4, 19)), at(11, 19));
+        assert_eq!(Recurrence::Weekdays.following(at(2, 7)), at(3, 7));
+        // Friday's next weekday is Monday
+        assert_eq!(Recurrence::Weekdays.following(at(6, 7)), at(9, 7));
+    }
+
+    #[test]
+    fn describes_recurrences() {
+        assert_eq!(Recurrence::Daily.describe(at(2, 9)), "every day at 9:30 AM");
+        assert_eq!(Recurrence::Weekly.describe(at(4, 19)), "every Wednesday at 7:30 PM");
+        assert_eq!(Recurrence::Weekdays.describe(at(2, 7)), "every weekday at 7:30 AM");
+        assert_eq!("weekdays".parse::<Recurrence>(), Ok(Recurrence::Weekdays));
+        assert!("monthly".parse::<Recurrence>().is_err());
+    }
+}
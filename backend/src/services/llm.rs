@@ -1,8 +1,21 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
+use regex::Regex;
 use tokio::sync::Mutex;
-use tracing::{info, warn};
+use tracing::info;
+
+/// Places asked about, as in "in Paris", "for London" or "at home".
+static LOCATIONS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"in ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
+        r"for ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
+        r"at ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect()
+});
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LlmIntent {
@@ -98,20 +111,9 @@ impl LlmService {
     }
     
     fn extract_location(&self, text: &str) -> Option<String> {
-        // Enhanced location extraction patterns
-        let patterns = [
-            r"in ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
-            r"for ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
-            r"at ([a-zA-Z\s,]+?)(?:\?|$|\s+(?:today|tomorrow|now))",
-        ];
-        
-        for pattern in &patterns {
-            if let Ok(re) = regex::Regex::new(pattern) {
-                if let Some(captures) = re.captures(text) {
-                    if let Some(location) = captures.get(1) {
-                        return Some(location.as_str().trim().to_string());
-                    }
-                }
+        for pattern in LOCATIONS.iter() {
+            if let Some(location) = pattern.captures(text).and_then(|captures| captures.get(1)) {
+                return Some(location.as_str().trim().to_string());
             }
         }
         None
@@ -128,24 +130,6 @@ impl LlmService {
         None
     }
 
-    fn extract_intent_fallback(&self, response: &str) -> String {
-        let response_lower = response.to_lowercase();
-        
-        if response_lower.contains("weather") {
-            "get_weather".to_string()
-        } else if response_lower.contains("time") {
-            "get_time".to_string()
-        } else if response_lower.contains("light") {
-            "control_lights".to_string()
-        } else if response_lower.contains("hello") || response_lower.contains("greet") {
-            "greet".to_string()
-        } else if response_lower.contains("goodbye") || response_lower.contains("bye") {
-            "goodbye".to_string()
-        } else {
-            "unknown".to_string()
-        }
-    }
-
     pub async fn is_available(&self) -> bool {
         let loaded = self.model_loaded.lock().await;
         *loaded
@@ -1,10 +1,30 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use tracing::{debug, warn};
+
+use crate::{config::settings::WeatherConfig, location::Location};
+
+/// How far ahead Open-Meteo is asked to forecast.
+pub const FORECAST_DAYS: i64 = 7;
+
+const CURRENT_FIELDS: &str = "temperature_2m,weather_code,wind_speed_10m";
+const HOURLY_FIELDS: &str = "temperature_2m,precipitation_probability,weather_code";
+const DAILY_FIELDS: &str = "weather_code,temperature_2m_max,temperature_2m_min,precipitation_probability_max,wind_speed_10m_max,sunrise,sunset";
 
 #[derive(Debug, Deserialize)]
 pub struct WeatherResponse {
+    pub utc_offset_seconds: i32,
     pub current: CurrentWeather,
+    pub hourly: HourlyResponse,
+    pub daily: DailyResponse,
 }
 
 #[derive(Debug, Deserialize)]
@@ -14,6 +34,27 @@ pub struct CurrentWeather {
     pub wind_speed_10m: f64,
 }
 
+/// Open-Meteo sends each hourly and daily field as its own array.
+#[derive(Debug, Deserialize)]
+pub struct HourlyResponse {
+    pub time: Vec<String>,
+    pub temperature_2m: Vec<Option<f64>>,
+    pub precipitation_probability: Vec<Option<f64>>,
+    pub weather_code: Vec<Option<u32>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DailyResponse {
+    pub time: Vec<String>,
+    pub weather_code: Vec<Option<u32>>,
+    pub temperature_2m_max: Vec<Option<f64>>,
+    pub temperature_2m_min: Vec<Option<f64>>,
+    pub precipitation_probability_max: Vec<Option<f64>>,
+    pub wind_speed_10m_max: Vec<Option<f64>>,
+    pub sunrise: Vec<Option<String>>,
+    pub sunset: Vec<Option<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GeocodingResponse {
     pub results: Option<Vec<GeocodingResult>>,
@@ -35,63 +76,240 @@ pub struct IpLocationResponse {
     pub country: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WeatherInfo {
     pub temperature: f64,
     pub description: String,
     pub wind_speed: f64,
 }
 
+/// Times are local to the forecast location.
+#[derive(Debug, Clone, Serialize)]
+pub struct HourlyForecast {
+    pub time: NaiveDateTime,
+    pub temperature: f64,
+    pub precipitation_probability: Option<f64>,
+    pub weather_code: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyForecast {
+    pub date: NaiveDate,
+    pub weather_code: u32,
+    pub description: String,
+    pub temperature_max: f64,
+    pub temperature_min: f64,
+    pub precipitation_probability: Option<f64>,
+    pub wind_speed_max: Option<f64>,
+    pub sunrise: Option<NaiveDateTime>,
+    pub sunset: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Forecast {
+    /// Offset of the location's timezone, to tell what time it is there.
+    pub utc_offset_seconds: i32,
+    pub fetched_at: DateTime<Utc>,
+    /// Served from the cache after it expired, because the provider
+    /// couldn't be reached.
+    pub stale: bool,
+    pub current: WeatherInfo,
+    pub hourly: Vec<HourlyForecast>,
+    pub daily: Vec<DailyForecast>,
+}
+
+impl Forecast {
+    /// The time it is now at the forecast location.
+    pub fn local_now(&self) -> NaiveDateTime {
+        Utc::now().naive_utc() + chrono::Duration::seconds(self.utc_offset_seconds.into())
+    }
+
+    pub fn day(&self, date: NaiveDate) -> Option<&DailyForecast> {
+        self.daily.iter().find(|day| day.date == date)
+    }
+}
+
+/// Latitude and longitude.
+type Coordinates = (f64, f64);
+
+/// A cached answer and when it was fetched.
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+/// Open-Meteo client shared through `AppState`. Forecasts and geocoding
+/// results are cached, and an expired forecast is still used for a while
+/// when the provider can't be reached.
 pub struct WeatherService {
     client: Client,
+    config: WeatherConfig,
+    forecasts: Mutex<HashMap<String, Cached<Forecast>>>,
+    /// Coordinates by lowercased place name, `None` for names that weren't
+    /// found.
+    places: Mutex<HashMap<String, Cached<Option<Coordinates>>>>,
 }
 
 impl WeatherService {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
+    pub fn new(config: &WeatherConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            config: WeatherConfig {
+                forecast_url: config.forecast_url.trim_end_matches('/').to_string(),
+                geocoding_url: config.geocoding_url.trim_end_matches('/').to_string(),
+                ip_lookup_url: config.ip_lookup_url.trim_end_matches('/').to_string(),
+                ..config.clone()
+            },
+            forecasts: Mutex::new(HashMap::new()),
+            places: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Times in the forecast are in the location's timezone, or the one
+    /// Open-Meteo finds for it when it has none.
+    pub async fn get_forecast(&self, location: &Location) -> Result<Forecast> {
+        let timezone = location.timezone.as_deref().unwrap_or("auto");
+        // Close enough to be the same place, about 100 m apart
+        let key = format!("{:.3},{:.3},{}", location.latitude, location.longitude, timezone);
+        let ttl = Duration::from_secs(self.config.forecast_ttl_secs);
+
+        let cached = self.cached_forecast(&key, |age| age < ttl);
+        if let Some(forecast) = cached {
+            debug!("Using cached forecast for {}", key);
+            return Ok(forecast);
+        }
+
+        match self.fetch_forecast(location, timezone).await {
+            Ok(forecast) => {
+                let mut forecasts = self.forecasts.lock().unwrap();
+                let stale = Duration::from_secs(self.config.stale_secs);
+                forecasts.retain(|_, cached| cached.fetched_at.elapsed() < stale);
+                forecasts.insert(
+                    key,
+                    Cached {
+                        value: forecast.clone(),
+                        fetched_at: Instant::now(),
+                    },
+                );
+                Ok(forecast)
+            }
+            Err(e) => {
+                let stale = Duration::from_secs(self.config.stale_secs);
+                match self.cached_forecast(&key, |age| age < stale) {
+                    Some(forecast) => {
+                        warn!("Weather provider unavailable ({}), using the forecast from {}", e, forecast.fetched_at);
+                        Ok(Forecast { stale: true, ..forecast })
+                    }
+                    None => Err(e),
+                }
+            }
         }
     }
 
-    pub async fn get_weather(&self, latitude: f64, longitude: f64) -> Result<WeatherInfo> {
+    fn cached_forecast(&self, key: &str, usable: impl Fn(Duration) -> bool) -> Option<Forecast> {
+        let forecasts = self.forecasts.lock().unwrap();
+        forecasts
+            .get(key)
+            .filter(|cached| usable(cached.fetched_at.elapsed()))
+            .map(|cached| cached.value.clone())
+    }
+
+    async fn fetch_forecast(&self, location: &Location, timezone: &str) -> Result<Forecast> {
         let url = format!(
-            "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&current=temperature_2m,weather_code,wind_speed_10m",
-            latitude, longitude
+            "{}/v1/forecast?latitude={}&longitude={}&current={}&hourly={}&daily={}&timezone={}&forecast_days={}",
+            self.config.forecast_url,
+            location.latitude,
+            location.longitude,
+            CURRENT_FIELDS,
+            HOURLY_FIELDS,
+            DAILY_FIELDS,
+            urlencoding::encode(timezone),
+            FORECAST_DAYS
         );
 
         let response: WeatherResponse = self.client
             .get(&url)
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
-        Ok(WeatherInfo {
-            temperature: response.current.temperature_2m,
-            description: weather_code_to_description(response.current.weather_code),
-            wind_speed: response.current.wind_speed_10m,
+        Ok(Forecast {
+            utc_offset_seconds: response.utc_offset_seconds,
+            fetched_at: Utc::now(),
+            stale: false,
+            current: WeatherInfo {
+                temperature: response.current.temperature_2m,
+                description: weather_code_to_description(response.current.weather_code),
+                wind_speed: response.current.wind_speed_10m,
+            },
+            hourly: hourly_forecasts(&response.hourly)?,
+            daily: daily_forecasts(&response.daily)?,
         })
     }
 
-    // Default location (can be made configurable)
-    pub async fn get_current_weather(&self) -> Result<WeatherInfo> {
-        match self.get_ip_location().await {
-            Ok((lat, lon)) => self.get_weather(lat, lon).await,
-            Err(_) => {
-                // Fallback to London coordinates
-                self.get_weather(51.5074, -0.1278).await
-            }
-        }
+    pub async fn get_forecast_for_location(&self, location: &str) -> Result<Forecast> {
+        let (latitude, longitude) = self.geocode_location(location).await?;
+        self.get_forecast(&Location {
+            name: Some(location.to_string()),
+            latitude,
+            longitude,
+            timezone: None,
+        })
+        .await
     }
 
-    pub async fn get_weather_for_location(&self, location: &str) -> Result<WeatherInfo> {
-        let (lat, lon) = self.geocode_location(location).await?;
-        self.get_weather(lat, lon).await
+    /// Places don't move, so a result up to `stale_secs` past its ttl is
+    /// still used when the provider can't be reached.
+    async fn geocode_location(&self, location: &str) -> Result<Coordinates> {
+        let key = location.trim().to_lowercase();
+        let ttl = Duration::from_secs(self.config.geocoding_ttl_secs);
+        let stale = ttl + Duration::from_secs(self.config.stale_secs);
+        let cached = {
+            let places = self.places.lock().unwrap();
+            places
+                .get(&key)
+                .filter(|cached| cached.fetched_at.elapsed() < stale)
+                .map(|cached| (cached.value, cached.fetched_at.elapsed() < ttl))
+        };
+
+        let coordinates = match cached {
+            Some((coordinates, true)) => coordinates,
+            _ => match self.fetch_coordinates(location).await {
+                Ok(coordinates) => {
+                    let mut places = self.places.lock().unwrap();
+                    places.retain(|_, cached| cached.fetched_at.elapsed() < stale);
+                    places.insert(
+                        key,
+                        Cached {
+                            value: coordinates,
+                            fetched_at: Instant::now(),
+                        },
+                    );
+                    coordinates
+                }
+                Err(e) => match cached {
+                    Some((coordinates, false)) => {
+                        warn!("Geocoding unavailable ({}), using the cached result for {}", e, location);
+                        coordinates
+                    }
+                    _ => return Err(e),
+                },
+            },
+        };
+
+        coordinates.ok_or_else(|| anyhow::anyhow!("Location not found: {}", location))
     }
 
-    async fn geocode_location(&self, location: &str) -> Result<(f64, f64)> {
+    async fn fetch_coordinates(&self, location: &str) -> Result<Option<Coordinates>> {
         let url = format!(
-            "https://geocoding-api.open-meteo.com/v1/search?name={}&count=1",
+            "{}/v1/search?name={}&count=1",
+            self.config.geocoding_url,
             urlencoding::encode(location)
         );
 
@@ -99,31 +317,92 @@ impl WeatherService {
             .get(&url)
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
-        if let Some(results) = response.results {
-            if let Some(result) = results.first() {
-                return Ok((result.latitude, result.longitude));
-            }
-        }
-
-        Err(anyhow::anyhow!("Location not found: {}", location))
+        Ok(response
+            .results
+            .and_then(|results| results.first().map(|result| (result.latitude, result.longitude))))
     }
 
-    async fn get_ip_location(&self) -> Result<(f64, f64)> {
+    /// Guesses where we are from our IP address. Only to be used when
+    /// `location.ip_lookup` is enabled, since it tells ip-api.com about us.
+    pub async fn locate_by_ip(&self) -> Result<Location> {
         let response: IpLocationResponse = self.client
-            .get("http://ip-api.com/json/?fields=lat,lon,city,country")
+            .get(format!("{}/json/?fields=lat,lon,city,country", self.config.ip_lookup_url))
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
-        Ok((response.lat, response.lon))
+        Ok(Location {
+            name: response.city,
+            latitude: response.lat,
+            longitude: response.lon,
+            timezone: None,
+        })
+    }
+}
+
+fn parse_local_time(time: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").with_context(|| format!("Invalid forecast time: {}", time))
+}
+
+/// Hours with gaps in the data are left out.
+fn hourly_forecasts(hourly: &HourlyResponse) -> Result<Vec<HourlyForecast>> {
+    let mut hours = Vec::new();
+    for (i, time) in hourly.time.iter().enumerate() {
+        let (Some(Some(temperature)), Some(Some(weather_code))) = (hourly.temperature_2m.get(i), hourly.weather_code.get(i))
+        else {
+            continue;
+        };
+        hours.push(HourlyForecast {
+            time: parse_local_time(time)?,
+            temperature: *temperature,
+            precipitation_probability: hourly.precipitation_probability.get(i).copied().flatten(),
+            weather_code: *weather_code,
+        });
+    }
+    Ok(hours)
+}
+
+fn daily_forecasts(daily: &DailyResponse) -> Result<Vec<DailyForecast>> {
+    let mut days = Vec::new();
+    for (i, date) in daily.time.iter().enumerate() {
+        let (Some(Some(weather_code)), Some(Some(temperature_max)), Some(Some(temperature_min))) = (
+            daily.weather_code.get(i),
+            daily.temperature_2m_max.get(i),
+            daily.temperature_2m_min.get(i),
+        ) else {
+            continue;
+        };
+        let sun = |times: &[Option<String>]| times.get(i).cloned().flatten().and_then(|time| parse_local_time(&time).ok());
+        days.push(DailyForecast {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").with_context(|| format!("Invalid forecast date: {}", date))?,
+            weather_code: *weather_code,
+            description: weather_code_to_description(*weather_code),
+            temperature_max: *temperature_max,
+            temperature_min: *temperature_min,
+            precipitation_probability: daily.precipitation_probability_max.get(i).copied().flatten(),
+            wind_speed_max: daily.wind_speed_10m_max.get(i).copied().flatten(),
+            sunrise: sun(&daily.sunrise),
+            sunset: sun(&daily.sunset),
+        });
     }
+    Ok(days)
 }
 
-fn weather_code_to_description(code: u32) -> String {
+pub fn is_snow(code: u32) -> bool {
+    matches!(code, 71..=77 | 85..=86)
+}
+
+pub fn is_rain(code: u32) -> bool {
+    matches!(code, 51..=67 | 80..=82 | 95..=99)
+}
+
+pub fn weather_code_to_description(code: u32) -> String {
     match code {
         0 => "Clear sky".to_string(),
         1..=3 => "Partly cloudy".to_string(),
@@ -136,4 +415,102 @@ fn weather_code_to_description(code: u32) -> String {
         95..=99 => "Thunderstorm".to_string(),
         _ => "Unknown".to_string(),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::config::Settings;
+
+    /// Whether the stub provider is down.
+    type Down = Arc<AtomicBool>;
+
+    async fn search(State(down): State<Down>) -> Result<Json<Value>, StatusCode> {
+        if down.load(Ordering::SeqCst) {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        Ok(Json(json!({
+            "results": [{ "latitude": 59.91, "longitude": 10.75, "name": "Oslo", "country": "Norway" }]
+        })))
+    }
+
+    async fn forecast(State(down): State<Down>) -> Result<Json<Value>, StatusCode> {
+        if down.load(Ordering::SeqCst) {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        Ok(Json(json!({
+            "utc_offset_seconds": 3600,
+            "current": { "temperature_2m": 4.5, "weather_code": 61, "wind_speed_10m": 12.0 },
+            "hourly": {
+                "time": ["2026-10-18T10:00"],
+                "temperature_2m": [4.5],
+                "precipitation_probability": [80.0],
+                "weather_code": [61]
+            },
+            "daily": {
+                "time": ["2026-10-18"],
+                "weather_code": [61],
+                "temperature_2m_max": [7.0],
+                "temperature_2m_min": [2.0],
+                "precipitation_probability_max": [90.0],
+                "wind_speed_10m_max": [20.0],
+                "sunrise": ["2026-10-18T08:01"],
+                "sunset": ["2026-10-18T18:02"]
+            }
+        })))
+    }
+
+    /// A weather service whose cache is always expired, talking to a stub
+    /// Open-Meteo that can be taken down.
+    async fn start_weather() -> (WeatherService, Down) {
+        let down = Down::default();
+        let app = Router::new()
+            .route("/v1/search", get(search))
+            .route("/v1/forecast", get(forecast))
+            .with_state(down.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = Settings::new().unwrap().weather;
+        config.forecast_url = url.clone();
+        config.geocoding_url = url;
+        config.forecast_ttl_secs = 0;
+        config.geocoding_ttl_secs = 0;
+        (WeatherService::new(&config).unwrap(), down)
+    }
+
+    #[tokio::test]
+    async fn expired_places_are_used_while_geocoding_is_down() {
+        let (weather, down) = start_weather().await;
+        assert_eq!(weather.geocode_location("Oslo").await.unwrap(), (59.91, 10.75));
+        // Caching another place must not evict the expired one
+        weather.geocode_location("Bergen").await.unwrap();
+
+        down.store(true, Ordering::SeqCst);
+        assert_eq!(weather.geocode_location("oslo ").await.unwrap(), (59.91, 10.75));
+        assert!(weather.geocode_location("Tromsø").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stale_forecasts_are_used_while_the_provider_is_down() {
+        let (weather, down) = start_weather().await;
+        let fresh = weather.get_forecast_for_location("Oslo").await.unwrap();
+        assert!(!fresh.stale);
+        assert_eq!(fresh.current.temperature, 4.5);
+        assert_eq!(fresh.daily.len(), 1);
+
+        down.store(true, Ordering::SeqCst);
+        let stale = weather.get_forecast_for_location("Oslo").await.unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.fetched_at, fresh.fetched_at);
+        assert_eq!(stale.daily[0].temperature_max, 7.0);
+    }
+}
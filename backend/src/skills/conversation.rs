@@ -0,0 +1,36 @@
+use async_trait::async_trait;
+
+use super::{IntentSpec, Skill, SkillError, SkillRequest};
+use crate::AppState;
+
+pub struct ConversationSkill;
+
+#[async_trait]
+impl Skill for ConversationSkill {
+    fn name(&self) -> &'static str {
+        "conversation"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[
+            IntentSpec {
+                intent: "greet",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+            IntentSpec {
+                intent: "goodbye",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+        ]
+    }
+
+    async fn execute(&self, _state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        match request.intent.as_str() {
+            "greet" => Ok("Hello! How can I help you today?".to_string()),
+            "goodbye" => Ok("Goodbye! Have a great day!".to_string()),
+            other => Err(SkillError::UnknownIntent(other.to_string())),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::{IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{
+    database::models::{Device, Room},
+    devices::{self, Capability, DeviceError, StateChange},
+    AppState,
+};
+
+/// Colours the NLU can extract, by name.
+const COLORS: &[(&str, &str)] = &[
+    ("warm white", "#ffd8a8"),
+    ("white", "#ffffff"),
+    ("red", "#ff0000"),
+    ("green", "#00ff00"),
+    ("blue", "#0000ff"),
+    ("yellow", "#ffff00"),
+    ("orange", "#ff8000"),
+    ("purple", "#8000ff"),
+    ("pink", "#ff69b4"),
+];
+
+const DIM_BRIGHTNESS: u8 = 30;
+
+pub struct LightsSkill;
+
+#[async_trait]
+impl Skill for LightsSkill {
+    fn name(&self) -> &'static str {
+        "lights"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[IntentSpec {
+            intent: "control_lights",
+            required_entities: &[],
+            optional_entities: &["room", "state", "brightness", "color"],
+        }]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        let change = requested_change(request)?;
+        let room = resolve_room(state, request).await?;
+
+        let lights: Vec<Device> = devices::devices_in_room(state, &room.id)
+            .await
+            .map_err(|_| SkillError::failed("Sorry, I couldn't look up the lights right now."))?
+            .into_iter()
+            .filter(|device| device.device_type == "light")
+            .collect();
+        if lights.is_empty() {
+            return Err(SkillError::failed(format!("There are no lights in the {}.", room.name)));
+        }
+
+        let mut changed = 0;
+        for light in &lights {
+            let change = supported_change(&change, &light.capabilities);
+            if change.is_empty() {
+                continue;
+            }
+
+            match devices::set_state(state, light, &change).await {
+                Ok(()) => changed += 1,
+                Err(DeviceError::Unavailable(_)) => {
+                    return Err(SkillError::failed("Sorry, I can't reach the lights right now."));
+                }
+                Err(e) => warn!("Failed to control light {}: {}", light.id, e),
+            }
+        }
+
+        if changed == 0 {
+            return Err(SkillError::failed(format!(
+                "Sorry, the lights in the {} can't do that.",
+                room.name
+            )));
+        }
+
+        Ok(describe(&change, &room.name))
+    }
+}
+
+/// Works out what to do from the entities, falling back to "dim" and
+/// "brighten" in the text.
+fn requested_change(request: &SkillRequest) -> Result<StateChange, SkillError> {
+    let text = request.text.to_lowercase();
+    let mut change = StateChange {
+        on: request.entity("state").map(|state| state == "on"),
+        brightness: request.entity("brightness").and_then(|value| value.parse::<u8>().ok()),
+        color: request.entity("color").and_then(color_hex),
+        ..Default::default()
+    };
+
+    if change.brightness.is_none() {
+        if text.contains("dim") {
+            change.brightness = Some(DIM_BRIGHTNESS);
+        } else if text.contains("brighten") {
+            change.brightness = Some(100);
+        }
+    }
+
+    // Asking for a brightness or colour implies switching the light on
+    if change.on.is_none() && (change.brightness.is_some_and(|b| b > 0) || change.color.is_some()) {
+        change.on = Some(true);
+    }
+
+    if change.is_empty() {
+        return Err(SkillError::failed("Do you want the lights on or off?"));
+    }
+    Ok(change)
+}
+
+/// The room named in the command, or else the room of the satellite that
+/// heard it.
+async fn resolve_room(state: &AppState, request: &SkillRequest) -> Result<Room, SkillError> {
+    let lookup_failed = |_| SkillError::failed("Sorry, I couldn't look up the lights right now.");
+
+    if let Some(name) = request.entity("room") {
+        return devices::find_room_by_name(state, name)
+            .await
+            .map_err(lookup_failed)?
+            .ok_or_else(|| SkillError::failed(format!("I don't know a room called {}.", name)));
+    }
+
+    // The NLU only knows a few common room names, so look for the configured ones too
+    let text = request.text.to_lowercase();
+    let mut rooms = devices::list_rooms(state).await.map_err(lookup_failed)?;
+    rooms.sort_by_key(|room| std::cmp::Reverse(room.name.len()));
+    if let Some(room) = rooms
+        .into_iter()
+        .find(|room| text.contains(&room.name.to_lowercase()))
+    {
+        return Ok(room);
+    }
+
+    if let Some(satellite_id) = &request.satellite_id {
+        let satellite_room: Option<Option<String>> =
+            sqlx::query_scalar("SELECT json_extract(config, '$.room') FROM satellites WHERE id = ?")
+                .bind(satellite_id)
+                .fetch_optional(&state.db)
+                .await
+                .map_err(lookup_failed)?;
+
+        if let Some(name) = satellite_room.flatten() {
+            if let Some(room) = devices::find_room_by_name(state, &name).await.map_err(lookup_failed)? {
+                return Ok(room);
+            }
+        }
+    }
+
+    Err(SkillError::MissingEntities {
+        intent: request.intent.clone(),
+        missing: vec!["room".to_string()],
+    })
+}
+
+/// Drops the parts of `change` a device can't do.
+fn supported_change(change: &StateChange, capabilities: &[String]) -> StateChange {
+    let supports = |capability: Capability| capabilities.iter().any(|c| c == capability.as_str());
+
+    StateChange {
+        on: change.on.filter(|_| supports(Capability::OnOff)),
+        brightness: change.brightness.filter(|_| supports(Capability::Brightness)),
+        color: change.color.clone().filter(|_| supports(Capability::Color)),
+        temperature: None,
+    }
+}
+
+fn color_hex(name: &str) -> Option<String> {
+    if devices::is_hex_color(name) {
+        return Some(name.to_lowercase());
+    }
+
+    COLORS
+        .iter()
+        .find(|(color, _)| name.eq_ignore_ascii_case(color))
+        .map(|(_, hex)| hex.to_string())
+}
+
+fn describe(change: &StateChange, room: &str) -> String {
+    if change.on == Some(false) {
+        return format!("Turning off the {} lights.", room);
+    }
+
+    let color = change.color.as_deref().and_then(|hex| {
+        COLORS
+            .iter()
+            .find(|(_, color_hex)| *color_hex == hex)
+            .map(|(name, _)| *name)
+    });
+    match (change.brightness, color) {
+        (Some(brightness), Some(color)) => {
+            format!("Setting the {} lights to {} at {}%.", room, color, brightness)
+        }
+        (Some(brightness), None) => format!("Setting the {} lights to {}%.", room, brightness),
+        (None, Some(color)) => format!("Turning the {} lights {}.", room, color),
+        (None, None) => format!("Turning on the {} lights.", room),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{self, request};
+
+    fn change(text: &str, entities: &[(&str, &str)]) -> Result<StateChange, SkillError> {
+        requested_change(&request("control_lights", text, entities))
+    }
+
+    #[test]
+    fn changes_come_from_entities_and_words() {
+        let on = change("turn on the lights", &[("state", "on")]).unwrap();
+        assert_eq!((on.on, on.brightness, on.color), (Some(true), None, None));
+
+        let off = change("lights off", &[("state", "off")]).unwrap();
+        assert_eq!(off.on, Some(false));
+
+        let dim = change("dim the lights", &[]).unwrap();
+        assert_eq!((dim.on, dim.brightness), (Some(true), Some(DIM_BRIGHTNESS)));
+
+        let bright = change("brighten the kitchen", &[]).unwrap();
+        assert_eq!((bright.on, bright.brightness), (Some(true), Some(100)));
+
+        // A brightness of 0 doesn't switch the light on
+        let zero = change("set the lights to 0 percent", &[("brightness", "0")]).unwrap();
+        assert_eq!((zero.on, zero.brightness), (None, Some(0)));
+
+        let colour = change("make the lights warm white", &[("color", "Warm White")]).unwrap();
+        assert_eq!((colour.on, colour.color.as_deref()), (Some(true), Some("#ffd8a8")));
+        let hex = change("lights to #00FF00", &[("color", "#00FF00")]).unwrap();
+        assert_eq!(hex.color.as_deref(), Some("#00ff00"));
+
+        match change("do the lights", &[("color", "plaid")]) {
+            Err(e) => assert_eq!(e.spoken_response(), "Do you want the lights on or off?"),
+            Ok(change) => panic!("unexpected {:?}", change),
+        }
+    }
+
+    #[test]
+    fn devices_only_get_what_they_can_do() {
+        let change = StateChange {
+            on: Some(true),
+            brightness: Some(50),
+            color: Some("#ff0000".to_string()),
+            temperature: Some(21.0),
+        };
+        let dimmer = supported_change(&change, &["on_off".to_string(), "brightness".to_string()]);
+        assert_eq!((dimmer.on, dimmer.brightness, dimmer.color, dimmer.temperature), (Some(true), Some(50), None, None));
+
+        let none = supported_change(&change, &[]);
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rooms_come_from_the_command_or_the_satellite() {
+        let state = test_support::state().await;
+        test_support::room(&state, "Loft").await;
+        test_support::room(&state, "Loft office").await;
+        test_support::room(&state, "Kitchen").await;
+        sqlx::query("INSERT INTO satellites (id, name, config) VALUES ('sat', 'Sat', '{\"room\": \"kitchen\"}')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+
+        let room = |text: &str, entities: &[(&str, &str)], satellite: Option<&str>| {
+            let mut request = request("control_lights", text, entities);
+            request.satellite_id = satellite.map(str::to_string);
+            let state = state.clone();
+            async move { resolve_room(&state, &request).await }
+        };
+
+        assert_eq!(room("lights on", &[("room", "kitchen")], None).await.unwrap().name, "Kitchen");
+        match room("lights on in the attic", &[("room", "attic")], None).await {
+            Err(e) => assert_eq!(e.spoken_response(), "I don't know a room called attic."),
+            Ok(room) => panic!("unexpected {:?}", room),
+        }
+        // The longest configured name in the text wins
+        assert_eq!(room("turn on the loft office lights", &[], None).await.unwrap().name, "Loft office");
+        assert_eq!(room("turn on the lights", &[], Some("sat")).await.unwrap().name, "Kitchen");
+        assert!(matches!(
+            room("turn on the lights", &[], None).await,
+            Err(SkillError::MissingEntities { missing, .. }) if missing == ["room"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn lights_in_the_room_are_changed() {
+        let mut state = test_support::state().await;
+        state.mqtt = Some(test_support::offline_mqtt().await);
+        let kitchen = test_support::room(&state, "Kitchen").await;
+        test_support::light(&state, "spots", Some(&kitchen), &["on_off"]).await;
+        test_support::light(&state, "strip", Some(&kitchen), &["on_off", "brightness", "color"]).await;
+
+        let red = request("control_lights", "make the kitchen lights red", &[("color", "red")]);
+        assert_eq!(LightsSkill.execute(&state, &red).await.unwrap(), "Turning the Kitchen lights red.");
+        for (id, expected) in [("spots", json!({ "on": true })), ("strip", json!({ "on": true, "color": "#ff0000" }))] {
+            let device = devices::find_device(&state, id).await.unwrap().unwrap();
+            assert_eq!(device.state.map(|state| state.0), Some(expected));
+        }
+
+        test_support::room(&state, "Hall").await;
+        let hall = request("control_lights", "hall lights on", &[("room", "hall"), ("state", "on")]);
+        match LightsSkill.execute(&state, &hall).await {
+            Err(e) => assert_eq!(e.spoken_response(), "There are no lights in the Hall."),
+            Ok(reply) => panic!("unexpected {}", reply),
+        }
+    }
+}
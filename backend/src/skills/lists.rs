@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+
+use super::{capitalize, IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{
+    database::models::List,
+    lists::{self, describe_item, normalize_list_name, ParsedItem, DEFAULT_LIST},
+    AppState,
+};
+
+pub struct ListsSkill;
+
+#[async_trait]
+impl Skill for ListsSkill {
+    fn name(&self) -> &'static str {
+        "lists"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[
+            IntentSpec {
+                intent: "add_to_list",
+                required_entities: &["item"],
+                optional_entities: &["list"],
+            },
+            IntentSpec {
+                intent: "remove_from_list",
+                required_entities: &["item"],
+                optional_entities: &["list"],
+            },
+            IntentSpec {
+                intent: "read_list",
+                required_entities: &[],
+                optional_entities: &["list"],
+            },
+            IntentSpec {
+                intent: "clear_list",
+                required_entities: &[],
+                optional_entities: &["list"],
+            },
+        ]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        let name = normalize_list_name(request.entity("list").unwrap_or(DEFAULT_LIST));
+        let user_id = request.user_id.as_deref();
+        let list = lists::find_by_name(state, &name, user_id)
+            .await
+            .map_err(storage_failed)?;
+
+        match (request.intent.as_str(), list) {
+            // Adding to a list that doesn't exist yet starts one for the household
+            ("add_to_list", None) => {
+                let list = lists::create_list(state, &name, None).await.map_err(storage_failed)?;
+                add(state, request, &list).await
+            }
+            ("add_to_list", Some(list)) => add(state, request, &list).await,
+            (_, None) => Err(SkillError::failed(format!("There's no {} list.", name))),
+            ("remove_from_list", Some(list)) => remove(state, request, &list).await,
+            ("read_list", Some(list)) => read(state, &list).await,
+            ("clear_list", Some(list)) => clear(state, &list).await,
+            (other, _) => Err(SkillError::UnknownIntent(other.to_string())),
+        }
+    }
+}
+
+fn storage_failed(_: sqlx::Error) -> SkillError {
+    SkillError::failed("Sorry, I couldn't get to your lists right now.")
+}
+
+async fn add(state: &AppState, request: &SkillRequest, list: &List) -> Result<String, SkillError> {
+    let mut added = Vec::new();
+    for said in request.entities_named("item") {
+        let Some(parsed) = ParsedItem::parse(said) else {
+            continue;
+        };
+        let result = lists::add_item(state, list, &parsed, request.user_id.as_deref())
+            .await
+            .map_err(storage_failed)?;
+
+        // "2 eggs (14 in total)" when they were already on the list
+        added.push(match (result.merged, result.item.quantity) {
+            (true, Some(total)) => format!("{} ({} in total)", said.trim(), total),
+            _ => describe_item(&result.item),
+        });
+    }
+
+    if added.is_empty() {
+        return Err(SkillError::failed(format!("What should I add to the {} list?", list.name)));
+    }
+    Ok(format!("Added {} to the {} list.", join(&added), list.name))
+}
+
+/// Ticks items off, so they can still be seen in the app until cleared.
+async fn remove(state: &AppState, request: &SkillRequest, list: &List) -> Result<String, SkillError> {
+    let mut checked = Vec::new();
+    let mut missing = Vec::new();
+    for said in request.entities_named("item") {
+        match lists::check_item(state, list, said).await.map_err(storage_failed)? {
+            Some(item) => checked.push(item.name),
+            None => missing.push(said.trim().to_string()),
+        }
+    }
+
+    match (checked.is_empty(), missing.is_empty()) {
+        (true, _) => Err(SkillError::failed(format!(
+            "{} isn't on the {} list.",
+            capitalize(&join(&missing)),
+            list.name
+        ))),
+        (false, true) => Ok(format!("Ticked {} off the {} list.", join(&checked), list.name)),
+        (false, false) => Ok(format!(
+            "Ticked {} off the {} list. {} wasn't on it.",
+            join(&checked),
+            list.name,
+            capitalize(&join(&missing))
+        )),
+    }
+}
+
+async fn read(state: &AppState, list: &List) -> Result<String, SkillError> {
+    let items = lists::items(state, &list.id).await.map_err(storage_failed)?;
+    let open: Vec<String> = items.iter().filter(|item| !item.checked).map(describe_item).collect();
+
+    Ok(match open.as_slice() {
+        [] => format!("The {} list is empty.", list.name),
+        [item] => format!("There is one thing on the {} list: {}.", list.name, item),
+        _ => format!(
+            "There are {} things on the {} list: {}.",
+            open.len(),
+            list.name,
+            join(&open)
+        ),
+    })
+}
+
+async fn clear(state: &AppState, list: &List) -> Result<String, SkillError> {
+    sqlx::query("DELETE FROM list_items WHERE list_id = ?")
+        .bind(&list.id)
+        .execute(&state.db)
+        .await
+        .map_err(storage_failed)?;
+    lists::touch(state, list).await.map_err(storage_failed)?;
+
+    Ok(format!("Cleared the {} list.", list.name))
+}
+
+/// "milk", "milk and eggs", "milk, eggs and bread".
+fn join(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{nlu::NluEntity, AppState};
+
+mod conversation;
+mod lights;
+mod lists;
+mod reminders;
+mod time;
+mod timers;
+mod weather;
+
+pub use conversation::ConversationSkill;
+pub use lights::LightsSkill;
+pub use lists::ListsSkill;
+pub use reminders::RemindersSkill;
+pub use time::TimeSkill;
+pub use timers::TimersSkill;
+pub use weather::WeatherSkill;
+
+/// An intent a skill can handle, and the entities it needs for it.
+#[derive(Debug, Clone, Copy)]
+pub struct IntentSpec {
+    pub intent: &'static str,
+    pub required_entities: &'static [&'static str],
+    pub optional_entities: &'static [&'static str],
+}
+
+/// Everything a skill gets to know about the command it is executing.
+#[derive(Debug, Clone)]
+pub struct SkillRequest {
+    pub intent: String,
+    pub text: String,
+    pub entities: Vec<NluEntity>,
+    pub user_id: Option<String>,
+    pub satellite_id: Option<String>,
+}
+
+impl SkillRequest {
+    pub fn entity(&self, name: &str) -> Option<&str> {
+        self.entities
+            .iter()
+            .find(|entity| entity.name == name)
+            .map(|entity| entity.value.as_str())
+    }
+
+    /// Every value of an entity that can appear more than once, like the
+    /// items in "add milk and eggs to the shopping list".
+    pub fn entities_named(&self, name: &str) -> Vec<&str> {
+        self.entities
+            .iter()
+            .filter(|entity| entity.name == name)
+            .map(|entity| entity.value.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SkillError {
+    #[error("no intent recognised")]
+    NotUnderstood,
+    #[error("no skill handles intent '{0}'")]
+    UnknownIntent(String),
+    #[error("intent '{intent}' is missing entities: {}", missing.join(", "))]
+    MissingEntities { intent: String, missing: Vec<String> },
+    #[error("{message}")]
+    Failed { message: String },
+}
+
+impl SkillError {
+    pub fn failed(message: impl Into<String>) -> Self {
+        SkillError::Failed {
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            SkillError::NotUnderstood => "not_understood",
+            SkillError::UnknownIntent(_) => "unknown_intent",
+            SkillError::MissingEntities { .. } => "missing_entities",
+            SkillError::Failed { .. } => "skill_failed",
+        }
+    }
+
+    /// What Barnaby says back when the command can't be carried out.
+    pub fn spoken_response(&self) -> String {
+        match self {
+            SkillError::NotUnderstood => "I didn't understand that command.".to_string(),
+            SkillError::UnknownIntent(_) => "Sorry, I don't know how to do that yet.".to_string(),
+            SkillError::MissingEntities { missing, .. } => {
+                format!("I need a bit more information: which {}?", missing.join(" and "))
+            }
+            SkillError::Failed { message } => message.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut error = json!({
+            "code": self.code(),
+            "message": self.to_string(),
+        });
+
+        match self {
+            SkillError::UnknownIntent(intent) => {
+                error["intent"] = json!(intent);
+            }
+            SkillError::MissingEntities { intent, missing } => {
+                error["intent"] = json!(intent);
+                error["missing_entities"] = json!(missing);
+            }
+            _ => {}
+        }
+
+        error
+    }
+}
+
+/// Upper-cases the first letter, for labels and items that start a sentence.
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[async_trait]
+pub trait Skill: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn intents(&self) -> &'static [IntentSpec];
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError>;
+}
+
+/// Maps intents to the skill that handles them. Built once at startup.
+#[derive(Default)]
+pub struct SkillRegistry {
+    skills: Vec<Arc<dyn Skill>>,
+    intents: HashMap<&'static str, (usize, IntentSpec)>,
+}
+
+impl SkillRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_builtin_skills() -> Self {
+        let mut registry = Self::new();
+        registry.register(TimeSkill);
+        registry.register(WeatherSkill);
+        registry.register(ConversationSkill);
+        registry.register(LightsSkill);
+        registry.register(TimersSkill);
+        registry.register(RemindersSkill);
+        registry.register(ListsSkill);
+        registry
+    }
+
+    pub fn register(&mut self, skill: impl Skill + 'static) {
+        let index = self.skills.len();
+        for spec in skill.intents() {
+            if let Some((previous, _)) = self.intents.insert(spec.intent, (index, *spec)) {
+                tracing::warn!(
+                    "Intent '{}' moved from skill '{}' to '{}'",
+                    spec.intent,
+                    self.skills[previous].name(),
+                    skill.name()
+                );
+            }
+        }
+        self.skills.push(Arc::new(skill));
+    }
+
+    /// Every intent the registry can execute, with the skill that handles it.
+    pub fn intents(&self) -> Vec<(&'static str, IntentSpec)> {
+        let mut intents: Vec<_> = self
+            .intents
+            .values()
+            .map(|(index, spec)| (self.skills[*index].name(), *spec))
+            .collect();
+        intents.sort_by_key(|(_, spec)| spec.intent);
+        intents
+    }
+
+    pub async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        if request.intent == "unknown" || request.intent.is_empty() {
+            return Err(SkillError::NotUnderstood);
+        }
+
+        let (index, spec) = self
+            .intents
+            .get(request.intent.as_str())
+            .ok_or_else(|| SkillError::UnknownIntent(request.intent.clone()))?;
+
+        let missing: Vec<String> = spec
+            .required_entities
+            .iter()
+            .filter(|name| request.entity(name).is_none())
+            .map(|name| name.to_string())
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(SkillError::MissingEntities {
+                intent: request.intent.clone(),
+                missing,
+            });
+        }
+
+        self.skills[*index].execute(state, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, request};
+
+    /// Answers with the entities it was given.
+    struct Echo;
+
+    #[async_trait]
+    impl Skill for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn intents(&self) -> &'static [IntentSpec] {
+            &[IntentSpec {
+                intent: "move_item",
+                required_entities: &["item", "list"],
+                optional_entities: &["position"],
+            }]
+        }
+
+        async fn execute(&self, _state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+            Ok(format!("Moved {} to {}", request.entity("item").unwrap(), request.entity("list").unwrap()))
+        }
+    }
+
+    fn registry() -> SkillRegistry {
+        let mut registry = SkillRegistry::new();
+        registry.register(Echo);
+        registry
+    }
+
+    #[tokio::test]
+    async fn intents_go_to_the_skill_that_handles_them() {
+        let state = test_support::state().await;
+        let request = request("move_item", "move milk to shopping", &[("item", "milk"), ("list", "shopping")]);
+        assert_eq!(registry().execute(&state, &request).await.unwrap(), "Moved milk to shopping");
+    }
+
+    #[tokio::test]
+    async fn unknown_intents_are_reported() {
+        let state = test_support::state().await;
+
+        for intent in ["unknown", ""] {
+            let error = registry().execute(&state, &request(intent, "mumble", &[])).await.unwrap_err();
+            assert_eq!(error.to_json(), json!({"code": "not_understood", "message": "no intent recognised"}));
+        }
+
+        let error = registry().execute(&state, &request("fly_kite", "fly a kite", &[])).await.unwrap_err();
+        assert_eq!(
+            error.to_json(),
+            json!({"code": "unknown_intent", "message": "no skill handles intent 'fly_kite'", "intent": "fly_kite"})
+        );
+        assert_eq!(error.spoken_response(), "Sorry, I don't know how to do that yet.");
+    }
+
+    #[tokio::test]
+    async fn missing_entities_are_listed() {
+        let state = test_support::state().await;
+
+        let error = registry()
+            .execute(&state, &request("move_item", "move it", &[("position", "top")]))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_json(),
+            json!({
+                "code": "missing_entities",
+                "message": "intent 'move_item' is missing entities: item, list",
+                "intent": "move_item",
+                "missing_entities": ["item", "list"],
+            })
+        );
+        assert_eq!(error.spoken_response(), "I need a bit more information: which item and list?");
+
+        let error = registry()
+            .execute(&state, &request("move_item", "move milk", &[("item", "milk")]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_json()["missing_entities"], json!(["list"]));
+    }
+}
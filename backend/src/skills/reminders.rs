@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use chrono::{Local, Utc};
+
+use super::{IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{
+    database::models::Reminder,
+    nlu::datetime::parse_date_time,
+    reminders::{self, Recurrence},
+    AppState,
+};
+
+pub struct RemindersSkill;
+
+#[async_trait]
+impl Skill for RemindersSkill {
+    fn name(&self) -> &'static str {
+        "reminders"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[
+            IntentSpec {
+                intent: "set_reminder",
+                required_entities: &["reminder"],
+                optional_entities: &["recurrence"],
+            },
+            IntentSpec {
+                intent: "cancel_reminder",
+                required_entities: &[],
+                optional_entities: &["reminder"],
+            },
+            IntentSpec {
+                intent: "list_reminders",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+        ]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        // Reminders follow the user around, so there has to be one
+        let user_id = request.user_id.as_deref().ok_or_else(|| match request.satellite_id {
+            Some(_) => SkillError::failed("Sorry, I can only set reminders on a satellite that belongs to someone."),
+            None => SkillError::failed("Sorry, I can only set reminders for someone who is signed in."),
+        })?;
+
+        match request.intent.as_str() {
+            "set_reminder" => set_reminder(state, request, user_id).await,
+            "cancel_reminder" => cancel(state, request, user_id).await,
+            "list_reminders" => list(state, user_id).await,
+            other => Err(SkillError::UnknownIntent(other.to_string())),
+        }
+    }
+}
+
+fn storage_failed(_: sqlx::Error) -> SkillError {
+    SkillError::failed("Sorry, I couldn't get to your reminders right now.")
+}
+
+async fn set_reminder(state: &AppState, request: &SkillRequest, user_id: &str) -> Result<String, SkillError> {
+    let text = request
+        .entity("reminder")
+        .ok_or_else(|| SkillError::failed("What should I remind you about?"))?;
+    let recurrence = request.entity("recurrence").and_then(|r| r.parse::<Recurrence>().ok());
+
+    let now = Local::now();
+    let mut at = parse_date_time(&request.text, now)
+        .ok_or_else(|| SkillError::failed("When should I remind you?"))?;
+    if at <= now {
+        return Err(SkillError::failed("That time has already passed."));
+    }
+    if let Some(recurrence) = recurrence {
+        at = recurrence.first(at);
+    }
+
+    let reminder = reminders::create(
+        state,
+        user_id,
+        request.satellite_id.as_deref(),
+        text,
+        at.with_timezone(&Utc),
+        recurrence,
+    )
+    .await
+    .map_err(storage_failed)?;
+
+    Ok(format!("OK, I'll remind you: {}.", reminders::describe(&reminder)))
+}
+
+/// Cancels the reminder the command points at: by what it is about, all of
+/// them when asked, or the only one there is.
+async fn cancel(state: &AppState, request: &SkillRequest, user_id: &str) -> Result<String, SkillError> {
+    let pending = reminders::pending(state, user_id).await.map_err(storage_failed)?;
+    if pending.is_empty() {
+        return Err(SkillError::failed("You don't have any reminders."));
+    }
+
+    let all = request.text.to_lowercase().split_whitespace().any(|word| word == "all");
+    let chosen: Vec<&Reminder> = if all {
+        pending.iter().collect()
+    } else if let Some(about) = request.entity("reminder") {
+        let about = about.to_lowercase();
+        pending
+            .iter()
+            .filter(|reminder| {
+                let text = reminder.text.to_lowercase();
+                text.contains(&about) || about.contains(&text)
+            })
+            .collect()
+    } else if pending.len() == 1 {
+        pending.iter().collect()
+    } else {
+        return Err(SkillError::failed(format!(
+            "You have {} reminders. Which one should I cancel?",
+            pending.len()
+        )));
+    };
+
+    if chosen.is_empty() {
+        return Err(SkillError::failed("I couldn't find that reminder."));
+    }
+
+    for reminder in &chosen {
+        reminders::cancel(state, &reminder.id).await.map_err(storage_failed)?;
+    }
+
+    Ok(match chosen.as_slice() {
+        [reminder] => format!("Cancelled your reminder: {}.", reminder.text),
+        _ => format!("Cancelled {} reminders.", chosen.len()),
+    })
+}
+
+async fn list(state: &AppState, user_id: &str) -> Result<String, SkillError> {
+    let pending = reminders::pending(state, user_id).await.map_err(storage_failed)?;
+    let entries: Vec<String> = pending.iter().map(reminders::describe).collect();
+
+    Ok(match entries.as_slice() {
+        [] => "You don't have any reminders.".to_string(),
+        [entry] => format!("You have one reminder: {}.", entry),
+        [rest @ .., last] => format!("You have {} reminders: {} and {}.", entries.len(), rest.join(", "), last),
+    })
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+
+use super::{IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{location, AppState};
+
+pub struct TimeSkill;
+
+#[async_trait]
+impl Skill for TimeSkill {
+    fn name(&self) -> &'static str {
+        "time"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[
+            IntentSpec {
+                intent: "get_time",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+            IntentSpec {
+                intent: "get_timezone",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+        ]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        // The satellite's or home's timezone, else the server's own
+        let timezone = location::for_satellite(state, request.satellite_id.as_deref())
+            .await
+            .map_err(|_| SkillError::failed("Sorry, I couldn't look up where home is right now."))?
+            .and_then(|location| location.tz());
+
+        answer(&request.intent, timezone, Utc::now())
+    }
+}
+
+fn answer(intent: &str, timezone: Option<Tz>, now: DateTime<Utc>) -> Result<String, SkillError> {
+    let (clock, zone) = match timezone {
+        Some(timezone) => {
+            let now = now.with_timezone(&timezone);
+            (now.format("%H:%M").to_string(), format!("{} ({})", timezone.name(), now.format("%Z %z")))
+        }
+        None => {
+            let now = now.with_timezone(&Local);
+            (now.format("%H:%M").to_string(), now.format("%Z %z").to_string())
+        }
+    };
+
+    match intent {
+        "get_time" => Ok(format!("The current time is {}", clock)),
+        "get_timezone" => Ok(format!("You are in timezone: {}", zone)),
+        other => Err(SkillError::UnknownIntent(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        config::Settings,
+        test_support::{self, request},
+    };
+
+    #[test]
+    fn the_time_is_told_in_the_locations_timezone() {
+        let now = Utc.with_ymd_and_hms(2026, 7, 1, 11, 30, 0).unwrap();
+
+        assert_eq!(answer("get_time", Some(Tz::Europe__London), now).unwrap(), "The current time is 12:30");
+        assert_eq!(answer("get_time", Some(Tz::Asia__Tokyo), now).unwrap(), "The current time is 20:30");
+        assert_eq!(
+            answer("get_timezone", Some(Tz::Europe__London), now).unwrap(),
+            "You are in timezone: Europe/London (BST +0100)"
+        );
+        assert_eq!(
+            answer("get_time", None, now).unwrap(),
+            format!("The current time is {}", now.with_timezone(&Local).format("%H:%M"))
+        );
+    }
+
+    #[tokio::test]
+    async fn a_satellites_timezone_overrides_homes() {
+        let mut config = Settings::new().unwrap();
+        config.location.latitude = Some(51.5);
+        config.location.longitude = Some(-0.13);
+        config.location.timezone = Some("Europe/London".to_string());
+        let state = test_support::state_with(config).await;
+        let config = r#"{"location": {"latitude": 35.7, "longitude": 139.7, "timezone": "Asia/Tokyo"}}"#;
+        sqlx::query("INSERT INTO satellites (id, name, config) VALUES ('tokyo', 'Tokyo', ?), ('home', 'Home', NULL)")
+            .bind(config)
+            .execute(&state.db)
+            .await
+            .unwrap();
+
+        let zones = [(None, "Europe/London"), (Some("home"), "Europe/London"), (Some("tokyo"), "Asia/Tokyo")];
+        for (satellite, timezone) in zones {
+            let mut request = request("get_timezone", "what timezone am I in", &[]);
+            request.satellite_id = satellite.map(str::to_string);
+            let response = TimeSkill.execute(&state, &request).await.unwrap();
+            let expected = format!("You are in timezone: {} (", timezone);
+            assert!(response.starts_with(&expected), "{:?}: {}", satellite, response);
+        }
+    }
+}
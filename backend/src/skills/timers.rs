@@ -0,0 +1,299 @@
+use async_trait::async_trait;
+use chrono::{Duration, Local, Utc};
+
+use super::{capitalize, IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{
+    database::models::Timer,
+    nlu::datetime::{describe_clock_time, describe_duration, next_clock_time, parse_clock_time, parse_duration},
+    timers::{self, Owner, TimerKind},
+    AppState,
+};
+
+const MAX_TIMER_HOURS: i64 = 24;
+
+pub struct TimersSkill;
+
+#[async_trait]
+impl Skill for TimersSkill {
+    fn name(&self) -> &'static str {
+        "timers"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[
+            IntentSpec {
+                intent: "set_timer",
+                required_entities: &["duration"],
+                optional_entities: &["label"],
+            },
+            IntentSpec {
+                intent: "cancel_timer",
+                required_entities: &[],
+                optional_entities: &["label", "duration"],
+            },
+            IntentSpec {
+                intent: "list_timers",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+            IntentSpec {
+                intent: "set_alarm",
+                required_entities: &["time"],
+                optional_entities: &["label"],
+            },
+            IntentSpec {
+                intent: "cancel_alarm",
+                required_entities: &[],
+                optional_entities: &["label", "time"],
+            },
+            IntentSpec {
+                intent: "list_alarms",
+                required_entities: &[],
+                optional_entities: &[],
+            },
+        ]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        let owner = Owner {
+            user_id: request.user_id.as_deref(),
+            satellite_id: request.satellite_id.as_deref(),
+        };
+
+        match request.intent.as_str() {
+            "set_timer" => set_timer(state, request, owner).await,
+            "set_alarm" => set_alarm(state, request, owner).await,
+            "cancel_timer" => cancel(state, request, owner, TimerKind::Timer).await,
+            "cancel_alarm" => cancel(state, request, owner, TimerKind::Alarm).await,
+            "list_timers" => list(state, owner, TimerKind::Timer).await,
+            "list_alarms" => list(state, owner, TimerKind::Alarm).await,
+            other => Err(SkillError::UnknownIntent(other.to_string())),
+        }
+    }
+}
+
+fn storage_failed(_: sqlx::Error) -> SkillError {
+    SkillError::failed("Sorry, I couldn't get to your timers right now.")
+}
+
+async fn set_timer(state: &AppState, request: &SkillRequest, owner: Owner<'_>) -> Result<String, SkillError> {
+    let duration = request
+        .entity("duration")
+        .and_then(parse_duration)
+        .ok_or_else(|| SkillError::failed("How long should the timer run for?"))?;
+    if duration > Duration::hours(MAX_TIMER_HOURS) {
+        return Err(SkillError::failed(format!(
+            "Timers can run for up to {} hours. Try an alarm instead.",
+            MAX_TIMER_HOURS
+        )));
+    }
+
+    let label = request.entity("label");
+    timers::create(
+        state,
+        TimerKind::Timer,
+        Utc::now() + duration,
+        label,
+        Some(duration.num_seconds()),
+        owner,
+    )
+    .await
+    .map_err(storage_failed)?;
+
+    Ok(match label {
+        Some(label) => format!("{} timer set for {}.", capitalize(label), describe_duration(duration)),
+        None => format!("Timer set for {}.", describe_duration(duration)),
+    })
+}
+
+async fn set_alarm(state: &AppState, request: &SkillRequest, owner: Owner<'_>) -> Result<String, SkillError> {
+    let now = Local::now();
+    // The entity is the time alone; "tomorrow" is elsewhere in the command
+    let at = match request.entity("time") {
+        Some(time) => next_clock_time(time, request.text.to_lowercase().contains("tomorrow"), now),
+        None => parse_clock_time(&request.text, now),
+    }
+    .ok_or_else(|| SkillError::failed("What time should the alarm go off?"))?;
+
+    let label = request.entity("label");
+    timers::create(state, TimerKind::Alarm, at.with_timezone(&Utc), label, None, owner)
+        .await
+        .map_err(storage_failed)?;
+
+    let day = if at.date_naive() == now.date_naive() { "" } else { " tomorrow" };
+    Ok(match label {
+        Some(label) => format!("{} alarm set for {}{}.", capitalize(label), describe_clock_time(at), day),
+        None => format!("Alarm set for {}{}.", describe_clock_time(at), day),
+    })
+}
+
+/// Cancels the timer the command points at: by label, by duration or time,
+/// all of them when asked, or the only one there is.
+async fn cancel(
+    state: &AppState,
+    request: &SkillRequest,
+    owner: Owner<'_>,
+    kind: TimerKind,
+) -> Result<String, SkillError> {
+    let pending = timers::pending(state, kind, owner).await.map_err(storage_failed)?;
+    if pending.is_empty() {
+        return Err(SkillError::failed(format!("You don't have any {}s.", kind.as_str())));
+    }
+
+    let all = request.text.to_lowercase().split_whitespace().any(|word| word == "all");
+    let chosen: Vec<&Timer> = if all {
+        pending.iter().collect()
+    } else if let Some(label) = request.entity("label") {
+        pending
+            .iter()
+            .filter(|timer| timer.label.as_deref().is_some_and(|l| l.eq_ignore_ascii_case(label)))
+            .collect()
+    } else if let Some(duration) = request.entity("duration").and_then(parse_duration) {
+        pending
+            .iter()
+            .filter(|timer| timer.duration_secs == Some(duration.num_seconds()))
+            .collect()
+    } else if let Some(at) = request.entity("time").and_then(|time| next_clock_time(time, false, Local::now())) {
+        pending
+            .iter()
+            .filter(|timer| timer.fires_at.with_timezone(&Local).time() == at.time())
+            .collect()
+    } else if pending.len() == 1 {
+        pending.iter().collect()
+    } else {
+        return Err(SkillError::failed(format!(
+            "You have {} {}s. Which one should I cancel?",
+            pending.len(),
+            kind.as_str()
+        )));
+    };
+
+    if chosen.is_empty() {
+        return Err(SkillError::failed(format!("I couldn't find that {}.", kind.as_str())));
+    }
+
+    for timer in &chosen {
+        timers::cancel(state, &timer.id).await.map_err(storage_failed)?;
+    }
+
+    Ok(match chosen.as_slice() {
+        [timer] => format!("Cancelled {}.", describe(timer)),
+        _ => format!("Cancelled {} {}s.", chosen.len(), kind.as_str()),
+    })
+}
+
+async fn list(state: &AppState, owner: Owner<'_>, kind: TimerKind) -> Result<String, SkillError> {
+    let pending = timers::pending(state, kind, owner).await.map_err(storage_failed)?;
+    let now = Utc::now();
+
+    let entries: Vec<String> = pending
+        .iter()
+        .map(|timer| match kind {
+            TimerKind::Timer => format!("{} with {} left", describe(timer), describe_duration(timer.fires_at - now)),
+            TimerKind::Alarm => describe(timer),
+        })
+        .collect();
+
+    Ok(match entries.as_slice() {
+        [] => format!("You don't have any {}s.", kind.as_str()),
+        [entry] => format!("You have {}.", entry),
+        [rest @ .., last] => format!(
+            "You have {} {}s: {} and {}.",
+            entries.len(),
+            kind.as_str(),
+            rest.join(", "),
+            last
+        ),
+    })
+}
+
+/// "the pasta timer", "the timer for 5 minutes", "the 7:30 AM alarm".
+fn describe(timer: &Timer) -> String {
+    match (timer.kind.as_str(), &timer.label) {
+        (kind, Some(label)) => format!("the {} {}", label, kind),
+        ("alarm", None) => format!("the {} alarm", describe_clock_time(timer.fires_at.with_timezone(&Local))),
+        (_, None) => match timer.duration_secs {
+            Some(secs) => format!("the timer for {}", describe_duration(Duration::seconds(secs))),
+            None => "the timer".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::test_support;
+
+    /// What the skill says to `user_id`'s command.
+    async fn ask(
+        state: &AppState,
+        user_id: &str,
+        intent: &str,
+        text: &str,
+        entities: &[(&str, &str)],
+    ) -> Result<String, SkillError> {
+        let mut request = test_support::request(intent, text, entities);
+        request.user_id = Some(user_id.to_string());
+        TimersSkill.execute(state, &request).await
+    }
+
+    /// When the user's alarms go off, soonest first.
+    async fn alarms(state: &AppState, user_id: &str) -> Vec<chrono::DateTime<Local>> {
+        let owner = Owner {
+            user_id: Some(user_id),
+            satellite_id: None,
+        };
+        let pending = timers::pending(state, TimerKind::Alarm, owner).await.unwrap();
+        pending.iter().map(|alarm| alarm.fires_at.with_timezone(&Local)).collect()
+    }
+
+    fn clock(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn alarms_are_set_for_the_time_entity() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        // Rasa found the time in words the text has no digits for
+        let reply = ask(&state, &sam, "set_alarm", "wake me at quarter past six", &[("time", "6:15 am")])
+            .await
+            .unwrap();
+        assert!(reply.starts_with("Alarm set for 6:15 AM"), "{}", reply);
+        assert_eq!(alarms(&state, &sam).await[0].time(), clock(6, 15));
+    }
+
+    #[tokio::test]
+    async fn the_rest_of_the_command_says_which_day() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        let entities = [("time", "7"), ("label", "gym")];
+        let reply = ask(&state, &sam, "set_alarm", "alarm at 7 tomorrow for the gym", &entities).await.unwrap();
+        assert_eq!(reply, "Gym alarm set for 7:00 AM tomorrow.");
+        let tomorrow = Local::now().date_naive().succ_opt().unwrap();
+        assert_eq!(alarms(&state, &sam).await[0].date_naive(), tomorrow);
+    }
+
+    #[tokio::test]
+    async fn the_text_is_used_without_a_time_entity() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        ask(&state, &sam, "set_alarm", "set an alarm for 7:30 pm", &[]).await.unwrap();
+        assert_eq!(alarms(&state, &sam).await[0].time(), clock(19, 30));
+        assert!(ask(&state, &sam, "set_alarm", "set an alarm", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn alarms_are_cancelled_by_time() {
+        let state = test_support::state().await;
+        let sam = test_support::user(&state, "sam", "user").await;
+        for time in ["6:15 am", "7:30 pm"] {
+            ask(&state, &sam, "set_alarm", "set an alarm", &[("time", time)]).await.unwrap();
+        }
+        ask(&state, &sam, "cancel_alarm", "cancel my 7:30 alarm", &[("time", "7:30 pm")]).await.unwrap();
+        let left: Vec<NaiveTime> = alarms(&state, &sam).await.iter().map(|at| at.time()).collect();
+        assert_eq!(left, [clock(6, 15)]);
+    }
+}
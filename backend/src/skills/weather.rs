@@ -0,0 +1,394 @@
+use std::sync::LazyLock;
+
+use async_trait::async_trait;
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
+use regex::Regex;
+use tracing::info;
+
+use super::{IntentSpec, Skill, SkillError, SkillRequest};
+use crate::{
+    location::{self, Location},
+    nlu::datetime::{parse_days, DayPeriod},
+    services::weather::{is_rain, is_snow, weather_code_to_description, DailyForecast, Forecast, WeatherService},
+    AppState,
+};
+
+pub struct WeatherSkill;
+
+#[async_trait]
+impl Skill for WeatherSkill {
+    fn name(&self) -> &'static str {
+        "weather"
+    }
+
+    fn intents(&self) -> &'static [IntentSpec] {
+        &[IntentSpec {
+            intent: "get_weather",
+            required_entities: &[],
+            optional_entities: &["location", "date"],
+        }]
+    }
+
+    async fn execute(&self, state: &AppState, request: &SkillRequest) -> Result<String, SkillError> {
+        let weather_service = &state.weather;
+
+        // Check if location entity is present
+        let location = request.entity("location");
+        info!("Location entity found: {:?}", location);
+
+        let (result, place) = if let Some(location) = location {
+            info!("Getting weather for location: {}", location);
+            (weather_service.get_forecast_for_location(location).await, Some(location.to_string()))
+        } else {
+            let home = home_location(state, request, weather_service).await?;
+            info!("No location specified, getting weather for {:?}", home);
+            (weather_service.get_forecast(&home).await, home.name)
+        };
+
+        match result {
+            Ok(forecast) => answer(&forecast, request, place.as_deref()),
+            Err(e) => {
+                if e.to_string().contains("Location not found") {
+                    Err(SkillError::failed(
+                        "Sorry, I couldn't find that location. Please try a different city name.",
+                    ))
+                } else {
+                    Err(SkillError::failed(
+                        "Sorry, there's a connection issue and I cannot get that information right now.",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Where "what's the weather?" is about: the satellite's location, else
+/// home. Only guessed from our IP address when that has been enabled.
+async fn home_location(
+    state: &AppState,
+    request: &SkillRequest,
+    weather_service: &WeatherService,
+) -> Result<Location, SkillError> {
+    let configured = location::for_satellite(state, request.satellite_id.as_deref())
+        .await
+        .map_err(|_| SkillError::failed("Sorry, I couldn't look up where home is right now."))?;
+
+    match configured {
+        Some(location) => Ok(location),
+        None if state.config.location.ip_lookup => weather_service.locate_by_ip().await.map_err(|_| {
+            SkillError::failed("Sorry, there's a connection issue and I cannot get that information right now.")
+        }),
+        None => Err(SkillError::failed(
+            "I don't know where home is yet. An admin can set the home location, or you can ask about a city.",
+        )),
+    }
+}
+
+/// What the question is about, so the answer can lead with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topic {
+    Rain,
+    Snow,
+    Temperature,
+    Wind,
+    Sunrise,
+    Sunset,
+    General,
+}
+
+/// Patterns for each topic, in the order they are tried.
+static TOPICS: LazyLock<Vec<(Regex, Topic)>> = LazyLock::new(|| {
+    [
+        (r"\b(rain\w*|umbrella|wet|showers?|drizzle|precipitation|dry)\b", Topic::Rain),
+        (r"\bsnow\w*\b", Topic::Snow),
+        (r"\b(?:sunrise|sun (?:comes? )?up)\b", Topic::Sunrise),
+        (r"\b(?:sunset|sun (?:go(?:es)? )?down)\b", Topic::Sunset),
+        (r"\b(wind\w*|breezy|gusts?)\b", Topic::Wind),
+        (r"\b(temperature|hot|cold|warm|chilly|degrees|freezing)\b", Topic::Temperature),
+    ]
+    .into_iter()
+    .map(|(pattern, topic)| (Regex::new(pattern).unwrap(), topic))
+    .collect()
+});
+
+impl Topic {
+    fn of(text: &str) -> Self {
+        let text = text.to_lowercase();
+        TOPICS
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&text))
+            .map_or(Topic::General, |(_, topic)| *topic)
+    }
+}
+
+/// A stretch of the forecast summed up: a day, or part of one.
+#[derive(Debug)]
+struct Outlook {
+    /// "today", "tomorrow morning", "on Saturday", ...
+    label: String,
+    description: String,
+    weather_code: u32,
+    temperature_min: f64,
+    temperature_max: f64,
+    precipitation_probability: Option<f64>,
+    wind_speed_max: Option<f64>,
+    sunrise: Option<NaiveDateTime>,
+    sunset: Option<NaiveDateTime>,
+}
+
+impl Outlook {
+    fn for_day(day: &DailyForecast, label: String) -> Self {
+        Self {
+            label,
+            description: day.description.clone(),
+            weather_code: day.weather_code,
+            temperature_min: day.temperature_min,
+            temperature_max: day.temperature_max,
+            precipitation_probability: day.precipitation_probability,
+            wind_speed_max: day.wind_speed_max,
+            sunrise: day.sunrise,
+            sunset: day.sunset,
+        }
+    }
+
+    /// The hours of `day` between `from` and `to`, e.g. the evening or the
+    /// rest of today. Falls back to the whole day when no hours are left.
+    fn for_hours(forecast: &Forecast, day: &DailyForecast, from: NaiveDateTime, to: u32, label: String) -> Self {
+        let hours: Vec<_> = forecast
+            .hourly
+            .iter()
+            .filter(|hour| hour.time.date() == day.date && hour.time >= from && hour.time.hour() < to)
+            .collect();
+        if hours.is_empty() {
+            return Self::for_day(day, label);
+        }
+
+        // Weather codes grow with how bad it gets, so the worst hour describes it
+        let weather_code = hours.iter().map(|hour| hour.weather_code).max().unwrap_or(day.weather_code);
+        let temperatures = hours.iter().map(|hour| hour.temperature);
+        Self {
+            label,
+            description: weather_code_to_description(weather_code),
+            weather_code,
+            temperature_min: temperatures.clone().fold(f64::INFINITY, f64::min),
+            temperature_max: temperatures.fold(f64::NEG_INFINITY, f64::max),
+            precipitation_probability: hours.iter().filter_map(|hour| hour.precipitation_probability).reduce(f64::max),
+            wind_speed_max: day.wind_speed_max,
+            sunrise: day.sunrise,
+            sunset: day.sunset,
+        }
+    }
+
+    fn rain_likelihood(&self) -> &'static str {
+        match self.precipitation_probability {
+            Some(chance) if chance >= 60.0 => "likely",
+            Some(chance) if chance >= 30.0 => "possible",
+            None if is_rain(self.weather_code) => "likely",
+            _ if is_rain(self.weather_code) => "possible",
+            _ => "unlikely",
+        }
+    }
+}
+
+fn answer(forecast: &Forecast, request: &SkillRequest, location: Option<&str>) -> Result<String, SkillError> {
+    let topic = Topic::of(&request.text);
+    let place = location.map(|location| format!(" in {}", location)).unwrap_or_default();
+    let when = request.entity("date").unwrap_or("");
+    let now = forecast.local_now();
+    let today = now.date();
+
+    let days = parse_days(when, today);
+    let period = DayPeriod::find(when);
+
+    // "what's the weather like?" is about right now
+    if days.is_empty() && period.is_none() && matches!(topic, Topic::General | Topic::Temperature | Topic::Wind) {
+        let weather = &forecast.current;
+        if forecast.stale {
+            let fetched_at = forecast.fetched_at.with_timezone(&Local);
+            return Ok(format!(
+                "I can't reach the weather service right now. At {} the weather{} was {}°C with {}.",
+                fetched_at.format("%-I:%M %p"),
+                if place.is_empty() { " for your location" } else { &place },
+                weather.temperature,
+                weather.description
+            ));
+        }
+        return Ok(format!(
+            "The current weather{} is {}°C with {}. Wind speed is {} km/h.",
+            if place.is_empty() { " for your location" } else { &place },
+            weather.temperature,
+            weather.description,
+            weather.wind_speed
+        ));
+    }
+
+    let days = if days.is_empty() { vec![today] } else { days };
+    let mut outlooks = Vec::new();
+    for date in days {
+        let day = forecast
+            .day(date)
+            .ok_or_else(|| SkillError::failed("Sorry, I can only see the weather for the coming week."))?;
+        let label = day_label(date, today, period, when);
+        let outlook = match period {
+            Some(period) => {
+                let start = date.and_hms_opt(period.hours().start, 0, 0).unwrap_or_default().max(now);
+                Outlook::for_hours(forecast, day, start, period.hours().end, label)
+            }
+            // Whatever is left of today, when asking if it will rain later
+            None if date == today && matches!(topic, Topic::Rain | Topic::Snow) => {
+                Outlook::for_hours(forecast, day, now, 24, label)
+            }
+            None => Outlook::for_day(day, label),
+        };
+        outlooks.push(outlook);
+    }
+
+    Ok(describe(topic, &outlooks, &place))
+}
+
+/// "today", "tonight", "tomorrow morning", "on Saturday", "on Saturday evening".
+fn day_label(date: NaiveDate, today: NaiveDate, period: Option<DayPeriod>, said: &str) -> String {
+    let day = match (date - today).num_days() {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        _ => date.format("on %A").to_string(),
+    };
+    match period {
+        Some(DayPeriod::Evening) if date == today && said.contains("tonight") => "tonight".to_string(),
+        Some(period) if date == today => format!("this {}", period.as_str()),
+        Some(period) => format!("{} {}", day, period.as_str()),
+        None => day,
+    }
+}
+
+fn describe(topic: Topic, outlooks: &[Outlook], place: &str) -> String {
+    let degrees = |outlook: &Outlook| {
+        format!(
+            "between {:.0} and {:.0}°C",
+            outlook.temperature_min, outlook.temperature_max
+        )
+    };
+    let chance = |outlook: &Outlook| {
+        outlook
+            .precipitation_probability
+            .map(|chance| format!("{:.0}%", chance))
+            .unwrap_or_else(|| "no forecast".to_string())
+    };
+
+    match (topic, outlooks) {
+        (Topic::Rain, [outlook]) => {
+            let answer = match outlook.rain_likelihood() {
+                "likely" => "Yes, rain is likely",
+                "possible" => "Rain is possible",
+                _ => "No, rain isn't expected",
+            };
+            match outlook.precipitation_probability {
+                Some(probability) => format!(
+                    "{} {}{}, with a {:.0}% chance.",
+                    answer, outlook.label, place, probability
+                ),
+                None => format!("{} {}{}.", answer, outlook.label, place),
+            }
+        }
+        (Topic::Rain, _) => format!(
+            "Rain is {}{}.",
+            join(outlooks.iter().map(|outlook| format!(
+                "{} {} ({})",
+                outlook.rain_likelihood(),
+                outlook.label,
+                chance(outlook)
+            ))),
+            place
+        ),
+        (Topic::Snow, _) => {
+            let snowy: Vec<&str> = outlooks
+                .iter()
+                .filter(|outlook| is_snow(outlook.weather_code))
+                .map(|outlook| outlook.label.as_str())
+                .collect();
+            if snowy.is_empty() {
+                format!("No, snow isn't expected {}{}.", join(outlooks.iter().map(|o| o.label.clone())), place)
+            } else {
+                format!("Yes, snow is expected {}{}.", join(snowy.into_iter().map(str::to_string)), place)
+            }
+        }
+        (Topic::Temperature, _) => format!(
+            "It will be {}{}.",
+            join(outlooks.iter().map(|outlook| format!("{} {}", degrees(outlook), outlook.label))),
+            place
+        ),
+        (Topic::Wind, _) => format!(
+            "Wind will reach {}{}.",
+            join(outlooks.iter().map(|outlook| match outlook.wind_speed_max {
+                Some(speed) => format!("{:.0} km/h {}", speed, outlook.label),
+                None => format!("an unknown speed {}", outlook.label),
+            })),
+            place
+        ),
+        (Topic::Sunrise | Topic::Sunset, _) => {
+            let (what, times): (&str, Vec<String>) = match topic {
+                Topic::Sunrise => ("Sunrise", outlooks.iter().map(|outlook| sun_time(outlook.sunrise, &outlook.label)).collect()),
+                _ => ("Sunset", outlooks.iter().map(|outlook| sun_time(outlook.sunset, &outlook.label)).collect()),
+            };
+            format!("{}{} is {}.", what, place, join(times.into_iter()))
+        }
+        (Topic::General, _) => outlooks
+            .iter()
+            .map(|outlook| {
+                format!(
+                    "{} {}{}, {}, with a {} chance of rain.",
+                    outlook.description,
+                    outlook.label,
+                    place,
+                    degrees(outlook),
+                    chance(outlook)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn sun_time(time: Option<NaiveDateTime>, label: &str) -> String {
+    match time {
+        Some(time) => format!("at {} {}", time.format("%-I:%M %p"), label),
+        None => format!("unknown {}", label),
+    }
+}
+
+/// "a", "a and b", "a, b and c".
+fn join(parts: impl Iterator<Item = String>) -> String {
+    let mut parts: Vec<String> = parts.collect();
+    match parts.len() {
+        0 => String::new(),
+        1 => parts.remove(0),
+        _ => {
+            let last = parts.pop().unwrap_or_default();
+            format!("{} and {}", parts.join(", "), last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions_are_about_a_topic() {
+        assert_eq!(Topic::of("Will it rain tomorrow?"), Topic::Rain);
+        assert_eq!(Topic::of("what time is sunrise"), Topic::Sunrise);
+        assert_eq!(Topic::of("when does the sun come up"), Topic::Sunrise);
+        assert_eq!(Topic::of("when does the sun comes up"), Topic::Sunrise);
+        assert_eq!(Topic::of("when does the sun go down"), Topic::Sunset);
+        assert_eq!(Topic::of("when is sunset"), Topic::Sunset);
+        assert_eq!(Topic::of("how cold is it"), Topic::Temperature);
+        assert_eq!(Topic::of("what's the weather like"), Topic::General);
+    }
+
+    #[test]
+    fn topics_are_whole_words() {
+        // Both ends of the alternation need a word boundary
+        assert_eq!(Topic::of("sunrises are nice"), Topic::General);
+        assert_eq!(Topic::of("tsun up north"), Topic::General);
+    }
+}
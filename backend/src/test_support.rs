@@ -0,0 +1,202 @@
+//! Shared setup for tests: an `AppState` on a fresh in-memory database,
+//! with RustNlu as the only NLU engine and nothing external configured.
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header::AUTHORIZATION, Method, Request, StatusCode},
+};
+use serde_json::Value;
+use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use tower::ServiceExt;
+
+use crate::{
+    api,
+    auth::jwt::generate_token,
+    config::Settings,
+    events::EventBus,
+    mqtt::MqttService,
+    nlu::{patterns, NluEntity, NluPipeline, NluSource, RasaManager},
+    services::weather::WeatherService,
+    skills::{SkillRegistry, SkillRequest},
+    AppState,
+};
+
+/// A migrated database that lives as long as the pool. One connection, as
+/// every in-memory connection would have a database of its own.
+pub async fn database() -> SqlitePool {
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&db).await.unwrap();
+    db
+}
+
+pub async fn state() -> AppState {
+    state_with(Settings::new().unwrap()).await
+}
+
+pub async fn state_with(mut config: Settings) -> AppState {
+    config.nlu.engines = "rust_nlu".to_string();
+    let rust_nlu = Arc::new(RwLock::new(patterns::load_rust_nlu(&config.nlu).unwrap()));
+    let nlu = NluPipeline::from_config(&config.nlu, None, rust_nlu.clone()).unwrap();
+    AppState {
+        db: database().await,
+        nlu: Arc::new(nlu),
+        events: EventBus::new(),
+        skills: Arc::new(SkillRegistry::with_builtin_skills()),
+        mqtt: None,
+        stt: None,
+        tts: None,
+        home_automation: None,
+        weather: Arc::new(WeatherService::new(&config.weather).unwrap()),
+        rust_nlu,
+        rasa: Arc::new(tokio::sync::Mutex::new(RasaManager::new(&config.nlu))),
+        config,
+    }
+}
+
+/// Adds a user with `role` and returns their id.
+pub async fn user(state: &AppState, username: &str, role: &str) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO users (id, username, email, password_hash, role) VALUES (?, ?, ?, 'x', ?)")
+        .bind(&id)
+        .bind(username)
+        .bind(format!("{}@example.com", username))
+        .bind(role)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    id
+}
+
+/// A bearer token for a user with `role`.
+pub fn token(state: &AppState, user_id: &str, role: &str) -> String {
+    let auth = &state.config.auth;
+    generate_token(user_id, user_id, role, &auth.jwt_secret, auth.jwt_expiration).unwrap()
+}
+
+/// Sends a JSON request through all the API routes, with `token` as the
+/// bearer token if there is one, and returns the status and the JSON body
+/// (`Null` when there is none).
+pub async fn call(
+    state: &AppState,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let request = request
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = api::create_routes(state.clone())
+        .with_state(state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+pub async fn room(state: &AppState, name: &str) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO rooms (id, name) VALUES (?, ?)")
+        .bind(&id)
+        .bind(name)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    id
+}
+
+/// Adds an MQTT light that doesn't report its state.
+pub async fn light(state: &AppState, id: &str, room_id: Option<&str>, capabilities: &[&str]) {
+    sqlx::query(
+        "INSERT INTO devices (id, name, room_id, device_type, capabilities, command_topic) VALUES (?, ?, ?, 'light', ?, ?)",
+    )
+    .bind(id)
+    .bind(id)
+    .bind(room_id)
+    .bind(serde_json::to_string(capabilities).unwrap())
+    .bind(format!("barnaby/devices/{}/set", id))
+    .execute(&state.db)
+    .await
+    .unwrap();
+}
+
+/// A client for a broker that isn't there. Publishing succeeds until its
+/// request queue is full.
+pub async fn offline_mqtt() -> MqttService {
+    let mut config = Settings::new().unwrap().mqtt;
+    config.broker = "127.0.0.1".to_string();
+    config.port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    MqttService::new(&config).await.unwrap().0
+}
+
+/// What a skill gets for `text`, understood as `intent` with `entities`.
+pub fn request(intent: &str, text: &str, entities: &[(&str, &str)]) -> SkillRequest {
+    SkillRequest {
+        intent: intent.to_string(),
+        text: text.to_string(),
+        entities: entities
+            .iter()
+            .map(|(name, value)| NluEntity {
+                name: name.to_string(),
+                value: value.to_string(),
+                start: None,
+                end: None,
+                confidence: 1.0,
+                source: NluSource::RustNlu,
+            })
+            .collect(),
+        user_id: None,
+        satellite_id: None,
+    }
+}
+
+/// Starts rumqttd on a free local port and waits until it accepts
+/// connections.
+pub fn start_broker() -> u16 {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let config: rumqttd::Config = toml::from_str(&format!(
+        r#"
+        id = 0
+
+        [router]
+        max_connections = 10
+        max_outgoing_packet_count = 200
+        max_segment_size = 10485760
+        max_segment_count = 10
+
+        [v4.1]
+        name = "v4-1"
+        listen = "127.0.0.1:{}"
+        next_connection_delay_ms = 1
+
+        [v4.1.connections]
+        connection_timeout_ms = 60000
+        max_payload_size = 1048576
+        max_inflight_count = 100
+        "#,
+        port
+    ))
+    .unwrap();
+    std::thread::spawn(move || rumqttd::Broker::new(config).start().unwrap());
+
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    while TcpStream::connect(address).is_err() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    port
+}
@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use serde_json::json;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    database::models::Timer,
+    events::Event,
+    nlu::datetime::{describe_clock_time, describe_duration},
+    pipeline, AppState,
+};
+
+pub const TIMER_COLUMNS: &str =
+    "id, kind, label, user_id, satellite_id, duration_secs, fires_at, status, fired_at, created_at";
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Timers that came due longer ago than this, e.g. while Barnaby was down,
+/// are marked missed instead of going off late.
+const MISSED_AFTER_SECS: i64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerKind {
+    Timer,
+    Alarm,
+}
+
+impl TimerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimerKind::Timer => "timer",
+            TimerKind::Alarm => "alarm",
+        }
+    }
+}
+
+impl std::str::FromStr for TimerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timer" => Ok(TimerKind::Timer),
+            "alarm" => Ok(TimerKind::Alarm),
+            _ => Err(format!("Invalid timer kind: {}", s)),
+        }
+    }
+}
+
+/// Who a timer belongs to. Timers set through a satellite belong to the
+/// satellite, so anyone in the room can ask about them.
+#[derive(Debug, Clone, Copy)]
+pub struct Owner<'a> {
+    pub user_id: Option<&'a str>,
+    pub satellite_id: Option<&'a str>,
+}
+
+pub async fn create(
+    state: &AppState,
+    kind: TimerKind,
+    fires_at: DateTime<Utc>,
+    label: Option<&str>,
+    duration_secs: Option<i64>,
+    owner: Owner<'_>,
+) -> Result<Timer, sqlx::Error> {
+    let timer = sqlx::query_as::<_, Timer>(&format!(
+        "INSERT INTO timers (id, kind, label, user_id, satellite_id, duration_secs, fires_at) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+        TIMER_COLUMNS
+    ))
+    .bind(Uuid::new_v4().to_string())
+    .bind(kind.as_str())
+    .bind(label)
+    .bind(owner.user_id)
+    .bind(owner.satellite_id)
+    .bind(duration_secs)
+    .bind(fires_at)
+    .fetch_one(&state.db)
+    .await?;
+
+    info!("Set {} {} for {}", kind.as_str(), timer.id, fires_at);
+    Ok(timer)
+}
+
+/// Timers still to go off, soonest first.
+pub async fn pending(state: &AppState, kind: TimerKind, owner: Owner<'_>) -> Result<Vec<Timer>, sqlx::Error> {
+    sqlx::query_as::<_, Timer>(&format!(
+        "SELECT {} FROM timers
+         WHERE status = 'pending' AND kind = ?
+           AND (satellite_id = ? OR (? IS NULL AND user_id = ?))
+         ORDER BY fires_at",
+        TIMER_COLUMNS
+    ))
+    .bind(kind.as_str())
+    .bind(owner.satellite_id)
+    .bind(owner.satellite_id)
+    .bind(owner.user_id)
+    .fetch_all(&state.db)
+    .await
+}
+
+pub async fn find_timer(state: &AppState, timer_id: &str) -> Result<Option<Timer>, sqlx::Error> {
+    sqlx::query_as::<_, Timer>(&format!("SELECT {} FROM timers WHERE id = ?", TIMER_COLUMNS))
+        .bind(timer_id)
+        .fetch_optional(&state.db)
+        .await
+}
+
+/// Returns false when the timer had already gone off or been cancelled.
+pub async fn cancel(state: &AppState, timer_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE timers SET status = 'cancelled' WHERE id = ? AND status = 'pending'")
+        .bind(timer_id)
+        .execute(&state.db)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// What Barnaby says when the timer goes off.
+pub fn announcement(timer: &Timer) -> String {
+    match (timer.kind.as_str(), &timer.label) {
+        ("alarm", Some(label)) => format!(
+            "It's {}. Your {} alarm is going off.",
+            describe_clock_time(timer.fires_at.with_timezone(&Local)),
+            label
+        ),
+        ("alarm", None) => format!(
+            "It's {}. Your alarm is going off.",
+            describe_clock_time(timer.fires_at.with_timezone(&Local))
+        ),
+        (_, Some(label)) => format!("Your {} timer is done.", label),
+        (_, None) => match timer.duration_secs {
+            Some(secs) => format!(
+                "Your timer for {} is done.",
+                describe_duration(chrono::Duration::seconds(secs))
+            ),
+            None => "Your timer is done.".to_string(),
+        },
+    }
+}
+
+/// Sets off timers as they come due. Timers that were due while Barnaby was
+/// down go off once it is back, unless they are too late to be useful.
+pub async fn run(state: AppState) {
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        sweep.tick().await;
+        if let Err(e) = fire_due(&state).await {
+            warn!("Failed to check timers: {}", e);
+        }
+    }
+}
+
+async fn fire_due(state: &AppState) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let due = sqlx::query_as::<_, Timer>(&format!(
+        "SELECT {} FROM timers WHERE status = 'pending' AND fires_at <= ? ORDER BY fires_at",
+        TIMER_COLUMNS
+    ))
+    .bind(now)
+    .fetch_all(&state.db)
+    .await?;
+
+    for timer in due {
+        if (now - timer.fires_at).num_seconds() > MISSED_AFTER_SECS {
+            sqlx::query("UPDATE timers SET status = 'missed' WHERE id = ? AND status = 'pending'")
+                .bind(&timer.id)
+                .execute(&state.db)
+                .await?;
+            info!("Missed {} {} due at {}", timer.kind, timer.id, timer.fires_at);
+            continue;
+        }
+
+        // Only whoever flips the status announces, in case it was cancelled meanwhile
+        let fired = sqlx::query("UPDATE timers SET status = 'fired', fired_at = ? WHERE id = ? AND status = 'pending'")
+            .bind(now)
+            .bind(&timer.id)
+            .execute(&state.db)
+            .await?;
+        if fired.rows_affected() > 0 {
+            announce(state, &timer).await;
+        }
+    }
+    Ok(())
+}
+
+async fn announce(state: &AppState, timer: &Timer) {
+    let text = announcement(timer);
+    info!("{} {} went off: {}", timer.kind, timer.id, text);
+
+    state.events.publish(Event::TimerFired {
+        timer_id: timer.id.clone(),
+        kind: timer.kind.clone(),
+        label: timer.label.clone(),
+        user_id: timer.user_id.clone(),
+        satellite_id: timer.satellite_id.clone(),
+        text: text.clone(),
+    });
+
+    let (Some(mqtt), Some(satellite_id)) = (&state.mqtt, &timer.satellite_id) else {
+        return;
+    };
+    let payload = json!({
+        "timer_id": timer.id,
+        "kind": timer.kind,
+        "label": timer.label,
+        "text": text,
+        "audio": pipeline::synthesize(state, &text).await,
+    });
+    if let Err(e) = mqtt
+        .publish_to_satellite(satellite_id, "timers/fired", &payload.to_string())
+        .await
+    {
+        warn!("Failed to tell satellite {} about {} {}: {}", satellite_id, timer.kind, timer.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::test_support;
+
+    async fn timer(state: &AppState, label: &str, fires_at: DateTime<Utc>) -> Timer {
+        let owner = Owner {
+            user_id: None,
+            satellite_id: None,
+        };
+        create(state, TimerKind::Timer, fires_at, Some(label), Some(60), owner).await.unwrap()
+    }
+
+    async fn status(state: &AppState, timer: &Timer) -> String {
+        find_timer(state, &timer.id).await.unwrap().unwrap().status
+    }
+
+    fn fired(events: &mut tokio::sync::broadcast::Receiver<crate::events::EventEnvelope>) -> Vec<String> {
+        std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|envelope| match envelope.event {
+                Event::TimerFired { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn due_timers_go_off_once() {
+        let state = test_support::state().await;
+        let now = Utc::now();
+        let pasta = timer(&state, "pasta", now - chrono::Duration::seconds(2)).await;
+        let eggs = timer(&state, "eggs", now + chrono::Duration::hours(1)).await;
+        let mut events = state.events.subscribe();
+
+        fire_due(&state).await.unwrap();
+        fire_due(&state).await.unwrap();
+
+        assert_eq!(status(&state, &pasta).await, "fired");
+        assert!(find_timer(&state, &pasta.id).await.unwrap().unwrap().fired_at.is_some());
+        assert_eq!(status(&state, &eggs).await, "pending");
+        assert_eq!(fired(&mut events), ["Your pasta timer is done."]);
+    }
+
+    #[tokio::test]
+    async fn timers_due_while_down_go_off_on_restart_unless_long_past() {
+        let state = test_support::state().await;
+        let now = Utc::now();
+        // As left in the database when Barnaby stopped
+        let recent = timer(&state, "tea", now - chrono::Duration::minutes(5)).await;
+        let stale = timer(&state, "bread", now - chrono::Duration::seconds(MISSED_AFTER_SECS + 60)).await;
+        let cancelled = timer(&state, "rice", now - chrono::Duration::minutes(1)).await;
+        assert!(cancel(&state, &cancelled.id).await.unwrap());
+        let mut events = state.events.subscribe();
+
+        // The first sweep after starting again
+        fire_due(&state).await.unwrap();
+
+        assert_eq!(status(&state, &recent).await, "fired");
+        assert_eq!(status(&state, &stale).await, "missed");
+        assert_eq!(status(&state, &cancelled).await, "cancelled");
+        assert_eq!(fired(&mut events), ["Your tea timer is done."]);
+        assert!(!cancel(&state, &stale.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn announcements_name_the_timer() {
+        let state = test_support::state().await;
+        let owner = Owner {
+            user_id: None,
+            satellite_id: None,
+        };
+        let fires_at = Local.with_ymd_and_hms(2026, 3, 4, 7, 30, 0).unwrap().with_timezone(&Utc);
+        let alarm = create(&state, TimerKind::Alarm, fires_at, None, None, owner).await.unwrap();
+        assert_eq!(announcement(&alarm), "It's 7:30 AM. Your alarm is going off.");
+        let gym = create(&state, TimerKind::Alarm, fires_at, Some("gym"), None, owner).await.unwrap();
+        assert_eq!(announcement(&gym), "It's 7:30 AM. Your gym alarm is going off.");
+        let unlabelled = create(&state, TimerKind::Timer, fires_at, None, Some(90), owner).await.unwrap();
+        assert_eq!(announcement(&unlabelled), "Your timer for 1 minute and 30 seconds is done.");
+    }
+}